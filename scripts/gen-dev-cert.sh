@@ -0,0 +1,21 @@
+#!/bin/sh
+# Generates a self-signed certificate and key, e.g. for a reverse proxy in front of the
+# server (builds have no TLS support of their own, so [server.tls] is refused at startup).
+#
+# FOR DEVELOPMENT ONLY. Browsers will warn about the certificate, and it proves nothing about
+# the server's identity. Production deployments must use a certificate signed by a proper CA.
+#
+# Usage: scripts/gen-dev-cert.sh [output-dir] [hostname]
+# Then give the proxy <output-dir>/cert.pem and <output-dir>/key.pem.
+set -eu
+
+out_dir="${1:-tls}"
+host="${2:-localhost}"
+
+mkdir -p "$out_dir"
+openssl req -x509 -newkey rsa:2048 -nodes -days 365 \
+    -keyout "$out_dir/key.pem" -out "$out_dir/cert.pem" \
+    -subj "/CN=$host" \
+    -addext "subjectAltName=DNS:$host,DNS:localhost,IP:127.0.0.1"
+chmod 600 "$out_dir/key.pem"
+echo "Wrote $out_dir/cert.pem and $out_dir/key.pem (self-signed, valid 365 days)"
@@ -0,0 +1,166 @@
+// src/ack.rs
+
+//! Firmware acknowledgements: the Arduino echoes each command it executes as "ACK:<cmd>"
+//! (e.g. "ACK:s51"). Every command line written to the port is recorded as pending, and the
+//! matching ACK line removes it and records the round-trip time.
+//!
+//! Pending commands are keyed by their text, so if the same command is sent again before
+//! its ACK arrives, the round trip is measured from the latest send.
+//!
+//! A command the firmware could not execute (e.g. a valve driver fault) is answered with
+//! "NACK:<cmd>" instead, which also ends its wait. The most recent ACK/NACK lines are kept
+//! for GET /ack/log, and the last NACK for GET /ack/last.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use rocket::serde::Serialize;
+
+use crate::latency::{LatencyStats, SharedLatency};
+
+/// Commands written to the port and not yet acknowledged, with the time they were written.
+pub type PendingCommands = Arc<Mutex<HashMap<String, Instant>>>;
+
+/// Response body for GET /ack/stats.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AckStats {
+    /// Commands still waiting for their ACK.
+    pub pending: usize,
+    /// ACK lines that did not match any pending command.
+    pub unmatched: u64,
+    /// Write-to-ACK round trips of recently acknowledged commands.
+    pub round_trip: LatencyStats,
+}
+
+/// Number of ACK/NACK lines kept for GET /ack/log.
+pub const ACK_LOG_SIZE: usize = 100;
+
+/// Whether the firmware executed a command.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AckKind {
+    Ack,
+    Nack,
+}
+
+/// An "ACK:<cmd>" or "NACK:<cmd>" line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AckMessage {
+    pub kind: AckKind,
+    pub command: String,
+}
+
+/// An ACK/NACK line as logged: the message plus the Arduino timestamp of the latest
+/// telemetry when it arrived.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AckEvent {
+    pub telemetry_ts: u64,
+    #[serde(flatten)]
+    pub message: AckMessage,
+}
+
+/// The most recent ACK/NACK lines, and the last NACK (which may be older than all of them).
+#[derive(Debug, Default)]
+pub struct AckLog {
+    events: VecDeque<AckEvent>,
+    last_nack: Option<AckEvent>,
+}
+
+/// A shared ACK log (appended by the serial loop, read by the handlers).
+pub type SharedAckLog = Arc<Mutex<AckLog>>;
+
+impl AckLog {
+    pub fn record(&mut self, telemetry_ts: u64, message: AckMessage) {
+        let event = AckEvent {
+            telemetry_ts,
+            message,
+        };
+        if event.message.kind == AckKind::Nack {
+            self.last_nack = Some(event.clone());
+        }
+        if self.events.len() == ACK_LOG_SIZE {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// The logged lines, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &AckEvent> {
+        self.events.iter()
+    }
+
+    pub fn last_nack(&self) -> Option<&AckEvent> {
+        self.last_nack.as_ref()
+    }
+}
+
+/// Parses an "ACK:<cmd>" or "NACK:<cmd>" line. Other lines (telemetry) give `None`.
+pub fn parse_ack_line(line: &str) -> Option<AckMessage> {
+    let (kind, command) = if let Some(cmd) = line.strip_prefix("ACK:") {
+        (AckKind::Ack, cmd)
+    } else {
+        (AckKind::Nack, line.strip_prefix("NACK:")?)
+    };
+    Some(AckMessage {
+        kind,
+        command: command.trim().to_string(),
+    })
+}
+
+/// Records every line of a command write (batches are several lines) as pending.
+pub fn record_sent(pending: &PendingCommands, text: &str, written_at: Instant) {
+    let mut pending = pending.lock().unwrap();
+    for cmd in text.lines().map(str::trim).filter(|cmd| !cmd.is_empty()) {
+        pending.insert(cmd.to_string(), written_at);
+    }
+}
+
+/// If `line` is an ACK or NACK line, removes its command from `pending` and records the
+/// round trip in `round_trips`. Returns `None` for other lines, otherwise the message and
+/// whether it matched a pending command.
+pub fn handle_ack_line(
+    line: &str,
+    pending: &PendingCommands,
+    round_trips: &SharedLatency,
+) -> Option<(AckMessage, bool)> {
+    let message = parse_ack_line(line)?;
+    let sent_at = pending.lock().unwrap().remove(&message.command);
+    if let Some(sent_at) = sent_at {
+        round_trips.lock().unwrap().record(sent_at.elapsed());
+    }
+    Some((message, sent_at.is_some()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_acks_and_nacks() {
+        assert_eq!(
+            parse_ack_line("NACK:s51"),
+            Some(AckMessage {
+                kind: AckKind::Nack,
+                command: "s51".to_string()
+            })
+        );
+        assert_eq!(parse_ack_line("ACK:a ").unwrap().kind, AckKind::Ack);
+        assert_eq!(parse_ack_line("TS:1500 | ARM:0"), None);
+    }
+
+    #[test]
+    fn log_keeps_the_latest_lines_and_the_last_nack() {
+        let mut log = AckLog::default();
+        log.record(10, parse_ack_line("NACK:s51").unwrap());
+        for ts in 0..ACK_LOG_SIZE as u64 {
+            log.record(100 + ts, parse_ack_line("ACK:a").unwrap());
+        }
+        assert_eq!(log.events().count(), ACK_LOG_SIZE);
+        assert!(log.events().all(|e| e.message.kind == AckKind::Ack));
+        assert_eq!(log.last_nack().unwrap().telemetry_ts, 10);
+    }
+}
@@ -0,0 +1,234 @@
+// src/alerts.rs
+
+//! Threshold alerts (`[[alert]]`). A background thread follows the telemetry broadcast and
+//! evaluates every rule on each sample. When a rule starts or stops holding, its webhook is
+//! POSTed `{"event":"alert_fired"|"alert_resolved",..}` once for that transition, so a reading
+//! that stays past its threshold alerts once. GET /alerts/active lists the rules firing now.
+//!
+//! Posting needs the `webhook` feature (see `webhook`); without it alerts are only tracked.
+
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::tokio::sync::broadcast;
+use rocket::State;
+use tracing::{info, warn};
+
+use crate::config::{AlertField, AlertOp, AlertRule};
+use crate::{AppState, Telemetry};
+
+/// The alerts firing now, for GET /alerts/active.
+pub type SharedActiveAlerts = Arc<Mutex<Vec<ActiveAlert>>>;
+
+/// A rule that holds for the latest telemetry.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ActiveAlert {
+    /// The rule's position among the `[[alert]]` sections, from 0.
+    pub rule: usize,
+    pub field: AlertField,
+    pub op: AlertOp,
+    pub threshold: f32,
+    /// The reading that set it off.
+    pub value: f32,
+    /// The telemetry timestamp it fired at.
+    pub since: u64,
+}
+
+/// The JSON body POSTed to a rule's webhook.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AlertEvent {
+    /// "alert_fired" or "alert_resolved".
+    pub event: &'static str,
+    pub rule: usize,
+    pub field: AlertField,
+    pub op: AlertOp,
+    pub threshold: f32,
+    /// The reading that fired or resolved the alert.
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+/// Evaluates the rules and remembers which are firing.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    /// By rule: the alert, while it is firing.
+    firing: Vec<Option<ActiveAlert>>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let firing = vec![None; rules.len()];
+        AlertEngine { rules, firing }
+    }
+
+    /// Evaluates every rule against `tel`. Returns an event for each rule that started or
+    /// stopped holding with it.
+    pub fn evaluate(&mut self, tel: &Telemetry) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (rule, (alert, firing)) in self.rules.iter().zip(&mut self.firing).enumerate() {
+            let value = match alert.field {
+                AlertField::Battery => tel.battery,
+                AlertField::Arming => tel.arming,
+                AlertField::Armed => f32::from(u8::from(tel.armed)),
+            };
+            let holds = match alert.op {
+                AlertOp::Lt => value < alert.threshold,
+                AlertOp::Gt => value > alert.threshold,
+                AlertOp::Eq => value == alert.threshold,
+            };
+            if holds == firing.is_some() {
+                continue;
+            }
+            *firing = holds.then_some(ActiveAlert {
+                rule,
+                field: alert.field,
+                op: alert.op,
+                threshold: alert.threshold,
+                value,
+                since: tel.timestamp,
+            });
+            events.push(AlertEvent {
+                event: if holds {
+                    "alert_fired"
+                } else {
+                    "alert_resolved"
+                },
+                rule,
+                field: alert.field,
+                op: alert.op,
+                threshold: alert.threshold,
+                value,
+                timestamp: tel.timestamp,
+            });
+        }
+        events
+    }
+
+    pub fn active(&self) -> Vec<ActiveAlert> {
+        self.firing.iter().flatten().cloned().collect()
+    }
+}
+
+/// Starts the alert thread if there are any rules, keeping `active` up to date.
+/// Returns an error for an unusable webhook URL so it can be reported at startup.
+pub fn spawn(
+    rules: Vec<AlertRule>,
+    mut telemetry: broadcast::Receiver<Telemetry>,
+    active: SharedActiveAlerts,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    #[cfg(feature = "webhook")]
+    let webhooks = rules
+        .iter()
+        .map(|rule| crate::webhook::WebhookUrl::parse(&rule.webhook))
+        .collect::<Result<Vec<_>, _>>()?;
+    #[cfg(not(feature = "webhook"))]
+    warn!("Built without the `webhook` feature; [[alert]] webhooks will not be sent");
+    let mut engine = AlertEngine::new(rules);
+    thread::spawn(move || loop {
+        let tel = match telemetry.blocking_recv() {
+            Ok(tel) => tel,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let events = engine.evaluate(&tel);
+        if events.is_empty() {
+            continue;
+        }
+        *active.lock().unwrap() = engine.active();
+        for event in events {
+            info!(
+                event = event.event,
+                rule = event.rule,
+                value = event.value,
+                "Alert"
+            );
+            #[cfg(feature = "webhook")]
+            {
+                let body = rocket::serde::json::to_string(&event).unwrap_or_default();
+                if let Err(e) = webhooks[event.rule].post(&body) {
+                    warn!(rule = event.rule, error = %e, "Alert webhook failed");
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// GET /alerts/active returns the `[[alert]]` rules that hold for the latest telemetry.
+#[get("/alerts/active")]
+pub fn active(state: &State<AppState>) -> Json<Vec<ActiveAlert>> {
+    Json(state.active_alerts.lock().unwrap().clone())
+}
+
+/// Response body for GET /alerts/battery_drain.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BatteryDrain {
+    /// The battery fell faster than `[safety] max_drain_rate_v_per_s` and has not yet been
+    /// back to a normal rate for three readings (see `safety::BatteryRateMonitor`).
+    pub draining: bool,
+}
+
+/// GET /alerts/battery_drain reports whether the battery is draining abnormally fast.
+#[get("/alerts/battery_drain")]
+pub fn battery_drain(state: &State<AppState>) -> Json<BatteryDrain> {
+    Json(BatteryDrain {
+        draining: state.battery_draining.load(Ordering::SeqCst),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(field: AlertField, op: AlertOp, threshold: f32) -> AlertRule {
+        AlertRule {
+            field,
+            op,
+            threshold,
+            webhook: "http://alerts.local/gcs".to_string(),
+        }
+    }
+
+    #[test]
+    fn events_only_on_transitions() {
+        let mut engine = AlertEngine::new(vec![
+            rule(AlertField::Battery, AlertOp::Lt, 11.0),
+            rule(AlertField::Armed, AlertOp::Eq, 1.0),
+            rule(AlertField::Arming, AlertOp::Gt, 20.0),
+        ]);
+        let mut tel = Telemetry {
+            battery: 12.0,
+            ..Telemetry::default()
+        };
+        let fired = |events: Vec<AlertEvent>| -> Vec<_> {
+            events.iter().map(|e| (e.event, e.rule)).collect()
+        };
+        assert!(engine.evaluate(&tel).is_empty());
+
+        tel.battery = 10.5;
+        tel.armed = true;
+        tel.timestamp = 40;
+        assert_eq!(
+            fired(engine.evaluate(&tel)),
+            [("alert_fired", 0), ("alert_fired", 1)]
+        );
+        tel.battery = 10.2;
+        tel.timestamp = 50;
+        assert!(engine.evaluate(&tel).is_empty());
+        let active = engine.active();
+        assert_eq!(active.len(), 2);
+        assert_eq!((active[0].value, active[0].since), (10.5, 40));
+
+        tel.battery = 11.5;
+        assert_eq!(fired(engine.evaluate(&tel)), [("alert_resolved", 0)]);
+        assert_eq!(engine.active()[0].rule, 1);
+    }
+}
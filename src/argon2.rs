@@ -0,0 +1,581 @@
+// src/argon2.rs
+
+//! Argon2id (RFC 9106) password hashing, with the BLAKE2b (RFC 7693) it is built on, for
+//! the `[auth] password_hash` of HTTP basic auth. Hashes use the PHC string format,
+//! `$argon2id$v=19$m=<KiB>,t=<passes>,p=<lanes>$<salt>$<hash>`, like other Argon2 tools.
+//!
+//! Lanes are filled one after another rather than in parallel threads; the result is the
+//! same.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::base64;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+const BLAKE2B_BLOCK_LEN: usize = 128;
+
+/// Unkeyed BLAKE2b with a digest of 1-64 bytes.
+struct Blake2b {
+    h: [u64; 8],
+    buf: Vec<u8>,
+    /// Bytes compressed so far.
+    counter: u128,
+    out_len: usize,
+}
+
+impl Blake2b {
+    fn new(out_len: usize) -> Self {
+        let mut h = BLAKE2B_IV;
+        h[0] ^= 0x0101_0000 ^ out_len as u64;
+        Blake2b {
+            h,
+            buf: Vec::with_capacity(BLAKE2B_BLOCK_LEN),
+            counter: 0,
+            out_len,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block is compressed differently, so a full buffer waits for more data.
+            if self.buf.len() == BLAKE2B_BLOCK_LEN {
+                self.counter += BLAKE2B_BLOCK_LEN as u128;
+                let block = std::mem::take(&mut self.buf);
+                self.compress(&block, false);
+                self.buf = block;
+                self.buf.clear();
+            }
+            let take = (BLAKE2B_BLOCK_LEN - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+    }
+
+    fn finalize(mut self) -> Vec<u8> {
+        self.counter += self.buf.len() as u128;
+        let mut block = std::mem::take(&mut self.buf);
+        block.resize(BLAKE2B_BLOCK_LEN, 0);
+        self.compress(&block, true);
+        let bytes: Vec<u8> = self.h.iter().flat_map(|w| w.to_le_bytes()).collect();
+        bytes[..self.out_len].to_vec()
+    }
+
+    fn compress(&mut self, block: &[u8], last: bool) {
+        let mut m = [0u64; 16];
+        for (word, bytes) in m.iter_mut().zip(block.chunks(8)) {
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&BLAKE2B_IV);
+        v[12] ^= self.counter as u64;
+        v[13] ^= (self.counter >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+        for round in 0..12 {
+            let s = &SIGMA[round % 10];
+            let mut g = |a: usize, b: usize, c: usize, d: usize, x: u64, y: u64| {
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+                v[d] = (v[d] ^ v[a]).rotate_right(32);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(24);
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+                v[d] = (v[d] ^ v[a]).rotate_right(16);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(63);
+            };
+            g(0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 {
+            self.h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+fn blake2b(out_len: usize, parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Blake2b::new(out_len);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+/// The variable-length hash H' of RFC 9106 section 3.3.
+fn blake2b_long(out_len: usize, input: &[&[u8]]) -> Vec<u8> {
+    let len = (out_len as u32).to_le_bytes();
+    let parts: Vec<&[u8]> = std::iter::once(&len[..])
+        .chain(input.iter().copied())
+        .collect();
+    if out_len <= 64 {
+        return blake2b(out_len, &parts);
+    }
+    // The first half of each 64-byte hash in a chain, then all of a final, shorter one.
+    let mut out = Vec::with_capacity(out_len);
+    let mut v = blake2b(64, &parts);
+    loop {
+        out.extend_from_slice(&v[..32]);
+        let rest = out_len - out.len();
+        if rest <= 64 {
+            out.extend_from_slice(&blake2b(rest, &[&v]));
+            return out;
+        }
+        v = blake2b(64, &[&v]);
+    }
+}
+
+const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: u32 = 4;
+const VERSION: u32 = 0x13;
+/// The Argon2 type number of Argon2id.
+const TYPE_ID: u32 = 2;
+
+type Block = [u64; BLOCK_WORDS];
+
+/// The BLAKE2b round function with its additions replaced by `a + b + 2 * lo(a) * lo(b)`.
+fn permute(v: &mut [u64; 16]) {
+    fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+        let mul = |x: u64, y: u64| {
+            2u64.wrapping_mul(x & 0xffff_ffff)
+                .wrapping_mul(y & 0xffff_ffff)
+        };
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(mul(v[a], v[b]));
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]).wrapping_add(mul(v[c], v[d]));
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(mul(v[a], v[b]));
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]).wrapping_add(mul(v[c], v[d]));
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    }
+    g(v, 0, 4, 8, 12);
+    g(v, 1, 5, 9, 13);
+    g(v, 2, 6, 10, 14);
+    g(v, 3, 7, 11, 15);
+    g(v, 0, 5, 10, 15);
+    g(v, 1, 6, 11, 12);
+    g(v, 2, 7, 8, 13);
+    g(v, 3, 4, 9, 14);
+}
+
+/// The compression function G: `next = G(prev, reference)`, XORed into the old `next`
+/// after the first pass.
+fn fill_block(prev: &Block, reference: &Block, next: &mut Block, xor_into: bool) {
+    let mut r = [0u64; BLOCK_WORDS];
+    for i in 0..BLOCK_WORDS {
+        r[i] = prev[i] ^ reference[i];
+    }
+    let mut out = r;
+    if xor_into {
+        for i in 0..BLOCK_WORDS {
+            out[i] ^= next[i];
+        }
+    }
+    // Rows of 16 words, then columns of pairs of words.
+    for row in 0..8 {
+        let mut v: [u64; 16] = r[16 * row..16 * row + 16].try_into().unwrap();
+        permute(&mut v);
+        r[16 * row..16 * row + 16].copy_from_slice(&v);
+    }
+    for col in 0..8 {
+        let index = |k: usize| 2 * col + (k / 2) * 16 + k % 2;
+        let mut v = [0u64; 16];
+        for (k, word) in v.iter_mut().enumerate() {
+            *word = r[index(k)];
+        }
+        permute(&mut v);
+        for (k, word) in v.iter().enumerate() {
+            r[index(k)] = *word;
+        }
+    }
+    for i in 0..BLOCK_WORDS {
+        next[i] = out[i] ^ r[i];
+    }
+}
+
+fn block_from_bytes(bytes: &[u8]) -> Block {
+    let mut block = [0u64; BLOCK_WORDS];
+    for (word, chunk) in block.iter_mut().zip(bytes.chunks(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    block
+}
+
+/// Cost parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    /// Memory in KiB (1 KiB blocks), at least `8 * lanes`.
+    pub memory_kib: u32,
+    /// Passes over the memory.
+    pub passes: u32,
+    /// Parallelism.
+    pub lanes: u32,
+}
+
+/// The OWASP-recommended minimum for Argon2id: 19 MiB, 2 passes, 1 lane.
+pub const DEFAULT_PARAMS: Params = Params {
+    memory_kib: 19 * 1024,
+    passes: 2,
+    lanes: 1,
+};
+
+/// Length of generated hashes and salts.
+const HASH_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// Argon2id of `password` and `salt`, with the optional `secret` and associated `data`.
+fn argon2id(
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    data: &[u8],
+    params: Params,
+    out_len: usize,
+) -> Vec<u8> {
+    let Params {
+        memory_kib,
+        passes,
+        lanes,
+    } = params;
+    let le = |n: u32| n.to_le_bytes();
+    let len = |b: &[u8]| (b.len() as u32).to_le_bytes();
+    let h0 = blake2b(
+        64,
+        &[
+            &le(lanes),
+            &le(out_len as u32),
+            &le(memory_kib),
+            &le(passes),
+            &le(VERSION),
+            &le(TYPE_ID),
+            &len(password),
+            password,
+            &len(salt),
+            salt,
+            &len(secret),
+            secret,
+            &len(data),
+            data,
+        ],
+    );
+
+    let segment_len = memory_kib / (SYNC_POINTS * lanes);
+    let lane_len = segment_len * SYNC_POINTS;
+    let mut memory = vec![[0u64; BLOCK_WORDS]; (lane_len * lanes) as usize];
+    for lane in 0..lanes {
+        for i in 0..2 {
+            let bytes = blake2b_long(1024, &[&h0, &le(i), &le(lane)]);
+            memory[(lane * lane_len + i) as usize] = block_from_bytes(&bytes);
+        }
+    }
+
+    let zero = [0u64; BLOCK_WORDS];
+    for pass in 0..passes {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                // The first half of the first pass uses data-independent addressing
+                // (Argon2i), everything after it data-dependent addressing (Argon2d).
+                let independent = pass == 0 && slice < SYNC_POINTS / 2;
+                let mut input = [0u64; BLOCK_WORDS];
+                let mut addresses = [0u64; BLOCK_WORDS];
+                input[..6].copy_from_slice(&[
+                    pass as u64,
+                    lane as u64,
+                    slice as u64,
+                    (lane_len * lanes) as u64,
+                    passes as u64,
+                    TYPE_ID as u64,
+                ]);
+                let next_addresses = |input: &mut Block, addresses: &mut Block| {
+                    input[6] += 1;
+                    fill_block(&zero, input, addresses, false);
+                    let first = *addresses;
+                    fill_block(&zero, &first, addresses, false);
+                };
+
+                let start = if pass == 0 && slice == 0 { 2 } else { 0 };
+                if independent && start != 0 {
+                    next_addresses(&mut input, &mut addresses);
+                }
+                for index in start..segment_len {
+                    let current = lane * lane_len + slice * segment_len + index;
+                    // The first block of a lane follows on from the lane's last one.
+                    let previous = if current.is_multiple_of(lane_len) {
+                        current + lane_len - 1
+                    } else {
+                        current - 1
+                    };
+                    let pseudo_rand = if independent {
+                        if (index as usize).is_multiple_of(BLOCK_WORDS) {
+                            next_addresses(&mut input, &mut addresses);
+                        }
+                        addresses[index as usize % BLOCK_WORDS]
+                    } else {
+                        memory[previous as usize][0]
+                    };
+                    let ref_lane = if pass == 0 && slice == 0 {
+                        lane
+                    } else {
+                        ((pseudo_rand >> 32) % lanes as u64) as u32
+                    };
+                    let same_lane = ref_lane == lane;
+
+                    // The blocks this one may reference: finished segments, plus the
+                    // current one (minus the previous block) in the same lane.
+                    let (area, start_position) = {
+                        let finished = if pass == 0 {
+                            slice * segment_len
+                        } else {
+                            lane_len - segment_len
+                        };
+                        let area = if same_lane {
+                            finished + index - 1
+                        } else if index == 0 {
+                            finished - 1
+                        } else {
+                            finished
+                        };
+                        let start_position = if pass == 0 || slice == SYNC_POINTS - 1 {
+                            0
+                        } else {
+                            (slice + 1) * segment_len
+                        };
+                        (area as u64, start_position as u64)
+                    };
+                    let x = pseudo_rand & 0xffff_ffff;
+                    let y = (x * x) >> 32;
+                    let relative = area - 1 - ((area * y) >> 32);
+                    let ref_index = (start_position + relative) % lane_len as u64;
+                    let reference = memory[(ref_lane * lane_len) as usize + ref_index as usize];
+
+                    let prev = memory[previous as usize];
+                    fill_block(&prev, &reference, &mut memory[current as usize], pass > 0);
+                }
+            }
+        }
+    }
+
+    let mut last = memory[(lane_len - 1) as usize];
+    for lane in 1..lanes {
+        let block = &memory[(lane * lane_len + lane_len - 1) as usize];
+        for (word, other) in last.iter_mut().zip(block) {
+            *word ^= other;
+        }
+    }
+    let bytes: Vec<u8> = last.iter().flat_map(|w| w.to_le_bytes()).collect();
+    blake2b_long(out_len, &[&bytes])
+}
+
+/// A fresh salt. Salts only need to be unique, which the standard library's randomly
+/// keyed hasher (plus the time) gives without a random number generator crate.
+fn generate_salt() -> [u8; SALT_LEN] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let mut salt = [0u8; SALT_LEN];
+    for (i, chunk) in salt.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(nanos);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    salt
+}
+
+/// Encodes without the `=` padding, as the PHC format does.
+fn encode_unpadded(bytes: &[u8]) -> String {
+    base64::encode(bytes).trim_end_matches('=').to_string()
+}
+
+/// A parsed (or freshly computed) Argon2id hash in PHC string format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordHash {
+    params: Params,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    /// Hashes `password` with a new salt.
+    pub fn generate(password: &str, params: Params) -> PasswordHash {
+        let salt = generate_salt().to_vec();
+        let hash = argon2id(password.as_bytes(), &salt, &[], &[], params, HASH_LEN);
+        PasswordHash { params, salt, hash }
+    }
+
+    /// Whether `password` hashes to this hash. The comparison does not stop early.
+    pub fn verify(&self, password: &str) -> bool {
+        let hash = argon2id(
+            password.as_bytes(),
+            &self.salt,
+            &[],
+            &[],
+            self.params,
+            self.hash.len(),
+        );
+        hash.iter()
+            .zip(&self.hash)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+impl fmt::Display for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "$argon2id$v={}$m={},t={},p={}${}${}",
+            VERSION,
+            self.params.memory_kib,
+            self.params.passes,
+            self.params.lanes,
+            encode_unpadded(&self.salt),
+            encode_unpadded(&self.hash)
+        )
+    }
+}
+
+impl FromStr for PasswordHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split('$').collect();
+        let ["", "argon2id", version, params, salt, hash] = fields[..] else {
+            return Err("expected $argon2id$v=19$m=...,t=...,p=...$<salt>$<hash>".to_string());
+        };
+        if version != format!("v={}", VERSION) {
+            return Err(format!("unsupported Argon2 version '{}'", version));
+        }
+        let mut memory_kib = None;
+        let mut passes = None;
+        let mut lanes = None;
+        for param in params.split(',') {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = value
+                .parse::<u32>()
+                .map_err(|_| format!("bad Argon2 parameter '{}'", param))?;
+            match key {
+                "m" => memory_kib = Some(value),
+                "t" => passes = Some(value),
+                "p" => lanes = Some(value),
+                _ => return Err(format!("unknown Argon2 parameter '{}'", param)),
+            }
+        }
+        let (Some(memory_kib), Some(passes), Some(lanes)) = (memory_kib, passes, lanes) else {
+            return Err("the m, t and p parameters are required".to_string());
+        };
+        if lanes == 0 || passes == 0 || memory_kib < 8 * lanes {
+            return Err("need p >= 1, t >= 1 and m >= 8 * p".to_string());
+        }
+        let decode = |field: &str| {
+            base64::decode(field)
+                .filter(|bytes| !bytes.is_empty())
+                .ok_or_else(|| format!("bad base64 '{}'", field))
+        };
+        Ok(PasswordHash {
+            params: Params {
+                memory_kib,
+                passes,
+                lanes,
+            },
+            salt: decode(salt)?,
+            hash: decode(hash)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn blake2b_matches_rfc_7693() {
+        assert_eq!(
+            hex(&blake2b(64, &[b"abc"])),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        // Exactly one block, split across updates.
+        let data = [0x61u8; 128];
+        assert_eq!(
+            blake2b(32, &[&data[..100], &data[100..]]),
+            blake2b(32, &[&data])
+        );
+    }
+
+    #[test]
+    fn argon2id_matches_rfc_9106() {
+        let params = Params {
+            memory_kib: 32,
+            passes: 3,
+            lanes: 4,
+        };
+        let tag = argon2id(&[1; 32], &[2; 16], &[3; 8], &[4; 12], params, 32);
+        assert_eq!(
+            hex(&tag),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"
+        );
+    }
+
+    #[test]
+    fn encoded_hashes_round_trip_and_verify() {
+        let params = Params {
+            memory_kib: 64,
+            passes: 1,
+            lanes: 1,
+        };
+        let hash = PasswordHash::generate("hunter2", params);
+        let encoded = hash.to_string();
+        assert!(encoded.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+        let parsed: PasswordHash = encoded.parse().unwrap();
+        assert_eq!(parsed, hash);
+        assert!(parsed.verify("hunter2"));
+        assert!(!parsed.verify("hunter3"));
+        assert_ne!(PasswordHash::generate("hunter2", params).salt, hash.salt);
+
+        assert!("$argon2i$v=19$m=64,t=1,p=1$c2FsdA$aGFzaA"
+            .parse::<PasswordHash>()
+            .is_err());
+        assert!("$argon2id$v=19$m=4,t=1,p=1$c2FsdA$aGFzaA"
+            .parse::<PasswordHash>()
+            .is_err());
+    }
+}
@@ -0,0 +1,166 @@
+// src/arm_audit.rs
+
+//! The arm audit log: every arm, disarm and emergency stop, with the wall-clock time and the
+//! IP address the request came from, for audits of who armed the stand and when.
+//!
+//! Unlike the flight log it is append-only: GET /audit/arm reads it and nothing clears it.
+//! With `--audit-log <path>` each event is also appended to the file as one JSON line, and
+//! the events already in the file are loaded at startup, so the list spans restarts.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::flight_log::{deserialize_epoch_ms, serialize_epoch_ms};
+
+/// A shared arm audit log (appended by the handlers and the watchdog).
+pub type SharedArmAudit = Arc<Mutex<ArmAuditLog>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ArmAction {
+    Arm,
+    Disarm,
+    /// POST /emergency_stop, which disarms too.
+    EmergencyStop,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ArmEvent {
+    /// When the command was queued, serialized as milliseconds since the Unix epoch.
+    #[serde(
+        serialize_with = "serialize_epoch_ms",
+        deserialize_with = "deserialize_epoch_ms"
+    )]
+    pub wall_clock: SystemTime,
+    pub action: ArmAction,
+    /// The client that sent the request; `None` for the watchdog's disarm.
+    pub source_ip: Option<IpAddr>,
+    /// The board the command went to.
+    pub board: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct ArmAuditLog {
+    events: Vec<ArmEvent>,
+    /// Every event is appended here too, if set.
+    file: Option<File>,
+}
+
+impl ArmAuditLog {
+    /// Loads the events already in the file at `path` and opens it (creating it if needed)
+    /// for appending. Lines that are not events are skipped with a warning.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)?;
+        let mut events = Vec::new();
+        for (index, line) in BufReader::new(&file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!(path, line = index + 1, error = %e, "Skipping bad audit line"),
+            }
+        }
+        Ok(ArmAuditLog {
+            events,
+            file: Some(file),
+        })
+    }
+
+    /// Appends an event stamped with the current time. A failed file write is logged; the
+    /// event is still kept in memory.
+    pub fn record(&mut self, action: ArmAction, source_ip: Option<IpAddr>, board: u8) {
+        let event = ArmEvent {
+            wall_clock: SystemTime::now(),
+            action,
+            source_ip,
+            board,
+        };
+        if let Some(file) = &mut self.file {
+            let written = serde_json::to_string(&event)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line))
+                .and_then(|()| file.sync_data());
+            if let Err(e) = written {
+                error!(error = %e, "Failed to write the arm audit log");
+            }
+        }
+        self.events.push(event);
+    }
+
+    pub fn events(&self) -> &[ArmEvent] {
+        &self.events
+    }
+}
+
+/// The IP address a request came from (`Request::remote()`), if known. Never fails.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceIp(pub Option<IpAddr>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SourceIp {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SourceIp(req.remote().map(|addr| addr.ip())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopening_appends_to_the_file() {
+        let path = std::env::temp_dir().join(format!("arm_audit_{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+        {
+            let mut log = ArmAuditLog::open(path).unwrap();
+            log.record(ArmAction::Arm, Some(ip), 0);
+            log.record(ArmAction::Disarm, None, 2);
+        }
+        let mut log = ArmAuditLog::open(path).unwrap();
+        log.record(ArmAction::EmergencyStop, Some(ip), 0);
+        let reopened = ArmAuditLog::open(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let summary: Vec<_> = reopened
+            .events()
+            .iter()
+            .map(|e| (e.action, e.source_ip, e.board))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (ArmAction::Arm, Some(ip), 0),
+                (ArmAction::Disarm, None, 2),
+                (ArmAction::EmergencyStop, Some(ip), 0),
+            ]
+        );
+        // Times survive the round trip at millisecond precision.
+        let ms = |t: SystemTime| {
+            t.duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        };
+        assert_eq!(
+            ms(reopened.events()[2].wall_clock),
+            ms(log.events()[2].wall_clock)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// src/auth.rs
+
+//! Shared-secret authentication for the command (POST and DELETE) endpoints.
+//!
+//! Every POST must carry `X-GCS-Signature: <hex HMAC-SHA256(secret, method + path + body)>`,
+//! e.g. the HMAC of `POST/solenoid/5/1` for a bodyless request. DELETE requests are signed
+//! the same way, e.g. over `DELETE/flight_log`. The path includes the query string, if any.
+//! The secret comes from `GCS_SECRET` or `[auth] secret`; with neither set, requests are not
+//! checked. GET endpoints stay open for monitoring tools.
+//!
+//! Routes without a body take the `Authenticated` request guard. Routes with a JSON body take
+//! `SignedJson<T>` in place of `Json<T>`, since a request guard cannot see the body.
+//! Failures are a `401 UNAUTHORIZED`.
+
+use std::ops::Deref;
+
+use rocket::data::{self, Data, FromData, ToByteUnit};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::serde::{json, Deserialize};
+
+use crate::sha256;
+use crate::AppState;
+
+/// The header carrying the request signature.
+pub const SIGNATURE_HEADER: &str = "X-GCS-Signature";
+
+/// Environment variable that overrides `[auth] secret`.
+pub const SECRET_ENV_VAR: &str = "GCS_SECRET";
+
+/// Largest signed JSON body accepted.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// The secret from `GCS_SECRET` if set, otherwise the configured one.
+pub fn resolve_secret(configured: Option<String>) -> Option<Vec<u8>> {
+    std::env::var(SECRET_ENV_VAR)
+        .ok()
+        .or(configured)
+        .filter(|secret| !secret.is_empty())
+        .map(String::into_bytes)
+}
+
+/// The expected signature of a request, as lowercase hex.
+pub fn sign(secret: &[u8], method: &str, path: &str, body: &[u8]) -> String {
+    sign_message(secret, &[method.as_bytes(), path.as_bytes(), body].concat())
+}
+
+/// HMAC-SHA256 of `message` under `secret`, as lowercase hex. Also signs the cluster
+/// heartbeats.
+pub fn sign_message(secret: &[u8], message: &[u8]) -> String {
+    sha256::hmac(secret, message)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compares without stopping at the first mismatch, so the timing doesn't reveal how much
+/// of a guessed signature was right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Checks the request's signature over `body`. Always passes when no secret is configured.
+fn verify(req: &Request<'_>, body: &[u8]) -> bool {
+    let Some(secret) = req
+        .rocket()
+        .state::<AppState>()
+        .and_then(|s| s.auth_secret.as_ref())
+    else {
+        return true;
+    };
+    let Some(signature) = req.headers().get_one(SIGNATURE_HEADER) else {
+        return false;
+    };
+    let expected = sign(secret, req.method().as_str(), &req.uri().to_string(), body);
+    constant_time_eq(
+        signature.trim().to_ascii_lowercase().as_bytes(),
+        expected.as_bytes(),
+    )
+}
+
+/// Request guard for a POST or DELETE without a body: the signature covers method and path.
+pub struct Authenticated;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if verify(req, b"") {
+            Outcome::Success(Authenticated)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+/// Data guard for a signed JSON body: checks the signature over the raw body, then parses
+/// it like `Json<T>`.
+pub struct SignedJson<T>(pub T);
+
+impl<T> SignedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for SignedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: for<'de> Deserialize<'de>> FromData<'r> for SignedJson<T> {
+    type Error = String;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let body = match data.open(MAX_BODY_BYTES.bytes()).into_bytes().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                return data::Outcome::Error((Status::PayloadTooLarge, "body too large".into()))
+            }
+            Err(e) => return data::Outcome::Error((Status::BadRequest, e.to_string())),
+        };
+        if !verify(req, &body) {
+            return data::Outcome::Error((Status::Unauthorized, "bad signature".into()));
+        }
+        match json::from_slice(&body) {
+            Ok(value) => data::Outcome::Success(SignedJson(value)),
+            Err(e) => data::Outcome::Error((Status::UnprocessableEntity, e.to_string())),
+        }
+    }
+}
+
+/// The body of a 401, in the style of `ApiError`.
+#[catch(401)]
+pub fn unauthorized() -> &'static str {
+    "UNAUTHORIZED"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_method_path_and_body() {
+        let sig = sign(b"secret", "POST", "/arm", b"");
+        assert_eq!(sig.len(), 64);
+        assert_ne!(sig, sign(b"secret", "POST", "/disarm", b""));
+        assert_ne!(sig, sign(b"other", "POST", "/arm", b""));
+        assert_ne!(
+            sign(b"secret", "POST", "/solenoid/mask", br#"{"mask":1}"#),
+            sign(b"secret", "POST", "/solenoid/mask", br#"{"mask":3}"#)
+        );
+        assert!(constant_time_eq(sig.as_bytes(), sig.as_bytes()));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+    }
+}
@@ -0,0 +1,155 @@
+// src/basic_auth.rs
+
+//! HTTP basic auth for the whole server (UI and API), enabled by `[auth] username` and
+//! `password_hash` in the config. The hash is an Argon2id PHC string, printed by
+//! `telemetry_server generate-password`; the plaintext password is never stored.
+//!
+//! The fairing checks the `Authorization` header of every request. Without valid
+//! credentials the request is rerouted to `challenge`, which answers `401` with
+//! `WWW-Authenticate: Basic realm="GCS"`, so no other handler runs. CORS preflights are let
+//! through, since browsers send them without credentials.
+//!
+//! Argon2 is slow on purpose, so a header that verified once is remembered (as a SHA-256
+//! digest) and not hashed again.
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::task;
+use rocket::{Data, Request};
+
+use crate::argon2::{PasswordHash, DEFAULT_PARAMS};
+use crate::{base64, sha256};
+
+/// Where requests without valid credentials are rerouted to.
+const CHALLENGE_PATH: &str = "/auth/challenge";
+
+/// The configured username and password hash.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password_hash: PasswordHash,
+}
+
+impl Credentials {
+    /// Checks an `Authorization: Basic <base64(username:password)>` header value.
+    fn check(&self, header: &str) -> bool {
+        let Some((scheme, encoded)) = header.trim().split_once(' ') else {
+            return false;
+        };
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return false;
+        }
+        let Some(decoded) = base64::decode(encoded.trim()).and_then(|b| String::from_utf8(b).ok())
+        else {
+            return false;
+        };
+        let Some((username, password)) = decoded.split_once(':') else {
+            return false;
+        };
+        // Hash even for a wrong username, so the timing doesn't tell usernames apart.
+        let password_ok = self.password_hash.verify(password);
+        password_ok && username == self.username
+    }
+}
+
+/// Reroutes every request without valid credentials to `challenge`.
+pub struct BasicAuthFairing {
+    credentials: Arc<Credentials>,
+    /// SHA-256 digests of `Authorization` headers that have already verified.
+    verified: Arc<Mutex<HashSet<[u8; 32]>>>,
+}
+
+impl BasicAuthFairing {
+    pub fn new(credentials: Credentials) -> Self {
+        BasicAuthFairing {
+            credentials: Arc::new(credentials),
+            verified: Arc::default(),
+        }
+    }
+
+    async fn authorized(&self, header: Option<&str>) -> bool {
+        let Some(header) = header else {
+            return false;
+        };
+        let digest = sha256::digest(header.as_bytes());
+        if self.verified.lock().unwrap().contains(&digest) {
+            return true;
+        }
+        let credentials = self.credentials.clone();
+        let header = header.to_string();
+        // Keep the (deliberately slow) hash off the async executor.
+        let ok = task::spawn_blocking(move || credentials.check(&header))
+            .await
+            .unwrap_or(false);
+        if ok {
+            self.verified.lock().unwrap().insert(digest);
+        }
+        ok
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for BasicAuthFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Basic auth",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        if req.method() == Method::Options {
+            return;
+        }
+        if self
+            .authorized(req.headers().get_one("Authorization"))
+            .await
+        {
+            return;
+        }
+        req.set_method(Method::Get);
+        req.set_uri(Origin::parse(CHALLENGE_PATH).expect("valid path"));
+    }
+}
+
+/// A `401` asking the browser for credentials.
+pub struct Challenge;
+
+impl<'r> Responder<'r, 'static> for Challenge {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from((Status::Unauthorized, "UNAUTHORIZED").respond_to(req)?)
+            .header(Header::new("WWW-Authenticate", r#"Basic realm="GCS""#))
+            .ok()
+    }
+}
+
+/// GET /auth/challenge: where the fairing sends unauthenticated requests. Always a `401`.
+#[get("/auth/challenge")]
+pub fn challenge() -> Challenge {
+    Challenge
+}
+
+/// `telemetry_server generate-password`: reads a password from stdin and prints its
+/// Argon2id hash for `[auth] password_hash`.
+pub fn generate_password() -> ! {
+    eprint!("Password: ");
+    let _ = io::stderr().flush();
+    let mut password = String::new();
+    if io::stdin().lock().read_line(&mut password).is_err() {
+        eprintln!("Could not read the password");
+        std::process::exit(1);
+    }
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        eprintln!("The password must not be empty");
+        std::process::exit(1);
+    }
+    println!("{}", PasswordHash::generate(password, DEFAULT_PARAMS));
+    std::process::exit(0);
+}
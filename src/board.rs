@@ -0,0 +1,341 @@
+// src/board.rs
+
+//! Multi-board test stands: several Arduinos, each on its own serial port and identified by
+//! the `id` of its `[[board]]` config section.
+//!
+//! The first board is the primary one. It is driven by the top-level `AppState` fields and
+//! endpoints (`/telemetry`, `/solenoid/...`, sequences, the flight log and so on), so a
+//! single-board stand works exactly as before. Every other board runs its own serial loop
+//! and is reached through `/board/<id>/...`, which also accepts the primary board's ID.
+//! POST /emergency_stop stops all boards.
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::tokio::sync::broadcast;
+use rocket::State;
+
+use crate::ack::{PendingCommands, SharedAckLog};
+use crate::arm_audit::{ArmAction, SourceIp};
+use crate::auth::Authenticated;
+use crate::command::CommandBuilder;
+use crate::command_queue::{self, CommandSender};
+use crate::error::ApiError;
+use crate::filters::SharedCalibration;
+use crate::flight_log::SharedFlightLog;
+use crate::health::SharedParseRate;
+use crate::interlock::SolenoidGuard;
+use crate::history::TelemetryHistory;
+use crate::latency::{CommandAck, RequestStart, SharedLatency};
+use crate::metrics::Metrics;
+use crate::safety::RateLimiter;
+use crate::shutdown::ShutdownSignal;
+use crate::{
+    arm_primary, disarm_primary, open_link, spawn_serial_loop, AppState, ConnectionStatus,
+    PortSwitch, QueuedCommand, SerialEndpoints, SerialSettings, SharedConnectionStatus,
+    SharedTelemetry, SolenoidState, SystemStatus, Telemetry, TelemetrySinks,
+};
+
+/// Samples kept in a secondary board's (unexposed) history buffer.
+const SECONDARY_HISTORY_SIZE: usize = 16;
+
+/// The state of a secondary board. The primary board's equivalents live in `AppState`.
+pub struct BoardState {
+    id: u8,
+    telemetry: SharedTelemetry,
+    command_tx: CommandSender,
+    emergency_tx: mpsc::SyncSender<String>,
+    connection_status: SharedConnectionStatus,
+    rate_limiter: Mutex<RateLimiter>,
+}
+
+impl BoardState {
+    /// Starts the serial loop for a secondary board and returns its state.
+    /// Command latencies and ACK round trips are recorded with the primary board's.
+    pub fn spawn(
+        id: u8,
+        settings: SerialSettings,
+        rate_limiter: RateLimiter,
+        acks: mpsc::Sender<CommandAck>,
+        ack_round_trips: SharedLatency,
+        metrics: Arc<Metrics>,
+        shutdown: &ShutdownSignal,
+    ) -> BoardState {
+        let (command_tx, commands) = command_queue::channel();
+        let (emergency_tx, emergency) = mpsc::sync_channel::<String>(1);
+        let (broadcast, _) = broadcast::channel::<Telemetry>(1);
+        let telemetry = SharedTelemetry::default();
+        let connection_status = Arc::new(Mutex::new(ConnectionStatus::Reconnecting(0)));
+        let sinks = TelemetrySinks {
+            telemetry: telemetry.clone(),
+            history: Arc::new(Mutex::new(TelemetryHistory::new(SECONDARY_HISTORY_SIZE))),
+            broadcast,
+            csv_log: None,
+            db: None,
+            udp_relay: None,
+            parse_rate: SharedParseRate::default(),
+        };
+        let endpoints = SerialEndpoints {
+            commands,
+            emergency,
+            acks,
+            pending_commands: PendingCommands::default(),
+            ack_round_trips,
+            ack_log: SharedAckLog::default(),
+            raw_bytes: None,
+            port_switch: Arc::new(PortSwitch::default()),
+            reconciler: Default::default(),
+            solenoid_guard: None,
+            open_timers: Default::default(),
+            duty_cycle: Default::default(),
+            flight_log: SharedFlightLog::default(),
+            sessions: Default::default(),
+            verifier: Default::default(),
+            arm_state: Default::default(),
+            firmware_reset: Default::default(),
+            battery_calibration: SharedCalibration::default(),
+            shutdown: shutdown.clone(),
+        };
+        let status = connection_status.clone();
+        shutdown.spawn(move || {
+            spawn_serial_loop(sinks, endpoints, settings, status, metrics, open_link)
+        });
+        BoardState {
+            id,
+            telemetry,
+            command_tx,
+            emergency_tx,
+            connection_status,
+            rate_limiter: Mutex::new(rate_limiter),
+        }
+    }
+
+    fn as_board(&self, require_armed: bool) -> Board<'_> {
+        Board {
+            id: self.id,
+            telemetry: &self.telemetry,
+            command_tx: &self.command_tx,
+            emergency_tx: &self.emergency_tx,
+            connection_status: &self.connection_status,
+            rate_limiter: &self.rate_limiter,
+            require_armed,
+            guard: None,
+        }
+    }
+}
+
+/// One board's state, borrowed either from `AppState` (primary) or a `BoardState`.
+pub struct Board<'a> {
+    pub id: u8,
+    pub telemetry: &'a SharedTelemetry,
+    command_tx: &'a CommandSender,
+    emergency_tx: &'a mpsc::SyncSender<String>,
+    pub connection_status: &'a SharedConnectionStatus,
+    rate_limiter: &'a Mutex<RateLimiter>,
+    require_armed: bool,
+    /// The interlocks and duty cycles; only the primary board has them.
+    guard: Option<SolenoidGuard>,
+}
+
+impl Board<'_> {
+    /// Queues a command for this board's serial loop.
+    pub fn send_command(&self, cmd: QueuedCommand) -> Result<(), ApiError> {
+        self.command_tx.send(cmd).map_err(|_| ApiError::SerialSendFailed)
+    }
+
+    /// Queues a command actuating `channels`, enforcing the `require_armed` interlock, the
+    /// `[[interlock]]`s and duty cycles (primary board only) and the per-channel rate limit.
+    /// The telemetry lock is held until the command is queued, so a disarm or valve change
+    /// reported in between cannot slip past.
+    pub fn send_solenoid_command(
+        &self,
+        cmd: QueuedCommand,
+        channels: &[u8],
+    ) -> Result<(), ApiError> {
+        let telemetry = self.telemetry.read().unwrap();
+        if self.require_armed && !telemetry.armed {
+            return Err(ApiError::SystemNotArmed);
+        }
+        if let Some(guard) = &self.guard {
+            guard.check_against(&cmd.text, &telemetry.solenoids)?;
+        }
+        let mut limiter = self.rate_limiter.lock().unwrap();
+        if limiter.try_actuate(channels, cmd.received_at).is_err() {
+            return Err(ApiError::RateLimited);
+        }
+        self.send_command(cmd)
+    }
+
+    /// Queues `sequence` on the priority channel. A stop that is already queued carries the
+    /// same sequence, so a full channel counts as sent.
+    pub fn send_emergency(&self, sequence: String) -> Result<(), ApiError> {
+        match self.emergency_tx.try_send(sequence) {
+            Ok(()) | Err(mpsc::TrySendError::Full(_)) => Ok(()),
+            Err(mpsc::TrySendError::Disconnected(_)) => Err(ApiError::SerialSendFailed),
+        }
+    }
+}
+
+impl AppState {
+    /// The primary board (the top-level fields).
+    pub fn primary_board(&self) -> Board<'_> {
+        Board {
+            id: self.board_id,
+            telemetry: &self.telemetry,
+            command_tx: &self.command_tx,
+            emergency_tx: &self.emergency_tx,
+            connection_status: &self.connection_status,
+            rate_limiter: &self.rate_limiter,
+            require_armed: self.require_armed,
+            guard: Some(self.solenoid_guard()),
+        }
+    }
+
+    /// Every board, primary first.
+    pub fn boards(&self) -> impl Iterator<Item = Board<'_>> {
+        std::iter::once(self.primary_board())
+            .chain(self.secondary_boards.iter().map(|b| b.as_board(self.require_armed)))
+    }
+
+    /// The board with the given ID.
+    pub fn board(&self, id: u8) -> Result<Board<'_>, ApiError> {
+        self.boards().find(|b| b.id == id).ok_or(ApiError::UnknownBoard(id))
+    }
+}
+
+/// An entry of GET /boards.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BoardInfo {
+    id: u8,
+    primary: bool,
+    connection: ConnectionStatus,
+}
+
+/// GET /boards lists the configured boards and their serial link state.
+#[get("/boards")]
+pub fn list_boards(state: &State<AppState>) -> Json<Vec<BoardInfo>> {
+    let boards = state
+        .boards()
+        .map(|b| BoardInfo {
+            id: b.id,
+            primary: b.id == state.board_id,
+            connection: *b.connection_status.lock().unwrap(),
+        })
+        .collect();
+    Json(boards)
+}
+
+/// GET /boards/telemetry returns the current telemetry of every board, keyed by board ID.
+#[get("/boards/telemetry")]
+pub fn get_all_telemetry(state: &State<AppState>) -> Json<HashMap<u8, Telemetry>> {
+    let telemetry = state
+        .boards()
+        .map(|b| (b.id, b.telemetry.read().unwrap().clone()))
+        .collect();
+    Json(telemetry)
+}
+
+/// GET /board/<id>/telemetry returns one board's current telemetry.
+#[get("/board/<id>/telemetry")]
+pub fn get_telemetry(id: u8, state: &State<AppState>) -> Result<Json<Telemetry>, ApiError> {
+    let board = state.board(id)?;
+    let tel = board.telemetry.read().unwrap().clone();
+    Ok(Json(tel))
+}
+
+/// GET /board/<id>/status reports the state of one board's serial link. The watchdog only
+/// watches the primary board, and only the primary board can be reset.
+#[get("/board/<id>/status")]
+pub fn get_status(id: u8, state: &State<AppState>) -> Result<Json<SystemStatus>, ApiError> {
+    let board = state.board(id)?;
+    Ok(Json(SystemStatus {
+        connection: *board.connection_status.lock().unwrap(),
+        watchdog_tripped: id == state.board_id && state.watchdog_tripped.load(Ordering::SeqCst),
+        dry_run: state.dry_run,
+        last_firmware_reset: (id == state.board_id).then(|| state.firmware_reset.last()).flatten(),
+    }))
+}
+
+/// GET /board/<id>/solenoid/<channel> returns the last reported state of one solenoid.
+/// Out-of-range channels are a 404.
+#[get("/board/<id>/solenoid/<channel>")]
+pub fn get_solenoid(
+    id: u8,
+    channel: u8,
+    state: &State<AppState>,
+) -> Result<Option<Json<SolenoidState>>, ApiError> {
+    let board = state.board(id)?;
+    let tel = board.telemetry.read().unwrap();
+    let solenoid = (channel as usize)
+        .checked_sub(1)
+        .and_then(|index| tel.solenoids.get(index))
+        .map(|&on| {
+            Json(SolenoidState {
+                channel,
+                state: on,
+                timestamp: tel.timestamp,
+            })
+        });
+    Ok(solenoid)
+}
+
+/// POST /board/<id>/arm arms one board. Refused with `require_two_step`, like POST /arm;
+/// the primary board's ID is POST /arm, arm lifecycle included.
+#[post("/board/<id>/arm")]
+pub fn arm(
+    id: u8,
+    _auth: Authenticated,
+    start: RequestStart,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let board = state.board(id)?;
+    if id == state.board_id {
+        return arm_primary(start, source, state);
+    }
+    if state.require_two_step {
+        return Err(ApiError::TwoStepArmRequired);
+    }
+    board.send_command(QueuedCommand::new(CommandBuilder::new().arm().build_joined(), start))?;
+    state.audit_arm(ArmAction::Arm, source, id);
+    Ok("OK")
+}
+
+/// POST /board/<id>/disarm disarms one board; the primary board's ID is POST /disarm.
+#[post("/board/<id>/disarm")]
+pub fn disarm(
+    id: u8,
+    _auth: Authenticated,
+    start: RequestStart,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    if id == state.board_id {
+        return disarm_primary(start, source, state);
+    }
+    let disarm = CommandBuilder::new().disarm().build_joined();
+    state.board(id)?.send_command(QueuedCommand::new(disarm, start))?;
+    state.audit_arm(ArmAction::Disarm, source, id);
+    Ok("OK")
+}
+
+/// POST /board/<id>/solenoid/<channel>/<state> sets a solenoid on one board, with the same
+/// validation, arming interlock and rate limit as POST /solenoid/<channel>/<state>. The
+/// `[[interlock]]`s and the duty cycle limit apply to the primary board only.
+#[post("/board/<id>/solenoid/<channel>/<sstate>")]
+pub fn solenoid(
+    id: u8,
+    channel: u8,
+    sstate: u8,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let board = state.board(id)?;
+    let cmd = CommandBuilder::new().solenoid_state(channel, sstate)?.build_joined();
+    board.send_solenoid_command(QueuedCommand::new(cmd, start), &[channel])?;
+    Ok("OK")
+}
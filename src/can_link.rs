@@ -0,0 +1,411 @@
+// src/can_link.rs
+
+//! Telemetry over a CAN bus instead of a serial port (`can` feature, `--can <interface>`),
+//! for stands whose nodes report over CAN. Linux only: the link is a raw SocketCAN socket.
+//!
+//! Like `udp_link`, the socket stands in for the port, so the serial loop runs unchanged.
+//! Frames mapped by `[[can.signal]]` update a telemetry sample, which is handed to the loop
+//! as a line of the built-in text format after every such frame. Frames on `text_id` (ACKs)
+//! are passed through as text, and commands go out as text on `command_id`, 8 bytes per
+//! frame.
+//!
+//! The bindings below are a minimal hand-written FFI layer over the socket calls SocketCAN
+//! needs.
+
+use std::ffi::{c_int, c_long, c_void, CString};
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::config::{CanConfig, CanField, CanSignal};
+use crate::metrics::Metrics;
+use crate::{
+    spawn_serial_loop, SerialEndpoints, SerialLink, SerialSettings, SessionEnd,
+    SharedConnectionStatus, TelemetryFormat, TelemetrySinks,
+};
+
+/// Size of a classic `struct can_frame`: the ID, the data length, 3 padding bytes and up to
+/// 8 data bytes.
+const FRAME_LEN: usize = 16;
+const MAX_DATA_LEN: usize = 8;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CAN_SFF_MASK: u32 = 0x7FF;
+
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::ffi::{c_char, c_int, c_long, c_uint, c_void};
+
+    pub const AF_CAN: c_int = 29;
+    pub const SOCK_RAW: c_int = 3;
+    pub const CAN_RAW: c_int = 1;
+    pub const SOL_SOCKET: c_int = 1;
+    pub const SO_RCVTIMEO: c_int = 20;
+
+    #[repr(C)]
+    pub struct sockaddr_can {
+        pub can_family: u16,
+        pub can_ifindex: c_int,
+        /// The protocol-specific address union; unused by raw sockets.
+        pub can_addr: [u64; 2],
+    }
+
+    #[repr(C)]
+    pub struct timeval {
+        pub tv_sec: c_long,
+        pub tv_usec: c_long,
+    }
+
+    extern "C" {
+        pub fn if_nametoindex(name: *const c_char) -> c_uint;
+        pub fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+        pub fn setsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *const c_void,
+            len: u32,
+        ) -> c_int;
+        pub fn bind(fd: c_int, addr: *const sockaddr_can, len: u32) -> c_int;
+    }
+}
+
+/// Runs the serial loop (see `spawn_serial_loop`) on a CAN socket bound to `interface`. An
+/// interface that cannot be opened (e.g. it is down) is retried like a missing port.
+pub fn spawn_can_loop(
+    sinks: TelemetrySinks,
+    endpoints: SerialEndpoints,
+    mut settings: SerialSettings,
+    interface: String,
+    can: CanConfig,
+    status: SharedConnectionStatus,
+    metrics: Arc<Metrics>,
+) {
+    settings.port_name = format!("can://{}", interface);
+    // The samples are handed over as lines of the built-in text format.
+    settings.format = TelemetryFormat::Ascii;
+    settings.line_format = None;
+    let open = move |settings: &SerialSettings| {
+        open(&interface, &can, settings.read_timeout).map_err(|e| {
+            warn!(error = %e, interface, "Failed to open CAN interface");
+            SessionEnd::Disconnected
+        })
+    };
+    spawn_serial_loop(sinks, endpoints, settings, status, metrics, open);
+}
+
+fn open(interface: &str, can: &CanConfig, read_timeout: Duration) -> io::Result<SerialLink> {
+    let socket = open_socket(interface, read_timeout)?;
+    Ok(link(socket.try_clone()?, socket, can))
+}
+
+/// Opens a raw CAN socket on `interface`, with `read_timeout` on reads.
+fn open_socket(interface: &str, read_timeout: Duration) -> io::Result<File> {
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "NUL in interface name"))?;
+    // SAFETY: valid NUL-terminated name.
+    let ifindex = unsafe { ffi::if_nametoindex(name.as_ptr()) };
+    if ifindex == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: plain socket call; the descriptor is owned below.
+    let fd = unsafe { ffi::socket(ffi::AF_CAN, ffi::SOCK_RAW, ffi::CAN_RAW) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` is a new descriptor nothing else owns; it is closed on every error below.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let timeout = ffi::timeval {
+        tv_sec: read_timeout.as_secs() as c_long,
+        tv_usec: read_timeout.subsec_micros() as c_long,
+    };
+    // SAFETY: `timeout` outlives the call and its size is passed along.
+    let rc = unsafe {
+        ffi::setsockopt(
+            socket.as_raw_fd(),
+            ffi::SOL_SOCKET,
+            ffi::SO_RCVTIMEO,
+            &timeout as *const ffi::timeval as *const c_void,
+            size_of::<ffi::timeval>() as u32,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let addr = ffi::sockaddr_can {
+        can_family: ffi::AF_CAN as u16,
+        can_ifindex: ifindex as c_int,
+        can_addr: [0; 2],
+    };
+    // SAFETY: `addr` outlives the call and its size is passed along.
+    let rc = unsafe {
+        ffi::bind(
+            socket.as_raw_fd(),
+            &addr,
+            size_of::<ffi::sockaddr_can>() as u32,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(File::from(socket))
+}
+
+/// The link over a stream of raw frames: `frames_in` is read, `frames_out` written.
+fn link(
+    frames_in: impl Read + Send + 'static,
+    frames_out: impl Write + Send + 'static,
+    can: &CanConfig,
+) -> SerialLink {
+    let reader = CanReader {
+        frames: frames_in,
+        decoder: FrameDecoder::new(can),
+        buf: Vec::new(),
+        pos: 0,
+    };
+    let writer = CanWriter {
+        frames: frames_out,
+        command_id: can.command_id,
+    };
+    SerialLink {
+        writer: Box::new(writer),
+        reader: Box::new(reader),
+        control: None,
+    }
+}
+
+/// The telemetry fields as last reported by the frames.
+#[derive(Debug, Default)]
+struct Sample {
+    timestamp: Option<u32>,
+    armed: bool,
+    battery: f32,
+    arming: f32,
+    solenoids: u16,
+}
+
+/// Turns frames into the text the serial loop reads.
+struct FrameDecoder {
+    signals: Vec<CanSignal>,
+    text_id: Option<u32>,
+    sample: Sample,
+    /// Timestamps without a `timestamp` signal count from here.
+    opened: Instant,
+}
+
+impl FrameDecoder {
+    fn new(can: &CanConfig) -> Self {
+        FrameDecoder {
+            signals: can.signals.clone(),
+            text_id: can.text_id,
+            sample: Sample::default(),
+            opened: Instant::now(),
+        }
+    }
+
+    /// Appends the text `frame` stands for to `out`, if any: its data for a text frame, a
+    /// telemetry line for a frame carrying signals. Remote and error frames are ignored, as
+    /// are signals a frame is too short for.
+    fn decode(&mut self, frame: &[u8; FRAME_LEN], out: &mut Vec<u8>) {
+        let raw_id = u32::from_ne_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        if raw_id & (CAN_RTR_FLAG | CAN_ERR_FLAG) != 0 {
+            return;
+        }
+        let id = if raw_id & CAN_EFF_FLAG != 0 {
+            raw_id & CAN_EFF_MASK
+        } else {
+            raw_id & CAN_SFF_MASK
+        };
+        let data = &frame[8..8 + usize::from(frame[4]).min(MAX_DATA_LEN)];
+        if Some(id) == self.text_id {
+            out.extend_from_slice(data);
+            return;
+        }
+        let mut carried_signal = false;
+        for signal in self.signals.iter().filter(|s| s.id == id) {
+            let Some(bytes) = data.get(signal.offset..signal.offset + signal.field.width()) else {
+                continue;
+            };
+            carried_signal = true;
+            let sample = &mut self.sample;
+            let unsigned = bytes.iter().rev().fold(0u32, |n, &b| n << 8 | u32::from(b));
+            match signal.field {
+                CanField::Timestamp => sample.timestamp = Some(unsigned),
+                CanField::Armed => sample.armed = unsigned != 0,
+                CanField::Battery => sample.battery = unsigned as f32 * signal.scale,
+                CanField::Arming => sample.arming = unsigned as f32 * signal.scale,
+                CanField::Solenoids => sample.solenoids = unsigned as u16,
+            }
+        }
+        if carried_signal {
+            out.extend_from_slice(self.line().as_bytes());
+        }
+    }
+
+    /// The sample as a line of the built-in text format.
+    fn line(&self) -> String {
+        let sample = &self.sample;
+        let timestamp = match sample.timestamp {
+            Some(ms) => u64::from(ms),
+            None => self.opened.elapsed().as_millis() as u64,
+        };
+        let solenoids: Vec<_> = (0..16)
+            .map(|bit| {
+                let on = sample.solenoids & (1 << bit) != 0;
+                format!("{}:{}", bit + 1, if on { "ON" } else { "OFF" })
+            })
+            .collect();
+        format!(
+            "TS:{} | ARM:{} | BATT:{:.2}V | ARM_SENSE:{:.2}V | SOL:{}\n",
+            timestamp,
+            u8::from(sample.armed),
+            sample.battery,
+            sample.arming,
+            solenoids.join(",")
+        )
+    }
+}
+
+/// Reads the frames as one stream of text.
+struct CanReader<R> {
+    frames: R,
+    decoder: FrameDecoder,
+    /// Text from the last frame that had any, and how much of it was consumed.
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Read for CanReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for CanReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos >= self.buf.len() {
+            let mut frame = [0u8; FRAME_LEN];
+            self.frames.read_exact(&mut frame)?;
+            self.buf.clear();
+            self.pos = 0;
+            self.decoder.decode(&frame, &mut self.buf);
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+/// Sends every write as text frames on `command_id`.
+struct CanWriter<W> {
+    frames: W,
+    command_id: Option<u32>,
+}
+
+impl<W: Write> Write for CanWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let id = self.command_id.ok_or_else(|| {
+            let message = "no [can] command_id to send commands on";
+            io::Error::new(io::ErrorKind::NotConnected, message)
+        })?;
+        let raw_id = if id > CAN_SFF_MASK {
+            id | CAN_EFF_FLAG
+        } else {
+            id
+        };
+        for chunk in buf.chunks(MAX_DATA_LEN) {
+            let mut frame = [0u8; FRAME_LEN];
+            frame[..4].copy_from_slice(&raw_id.to_ne_bytes());
+            frame[4] = chunk.len() as u8;
+            frame[8..8 + chunk.len()].copy_from_slice(chunk);
+            self.frames.write_all(&frame)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.frames.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::parse_telemetry_line;
+
+    fn frame(raw_id: u32, data: &[u8]) -> [u8; FRAME_LEN] {
+        let mut frame = [0u8; FRAME_LEN];
+        frame[..4].copy_from_slice(&raw_id.to_ne_bytes());
+        frame[4] = data.len() as u8;
+        frame[8..8 + data.len()].copy_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn signals_become_telemetry_lines_and_commands_become_frames() {
+        let text = "command_id = 0x1234\ntext_id = 0x201\n\
+                    [[signal]]\nid = 0x100\nfield = \"armed\"\n\
+                    [[signal]]\nid = 0x100\nfield = \"solenoids\"\noffset = 1\n\
+                    [[signal]]\nid = 0x100\nfield = \"timestamp\"\noffset = 3\n\
+                    [[signal]]\nid = 0x101\nfield = \"battery\"\nscale = 0.01";
+        let can: CanConfig = toml::from_str(text).unwrap();
+        let mut frames_in = Vec::new();
+        for f in [
+            frame(0x101, &[0xD2, 0x04]),
+            frame(0x300, &[1, 2, 3]),
+            frame(0x201, b"ACK:s31\n"),
+            frame(0x100, &[1, 0x05, 0x80, 0x40, 0xE2, 0x01, 0x00]),
+            frame(0x100 | CAN_RTR_FLAG, &[]),
+            // Too short for the timestamp, so only the other signals count.
+            frame(0x100, &[0, 0x00, 0x00]),
+        ] {
+            frames_in.extend_from_slice(&f);
+        }
+        let SerialLink { reader, .. } = link(io::Cursor::new(frames_in), io::sink(), &can);
+        let lines: Vec<_> = reader.lines().take(4).map(|line| line.unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "ACK:s31");
+
+        let battery_only = parse_telemetry_line(&lines[0]).unwrap();
+        assert!((battery_only.battery - 12.34).abs() < 1e-3);
+        let armed = parse_telemetry_line(&lines[2]).unwrap();
+        assert_eq!(armed.timestamp, 123456);
+        assert!(armed.armed);
+        let on: Vec<_> = (1..=16).filter(|&ch| armed.solenoids[ch - 1]).collect();
+        assert_eq!(on, [1, 3, 16]);
+        let disarmed = parse_telemetry_line(&lines[3]).unwrap();
+        assert_eq!(disarmed.timestamp, 123456);
+        assert!(!disarmed.armed && disarmed.solenoids.iter().all(|&on| !on));
+
+        let mut writer = CanWriter {
+            frames: Vec::new(),
+            command_id: can.command_id,
+        };
+        writer.write_all(b"s31\ns40\nd\n").unwrap();
+        let extended = 0x1234 | CAN_EFF_FLAG;
+        let mut expected = frame(extended, b"s31\ns40\n").to_vec();
+        expected.extend_from_slice(&frame(extended, b"d\n"));
+        assert_eq!(writer.frames, expected);
+
+        let mut unconfigured = CanWriter {
+            frames: Vec::new(),
+            command_id: None,
+        };
+        let err = unconfigured.write_all(b"d\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+}
@@ -0,0 +1,753 @@
+// src/cluster.rs
+
+//! High availability for test stands that run two ground control servers
+//! (`--cluster-peers`, `[cluster] peers`). One server leads: it has the serial port and
+//! serves the POST endpoints. The others follow: they serve GETs from their own state
+//! and proxy every other request to the leader.
+//!
+//! The election is Raft-style but without votes (with two servers a majority would need
+//! both). The leader sends a heartbeat to every peer each `HEARTBEAT_INTERVAL` over UDP, on
+//! the same port number as its HTTP server. A follower that hears none for
+//! `ELECTION_TIMEOUT` starts a new term as leader and opens the serial port. A leader that
+//! hears from one with a later term steps down and closes it. Two leaders of the same
+//! term (both timed out at once) are settled by their random node IDs.
+//!
+//! Heartbeats are taken only from the configured peer addresses and, with a shared secret
+//! (`GCS_SECRET`, `[auth] secret`), only when signed with it, so no other host can make a
+//! server step down or follow it.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, Read};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::data::{Data, ToByteUnit};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{ContentType, Method, Status};
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::{json::Json, Serialize};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpStream;
+use rocket::tokio::time::timeout;
+use rocket::State;
+use tracing::{debug, warn};
+
+use crate::auth;
+use crate::error::ApiError;
+use crate::shutdown::ShutdownSignal;
+use crate::AppState;
+
+/// How often the leader sends its heartbeat.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a follower goes without a heartbeat before it takes over.
+pub const ELECTION_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Where the fairing reroutes the requests a follower proxies.
+const PROXY_PATH: &str = "/cluster/proxy";
+
+/// Marks a proxied request, so a server that is not the leader either does not pass it on.
+const PROXIED_HEADER: &str = "X-Cluster-Proxied";
+
+/// How long a proxied request may take to connect, and then to be answered.
+const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request body a follower proxies.
+const PROXY_BODY_LIMIT_KIB: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Role {
+    /// No peers: this server is the only one.
+    Standalone,
+    Follower,
+    Leader,
+}
+
+/// "GCS-HEARTBEAT <term> <node_id> <http_port>": the leader's term, its ID and where to
+/// send proxied requests (at the address the heartbeat came from). With a secret, followed
+/// by " <signature>", the hex HMAC-SHA256 of the rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Heartbeat {
+    term: u64,
+    node_id: u64,
+    http_port: u16,
+}
+
+impl Heartbeat {
+    fn encode(&self, secret: Option<&[u8]>) -> String {
+        let text = format!(
+            "GCS-HEARTBEAT {} {} {}",
+            self.term, self.node_id, self.http_port
+        );
+        match secret {
+            Some(secret) => {
+                let signature = auth::sign_message(secret, text.as_bytes());
+                format!("{} {}", text, signature)
+            }
+            None => text,
+        }
+    }
+
+    /// `None` unless `text` is a heartbeat, signed with `secret` if there is one.
+    fn decode(text: &str, secret: Option<&[u8]>) -> Option<Heartbeat> {
+        let mut text = text.trim();
+        if let Some(secret) = secret {
+            let (signed, signature) = text.rsplit_once(' ')?;
+            let expected = auth::sign_message(secret, signed.as_bytes());
+            if !auth::constant_time_eq(signature.as_bytes(), expected.as_bytes()) {
+                return None;
+            }
+            text = signed;
+        }
+        let mut fields = text.strip_prefix("GCS-HEARTBEAT ")?.split(' ');
+        let heartbeat = Heartbeat {
+            term: fields.next()?.parse().ok()?,
+            node_id: fields.next()?.parse().ok()?,
+            http_port: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(heartbeat)
+    }
+}
+
+/// One server's view of the election.
+#[derive(Debug)]
+struct Election {
+    role: Role,
+    term: u64,
+    node_id: u64,
+    /// The leader's HTTP address, while following one.
+    leader: Option<SocketAddr>,
+    /// When the leader was last heard from (or, before that, when this server started).
+    last_heartbeat: Instant,
+}
+
+impl Election {
+    fn new(node_id: u64, now: Instant) -> Self {
+        Election {
+            role: Role::Follower,
+            term: 0,
+            node_id,
+            leader: None,
+            last_heartbeat: now,
+        }
+    }
+
+    /// Follows the sender of `heartbeat` unless this server's term is later (or, being
+    /// leader of the same term, its node ID is higher). Returns whether this server
+    /// stepped down as leader.
+    fn on_heartbeat(&mut self, heartbeat: Heartbeat, leader: SocketAddr, now: Instant) -> bool {
+        if heartbeat.node_id == self.node_id {
+            return false;
+        }
+        let follows = heartbeat.term > self.term
+            || (heartbeat.term == self.term
+                && (self.role == Role::Follower || heartbeat.node_id > self.node_id));
+        if !follows {
+            return false;
+        }
+        let stepped_down = self.role == Role::Leader;
+        self.role = Role::Follower;
+        self.term = heartbeat.term;
+        self.leader = Some(leader);
+        self.last_heartbeat = now;
+        stepped_down
+    }
+
+    /// Takes over with a new term once the leader has been silent for `ELECTION_TIMEOUT`.
+    /// Returns whether this server just became leader.
+    fn tick(&mut self, now: Instant) -> bool {
+        if self.role != Role::Follower || now - self.last_heartbeat <= ELECTION_TIMEOUT {
+            return false;
+        }
+        self.role = Role::Leader;
+        self.term += 1;
+        self.leader = None;
+        true
+    }
+}
+
+/// Response body for GET /cluster/status.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ClusterStatus {
+    pub role: Role,
+    pub term: u64,
+    /// The leader's HTTP address: this server's own unless following; `null` while a
+    /// follower knows of no leader.
+    pub leader_addr: Option<String>,
+    /// Time since the last heartbeat from the leader; `null` unless following one.
+    pub heartbeat_age_ms: Option<u64>,
+    pub peers: Vec<String>,
+}
+
+/// This server's place in its cluster.
+pub struct Cluster {
+    peers: Vec<SocketAddr>,
+    /// Where this server's HTTP server (and heartbeat socket) listens.
+    http_addr: SocketAddr,
+    /// Signs the heartbeats sent, and must have signed those received.
+    secret: Option<Vec<u8>>,
+    election: Mutex<Election>,
+}
+
+/// The cluster, shared between the heartbeat thread, the serial loop and the handlers.
+pub type SharedCluster = Arc<Cluster>;
+
+impl Default for Cluster {
+    fn default() -> Self {
+        Cluster::new(Vec::new(), SocketAddr::from(([127, 0, 0, 1], 8000)), None)
+    }
+}
+
+impl Cluster {
+    /// A cluster of this server and `peers`; with no peers, a standalone server. The node
+    /// ID comes from the standard library's randomly keyed hasher.
+    pub fn new(peers: Vec<SocketAddr>, http_addr: SocketAddr, secret: Option<Vec<u8>>) -> Self {
+        let node_id = RandomState::new().build_hasher().finish();
+        Cluster {
+            peers,
+            http_addr,
+            secret,
+            election: Mutex::new(Election::new(node_id, Instant::now())),
+        }
+    }
+
+    pub fn is_standalone(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    pub fn role(&self) -> Role {
+        if self.is_standalone() {
+            Role::Standalone
+        } else {
+            self.election.lock().unwrap().role
+        }
+    }
+
+    /// Whether this server should have the serial port and serve writes.
+    pub fn is_leader(&self) -> bool {
+        matches!(self.role(), Role::Standalone | Role::Leader)
+    }
+
+    /// The leader's HTTP address, if this server follows one.
+    fn leader(&self) -> Option<SocketAddr> {
+        self.election.lock().unwrap().leader
+    }
+
+    pub fn status(&self, now: Instant) -> ClusterStatus {
+        let role = self.role();
+        let election = self.election.lock().unwrap();
+        let following = election.leader.filter(|_| role == Role::Follower);
+        ClusterStatus {
+            role,
+            term: election.term,
+            leader_addr: match role {
+                Role::Follower => following.map(|addr| addr.to_string()),
+                Role::Standalone | Role::Leader => Some(self.http_addr.to_string()),
+            },
+            heartbeat_age_ms: following.map(|_| {
+                now.saturating_duration_since(election.last_heartbeat)
+                    .as_millis() as u64
+            }),
+            peers: self.peers.iter().map(SocketAddr::to_string).collect(),
+        }
+    }
+
+    /// Runs the election on `socket` (bound to the heartbeat port) until shutdown.
+    pub fn spawn(self: &Arc<Self>, socket: UdpSocket, shutdown: &ShutdownSignal) -> io::Result<()> {
+        socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+        let cluster = self.clone();
+        let signal = shutdown.clone();
+        shutdown.spawn(move || cluster.run(socket, signal));
+        Ok(())
+    }
+
+    fn run(&self, socket: UdpSocket, shutdown: ShutdownSignal) {
+        let mut buf = [0u8; 128];
+        let mut last_sent: Option<Instant> = None;
+        while !shutdown.is_set() {
+            if let Ok((n, from)) = socket.recv_from(&mut buf) {
+                let text = String::from_utf8_lossy(&buf[..n]);
+                let heartbeat = Heartbeat::decode(&text, self.secret.as_deref());
+                match heartbeat.filter(|_| self.peers.contains(&from)) {
+                    Some(heartbeat) => {
+                        let leader = SocketAddr::new(from.ip(), heartbeat.http_port);
+                        let mut election = self.election.lock().unwrap();
+                        if election.on_heartbeat(heartbeat, leader, Instant::now()) {
+                            warn!(%leader, term = heartbeat.term,
+                                  "Another server leads a later term; stepping down");
+                        }
+                    }
+                    None => {
+                        debug!(%from, "Ignoring a datagram that is not a peer's signed heartbeat")
+                    }
+                }
+            }
+            let now = Instant::now();
+            let mut election = self.election.lock().unwrap();
+            if election.tick(now) {
+                warn!(
+                    term = election.term,
+                    "No heartbeat from a leader; taking over as leader"
+                );
+                last_sent = None;
+            }
+            let due = last_sent.is_none_or(|sent| now - sent >= HEARTBEAT_INTERVAL);
+            if election.role == Role::Leader && due {
+                let heartbeat = Heartbeat {
+                    term: election.term,
+                    node_id: election.node_id,
+                    http_port: self.http_addr.port(),
+                };
+                for peer in &self.peers {
+                    let text = heartbeat.encode(self.secret.as_deref());
+                    if let Err(e) = socket.send_to(text.as_bytes(), peer) {
+                        debug!(%peer, error = %e, "Could not send heartbeat");
+                    }
+                }
+                last_sent = Some(now);
+            }
+        }
+    }
+}
+
+/// Resolves the `host:port` of each peer.
+pub fn resolve_peers(peers: &[String]) -> Result<Vec<SocketAddr>, String> {
+    peers
+        .iter()
+        .map(|peer| {
+            peer.to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or_else(|| format!("cluster peer {:?} is not a reachable host:port", peer))
+        })
+        .collect()
+}
+
+/// A link reader that fails once this server is no longer the leader, which ends the
+/// serial session so the port is closed for the new leader.
+pub struct LeaderOnly<R> {
+    inner: R,
+    cluster: SharedCluster,
+}
+
+impl<R> LeaderOnly<R> {
+    pub fn new(inner: R, cluster: SharedCluster) -> Self {
+        LeaderOnly { inner, cluster }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.cluster.is_leader() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "no longer the cluster leader",
+            ))
+        }
+    }
+}
+
+impl<R: Read> Read for LeaderOnly<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for LeaderOnly<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// The method and URI of a request the fairing rerouted to `proxy`.
+#[derive(Debug, Clone)]
+struct ProxyTarget {
+    method: Method,
+    uri: String,
+}
+
+/// On a follower, reroutes every request that is not a GET (or HEAD or OPTIONS) to
+/// `proxy`, which passes it on to the leader.
+pub struct ClusterProxyFairing;
+
+#[rocket::async_trait]
+impl Fairing for ClusterProxyFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Cluster proxy",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let reads = matches!(req.method(), Method::Get | Method::Head | Method::Options);
+        let follower = req
+            .rocket()
+            .state::<AppState>()
+            .is_some_and(|state| state.cluster.role() == Role::Follower);
+        if reads || !follower || req.uri().path().starts_with("/cluster/") {
+            return;
+        }
+        let target = ProxyTarget {
+            method: req.method(),
+            uri: req.uri().to_string(),
+        };
+        req.local_cache(|| Some(target));
+        req.set_method(Method::Post);
+        req.set_uri(Origin::parse(PROXY_PATH).expect("valid path"));
+    }
+}
+
+/// A rerouted request, as it is passed on to the leader.
+pub struct ProxiedRequest {
+    target: ProxyTarget,
+    /// The request's headers, less those that describe the connection or the body.
+    headers: Vec<(String, String)>,
+    /// Already proxied by another server, which took this one for the leader.
+    already_proxied: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ProxiedRequest {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        // Only the fairing sends requests here.
+        let Some(target) = req.local_cache(|| None::<ProxyTarget>).clone() else {
+            return Outcome::Forward(Status::NotFound);
+        };
+        let skipped = [
+            "host",
+            "content-length",
+            "connection",
+            "transfer-encoding",
+            "x-real-ip",
+        ];
+        let mut headers: Vec<_> = req
+            .headers()
+            .iter()
+            .filter(|h| !skipped.contains(&h.name().as_str().to_ascii_lowercase().as_str()))
+            .map(|h| (h.name().to_string(), h.value().to_string()))
+            .collect();
+        if let Some(ip) = req.client_ip() {
+            headers.push(("X-Real-IP".to_string(), ip.to_string()));
+        }
+        Outcome::Success(ProxiedRequest {
+            target,
+            headers,
+            already_proxied: req.headers().contains(PROXIED_HEADER),
+        })
+    }
+}
+
+/// The leader's answer to a proxied request, passed back as it came.
+#[derive(Debug, PartialEq)]
+pub struct ProxiedResponse {
+    status: u16,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for ProxiedResponse {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response.status(Status::new(self.status));
+        if let Some(content_type) = self
+            .content_type
+            .as_deref()
+            .and_then(ContentType::parse_flexible)
+        {
+            response.header(content_type);
+        }
+        response
+            .sized_body(self.body.len(), io::Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// Where a follower's fairing sends the requests it does not serve: they are passed on to
+/// the leader and its response returned. 503 NO_CLUSTER_LEADER while no leader is known.
+#[post("/cluster/proxy", data = "<body>")]
+pub async fn proxy(
+    request: ProxiedRequest,
+    body: Data<'_>,
+    state: &State<AppState>,
+) -> Result<ProxiedResponse, ApiError> {
+    let leader = state.cluster.leader().filter(|_| !request.already_proxied);
+    let leader = leader.ok_or(ApiError::NoClusterLeader)?;
+    let body = body
+        .open(PROXY_BODY_LIMIT_KIB.kibibytes())
+        .into_bytes()
+        .await
+        .map_err(|e| ApiError::ClusterProxyFailed(e.to_string()))?;
+    if !body.is_complete() {
+        return Err(ApiError::ClusterProxyFailed(
+            "request body too large".to_string(),
+        ));
+    }
+    debug!(%leader, method = %request.target.method, uri = %request.target.uri,
+           "Proxying to the cluster leader");
+    forward(leader, &request, &body).await.map_err(|e| {
+        warn!(%leader, error = %e, "Could not proxy a request to the cluster leader");
+        ApiError::ClusterProxyFailed(e.to_string())
+    })
+}
+
+/// Sends `request` to `leader` over plain HTTP/1.1 and reads the whole response.
+async fn forward(
+    leader: SocketAddr,
+    request: &ProxiedRequest,
+    body: &[u8],
+) -> io::Result<ProxiedResponse> {
+    let timed_out = |_| io::Error::new(io::ErrorKind::TimedOut, "the leader did not answer");
+    let mut stream = timeout(PROXY_TIMEOUT, TcpStream::connect(leader))
+        .await
+        .map_err(timed_out)??;
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\n",
+        request.target.method, request.target.uri, leader
+    );
+    for (name, value) in &request.headers {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += &format!(
+        "{}: 1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        PROXIED_HEADER,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    let mut response = Vec::new();
+    timeout(PROXY_TIMEOUT, stream.read_to_end(&mut response))
+        .await
+        .map_err(timed_out)??;
+    parse_response(&response)
+}
+
+/// Parses an HTTP/1.1 response, with a sized or chunked body.
+fn parse_response(bytes: &[u8]) -> io::Result<ProxiedResponse> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let split = bytes
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("response has no end of headers"))?;
+    let head = String::from_utf8_lossy(&bytes[..split]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("bad status line"))?;
+    let mut content_type = None;
+    let mut chunked = false;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+    let mut body = &bytes[split + 4..];
+    let body = if chunked {
+        let mut decoded = Vec::new();
+        loop {
+            let line_end = body
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .ok_or_else(|| invalid("truncated chunk"))?;
+            let size = String::from_utf8_lossy(&body[..line_end]);
+            let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+                .map_err(|_| invalid("bad chunk size"))?;
+            let chunk = body
+                .get(line_end + 2..line_end + 2 + size)
+                .ok_or_else(|| invalid("truncated chunk"))?;
+            if size == 0 {
+                break decoded;
+            }
+            decoded.extend_from_slice(chunk);
+            body = body.get(line_end + 4 + size..).unwrap_or_default();
+        }
+    } else {
+        body.to_vec()
+    };
+    Ok(ProxiedResponse {
+        status,
+        content_type,
+        body,
+    })
+}
+
+/// GET /cluster/status reports this server's role, the leader and when it was last heard.
+#[get("/cluster/status")]
+pub fn status(state: &State<AppState>) -> Json<ClusterStatus> {
+    Json(state.cluster.status(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(term: u64, node_id: u64) -> Heartbeat {
+        Heartbeat {
+            term,
+            node_id,
+            http_port: 8000,
+        }
+    }
+
+    #[test]
+    fn followers_take_over_and_later_terms_win() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let other: SocketAddr = "10.0.0.2:8000".parse().unwrap();
+        let mut election = Election::new(5, start);
+        assert!(!election.tick(ms(1500)));
+        assert!(election.tick(ms(1501)));
+        assert_eq!((election.role, election.term), (Role::Leader, 1));
+
+        // A leader of the same term with a lower ID yields; one with a higher ID wins.
+        assert!(!election.on_heartbeat(heartbeat(1, 4), other, ms(1600)));
+        assert_eq!(election.role, Role::Leader);
+        assert!(election.on_heartbeat(heartbeat(1, 6), other, ms(1700)));
+        assert_eq!(
+            (election.role, election.leader),
+            (Role::Follower, Some(other))
+        );
+
+        // Heartbeats keep a follower following; earlier terms are ignored.
+        assert!(!election.on_heartbeat(heartbeat(1, 6), other, ms(3000)));
+        assert!(!election.tick(ms(4400)));
+        assert!(!election.on_heartbeat(heartbeat(0, 7), other, ms(4500)));
+        assert!(election.tick(ms(4600)));
+        assert_eq!((election.role, election.term), (Role::Leader, 2));
+        assert!(election.on_heartbeat(heartbeat(3, 1), other, ms(4700)));
+        assert_eq!((election.role, election.term), (Role::Follower, 3));
+        // Its own heartbeat (if it is in its own peer list) changes nothing.
+        assert!(!election.on_heartbeat(heartbeat(9, 5), other, ms(4800)));
+    }
+
+    #[test]
+    fn heartbeats_round_trip() {
+        let sent = heartbeat(3, u64::MAX);
+        assert_eq!(Heartbeat::decode(&sent.encode(None), None), Some(sent));
+        assert_eq!(Heartbeat::decode("GCS-HEARTBEAT 3 4", None), None);
+        assert_eq!(Heartbeat::decode("GCS-HEARTBEAT 3 4 5 6", None), None);
+        assert_eq!(Heartbeat::decode("hello", None), None);
+
+        // With a secret, only heartbeats signed with it are taken.
+        let secret = Some(&b"s3cret"[..]);
+        assert_eq!(Heartbeat::decode(&sent.encode(secret), secret), Some(sent));
+        assert_eq!(Heartbeat::decode(&sent.encode(None), secret), None);
+        let forged = sent.encode(Some(b"guess"));
+        assert_eq!(Heartbeat::decode(&forged, secret), None);
+        let tampered = sent.encode(secret).replacen(" 3 ", " 4 ", 1);
+        assert_eq!(Heartbeat::decode(&tampered, secret), None);
+    }
+
+    #[test]
+    fn parses_sized_and_chunked_responses() {
+        let sized = b"HTTP/1.1 409 Conflict\r\ncontent-type: text/plain; charset=utf-8\r\n\
+                      content-length: 2\r\n\r\nno";
+        let response = parse_response(sized).unwrap();
+        assert_eq!(response.status, 409);
+        assert_eq!(
+            response.content_type.as_deref(),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(response.body, b"no");
+
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                        2\r\nOK\r\n3\r\n!!!\r\n0\r\n\r\n";
+        assert_eq!(parse_response(chunked).unwrap().body, b"OK!!!");
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn two_servers_agree_on_a_leader() {
+        let shutdown = ShutdownSignal::default();
+        let sockets: Vec<_> = (0..2)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs: Vec<_> = sockets.iter().map(|s| s.local_addr().unwrap()).collect();
+        let clusters: Vec<_> = (0..2)
+            .map(|i| Arc::new(Cluster::new(vec![addrs[1 - i]], addrs[i], None)))
+            .collect();
+        for (cluster, socket) in clusters.iter().zip(sockets) {
+            cluster.spawn(socket, &shutdown).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (leader, follower) = loop {
+            match (clusters[0].role(), clusters[1].role()) {
+                (Role::Leader, Role::Follower) => break (0, 1),
+                (Role::Follower, Role::Leader) => break (1, 0),
+                _ => assert!(Instant::now() < deadline, "no single leader"),
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let status = clusters[follower].status(Instant::now());
+        assert_eq!(status.leader_addr, Some(addrs[leader].to_string()));
+        assert!(status.heartbeat_age_ms.unwrap() <= ELECTION_TIMEOUT.as_millis() as u64);
+        assert_eq!(
+            clusters[leader].status(Instant::now()).heartbeat_age_ms,
+            None
+        );
+    }
+
+    #[test]
+    fn forged_heartbeats_are_ignored() {
+        let shutdown = ShutdownSignal::default();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let http_addr = socket.local_addr().unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let secret = Some(&b"s3cret"[..]);
+        let cluster = Arc::new(Cluster::new(
+            vec![peer.local_addr().unwrap()],
+            http_addr,
+            secret.map(<[u8]>::to_vec),
+        ));
+        cluster.spawn(socket, &shutdown).unwrap();
+
+        // Unsigned or wrongly signed from the peer, or correctly signed from another host.
+        let sent = heartbeat(7, 1);
+        peer.send_to(sent.encode(None).as_bytes(), http_addr)
+            .unwrap();
+        peer.send_to(sent.encode(Some(b"guess")).as_bytes(), http_addr)
+            .unwrap();
+        stranger
+            .send_to(sent.encode(secret).as_bytes(), http_addr)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(cluster.leader(), None);
+        assert_eq!(cluster.status(Instant::now()).term, 0);
+
+        peer.send_to(sent.encode(secret).as_bytes(), http_addr)
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while cluster.leader().is_none() {
+            assert!(
+                Instant::now() < deadline,
+                "the signed heartbeat was not taken"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            cluster.leader(),
+            Some(SocketAddr::new(http_addr.ip(), 8000))
+        );
+        assert_eq!(cluster.status(Instant::now()).term, 7);
+    }
+}
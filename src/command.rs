@@ -0,0 +1,133 @@
+// src/command.rs
+
+//! The commands the Arduino understands, encoded in one place: "a" arms, "d" disarms and
+//! "s<channel><state>" sets a solenoid, e.g. "s71" energizes solenoid 7.
+//!
+//! ```text
+//! let mut builder = CommandBuilder::new();
+//! builder.arm().solenoid(3, true)?.solenoid(7, false)?.disarm();
+//! assert_eq!(builder.build(), ["a", "s31", "s70", "d"]);
+//! ```
+
+use std::fmt;
+
+/// An argument the firmware would not understand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandError {
+    /// Not a solenoid channel (1-16).
+    InvalidChannel(u8),
+    /// Not a solenoid state (0 or 1).
+    InvalidState(u8),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::InvalidChannel(ch) => write!(f, "{} is not a channel (1-16)", ch),
+            CommandError::InvalidState(st) => write!(f, "{} is not a state (0 or 1)", st),
+        }
+    }
+}
+
+/// Collects validated command strings, in the order they are to be sent.
+#[derive(Debug, Default)]
+pub struct CommandBuilder {
+    commands: Vec<String>,
+}
+
+impl CommandBuilder {
+    pub fn new() -> Self {
+        CommandBuilder::default()
+    }
+
+    pub fn arm(&mut self) -> &mut Self {
+        self.commands.push("a".to_string());
+        self
+    }
+
+    pub fn disarm(&mut self) -> &mut Self {
+        self.commands.push("d".to_string());
+        self
+    }
+
+    /// Energizes (`on`) or de-energizes the solenoid on `channel`.
+    pub fn solenoid(&mut self, channel: u8, on: bool) -> Result<&mut Self, CommandError> {
+        if !(1..=16).contains(&channel) {
+            return Err(CommandError::InvalidChannel(channel));
+        }
+        self.commands.push(format!("s{}{}", channel, on as u8));
+        Ok(self)
+    }
+
+    /// Like `solenoid`, with the state as the firmware's 0 or 1, e.g. from a request path.
+    pub fn solenoid_state(&mut self, channel: u8, state: u8) -> Result<&mut Self, CommandError> {
+        if !(1..=16).contains(&channel) {
+            return Err(CommandError::InvalidChannel(channel));
+        }
+        match state {
+            0 | 1 => self.solenoid(channel, state == 1),
+            _ => Err(CommandError::InvalidState(state)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// The commands so far, leaving the builder empty.
+    pub fn build(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+
+    /// The commands as one `QueuedCommand` text: separated by newlines, without the last
+    /// one, which the serial loop appends.
+    pub fn build_joined(&mut self) -> String {
+        self.build().join("\n")
+    }
+
+    /// The commands each terminated by a newline, ready to be written to the port as is.
+    pub fn build_lines(&mut self) -> String {
+        self.build()
+            .iter()
+            .map(|cmd| format!("{}\n", cmd))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_validated_commands() {
+        let mut builder = CommandBuilder::new();
+        builder
+            .arm()
+            .solenoid(3, true)
+            .unwrap()
+            .solenoid_state(16, 0)
+            .unwrap()
+            .disarm();
+        assert_eq!(builder.build(), ["a", "s31", "s160", "d"]);
+        assert!(builder.is_empty());
+
+        assert_eq!(
+            builder.solenoid(0, true).unwrap_err(),
+            CommandError::InvalidChannel(0)
+        );
+        assert_eq!(
+            builder.solenoid_state(17, 1).unwrap_err(),
+            CommandError::InvalidChannel(17)
+        );
+        assert_eq!(
+            builder.solenoid_state(5, 2).unwrap_err(),
+            CommandError::InvalidState(2)
+        );
+        assert!(builder.is_empty());
+
+        builder.disarm().solenoid(1, false).unwrap();
+        assert_eq!(builder.build_lines(), "d\ns10\n");
+        builder.arm().solenoid(2, true).unwrap();
+        assert_eq!(builder.build_joined(), "a\ns21");
+    }
+}
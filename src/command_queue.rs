@@ -0,0 +1,145 @@
+// src/command_queue.rs
+
+//! The channel carrying commands from the HTTP handlers to a board's serial loop, with a
+//! count of the commands still waiting in it.
+//!
+//! Under load, commands can pile up before the serial loop gets to write them. GET
+//! /commands/pending reports how many; DELETE /commands/pending throws them away, so stale
+//! commands don't go out after an emergency stop (which has its own channel and is never
+//! queued here).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::auth::Authenticated;
+use crate::{AppState, QueuedCommand};
+
+/// Creates a command queue: the handlers' end and the serial loop's end.
+pub fn channel() -> (CommandSender, CommandReceiver) {
+    let (tx, rx) = mpsc::channel();
+    let rx = Arc::new(Mutex::new(rx));
+    let depth = Arc::new(AtomicUsize::new(0));
+    let sender = CommandSender {
+        tx,
+        rx: Arc::downgrade(&rx),
+        depth: depth.clone(),
+    };
+    (sender, CommandReceiver { rx, depth })
+}
+
+/// Queues commands, and can report or discard the ones not yet received.
+#[derive(Clone)]
+pub struct CommandSender {
+    tx: mpsc::Sender<QueuedCommand>,
+    /// The `CommandReceiver`'s receiver, so `drain` can empty the queue. Weak, so the
+    /// channel still closes when the serial loop drops its end.
+    rx: Weak<Mutex<mpsc::Receiver<QueuedCommand>>>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl CommandSender {
+    /// Queues `cmd`. Fails if the receiving serial loop has gone away.
+    pub fn send(&self, cmd: QueuedCommand) -> Result<(), mpsc::SendError<QueuedCommand>> {
+        // Counted before sending, so the receiver never sees the count go below zero.
+        self.depth.fetch_add(1, Ordering::SeqCst);
+        self.tx.send(cmd).inspect_err(|_| {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+        })
+    }
+
+    /// How many queued commands the serial loop has not picked up yet.
+    pub fn pending(&self) -> usize {
+        if self.rx.strong_count() == 0 {
+            return 0;
+        }
+        self.depth.load(Ordering::SeqCst)
+    }
+
+    /// Discards every queued command. Returns how many there were.
+    pub fn drain(&self) -> usize {
+        let Some(rx) = self.rx.upgrade() else {
+            return 0;
+        };
+        let rx = rx.lock().unwrap();
+        let mut discarded = 0;
+        while rx.try_recv().is_ok() {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+            discarded += 1;
+        }
+        discarded
+    }
+}
+
+/// The serial loop's end of the queue.
+pub struct CommandReceiver {
+    rx: Arc<Mutex<mpsc::Receiver<QueuedCommand>>>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl CommandReceiver {
+    /// The next queued command, if any.
+    pub fn try_recv(&self) -> Result<QueuedCommand, mpsc::TryRecvError> {
+        let cmd = self.rx.lock().unwrap().try_recv()?;
+        self.depth.fetch_sub(1, Ordering::SeqCst);
+        Ok(cmd)
+    }
+}
+
+/// Response body for GET /commands/pending.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PendingCount {
+    pending: usize,
+}
+
+/// Response body for DELETE /commands/pending.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DiscardedCount {
+    discarded: usize,
+}
+
+/// GET /commands/pending reports how many commands are queued for the primary board but
+/// not yet written to the port.
+#[get("/commands/pending")]
+pub fn get_pending(state: &State<AppState>) -> Json<PendingCount> {
+    Json(PendingCount {
+        pending: state.command_tx.pending(),
+    })
+}
+
+/// DELETE /commands/pending discards the primary board's queued commands before they are
+/// written. Commands already written, and emergency stops, are unaffected.
+#[delete("/commands/pending")]
+pub fn clear_pending(_auth: Authenticated, state: &State<AppState>) -> Json<DiscardedCount> {
+    Json(DiscardedCount {
+        discarded: state.command_tx.drain(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_drains_queued_commands() {
+        let (sender, receiver) = channel();
+        for text in ["a", "s11", "s21"] {
+            sender.send(QueuedCommand::immediate(text)).unwrap();
+        }
+        assert_eq!(sender.pending(), 3);
+        assert_eq!(receiver.try_recv().unwrap().text, "a");
+        assert_eq!(sender.pending(), 2);
+
+        assert_eq!(sender.drain(), 2);
+        assert_eq!(sender.pending(), 0);
+        assert!(receiver.try_recv().is_err());
+
+        drop(receiver);
+        assert!(sender.send(QueuedCommand::immediate("d")).is_err());
+        assert_eq!(sender.pending(), 0);
+    }
+}
@@ -0,0 +1,1000 @@
+// src/config.rs
+
+//! Startup configuration, read from a TOML file (`gcs.toml` by default, or `--config <path>`).
+//!
+//! Every field has a default, so a missing file or section means "as before". Command-line
+//! flags are applied on top of the file, so they always win.
+//!
+//! ```toml
+//! [serial]
+//! port = "/dev/ttyACM0"
+//! baud = 115200
+//! timeout_ms = 100
+//! format = "ascii"  # or "binary"
+//! # Read telemetry from UDP datagrams (e.g. a WiFi bridge) instead of `port`, and send
+//! # commands to `udp_command` (default: wherever the last datagram came from).
+//! # udp_in = "0.0.0.0:9100"
+//! # udp_command = "192.168.1.30:9101"
+//! # Read telemetry from this SocketCAN interface instead (needs the `can` feature; the
+//! # frames are mapped by [can] below).
+//! # can = "can0"
+//! # Queued commands still sent on shutdown (Ctrl+C, SIGTERM) before the board is disarmed.
+//! shutdown_drain = 10
+//!
+//! [server]
+//! address = "0.0.0.0"
+//! port = 8000
+//! # allow_inject = true  # development only: mounts POST /telemetry/inject
+//! # dev_mode = true      # debug builds only: mounts POST /firmware/command
+//! # allow_firmware_reset = true  # mounts POST /firmware/reset (reboots the Arduino)
+//!
+//! # HTTPS is not supported yet: builds have no TLS support, so the server refuses to start
+//! # with this set. Terminate TLS in a reverse proxy instead.
+//! [server.tls]
+//! certs = "tls/cert.pem"
+//! key = "tls/key.pem"
+//!
+//! [logging]
+//! log_file = "telemetry.csv"
+//! db_file = "telemetry.db"
+//! audit_log = "arm_audit.jsonl"
+//! state_file = "gcs_state.json"  # the last known telemetry, kept across restarts
+//! ring_buffer_size = 1000
+//! format = "pretty"  # or "json"
+//!
+//! # Forward telemetry to other displays as JSON datagrams.
+//! [relay]
+//! udp_out = ["192.168.1.20:9000", "display.local:9000"]
+//!
+//! # Two servers sharing a serial port: one leads and has the port, the other proxies
+//! # writes to it and takes over if it goes quiet (see `cluster`). Heartbeats go over UDP
+//! # on the HTTP port number, signed with the [auth] secret, and only peers' are taken.
+//! [cluster]
+//! peers = ["192.168.1.11:8000"]
+//!
+//! [safety]
+//! require_armed_for_solenoid = true
+//! min_interval_ms = 500
+//! watchdog_timeout_s = 5
+//! # Arm only through POST /arm/intent + /arm/confirm, within the window.
+//! require_two_step = true
+//! arm_confirm_window_ms = 5000
+//! # Keep solenoids from being switched on after more than 80% of the last minute on.
+//! max_duty_pct = 80.0
+//! # Flag GET /alerts/battery_drain when the battery drops faster than 1 V/s.
+//! max_drain_rate_v_per_s = 1.0
+//! # POST /solenoid/all/open|close work while disarmed too.
+//! require_armed_for_all = false
+//! # Log an ERROR when telemetry does not show a solenoid command's effect within 500 ms.
+//! verify_timeout_ms = 500
+//!
+//! [filters]
+//! battery_window = 10
+//!
+//! # Firmware with a line format of its own: a regex whose named groups pick out the fields
+//! # (see `LineFormat`). Without it, the built-in format is parsed.
+//! [telemetry.format]
+//! regex = '^(?P<timestamp>\d+);(?P<armed>[01]);(?P<battery>[\d.]+);(?P<solenoids>[01]{16})$'
+//!
+//! # POSTs per client IP: a burst of 100, then 10 per second (burst = 0 disables).
+//! [rate_limit]
+//! burst = 100
+//! refill_per_s = 10.0
+//!
+//! [cors]
+//! allowed_origins = ["http://localhost:3000"]  # or ["*"]
+//!
+//! [auth]
+//! # Require signed POST requests (or set GCS_SECRET instead).
+//! secret = "change-me"
+//! # Require a login for everything; see `telemetry_server generate-password`.
+//! username = "operator"
+//! password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+//!
+//! # Names shown in the UI instead of "Solenoid N".
+//! [solenoid_labels]
+//! 7 = "LOX Main Valve"
+//! 8 = "Fuel Main Valve"
+//!
+//! # Valves that are open while their solenoid is off; the rest are normally closed.
+//! [solenoid_directions]
+//! 3 = "normally_open"
+//!
+//! # Solenoids turned off automatically after being on this long.
+//! [solenoid_max_open_ms]
+//! 7 = 30000
+//!
+//! [webhook]
+//! url = "http://alerts.local:9000/gcs"
+//! low_battery_threshold = 11.1
+//! hysteresis = 1.0
+//!
+//! # With [serial] can: commands go out as text on `command_id`, 8 bytes per frame, and
+//! # text on `text_id` (ACKs) is read like serial data. Each [[can.signal]] places a
+//! # telemetry field in a frame; a sample is published after every frame carrying one.
+//! [can]
+//! command_id = 0x200
+//! text_id = 0x201
+//!
+//! [[can.signal]]
+//! id = 0x100
+//! field = "armed"      # 1 byte, nonzero = armed
+//! offset = 0
+//!
+//! [[can.signal]]
+//! id = 0x100
+//! field = "solenoids"  # 16-bit little-endian mask, bit 0 = channel 1
+//! offset = 1
+//!
+//! [[can.signal]]
+//! id = 0x101
+//! field = "battery"    # 16-bit little-endian, times scale: volts ("arming" likewise)
+//! offset = 0
+//! scale = 0.01
+//!
+//! # GET /diagram: where each valve is drawn, in SVG units (unlisted ones go in a 4x4
+//! # grid), and the pipes drawn between valves.
+//! [diagram]
+//! positions = { 1 = [60, 60], 2 = [180, 60], 3 = [300, 60] }
+//! pipes = [[1, 2], [2, 3]]
+//!
+//! # Sent when a POST /countdown/start countdown reaches T-0.
+//! [countdown]
+//! arm_at_t0 = true
+//! open_at_t0 = [3, 7]
+//!
+//! # Solenoids opened and closed together by POST /group/<name>/open|close.
+//! [[group]]
+//! name = "purge"
+//! channels = [3, 4, 9]
+//!
+//! # Valve 7 can't open while valve 3 is open, nor 3 while 7 is.
+//! [[interlock]]
+//! prevent = [3, 7]
+//!
+//! # POST to a webhook when a reading crosses a threshold and again when it recovers (needs
+//! # the `webhook` feature; GET /alerts/active works without). Fields: battery, arming,
+//! # armed (0 or 1); ops: lt, gt, eq.
+//! [[alert]]
+//! field = "battery"
+//! op = "lt"
+//! threshold = 11.0
+//! webhook = "http://alerts.local:9000/gcs"
+//!
+//! # Test stands with more than one Arduino list each board. The first one replaces
+//! # [serial] port/baud and drives the top-level endpoints; the others are reached through
+//! # /board/<id>/... (see `board`).
+//! [[board]]
+//! id = 1
+//! port = "/dev/ttyACM0"
+//!
+//! [[board]]
+//! id = 2
+//! port = "/dev/ttyACM1"
+//! baud = 57600  # defaults to [serial] baud
+//! ```
+//!
+//! `scripts/gen-dev-cert.sh` creates a self-signed certificate and key, e.g. for a reverse
+//! proxy in front of the server. That is for development only: browsers will warn about it,
+//! and anyone on the network can impersonate a server using one. Production deployments
+//! should use a certificate signed by a proper CA (or an internal CA the operator machines
+//! trust).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::argon2::PasswordHash;
+use crate::basic_auth::Credentials;
+use crate::filters::DEFAULT_FILTER_WINDOW;
+use crate::history::DEFAULT_HISTORY_CAPACITY;
+use crate::logging::LogFormat;
+use crate::request_limit::{DEFAULT_BURST, DEFAULT_REFILL_PER_S};
+use crate::safety::{
+    DEFAULT_ARM_CONFIRM_WINDOW_MS, DEFAULT_MAX_DRAIN_RATE_V_PER_S, DEFAULT_MAX_DUTY_PCT,
+    DEFAULT_MIN_INTERVAL_MS, DEFAULT_WATCHDOG_TIMEOUT_S,
+};
+use crate::shutdown::DEFAULT_SHUTDOWN_DRAIN;
+use crate::telemetry::{LineFormat, TelemetryFormat};
+use crate::verifier::DEFAULT_VERIFY_TIMEOUT_MS;
+use crate::{DEFAULT_BAUD_RATE, DEFAULT_ERROR_THRESHOLD, SUPPORTED_BAUD_RATES};
+
+/// Where the config is read from when `--config` is not given. It's fine for it not to exist.
+pub const DEFAULT_CONFIG_PATH: &str = "gcs.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct Config {
+    pub serial: SerialConfig,
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+    pub relay: RelayConfig,
+    pub cluster: ClusterConfig,
+    pub safety: SafetyConfig,
+    pub filters: FilterConfig,
+    pub telemetry: TelemetryConfig,
+    pub webhook: WebhookConfig,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub auth: AuthConfig,
+    pub can: CanConfig,
+    pub diagram: DiagramConfig,
+    pub countdown: CountdownConfig,
+    /// `[solenoid_labels]`: display names keyed by channel, e.g. `7 = "LOX Main Valve"`.
+    pub solenoid_labels: HashMap<String, String>,
+    /// `[solenoid_directions]`: valve types keyed by channel, e.g. `3 = "normally_open"`.
+    /// Channels not listed are normally closed.
+    pub solenoid_directions: HashMap<String, SolenoidDirection>,
+    /// `[solenoid_max_open_ms]`: how long a solenoid may stay on, keyed by channel, e.g.
+    /// `7 = 30000`. Channels not listed have no limit.
+    pub solenoid_max_open_ms: HashMap<String, u64>,
+    /// `[[board]]` sections; empty for a single-board stand configured through `[serial]`.
+    #[serde(rename = "board")]
+    pub boards: Vec<BoardConfig>,
+    /// `[[group]]` sections: named sets of solenoids.
+    #[serde(rename = "group")]
+    pub groups: Vec<SolenoidGroup>,
+    /// `[[interlock]]` sections: pairs of valves never open at the same time.
+    #[serde(rename = "interlock")]
+    pub interlocks: Vec<Interlock>,
+    /// `[[alert]]` sections: threshold rules that POST to a webhook.
+    #[serde(rename = "alert")]
+    pub alerts: Vec<AlertRule>,
+}
+
+/// `[serial]`: the link to the Arduino.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct SerialConfig {
+    pub port: String,
+    pub baud: u32,
+    /// How long a serial read waits for data before the loop goes on to check for commands.
+    /// Also accepted as `read_timeout_ms`.
+    #[serde(alias = "read_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive read errors before the port is re-opened.
+    pub reconnect_threshold: u32,
+    /// Talk to a simulated Arduino instead of opening `port`.
+    pub simulate: bool,
+    /// Talk to the hardware-in-the-loop fake Arduino instead of opening `port`.
+    pub hil: bool,
+    /// Serve the telemetry recorded in this log (CSV or raw serial lines) instead of
+    /// opening `port`; commands are logged but not sent.
+    pub replay: Option<String>,
+    /// Open the port and read telemetry, but only log commands instead of writing them.
+    pub dry_run: bool,
+    /// The telemetry wire format.
+    pub format: TelemetryFormat,
+    /// Receive telemetry as UDP datagrams on this `host:port` instead of opening `port`.
+    pub udp_in: Option<String>,
+    /// Where commands are sent as datagrams with `udp_in`; the sender of the last datagram
+    /// if unset.
+    pub udp_command: Option<String>,
+    /// How many queued commands are still written on shutdown, before the final disarm.
+    pub shutdown_drain: usize,
+    /// Read telemetry from this SocketCAN interface (e.g. "can0") instead of opening `port`;
+    /// see `[can]`.
+    pub can: Option<String>,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            port: "COM5".into(),
+            baud: DEFAULT_BAUD_RATE,
+            timeout_ms: 100,
+            reconnect_threshold: DEFAULT_ERROR_THRESHOLD,
+            simulate: false,
+            hil: false,
+            dry_run: false,
+            replay: None,
+            format: TelemetryFormat::Ascii,
+            udp_in: None,
+            udp_command: None,
+            shutdown_drain: DEFAULT_SHUTDOWN_DRAIN,
+            can: None,
+        }
+    }
+}
+
+/// `[[board]]`: one Arduino of a multi-board stand. Its other serial settings come from
+/// `[serial]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct BoardConfig {
+    pub id: u8,
+    pub port: String,
+    pub baud: Option<u32>,
+}
+
+/// `[[group]]`: solenoids actuated together (see `groups`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct SolenoidGroup {
+    pub name: String,
+    pub channels: Vec<u8>,
+}
+
+impl SolenoidGroup {
+    /// Why this group is invalid, if it is: no name, no channels, or a channel outside
+    /// 1-16 or listed twice.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("group name must not be empty".to_string());
+        }
+        if self.channels.is_empty() {
+            return Err(format!("group '{}' has no channels", self.name));
+        }
+        for (i, &channel) in self.channels.iter().enumerate() {
+            if !(1..=16).contains(&channel) {
+                return Err(format!("group '{}': {} is not a channel (1-16)", self.name, channel));
+            }
+            if self.channels[..i].contains(&channel) {
+                return Err(format!("group '{}': channel {} listed twice", self.name, channel));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[[interlock]]`: `prevent = [3, 7]` keeps valve 7 from opening while valve 3 is open,
+/// and the other way round (see `interlock`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct Interlock {
+    pub prevent: Vec<u8>,
+}
+
+impl Interlock {
+    /// Why this interlock is invalid, if it is: not two channels, a channel outside 1-16, or
+    /// the same channel twice.
+    pub fn validate(&self) -> Result<(), String> {
+        let &[a, b] = self.prevent.as_slice() else {
+            return Err(format!("interlock: prevent needs two channels, got {:?}", self.prevent));
+        };
+        if let Some(ch) = [a, b].into_iter().find(|ch| !(1..=16).contains(ch)) {
+            return Err(format!("interlock: {} is not a channel (1-16)", ch));
+        }
+        if a == b {
+            return Err(format!("interlock: channel {} listed twice", a));
+        }
+        Ok(())
+    }
+}
+
+/// `[[alert]]`: fires while `field` `op` `threshold` holds for the latest telemetry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct AlertRule {
+    pub field: AlertField,
+    pub op: AlertOp,
+    pub threshold: f32,
+    /// `http://` URL POSTed when the alert fires and when it clears.
+    pub webhook: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum AlertField {
+    Battery,
+    Arming,
+    /// 1 while armed, 0 otherwise.
+    Armed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum AlertOp {
+    Lt,
+    Gt,
+    Eq,
+}
+
+/// `[server]`: where the HTTP server listens. Unset values keep Rocket's own defaults
+/// (which `Rocket.toml` and `ROCKET_*` variables can still change).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    /// Serve HTTPS with this certificate and key. No build supports it yet, so setting it
+    /// stops the server at startup rather than serving plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// Mount POST /telemetry/inject, which lets any client fake telemetry. For development
+    /// and tests only.
+    pub allow_inject: bool,
+    /// Mount POST /firmware/command, which writes raw lines to the Arduino. Refused by
+    /// release builds.
+    pub dev_mode: bool,
+    /// Mount POST /firmware/reset, which reboots the Arduino by toggling DTR.
+    pub allow_firmware_reset: bool,
+}
+
+/// `[server.tls]`: PEM files for HTTPS.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Certificate chain.
+    pub certs: String,
+    /// Private key.
+    pub key: String,
+}
+
+/// `[logging]`: telemetry recording and the server's log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Append parsed telemetry to this CSV file.
+    pub log_file: Option<String>,
+    /// Also store parsed telemetry in this SQLite database (`sqlite` feature).
+    pub db_file: Option<String>,
+    /// Append every arm, disarm and emergency stop to this file (see `arm_audit`).
+    pub audit_log: Option<String>,
+    /// Save the telemetry here on shutdown and show it at startup until the first sample.
+    pub state_file: Option<String>,
+    /// Number of samples kept for GET /telemetry/history.
+    pub ring_buffer_size: usize,
+    /// How the server's own log lines are written.
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            log_file: None,
+            db_file: None,
+            audit_log: None,
+            state_file: None,
+            ring_buffer_size: DEFAULT_HISTORY_CAPACITY,
+            format: LogFormat::Pretty,
+        }
+    }
+}
+
+/// `[relay]`: telemetry forwarded to other machines (see `udp_relay`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct RelayConfig {
+    /// `host:port` destinations every parsed sample is sent to as a JSON datagram.
+    pub udp_out: Vec<String>,
+}
+
+/// `[cluster]`: the other servers of a high-availability pair (see `cluster`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// `host:port` of each peer's HTTP server (and heartbeat socket). Empty: standalone.
+    pub peers: Vec<String>,
+}
+
+/// `[telemetry]`: how telemetry lines are parsed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// `[telemetry.format]`: a custom line format instead of the built-in one.
+    pub format: Option<LineFormatConfig>,
+}
+
+/// `[telemetry.format]`: see `LineFormat`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct LineFormatConfig {
+    /// A regular expression (see `regex`) with a named group per field.
+    pub regex: String,
+}
+
+/// `[can]`: how frames map onto telemetry and commands with `[serial] can`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct CanConfig {
+    /// Frame ID commands are sent on as text, 8 bytes per frame; commands fail without it.
+    pub command_id: Option<u32>,
+    /// Frame ID whose payload is text from the board (ACKs), read as if from serial.
+    pub text_id: Option<u32>,
+    /// `[[can.signal]]` sections.
+    #[serde(rename = "signal")]
+    pub signals: Vec<CanSignal>,
+}
+
+/// `[[can.signal]]`: where one telemetry field sits in a frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", deny_unknown_fields)]
+pub struct CanSignal {
+    /// The frame ID; above 0x7FF means an extended (29-bit) ID.
+    pub id: u32,
+    pub field: CanField,
+    /// The field's first byte in the frame's data.
+    #[serde(default)]
+    pub offset: usize,
+    /// Multiplies the raw value of `battery` and `arming`.
+    #[serde(default = "unit_scale")]
+    pub scale: f32,
+}
+
+fn unit_scale() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum CanField {
+    /// 32-bit little-endian milliseconds; without it, milliseconds since the link opened.
+    Timestamp,
+    /// One byte, nonzero when armed.
+    Armed,
+    /// 16-bit little-endian, times `scale`.
+    Battery,
+    /// 16-bit little-endian, times `scale`.
+    Arming,
+    /// 16-bit little-endian mask, bit 0 = channel 1.
+    Solenoids,
+}
+
+impl CanField {
+    /// How many bytes of the frame the field takes.
+    pub fn width(self) -> usize {
+        match self {
+            CanField::Armed => 1,
+            CanField::Battery | CanField::Arming | CanField::Solenoids => 2,
+            CanField::Timestamp => 4,
+        }
+    }
+}
+
+/// `[diagram]`: the piping layout of GET /diagram (see `diagram`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct DiagramConfig {
+    /// `[x, y]` of each valve keyed by channel, e.g. `7 = [120, 40]`. Channels not listed
+    /// keep their place in the default 4x4 grid.
+    pub positions: HashMap<String, [f32; 2]>,
+    /// Pairs of channels joined by a pipe.
+    pub pipes: Vec<[u8; 2]>,
+}
+
+/// `[countdown]`: what happens at T-0 of a countdown (see `countdown`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct CountdownConfig {
+    /// Arm the system at T-0.
+    pub arm_at_t0: bool,
+    /// Solenoids energized at T-0, after the arm.
+    pub open_at_t0: Vec<u8>,
+}
+
+/// Largest extended (29-bit) CAN ID.
+const MAX_CAN_ID: u32 = 0x1FFF_FFFF;
+
+/// `[safety]`: command interlocks.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    /// Refuse solenoid commands (403 SYSTEM_NOT_ARMED) while the system reports disarmed.
+    pub require_armed_for_solenoid: bool,
+    /// Refuse (429 RATE_LIMITED) a solenoid command within this long of the previous one
+    /// for the same channel; 0 disables the limit.
+    pub min_interval_ms: u64,
+    /// Disarm when no telemetry has arrived for this many seconds; 0 disables the watchdog.
+    pub watchdog_timeout_s: u64,
+    /// Refuse single-step POST /arm (403 TWO_STEP_ARM_REQUIRED); arming then takes POST
+    /// /arm/intent followed by POST /arm/confirm. Sequences that arm are refused too (409
+    /// SEQUENCE_ARM_REFUSED).
+    pub require_two_step: bool,
+    /// How long after POST /arm/intent its token is accepted by POST /arm/confirm.
+    pub arm_confirm_window_ms: u64,
+    /// Refuse (429 DUTY_CYCLE_EXCEEDED) switching on a solenoid that was on for more than
+    /// this share of the last minute; 100 disables the limit.
+    pub max_duty_pct: f32,
+    /// Raise GET /alerts/battery_drain when the battery falls faster than this many volts
+    /// per second; 0 disables the check.
+    pub max_drain_rate_v_per_s: f32,
+    /// How long telemetry has to show the effect of a solenoid command before it counts as
+    /// failed (GET /verifier/failures); 0 disables the check.
+    pub verify_timeout_ms: u64,
+    /// Refuse POST /solenoid/all/open|close (403 SYSTEM_NOT_ARMED) while the system
+    /// reports disarmed. On by default; they skip every other check.
+    pub require_armed_for_all: bool,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            require_armed_for_solenoid: false,
+            min_interval_ms: DEFAULT_MIN_INTERVAL_MS,
+            watchdog_timeout_s: DEFAULT_WATCHDOG_TIMEOUT_S,
+            require_two_step: false,
+            arm_confirm_window_ms: DEFAULT_ARM_CONFIRM_WINDOW_MS,
+            max_duty_pct: DEFAULT_MAX_DUTY_PCT,
+            max_drain_rate_v_per_s: DEFAULT_MAX_DRAIN_RATE_V_PER_S,
+            verify_timeout_ms: DEFAULT_VERIFY_TIMEOUT_MS,
+            require_armed_for_all: true,
+        }
+    }
+}
+
+/// `[filters]`: smoothing of the voltage readings.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Samples in the battery and arming sense rolling means (1 disables smoothing).
+    pub battery_window: usize,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            battery_window: DEFAULT_FILTER_WINDOW,
+        }
+    }
+}
+
+/// `[webhook]`: low-battery alerts (only sent by builds with the `webhook` feature).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// `http://` URL to POST alerts to; no alerts without one.
+    pub url: Option<String>,
+    /// Alert when the (filtered) battery voltage drops below this.
+    pub low_battery_threshold: f32,
+    /// Volts above the threshold the battery must recover by before alerting again.
+    pub hysteresis: f32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: None,
+            low_battery_threshold: 11.1,
+            hysteresis: 1.0,
+        }
+    }
+}
+
+/// `[cors]`: browser access from other origins.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, e.g. "http://localhost:3000"; "*" allows any.
+    pub allowed_origins: Vec<String>,
+}
+
+/// `[rate_limit]`: the per-client limit on POST requests (see `request_limit`).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// POSTs a client can make at once; 0 disables the limit.
+    pub burst: u32,
+    /// POSTs per second a client regains, up to `burst`.
+    pub refill_per_s: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            burst: DEFAULT_BURST,
+            refill_per_s: DEFAULT_REFILL_PER_S,
+        }
+    }
+}
+
+/// `[auth]`: request signing for the POST endpoints (see `auth`) and HTTP basic auth for
+/// everything (see `basic_auth`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// The shared secret; `GCS_SECRET` overrides it. Never printed with the resolved config.
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    /// Basic auth username; requires `password_hash`.
+    pub username: Option<String>,
+    /// Argon2id hash of the basic auth password, from `telemetry_server generate-password`.
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+}
+
+impl AuthConfig {
+    /// The basic auth credentials, if configured. `Config::load` has checked the hash.
+    pub fn basic_credentials(&self) -> Option<Credentials> {
+        Some(Credentials {
+            username: self.username.clone()?,
+            password_hash: self.password_hash.as_ref()?.parse().ok()?,
+        })
+    }
+}
+
+impl Config {
+    /// Reads and checks the config file at `path`.
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| format!("'{}': {}", path, e))?;
+        if let Err(errors) = validate_config(&config) {
+            let errors: Vec<_> = errors.iter().map(|e| format!("'{}': {}", path, e)).collect();
+            return Err(errors.join("\n"));
+        }
+        // The first board is the primary one, driven through [serial]; the others default
+        // to its baud rate.
+        for board in config.boards.iter_mut().skip(1) {
+            board.baud.get_or_insert(config.serial.baud);
+        }
+        if let Some(primary) = config.boards.first() {
+            config.serial.port = primary.port.clone();
+            config.serial.baud = primary.baud.unwrap_or(config.serial.baud);
+        }
+        Ok(config)
+    }
+}
+
+/// One problem found by `validate_config`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Runs every check on a parsed config, including those across sections, and reports all the
+/// problems found rather than just the first.
+pub fn validate_config(config: &Config) -> Result<(), Vec<ConfigError>> {
+    let mut errors = Vec::new();
+    let mut error = |message: String| errors.push(ConfigError(message));
+    if !SUPPORTED_BAUD_RATES.contains(&config.serial.baud) {
+        error(format!("unsupported baud rate {}", config.serial.baud));
+    }
+    if config.serial.reconnect_threshold == 0 {
+        error("reconnect_threshold must be positive".to_string());
+    }
+    let refill_per_s = config.rate_limit.refill_per_s;
+    if config.rate_limit.burst > 0 && !(refill_per_s.is_finite() && refill_per_s > 0.0) {
+        error("rate_limit: refill_per_s must be positive".to_string());
+    }
+    let max_duty_pct = config.safety.max_duty_pct;
+    if !(max_duty_pct > 0.0 && max_duty_pct <= 100.0) {
+        error("max_duty_pct must be above 0 and at most 100".to_string());
+    }
+    let max_drain_rate = config.safety.max_drain_rate_v_per_s;
+    if !(max_drain_rate.is_finite() && max_drain_rate >= 0.0) {
+        error("max_drain_rate_v_per_s must be 0 or more".to_string());
+    }
+    if config.safety.require_two_step && config.safety.arm_confirm_window_ms == 0 {
+        error("require_two_step needs a positive arm_confirm_window_ms".to_string());
+    }
+    if config.filters.battery_window == 0 {
+        error("battery_window must be positive".to_string());
+    }
+    if let Some(format) = &config.telemetry.format {
+        if let Err(e) = LineFormat::new(&format.regex) {
+            error(format!("telemetry.format: {}", e));
+        }
+    }
+    let channels = [
+        ("solenoid_labels", config.solenoid_labels.keys().collect::<Vec<_>>()),
+        ("solenoid_directions", config.solenoid_directions.keys().collect()),
+        ("solenoid_max_open_ms", config.solenoid_max_open_ms.keys().collect()),
+        ("diagram.positions", config.diagram.positions.keys().collect()),
+    ];
+    for (table, keys) in channels {
+        for channel in keys {
+            if !matches!(channel.parse::<u8>(), Ok(1..=16)) {
+                error(format!("{}: '{}' is not a channel (1-16)", table, channel));
+            }
+        }
+    }
+    let mut max_open_ms: Vec<_> = config.solenoid_max_open_ms.iter().collect();
+    max_open_ms.sort();
+    for (channel, &ms) in max_open_ms {
+        if ms == 0 {
+            error(format!("solenoid_max_open_ms: {} must be positive", channel));
+        } else if ms < config.safety.min_interval_ms {
+            // Closing the solenoid by hand would be rate limited until after the auto-close.
+            error(format!(
+                "solenoid_max_open_ms: {} is {} ms, shorter than min_interval_ms ({} ms)",
+                channel, ms, config.safety.min_interval_ms
+            ));
+        }
+    }
+    match (&config.auth.username, &config.auth.password_hash) {
+        (Some(_), Some(hash)) => {
+            if let Err(e) = hash.parse::<PasswordHash>() {
+                error(format!("auth: password_hash: {}", e));
+            }
+        }
+        (None, None) => {}
+        _ => error("auth: username and password_hash go together".to_string()),
+    }
+    for (i, board) in config.boards.iter().enumerate() {
+        if config.boards[..i].iter().any(|other| other.id == board.id) {
+            error(format!("duplicate board id {}", board.id));
+        }
+        if let Some(baud) = board.baud.filter(|baud| !SUPPORTED_BAUD_RATES.contains(baud)) {
+            error(format!("board {}: unsupported baud rate {}", board.id, baud));
+        }
+    }
+    for (i, group) in config.groups.iter().enumerate() {
+        if let Err(e) = group.validate() {
+            error(e);
+        }
+        if config.groups[..i].iter().any(|other| other.name == group.name) {
+            error(format!("duplicate group '{}'", group.name));
+        }
+    }
+    for interlock in &config.interlocks {
+        if let Err(e) = interlock.validate() {
+            error(e);
+        }
+    }
+    for alert in config.alerts.iter().filter(|alert| !alert.threshold.is_finite()) {
+        error(format!("alert on {:?}: threshold must be finite", alert.field));
+    }
+    for (channel, xy) in &config.diagram.positions {
+        if !xy.iter().all(|v| v.is_finite()) {
+            error(format!("diagram.positions: {} must be finite", channel));
+        }
+    }
+    for pipe in &config.diagram.pipes {
+        if let Some(ch) = pipe.iter().find(|ch| !(1..=16).contains(*ch)) {
+            error(format!("diagram.pipes: {} is not a channel (1-16)", ch));
+        }
+    }
+    for ch in config.countdown.open_at_t0.iter().filter(|ch| !(1..=16).contains(*ch)) {
+        error(format!("countdown.open_at_t0: {} is not a channel (1-16)", ch));
+    }
+    let can = &config.can;
+    for id in can.command_id.iter().chain(&can.text_id).filter(|&&id| id > MAX_CAN_ID) {
+        error(format!("can: {:#x} is not a CAN ID", id));
+    }
+    for signal in &can.signals {
+        if signal.id > MAX_CAN_ID {
+            error(format!("can.signal: {:#x} is not a CAN ID", signal.id));
+        }
+        if signal.offset + signal.field.width() > 8 {
+            error(format!(
+                "can.signal {:#x}: {:?} at offset {} does not fit in 8 bytes",
+                signal.id, signal.field, signal.offset
+            ));
+        }
+        if !signal.scale.is_finite() {
+            error(format!("can.signal {:#x}: scale must be finite", signal.id));
+        }
+        if Some(signal.id) == can.text_id {
+            error(format!("can.signal {:#x}: that is the text_id", signal.id));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Whether a valve is open or closed while its solenoid is de-energized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum SolenoidDirection {
+    /// Open at rest; energizing the solenoid closes it.
+    NormallyOpen,
+    /// Closed at rest; energizing the solenoid opens it.
+    #[default]
+    NormallyClosed,
+}
+
+impl SolenoidDirection {
+    /// Whether the valve is physically open while its solenoid is `energized`.
+    pub fn is_open(self, energized: bool) -> bool {
+        energized ^ (self == SolenoidDirection::NormallyOpen)
+    }
+
+    /// Whether the solenoid must be energized for the valve to be `open`.
+    pub fn energized(self, open: bool) -> bool {
+        open ^ (self == SolenoidDirection::NormallyOpen)
+    }
+}
+
+/// How a solenoid is presented: its display name and valve direction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SolenoidInfo {
+    pub label: String,
+    pub direction: SolenoidDirection,
+}
+
+/// Labels and directions for all 16 solenoids: the configured ones, and "Solenoid N" and
+/// normally closed for the rest. Both maps have been validated by `Config::load`.
+pub fn solenoid_info(
+    labels: &HashMap<String, String>,
+    directions: &HashMap<String, SolenoidDirection>,
+) -> HashMap<u8, SolenoidInfo> {
+    (1..=16u8)
+        .map(|channel| {
+            let key = channel.to_string();
+            let label = labels
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| format!("Solenoid {}", channel));
+            let direction = directions.get(&key).copied().unwrap_or_default();
+            (channel, SolenoidInfo { label, direction })
+        })
+        .collect()
+}
+
+/// The open-duration limit of each solenoid (index = channel - 1). `configured` has been
+/// validated by `Config::load`.
+pub fn solenoid_max_open(configured: &HashMap<String, u64>) -> [Option<Duration>; 16] {
+    let mut limits = [None; 16];
+    for (channel, &ms) in configured {
+        if let Ok(channel @ 1..=16) = channel.parse::<usize>() {
+            limits[channel - 1] = Some(Duration::from_millis(ms));
+        }
+    }
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_bad_groups() {
+        let group = |name: &str, channels: Vec<u8>| SolenoidGroup {
+            name: name.to_string(),
+            channels,
+        };
+        assert!(group("purge", vec![3, 4]).validate().is_ok());
+        assert!(group("", vec![3]).validate().is_err());
+        assert!(group("purge", vec![]).validate().is_err());
+        assert!(group("purge", vec![3, 17]).validate().is_err());
+        assert!(group("purge", vec![3, 3]).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_interlocks() {
+        let config: Config = toml::from_str("[[interlock]]\nprevent = [3, 7]").unwrap();
+        assert_eq!(config.interlocks[0].prevent, [3, 7]);
+        assert!(config.interlocks[0].validate().is_ok());
+        let interlock = |prevent: Vec<u8>| Interlock { prevent };
+        assert!(interlock(vec![3, 7, 9]).validate().is_err());
+        assert!(interlock(vec![3]).validate().is_err());
+        assert!(interlock(vec![3, 17]).validate().is_err());
+        assert!(interlock(vec![0, 3]).validate().is_err());
+        assert!(interlock(vec![3, 3]).validate().is_err());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let text = "[safety]\nmin_interval_ms = 500\n\
+                    [filters]\nbattery_window = 0\n\
+                    [solenoid_max_open_ms]\n7 = 200\n\
+                    [[interlock]]\nprevent = [3, 17]";
+        let config: Config = toml::from_str(text).unwrap();
+        let errors = validate_config(&config).unwrap_err();
+        let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            [
+                "battery_window must be positive",
+                "solenoid_max_open_ms: 7 is 200 ms, shorter than min_interval_ms (500 ms)",
+                "interlock: 17 is not a channel (1-16)",
+            ]
+        );
+        assert_eq!(validate_config(&Config::default()), Ok(()));
+    }
+
+    #[test]
+    fn direction_maps_electrical_to_physical_state() {
+        let text = "[solenoid_directions]\n3 = \"normally_open\"";
+        let config: Config = toml::from_str(text).unwrap();
+        let info = solenoid_info(&config.solenoid_labels, &config.solenoid_directions);
+        assert_eq!(info[&3].direction, SolenoidDirection::NormallyOpen);
+        assert_eq!(info[&4].direction, SolenoidDirection::NormallyClosed);
+        assert!(SolenoidDirection::NormallyOpen.is_open(false));
+        assert!(!SolenoidDirection::NormallyOpen.is_open(true));
+        assert!(SolenoidDirection::NormallyClosed.is_open(true));
+        assert!(!SolenoidDirection::NormallyClosed.is_open(false));
+        assert!(!SolenoidDirection::NormallyOpen.energized(true));
+    }
+}
@@ -0,0 +1,94 @@
+// src/continuity.rs
+
+//! Pre-launch wiring checks (POST /test/solenoid/<channel>): energize one solenoid, confirm
+//! from telemetry that the board switched it, de-energize it and confirm again. A channel
+//! whose state never changes points at a broken or swapped connection.
+//!
+//! Only allowed while armed, since the board switches nothing otherwise, and while telemetry
+//! shows the solenoid off, since otherwise no change could be seen. The energize is
+//! checked like POST /solenoid (interlocks, duty cycle, rate limit); the release is sent
+//! regardless, like the close of a pulse.
+
+use std::time::{Duration, Instant};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::tokio::time::sleep;
+use rocket::State;
+
+use crate::auth::Authenticated;
+use crate::command::CommandBuilder;
+use crate::error::ApiError;
+use crate::latency::RequestStart;
+use crate::{AppState, QueuedCommand};
+
+/// How long telemetry has to show each change: a couple of samples of the firmware's
+/// 100 ms stream, so a change applied at once is seen even between samples.
+const CONFIRM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How often telemetry is looked at while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Response body for POST /test/solenoid/<channel>.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SolenoidTestResult {
+    pub channel: u8,
+    /// Telemetry showed the solenoid on within `CONFIRM_TIMEOUT` of the energize command.
+    pub open_confirmed: bool,
+    /// After being confirmed on, telemetry showed it off again within `CONFIRM_TIMEOUT` of
+    /// the release.
+    pub close_confirmed: bool,
+    /// From queuing the energize command until telemetry showed it; `None` if it never did.
+    pub latency_ms: Option<u64>,
+}
+
+/// Waits up to `CONFIRM_TIMEOUT` for telemetry to report `channel` (validated) `on`.
+/// Returns how long that took.
+async fn confirm(state: &AppState, channel: u8, on: bool) -> Option<Duration> {
+    let started = Instant::now();
+    let index = usize::from(channel) - 1;
+    loop {
+        if state.telemetry.read().unwrap().solenoids.get(index) == Some(&on) {
+            return Some(started.elapsed());
+        }
+        if started.elapsed() >= CONFIRM_TIMEOUT {
+            return None;
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// POST /test/solenoid/<channel> energizes the solenoid, waits for telemetry to confirm
+/// it, releases it and waits for that too. 403 SYSTEM_NOT_ARMED while disarmed, 409
+/// SOLENOID_ALREADY_ON if telemetry already shows it on.
+#[post("/test/solenoid/<channel>")]
+pub async fn test_solenoid(
+    channel: u8,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<Json<SolenoidTestResult>, ApiError> {
+    let on = CommandBuilder::new().solenoid(channel, true)?.build_joined();
+    let off = CommandBuilder::new().solenoid(channel, false)?.build_joined();
+    {
+        let telemetry = state.telemetry.read().unwrap();
+        if !telemetry.armed {
+            return Err(ApiError::SystemNotArmed);
+        }
+        if telemetry.solenoids.get(usize::from(channel) - 1) != Some(&false) {
+            return Err(ApiError::SolenoidAlreadyOn(channel));
+        }
+    }
+    state.send_solenoid_command(QueuedCommand::new(on, start), &[channel])?;
+    let latency = confirm(state, channel, true).await;
+    // Straight to the queue: the rate limit would refuse a second command this soon.
+    let off = QueuedCommand { session: start.session, ..QueuedCommand::immediate(off) };
+    state.send_command(off)?;
+    let close_confirmed = latency.is_some() && confirm(state, channel, false).await.is_some();
+    Ok(Json(SolenoidTestResult {
+        channel,
+        open_confirmed: latency.is_some(),
+        close_confirmed,
+        latency_ms: latency.map(|latency| latency.as_millis() as u64),
+    }))
+}
@@ -0,0 +1,55 @@
+// src/cors.rs
+
+//! CORS headers so dashboards served from another origin (e.g. a React dev server on
+//! localhost:3000) can call the API from the browser.
+//!
+//! Only origins listed in `[cors] allowed_origins` get the headers; `"*"` allows any origin.
+//! With an empty list (the default) responses are unchanged.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::{Request, Response};
+
+const ALLOWED_METHODS: &str = "GET, POST, DELETE, OPTIONS";
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, X-GCS-Signature";
+
+/// Adds the `Access-Control-Allow-*` headers to every response for an allowed origin.
+pub struct CorsFairing {
+    allowed_origins: Vec<String>,
+}
+
+impl CorsFairing {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        CorsFairing { allowed_origins }
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for CorsFairing {
+    fn info(&self) -> Info {
+        Info { name: "CORS", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(origin) = req.headers().get_one("Origin") else {
+            return;
+        };
+        if !self.allows(origin) {
+            return;
+        }
+        res.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        res.set_header(Header::new("Vary", "Origin"));
+        res.set_header(Header::new("Access-Control-Allow-Methods", ALLOWED_METHODS));
+        res.set_header(Header::new("Access-Control-Allow-Headers", ALLOWED_HEADERS));
+    }
+}
+
+/// OPTIONS /<any path> answers CORS preflight requests; the fairing adds the headers.
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
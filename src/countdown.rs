@@ -0,0 +1,311 @@
+// src/countdown.rs
+
+//! The launch countdown: POST /countdown/start counts down from T-minus `t_minus_s`, GET
+//! /countdown reports it (the page shows it as a large T-minus clock) and POST
+//! /countdown/abort stops it.
+//!
+//! At T-0 the `[countdown]` commands go out: an arm if `arm_at_t0`, through the arm lifecycle
+//! and audit like POST /arm, then the solenoids in `open_at_t0` in one write, checked against
+//! the interlocks and duty cycles like a sequence step. If either is refused, nothing more is
+//! sent and the refusal is logged. With `require_two_step` a countdown that arms cannot be
+//! started at all. POST /emergency_stop, POST /disarm and the telemetry watchdog abort the
+//! countdown. After T-0 the countdown stays expired, counting up (negative `remaining_s`),
+//! until it is aborted or a new one started.
+
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
+use tracing::{error, info, warn};
+
+use crate::auth::{Authenticated, SignedJson};
+use crate::command::CommandBuilder;
+use crate::command_queue::CommandSender;
+use crate::config::CountdownConfig;
+use crate::error::ApiError;
+use crate::interlock::SolenoidGuard;
+use crate::{AppState, ArmPath, QueuedCommand};
+
+/// The longest countdown POST /countdown/start accepts: a day.
+pub const MAX_T_MINUS_S: u64 = 24 * 60 * 60;
+
+/// Request body for POST /countdown/start.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CountdownRequest {
+    pub t_minus_s: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum CountdownState {
+    Idle,
+    Running,
+    /// T-0 has passed.
+    Expired,
+}
+
+/// Response body of the countdown endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CountdownStatus {
+    /// Whole seconds to T-0, rounded up; negative after it, 0 while idle.
+    pub remaining_s: i64,
+    pub state: CountdownState,
+}
+
+struct Running {
+    countdown_start: Instant,
+    countdown_duration: Duration,
+    /// Wakes the countdown's thread to abort it.
+    abort_tx: mpsc::Sender<()>,
+}
+
+/// The current countdown, if any, and what to send at T-0.
+#[derive(Default)]
+pub struct Countdown {
+    current: Mutex<Option<Running>>,
+    arm_at_t0: bool,
+    /// Newline-separated; empty when `[countdown]` opens nothing.
+    open_at_t0: String,
+}
+
+impl Countdown {
+    pub fn new(config: &CountdownConfig) -> Self {
+        let mut builder = CommandBuilder::new();
+        for &channel in &config.open_at_t0 {
+            // Validated by `Config::load`.
+            builder.solenoid(channel, true).expect("valid channel");
+        }
+        Countdown {
+            current: Mutex::new(None),
+            arm_at_t0: config.arm_at_t0,
+            open_at_t0: builder.build_joined(),
+        }
+    }
+
+    /// Starts counting down `duration` (at most `MAX_T_MINUS_S`) from `now`; at T-0 the
+    /// board is armed through `arm` and the valves are checked with `guard` and queued on
+    /// `command_tx`. Refused while another countdown is running.
+    pub fn start(
+        &self,
+        duration: Duration,
+        now: Instant,
+        command_tx: CommandSender,
+        guard: SolenoidGuard,
+        arm: ArmPath,
+    ) -> Result<CountdownStatus, ApiError> {
+        if duration.as_secs() > MAX_T_MINUS_S {
+            return Err(ApiError::InvalidCountdown(duration.as_secs()));
+        }
+        if self.arm_at_t0 && arm.require_two_step {
+            return Err(ApiError::TwoStepArmRequired);
+        }
+        let mut current = self.current.lock().unwrap();
+        if status_of(current.as_ref(), now).state == CountdownState::Running {
+            return Err(ApiError::CountdownAlreadyRunning);
+        }
+        let (abort_tx, abort_rx) = mpsc::channel();
+        let arm_at_t0 = self.arm_at_t0;
+        let at_t0 = self.open_at_t0.clone();
+        thread::spawn(move || {
+            // Aborted, or replaced by a new countdown (which drops `abort_tx`).
+            if abort_rx.recv_timeout(duration) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return;
+            }
+            if arm_at_t0 {
+                if let Err(e) = arm.arm(&command_tx) {
+                    let error = e.body();
+                    error!(%error, "Countdown reached T-0 but could not arm; nothing sent");
+                    return;
+                }
+                warn!("Countdown reached T-0; armed");
+            }
+            if at_t0.is_empty() {
+                info!("Countdown reached T-0");
+            } else if let Err(e) = guard.check(&at_t0) {
+                let error = e.body();
+                error!(commands = ?at_t0, %error, "Countdown reached T-0; commands refused");
+            } else if command_tx
+                .send(QueuedCommand::immediate(at_t0.as_str()))
+                .is_ok()
+            {
+                warn!(commands = ?at_t0, "Countdown reached T-0; commands sent");
+            } else {
+                error!(commands = ?at_t0, "Countdown reached T-0 but could not queue commands");
+            }
+        });
+        *current = Some(Running {
+            countdown_start: now,
+            countdown_duration: duration,
+            abort_tx,
+        });
+        Ok(status_of(current.as_ref(), now))
+    }
+
+    /// Stops the countdown (before T-0 nothing is sent) and goes back to idle.
+    pub fn abort(&self) -> CountdownStatus {
+        if let Some(running) = self.current.lock().unwrap().take() {
+            let _ = running.abort_tx.send(());
+        }
+        status_of(None, Instant::now())
+    }
+
+    pub fn status(&self, now: Instant) -> CountdownStatus {
+        status_of(self.current.lock().unwrap().as_ref(), now)
+    }
+}
+
+fn status_of(current: Option<&Running>, now: Instant) -> CountdownStatus {
+    let Some(running) = current else {
+        return CountdownStatus {
+            remaining_s: 0,
+            state: CountdownState::Idle,
+        };
+    };
+    let t0 = running.countdown_start + running.countdown_duration;
+    if now < t0 {
+        let remaining = t0 - now;
+        let whole = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        CountdownStatus {
+            remaining_s: whole as i64,
+            state: CountdownState::Running,
+        }
+    } else {
+        CountdownStatus {
+            remaining_s: -((now - t0).as_secs() as i64),
+            state: CountdownState::Expired,
+        }
+    }
+}
+
+/// GET /countdown reports the countdown.
+#[get("/countdown")]
+pub fn get(state: &State<AppState>) -> Json<CountdownStatus> {
+    Json(state.countdown.status(Instant::now()))
+}
+
+/// POST /countdown/start starts a countdown from T-minus `t_minus_s` (at most
+/// `MAX_T_MINUS_S`, else 400). 409 COUNTDOWN_ALREADY_RUNNING while one is running; abort it
+/// first. 403 TWO_STEP_ARM_REQUIRED if it would arm at T-0 under `require_two_step`.
+#[post("/countdown/start", data = "<request>")]
+pub fn start(
+    request: SignedJson<CountdownRequest>,
+    state: &State<AppState>,
+) -> Result<Json<CountdownStatus>, ApiError> {
+    let duration = Duration::from_secs(request.t_minus_s);
+    let status = state.countdown.start(
+        duration,
+        Instant::now(),
+        state.command_tx.clone(),
+        state.solenoid_guard(),
+        state.arm_path(),
+    )?;
+    info!(t_minus_s = request.t_minus_s, "Countdown started");
+    Ok(Json(status))
+}
+
+/// POST /countdown/abort stops the countdown and resets it to idle.
+#[post("/countdown/abort")]
+pub fn abort(_auth: Authenticated, state: &State<AppState>) -> Json<CountdownStatus> {
+    let status = state.countdown.abort();
+    info!("Countdown aborted");
+    Json(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_queue;
+    use crate::state_machine::ArmState;
+
+    fn countdown() -> Countdown {
+        Countdown::new(&CountdownConfig {
+            arm_at_t0: true,
+            open_at_t0: vec![3, 7],
+        })
+    }
+
+    #[test]
+    fn countdown_sends_the_commands_at_t0() {
+        let countdown = countdown();
+        let (command_tx, command_rx) = command_queue::channel();
+        let arm = ArmPath::default();
+        let start_at = |duration, now| {
+            let guard = SolenoidGuard::default();
+            countdown.start(duration, now, command_tx.clone(), guard, arm.clone())
+        };
+        let start = Instant::now();
+        let status = start_at(Duration::from_millis(300), start).unwrap();
+        assert_eq!(status.remaining_s, 1);
+        assert_eq!(status.state, CountdownState::Running);
+        assert!(start_at(Duration::from_secs(1), start).is_err());
+        let later = countdown.status(start + Duration::from_millis(2400));
+        assert_eq!(later.remaining_s, -2);
+        assert_eq!(later.state, CountdownState::Expired);
+
+        let mut sent = Vec::new();
+        while sent.len() < 2 {
+            if let Ok(cmd) = command_rx.try_recv() {
+                sent.push(cmd.text);
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "nothing sent at T-0"
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(sent, ["a", "s31\ns71"]);
+        // The arm went through the lifecycle and the audit, like POST /arm.
+        assert_eq!(
+            *arm.arm_state.lock().unwrap(),
+            ArmState::Armed { confirmed: false }
+        );
+        assert_eq!(arm.arm_audit.lock().unwrap().events().len(), 1);
+
+        // Aborted before T-0, nothing is sent.
+        start_at(Duration::from_millis(50), Instant::now()).unwrap();
+        assert_eq!(countdown.abort().state, CountdownState::Idle);
+        thread::sleep(Duration::from_millis(150));
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn nothing_is_sent_when_the_arm_is_refused() {
+        let countdown = countdown();
+        let (command_tx, command_rx) = command_queue::channel();
+        let arm = ArmPath::default();
+        // Armed already, so the lifecycle refuses another arm.
+        *arm.arm_state.lock().unwrap() = ArmState::Armed { confirmed: true };
+        let guard = SolenoidGuard::default();
+        let now = Instant::now();
+        countdown
+            .start(Duration::ZERO, now, command_tx, guard, arm)
+            .unwrap();
+        thread::sleep(Duration::from_millis(150));
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn overlong_and_two_step_countdowns_are_refused() {
+        let countdown = countdown();
+        let (command_tx, _command_rx) = command_queue::channel();
+        let start = |t_minus_s, arm: ArmPath| {
+            let guard = SolenoidGuard::default();
+            let duration = Duration::from_secs(t_minus_s);
+            countdown.start(duration, Instant::now(), command_tx.clone(), guard, arm)
+        };
+        assert_eq!(
+            start(u64::MAX, ArmPath::default()),
+            Err(ApiError::InvalidCountdown(u64::MAX))
+        );
+        let two_step = ArmPath {
+            require_two_step: true,
+            ..ArmPath::default()
+        };
+        assert_eq!(start(10, two_step), Err(ApiError::TwoStepArmRequired));
+        assert_eq!(countdown.status(Instant::now()).state, CountdownState::Idle);
+    }
+}
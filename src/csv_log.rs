@@ -0,0 +1,93 @@
+// src/csv_log.rs
+
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Telemetry;
+
+/// A CSV log shared between the serial loop (which writes) and the rest of the server.
+pub type SharedCsvLog = Arc<Mutex<CsvLog>>;
+
+/// Appends every parsed telemetry sample to a CSV file.
+///
+/// Each row is prefixed with a wall-clock timestamp (milliseconds since the Unix epoch) so
+/// the Arduino's own `timestamp` can be lined up against real time during post-flight analysis.
+/// Booleans are written as `0`/`1` so spreadsheet tools can do arithmetic on them.
+pub struct CsvLog {
+    writer: BufWriter<File>,
+}
+
+impl CsvLog {
+    /// Opens (or creates) the file at `path` in append mode.
+    /// The header row is only written when the file is empty, so restarting the server
+    /// keeps adding to the same table.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut log = CsvLog { writer: BufWriter::new(file) };
+        if is_empty {
+            log.write_header()?;
+        }
+        Ok(log)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        write!(self.writer, "wall_clock_ms,timestamp,armed,battery,arming")?;
+        for ch in 1..=16 {
+            write!(self.writer, ",sol{}", ch)?;
+        }
+        for ch in 1..=4 {
+            write!(self.writer, ",pyro{}", ch)?;
+        }
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+
+    /// Writes one telemetry sample and flushes it to disk immediately.
+    pub fn write_record(&mut self, tel: &Telemetry) -> io::Result<()> {
+        let wall_clock_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        write!(
+            self.writer,
+            "{},{},{},{},{}",
+            wall_clock_ms,
+            tel.timestamp,
+            tel.armed as u8,
+            tel.battery,
+            tel.arming
+        )?;
+        for &flag in tel.solenoids.iter().chain(&tel.pyro_continuity) {
+            write!(self.writer, ",{}", flag as u8)?;
+        }
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+/// Renders `samples` as the CSV body of GET /telemetry/csv: a
+/// `timestamp,armed,battery,arming,sol1,...,sol16` header, then one row per sample.
+/// Booleans are `0`/`1`, as in the log.
+pub fn history_csv(samples: &[Telemetry]) -> String {
+    let mut csv = String::from("timestamp,armed,battery,arming");
+    for ch in 1..=16 {
+        let _ = write!(csv, ",sol{}", ch);
+    }
+    csv.push('\n');
+    for tel in samples {
+        let _ = write!(
+            csv,
+            "{},{},{},{}",
+            tel.timestamp, tel.armed as u8, tel.battery, tel.arming
+        );
+        for &open in &tel.solenoids {
+            let _ = write!(csv, ",{}", open as u8);
+        }
+        csv.push('\n');
+    }
+    csv
+}
@@ -0,0 +1,449 @@
+// src/db.rs
+
+//! Telemetry storage in SQLite (`sqlite` feature, `--db <path>`).
+//!
+//! Every parsed sample is handed to a background thread that owns its own connection and
+//! inserts it into the `telemetry` table, so a slow disk never stalls the serial reader.
+//! GET /db/query runs read-only SELECT queries on a separate read-only connection.
+//!
+//! The bindings below are a minimal hand-written FFI layer over the system `libsqlite3`,
+//! covering only what this module needs.
+
+use std::ffi::{c_char, c_double, c_int, CStr, CString};
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::Serialize;
+use rocket::State;
+use tracing::error;
+
+use crate::{AppState, Telemetry};
+
+/// Most rows GET /db/query returns; add a LIMIT for paging through more.
+const MAX_QUERY_ROWS: usize = 10_000;
+
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::ffi::{c_char, c_double, c_int, c_void};
+
+    pub enum sqlite3 {}
+    pub enum sqlite3_stmt {}
+
+    pub const SQLITE_OK: c_int = 0;
+    pub const SQLITE_ROW: c_int = 100;
+    pub const SQLITE_DONE: c_int = 101;
+    pub const SQLITE_OPEN_READONLY: c_int = 0x01;
+    pub const SQLITE_OPEN_READWRITE: c_int = 0x02;
+    pub const SQLITE_OPEN_CREATE: c_int = 0x04;
+    pub const SQLITE_INTEGER: c_int = 1;
+    pub const SQLITE_FLOAT: c_int = 2;
+    pub const SQLITE_TEXT: c_int = 3;
+    pub const SQLITE_BLOB: c_int = 4;
+
+    #[link(name = "sqlite3")]
+    extern "C" {
+        pub fn sqlite3_open_v2(
+            filename: *const c_char,
+            db: *mut *mut sqlite3,
+            flags: c_int,
+            vfs: *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_close(db: *mut sqlite3) -> c_int;
+        pub fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+        pub fn sqlite3_busy_timeout(db: *mut sqlite3, ms: c_int) -> c_int;
+        pub fn sqlite3_prepare_v2(
+            db: *mut sqlite3,
+            sql: *const c_char,
+            n_byte: c_int,
+            stmt: *mut *mut sqlite3_stmt,
+            tail: *mut *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_finalize(stmt: *mut sqlite3_stmt) -> c_int;
+        pub fn sqlite3_reset(stmt: *mut sqlite3_stmt) -> c_int;
+        pub fn sqlite3_step(stmt: *mut sqlite3_stmt) -> c_int;
+        pub fn sqlite3_stmt_readonly(stmt: *mut sqlite3_stmt) -> c_int;
+        pub fn sqlite3_bind_int64(stmt: *mut sqlite3_stmt, index: c_int, value: i64) -> c_int;
+        pub fn sqlite3_bind_double(stmt: *mut sqlite3_stmt, index: c_int, value: c_double)
+            -> c_int;
+        pub fn sqlite3_column_count(stmt: *mut sqlite3_stmt) -> c_int;
+        pub fn sqlite3_column_name(stmt: *mut sqlite3_stmt, col: c_int) -> *const c_char;
+        pub fn sqlite3_column_type(stmt: *mut sqlite3_stmt, col: c_int) -> c_int;
+        pub fn sqlite3_column_int64(stmt: *mut sqlite3_stmt, col: c_int) -> i64;
+        pub fn sqlite3_column_double(stmt: *mut sqlite3_stmt, col: c_int) -> c_double;
+        pub fn sqlite3_column_text(stmt: *mut sqlite3_stmt, col: c_int) -> *const u8;
+        pub fn sqlite3_column_blob(stmt: *mut sqlite3_stmt, col: c_int) -> *const c_void;
+        pub fn sqlite3_column_bytes(stmt: *mut sqlite3_stmt, col: c_int) -> c_int;
+    }
+}
+
+/// An open database connection.
+struct Connection {
+    db: *mut ffi::sqlite3,
+}
+
+// A connection is only ever used from one thread at a time (it is moved into the writer
+// thread, or created and dropped within one request).
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(path: &str, flags: c_int) -> Result<Connection, String> {
+        let c_path = CString::new(path).map_err(|_| "path contains a NUL byte".to_string())?;
+        let mut db = ptr::null_mut();
+        // SAFETY: valid NUL-terminated path and out-pointer; a handle is returned even on
+        // failure and is closed by `Connection`'s Drop.
+        let rc = unsafe { ffi::sqlite3_open_v2(c_path.as_ptr(), &mut db, flags, ptr::null()) };
+        let conn = Connection { db };
+        if rc != ffi::SQLITE_OK {
+            return Err(conn.error());
+        }
+        // SAFETY: `db` is an open handle.
+        unsafe { ffi::sqlite3_busy_timeout(db, 1000) };
+        Ok(conn)
+    }
+
+    /// The most recent error message.
+    fn error(&self) -> String {
+        if self.db.is_null() {
+            return "out of memory".to_string();
+        }
+        // SAFETY: sqlite3_errmsg returns a NUL-terminated string owned by the connection.
+        unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.db)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Compiles the first statement in `sql`; also returns what follows it.
+    fn prepare<'a>(&'a self, sql: &'a CStr) -> Result<(Statement<'a>, &'a CStr), String> {
+        let mut stmt = ptr::null_mut();
+        let mut tail: *const c_char = ptr::null();
+        // SAFETY: `sql` is NUL-terminated (-1 length) and outlives the returned tail pointer.
+        let rc =
+            unsafe { ffi::sqlite3_prepare_v2(self.db, sql.as_ptr(), -1, &mut stmt, &mut tail) };
+        if rc != ffi::SQLITE_OK {
+            return Err(self.error());
+        }
+        if stmt.is_null() {
+            return Err("empty statement".to_string());
+        }
+        // SAFETY: `tail` points into `sql`, at or before its terminating NUL.
+        let tail = unsafe { CStr::from_ptr(tail) };
+        Ok((Statement { conn: self, stmt }, tail))
+    }
+
+    /// Runs a statement that returns no rows.
+    fn execute(&self, sql: &str) -> Result<(), String> {
+        let sql = CString::new(sql).map_err(|_| "SQL contains a NUL byte".to_string())?;
+        let (mut stmt, _) = self.prepare(&sql)?;
+        while stmt.step()? {}
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: all statements borrow the connection, so they are finalized already.
+        unsafe { ffi::sqlite3_close(self.db) };
+    }
+}
+
+/// A compiled statement.
+struct Statement<'a> {
+    conn: &'a Connection,
+    stmt: *mut ffi::sqlite3_stmt,
+}
+
+impl Statement<'_> {
+    /// Advances to the next row; `false` when the statement is done.
+    fn step(&mut self) -> Result<bool, String> {
+        // SAFETY: `stmt` is a valid prepared statement.
+        match unsafe { ffi::sqlite3_step(self.stmt) } {
+            ffi::SQLITE_ROW => Ok(true),
+            ffi::SQLITE_DONE => Ok(false),
+            _ => Err(self.conn.error()),
+        }
+    }
+
+    fn reset(&mut self) {
+        // SAFETY: `stmt` is a valid prepared statement.
+        unsafe { ffi::sqlite3_reset(self.stmt) };
+    }
+
+    fn is_readonly(&self) -> bool {
+        // SAFETY: `stmt` is a valid prepared statement.
+        unsafe { ffi::sqlite3_stmt_readonly(self.stmt) != 0 }
+    }
+
+    /// Binds parameter `index` (1-based).
+    fn bind_i64(&mut self, index: c_int, value: i64) -> Result<(), String> {
+        // SAFETY: `stmt` is a valid prepared statement.
+        match unsafe { ffi::sqlite3_bind_int64(self.stmt, index, value) } {
+            ffi::SQLITE_OK => Ok(()),
+            _ => Err(self.conn.error()),
+        }
+    }
+
+    fn bind_f64(&mut self, index: c_int, value: c_double) -> Result<(), String> {
+        // SAFETY: `stmt` is a valid prepared statement.
+        match unsafe { ffi::sqlite3_bind_double(self.stmt, index, value) } {
+            ffi::SQLITE_OK => Ok(()),
+            _ => Err(self.conn.error()),
+        }
+    }
+
+    fn column_names(&self) -> Vec<String> {
+        // SAFETY: `stmt` is valid and `col` is in range; names are NUL-terminated strings
+        // owned by the statement, copied out immediately.
+        unsafe {
+            (0..ffi::sqlite3_column_count(self.stmt))
+                .map(|col| {
+                    let name = ffi::sqlite3_column_name(self.stmt, col);
+                    if name.is_null() {
+                        String::new()
+                    } else {
+                        CStr::from_ptr(name).to_string_lossy().into_owned()
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// The current row as JSON values. Blobs are returned base64-encoded.
+    fn row(&self) -> Vec<Value> {
+        // SAFETY: called after `step` returned a row; `col` is in range, and text/blob
+        // pointers are copied before the statement is stepped again.
+        unsafe {
+            (0..ffi::sqlite3_column_count(self.stmt))
+                .map(|col| match ffi::sqlite3_column_type(self.stmt, col) {
+                    ffi::SQLITE_INTEGER => Value::from(ffi::sqlite3_column_int64(self.stmt, col)),
+                    ffi::SQLITE_FLOAT => Value::from(ffi::sqlite3_column_double(self.stmt, col)),
+                    ffi::SQLITE_TEXT => {
+                        let text = ffi::sqlite3_column_text(self.stmt, col);
+                        let len = ffi::sqlite3_column_bytes(self.stmt, col) as usize;
+                        let bytes = std::slice::from_raw_parts(text, len);
+                        Value::from(String::from_utf8_lossy(bytes).into_owned())
+                    }
+                    ffi::SQLITE_BLOB => {
+                        let blob = ffi::sqlite3_column_blob(self.stmt, col) as *const u8;
+                        let len = ffi::sqlite3_column_bytes(self.stmt, col) as usize;
+                        let bytes = if blob.is_null() {
+                            &[][..]
+                        } else {
+                            std::slice::from_raw_parts(blob, len)
+                        };
+                        Value::from(crate::base64::encode(bytes))
+                    }
+                    _ => Value::Null,
+                })
+                .collect()
+        }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `stmt` is a valid prepared statement, finalized exactly once.
+        unsafe { ffi::sqlite3_finalize(self.stmt) };
+    }
+}
+
+/// The `telemetry` table: a wall-clock column plus one column per `Telemetry` field
+/// (solenoid and pyro flags get one 0/1 column each, like the CSV log).
+fn create_table_sql() -> String {
+    let mut sql = String::from(
+        "CREATE TABLE IF NOT EXISTS telemetry (wall_clock_ms INTEGER NOT NULL, \
+         timestamp INTEGER NOT NULL, armed INTEGER NOT NULL, battery REAL NOT NULL, \
+         battery_raw REAL NOT NULL, arming REAL NOT NULL",
+    );
+    for ch in 1..=16 {
+        sql.push_str(&format!(", sol{} INTEGER NOT NULL", ch));
+    }
+    for ch in 1..=4 {
+        sql.push_str(&format!(", pyro{} INTEGER NOT NULL", ch));
+    }
+    sql.push(')');
+    sql
+}
+
+fn insert_sql() -> String {
+    let placeholders = vec!["?"; 6 + 16 + 4].join(", ");
+    format!("INSERT INTO telemetry VALUES ({})", placeholders)
+}
+
+/// Widens an f32 by its shortest decimal form, so 12.6 is stored as 12.6 rather than
+/// 12.600000381469727.
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+fn insert(stmt: &mut Statement<'_>, tel: &Telemetry) -> Result<(), String> {
+    let wall_clock_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    stmt.reset();
+    stmt.bind_i64(1, wall_clock_ms)?;
+    stmt.bind_i64(2, tel.timestamp as i64)?;
+    stmt.bind_i64(3, tel.armed as i64)?;
+    stmt.bind_f64(4, widen(tel.battery))?;
+    stmt.bind_f64(5, widen(tel.battery_raw))?;
+    stmt.bind_f64(6, widen(tel.arming))?;
+    let flags = tel
+        .solenoids
+        .iter()
+        .chain(&tel.pyro_continuity)
+        .take(16 + 4);
+    for (i, &flag) in flags.enumerate() {
+        stmt.bind_i64(7 + i as c_int, flag as i64)?;
+    }
+    while stmt.step()? {}
+    Ok(())
+}
+
+/// Opens (or creates) the database at `path`, creates the `telemetry` table if needed and
+/// starts the writer thread. Samples sent on the returned channel are inserted in order.
+pub fn spawn_writer(path: &str) -> Result<mpsc::Sender<Telemetry>, String> {
+    let conn = Connection::open(path, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)?;
+    conn.execute(&create_table_sql())?;
+    let (tx, rx) = mpsc::channel::<Telemetry>();
+    thread::spawn(move || {
+        let sql = CString::new(insert_sql()).expect("no NUL in SQL");
+        let mut stmt = match conn.prepare(&sql) {
+            Ok((stmt, _)) => stmt,
+            Err(e) => {
+                error!(error = %e, "Failed to prepare telemetry insert");
+                return;
+            }
+        };
+        for tel in rx {
+            if let Err(e) = insert(&mut stmt, &tel) {
+                error!(error = %e, "Error writing telemetry to database");
+            }
+        }
+    });
+    Ok(tx)
+}
+
+/// Response body for GET /db/query.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct QueryResult {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    /// Set when the result was cut off at `MAX_QUERY_ROWS`.
+    truncated: bool,
+}
+
+/// Runs one read-only SELECT statement.
+fn run_query(path: &str, sql: &str) -> Result<QueryResult, String> {
+    let first_word = sql.split_whitespace().next().unwrap_or("");
+    if !first_word.eq_ignore_ascii_case("select") {
+        return Err("only SELECT statements are allowed".to_string());
+    }
+    let conn = Connection::open(path, ffi::SQLITE_OPEN_READONLY)?;
+    let sql = CString::new(sql).map_err(|_| "SQL contains a NUL byte".to_string())?;
+    let (mut stmt, tail) = conn.prepare(&sql)?;
+    if !tail
+        .to_string_lossy()
+        .trim_matches(|c: char| c == ';' || c.is_whitespace())
+        .is_empty()
+    {
+        return Err("only a single statement is allowed".to_string());
+    }
+    if !stmt.is_readonly() {
+        return Err("only SELECT statements are allowed".to_string());
+    }
+    let columns = stmt.column_names();
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    while stmt.step()? {
+        if rows.len() == MAX_QUERY_ROWS {
+            truncated = true;
+            break;
+        }
+        rows.push(stmt.row());
+    }
+    Ok(QueryResult {
+        columns,
+        rows,
+        truncated,
+    })
+}
+
+/// GET /db/query?sql=<SELECT ...> runs a read-only query against the `--db` database and
+/// returns the column names and rows. Anything but a single SELECT is a 400.
+#[get("/db/query?<sql>")]
+pub fn query(sql: &str, state: &State<AppState>) -> Result<Json<QueryResult>, (Status, String)> {
+    let Some(path) = &state.db_path else {
+        return Err((Status::NotFound, "DB_NOT_CONFIGURED".to_string()));
+    };
+    run_query(path, sql)
+        .map(Json)
+        .map_err(|e| (Status::BadRequest, format!("QUERY_FAILED: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("gcs-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn stores_samples_and_queries_them_back() {
+        let path = temp_db("store");
+        let conn =
+            Connection::open(&path, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE).unwrap();
+        conn.execute(&create_table_sql()).unwrap();
+        let sql = CString::new(insert_sql()).unwrap();
+        let (mut stmt, _) = conn.prepare(&sql).unwrap();
+        let mut tel = Telemetry {
+            timestamp: 1234,
+            armed: true,
+            battery: 12.5,
+            ..Telemetry::default()
+        };
+        tel.solenoids[2] = true;
+        insert(&mut stmt, &tel).unwrap();
+
+        let result = run_query(
+            &path,
+            "SELECT timestamp, armed, battery, sol3, sol4 FROM telemetry",
+        )
+        .unwrap();
+        assert_eq!(
+            result.columns,
+            ["timestamp", "armed", "battery", "sol3", "sol4"]
+        );
+        assert_eq!(
+            result.rows,
+            [vec![
+                Value::from(1234),
+                Value::from(1),
+                Value::from(12.5),
+                Value::from(1),
+                Value::from(0)
+            ]]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_anything_but_a_single_select() {
+        let path = temp_db("readonly");
+        Connection::open(&path, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)
+            .unwrap()
+            .execute(&create_table_sql())
+            .unwrap();
+        assert!(run_query(&path, "DELETE FROM telemetry").is_err());
+        assert!(run_query(&path, "SELECT 1; DROP TABLE telemetry").is_err());
+        assert!(run_query(&path, "select count(*) from telemetry;").is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+}
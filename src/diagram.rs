@@ -0,0 +1,165 @@
+// src/diagram.rs
+
+//! GET /diagram: an SVG piping diagram of the 16 valves, drawn from the latest telemetry.
+//! Open valves are green and closed ones red, with open and closed the physical states as
+//! in `groups`. The SVG is built on the server, so it shows up in monitoring tools that
+//! display images but run no JavaScript.
+//!
+//! `[diagram]` in the config places the valves and lists the pipes between them. Without
+//! it the valves sit in a 4x4 grid with no pipes.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use rocket::http::ContentType;
+use rocket::State;
+
+use crate::config::{DiagramConfig, SolenoidInfo};
+use crate::raw_log::escape_html;
+use crate::AppState;
+
+/// Distance between neighbours in the default grid.
+const GRID_SPACING: f32 = 120.0;
+/// Half the width of a valve symbol.
+const VALVE_SIZE: f32 = 16.0;
+/// Room around the outermost valves for their labels.
+const MARGIN: f32 = 60.0;
+
+const OPEN_COLOR: &str = "#2e7d32";
+const CLOSED_COLOR: &str = "#c62828";
+
+/// Where the valves and pipes are drawn.
+#[derive(Debug, Clone)]
+pub struct DiagramLayout {
+    /// Centre of each valve (index = channel - 1).
+    positions: [(f32, f32); 16],
+    pipes: Vec<[u8; 2]>,
+}
+
+impl DiagramLayout {
+    /// The layout from `[diagram]`, validated by `Config::load`.
+    pub fn new(config: &DiagramConfig) -> Self {
+        let mut positions = [(0.0, 0.0); 16];
+        for (i, position) in positions.iter_mut().enumerate() {
+            let [x, y] = config
+                .positions
+                .get(&(i + 1).to_string())
+                .copied()
+                .unwrap_or_else(|| {
+                    let (col, row) = ((i % 4) as f32, (i / 4) as f32);
+                    [GRID_SPACING * (col + 0.5), GRID_SPACING * (row + 0.5)]
+                });
+            *position = (x, y);
+        }
+        DiagramLayout {
+            positions,
+            pipes: config.pipes.clone(),
+        }
+    }
+
+    fn position(&self, channel: u8) -> (f32, f32) {
+        self.positions[channel as usize - 1]
+    }
+}
+
+impl Default for DiagramLayout {
+    fn default() -> Self {
+        DiagramLayout::new(&DiagramConfig::default())
+    }
+}
+
+/// The diagram for the energized `solenoids` (index = channel - 1), labelled from `info`.
+pub fn render(
+    layout: &DiagramLayout,
+    solenoids: &[bool],
+    info: &HashMap<u8, SolenoidInfo>,
+) -> String {
+    let width = layout.positions.iter().map(|p| p.0).fold(0.0, f32::max) + MARGIN;
+    let height = layout.positions.iter().map(|p| p.1).fold(0.0, f32::max) + MARGIN;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        w = width,
+        h = height
+    );
+    for &[a, b] in &layout.pipes {
+        let ((x1, y1), (x2, y2)) = (layout.position(a), layout.position(b));
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#607d8b\" stroke-width=\"4\"/>",
+            x1, y1, x2, y2
+        );
+    }
+    for channel in 1..=16u8 {
+        let info = &info[&channel];
+        let energized = solenoids
+            .get(channel as usize - 1)
+            .copied()
+            .unwrap_or(false);
+        let open = info.direction.is_open(energized);
+        let (x, y) = layout.position(channel);
+        let s = VALVE_SIZE;
+        // The usual valve symbol: two triangles meeting at the centre.
+        let _ = writeln!(
+            svg,
+            "<g id=\"valve-{ch}\" class=\"{state}\">\
+             <polygon points=\"{l},{t} {r},{b} {r},{t} {l},{b}\" fill=\"{color}\" \
+             stroke=\"#263238\" stroke-width=\"1.5\"/>\
+             <text x=\"{x}\" y=\"{label_y}\" text-anchor=\"middle\">{label}</text>\
+             <text x=\"{x}\" y=\"{state_y}\" text-anchor=\"middle\">{ch} {state}</text></g>",
+            ch = channel,
+            state = if open { "open" } else { "closed" },
+            l = x - s,
+            r = x + s,
+            t = y - s / 2.0,
+            b = y + s / 2.0,
+            color = if open { OPEN_COLOR } else { CLOSED_COLOR },
+            x = x,
+            label_y = y - s,
+            state_y = y + s + 8.0,
+            label = escape_html(&info.label),
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// GET /diagram returns the piping diagram as `image/svg+xml`.
+#[get("/diagram")]
+pub fn get(state: &State<AppState>) -> (ContentType, String) {
+    let solenoids = state.telemetry.read().unwrap().solenoids.clone();
+    let info = state.runtime_config.lock().unwrap().solenoid_info();
+    (ContentType::SVG, render(&state.diagram, &solenoids, &info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{solenoid_info, SolenoidDirection};
+
+    #[test]
+    fn colors_follow_the_valve_state() {
+        let config = DiagramConfig {
+            positions: HashMap::from([("2".to_string(), [600.0, 300.0])]),
+            pipes: vec![[1, 2]],
+        };
+        let layout = DiagramLayout::new(&config);
+        let labels = HashMap::from([("1".to_string(), "LOX <Main>".to_string())]);
+        let directions = HashMap::from([("3".to_string(), SolenoidDirection::NormallyOpen)]);
+        let mut solenoids = vec![false; 16];
+        solenoids[0] = true;
+        let svg = render(&layout, &solenoids, &solenoid_info(&labels, &directions));
+
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains("width=\"660\" height=\"480\""));
+        assert!(svg.contains("<line x1=\"60\" y1=\"60\" x2=\"600\" y2=\"300\""));
+        let valve = |ch: u8| {
+            let start = svg.find(&format!("<g id=\"valve-{}\"", ch)).unwrap();
+            &svg[start..start + svg[start..].find("</g>").unwrap()]
+        };
+        assert!(valve(1).contains(OPEN_COLOR) && valve(1).contains("LOX &lt;Main&gt;"));
+        assert!(valve(2).contains(CLOSED_COLOR) && valve(2).contains("Solenoid 2"));
+        // Normally open and not energized.
+        assert!(valve(3).contains(OPEN_COLOR));
+    }
+}
@@ -0,0 +1,238 @@
+// src/error.rs
+
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::{json, Json};
+
+use crate::command::CommandError;
+use crate::countdown::MAX_T_MINUS_S;
+use crate::firmware_command::MAX_PAYLOAD_LEN;
+use crate::state_machine::{ArmError, ArmState};
+
+/// Errors returned by the command endpoints.
+/// Each variant maps to an HTTP status and a short machine-readable body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// The command could not be handed to the serial loop (it is no longer running).
+    SerialSendFailed,
+    /// Solenoid channel outside 1..=16.
+    InvalidChannel(u8),
+    /// Solenoid state other than 0 or 1.
+    InvalidState(u8),
+    /// One or more entries of a batch failed validation: `(channel, reason)` for each.
+    InvalidBatch(Vec<(u8, ApiError)>),
+    /// A sequence step failed validation; carries the step index and the reason.
+    InvalidSequenceStep(usize, Box<ApiError>),
+    /// A POST /sequence step arms while `require_two_step` is set; carries the step index.
+    SequenceArmRefused(usize),
+    /// A sequence is already running; abort it first.
+    SequenceAlreadyRunning,
+    /// POST /countdown/start while a countdown is running; abort it first.
+    CountdownAlreadyRunning,
+    /// POST /countdown/start with `t_minus_s` above `MAX_T_MINUS_S`.
+    InvalidCountdown(u64),
+    /// A POST /script/run line failed to parse; carries the line number and the reason.
+    InvalidScript(usize, String),
+    /// A script is already running.
+    ScriptAlreadyRunning,
+    /// Solenoid commands require the system to be armed (`require_armed_for_solenoid`).
+    SystemNotArmed,
+    /// POST /serial/reconnect was given an empty port name.
+    InvalidPortName,
+    /// A baud rate outside `SUPPORTED_BAUD_RATES`.
+    InvalidBaudRate(u32),
+    /// A solenoid was commanded again within its minimum interval.
+    RateLimited,
+    /// A solenoid was on for more than `max_duty_pct` of the last minute.
+    DutyCycleExceeded,
+    /// The client used up its `[rate_limit]` POST budget.
+    TooManyRequests,
+    /// No `[[board]]` has this ID.
+    UnknownBoard(u8),
+    /// No `[[group]]` has this name.
+    UnknownGroup(String),
+    /// No pulse has this ID (or it finished long enough ago to be forgotten).
+    UnknownPulse(u64),
+    /// No snapshot has this ID among the last `MAX_SNAPSHOTS`.
+    UnknownSnapshot(u64),
+    /// A calibration coefficient is NaN or infinite.
+    InvalidCalibration,
+    /// A POST /telemetry/inject sample the serial loop would have dropped as implausible.
+    InvalidTelemetry,
+    /// The host's serial ports could not be listed.
+    PortEnumerationFailed(String),
+    /// `require_two_step` is set, so POST /arm is refused; use /arm/intent and /arm/confirm.
+    TwoStepArmRequired,
+    /// POST /arm/confirm without an outstanding POST /arm/intent.
+    NoArmIntent,
+    /// POST /arm/confirm came after the confirm window; the intent was cleared.
+    ArmIntentExpired,
+    /// POST /arm/confirm with the wrong token; the intent was cleared.
+    InvalidArmToken,
+    /// An arm endpoint the arm/disarm lifecycle does not allow in this state.
+    InvalidArmTransition(ArmState),
+    /// POST /test/solenoid/<channel> while telemetry already shows the solenoid on, so
+    /// switching it on could not be confirmed.
+    SolenoidAlreadyOn(u8),
+    /// Opening `channel` would break one of its `[[interlock]]`s, as `blocked_by` is open.
+    InterlockViolation { channel: u8, blocked_by: u8 },
+    /// A POST /firmware/command payload longer than `MAX_PAYLOAD_LEN` bytes.
+    RawCommandTooLong(usize),
+    /// A POST /firmware/command payload that is empty or has control characters.
+    InvalidRawCommand,
+    /// A label, group or interlock for /config/... that `Config::load` would refuse.
+    InvalidConfig(String),
+    /// POST /config/groups with the name of an existing group.
+    DuplicateGroup(String),
+    /// DELETE /config/interlocks/<index> beyond the last interlock.
+    UnknownInterlock(usize),
+    /// POST /config/save could not write the config file.
+    ConfigSaveFailed(String),
+    /// GET /session/<token>/log for a session that does not exist or has expired.
+    UnknownSession(String),
+    /// A write to a cluster follower while it knows of no leader to proxy it to.
+    NoClusterLeader,
+    /// A write a cluster follower could not proxy to the leader.
+    ClusterProxyFailed(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::SerialSendFailed
+            | ApiError::PortEnumerationFailed(_)
+            | ApiError::ConfigSaveFailed(_) => Status::InternalServerError,
+            ApiError::InvalidChannel(_)
+            | ApiError::InvalidState(_)
+            | ApiError::InvalidBatch(_)
+            | ApiError::InvalidSequenceStep(..)
+            | ApiError::InvalidPortName
+            | ApiError::InvalidBaudRate(_)
+            | ApiError::InvalidCalibration
+            | ApiError::InvalidTelemetry
+            | ApiError::InvalidCountdown(_)
+            | ApiError::RawCommandTooLong(_)
+            | ApiError::InvalidRawCommand
+            | ApiError::InvalidConfig(_)
+            | ApiError::InvalidScript(..) => Status::BadRequest,
+            ApiError::SequenceAlreadyRunning
+            | ApiError::SequenceArmRefused(_)
+            | ApiError::CountdownAlreadyRunning
+            | ApiError::ScriptAlreadyRunning
+            | ApiError::InterlockViolation { .. }
+            | ApiError::SolenoidAlreadyOn(_)
+            | ApiError::DuplicateGroup(_)
+            | ApiError::NoArmIntent
+            | ApiError::ArmIntentExpired
+            | ApiError::InvalidArmTransition(_) => Status::Conflict,
+            ApiError::SystemNotArmed
+            | ApiError::TwoStepArmRequired
+            | ApiError::InvalidArmToken => Status::Forbidden,
+            ApiError::RateLimited | ApiError::DutyCycleExceeded | ApiError::TooManyRequests => {
+                Status::TooManyRequests
+            }
+            ApiError::UnknownBoard(_)
+            | ApiError::UnknownGroup(_)
+            | ApiError::UnknownPulse(_)
+            | ApiError::UnknownInterlock(_)
+            | ApiError::UnknownSession(_)
+            | ApiError::UnknownSnapshot(_) => Status::NotFound,
+            ApiError::NoClusterLeader => Status::ServiceUnavailable,
+            ApiError::ClusterProxyFailed(_) => Status::BadGateway,
+        }
+    }
+
+    /// The response body: an upper-case error code, followed by details where useful.
+    pub fn body(&self) -> String {
+        match self {
+            ApiError::SerialSendFailed => "SERIAL_SEND_FAILED".to_string(),
+            ApiError::InvalidChannel(ch) => format!("INVALID_CHANNEL: {} (expected 1-16)", ch),
+            ApiError::InvalidState(st) => format!("INVALID_STATE: {} (expected 0 or 1)", st),
+            ApiError::InvalidBatch(failed) => {
+                format!("INVALID_BATCH: {} entries failed", failed.len())
+            }
+            ApiError::InvalidSequenceStep(index, reason) => {
+                format!("INVALID_SEQUENCE_STEP {}: {}", index, reason.body())
+            }
+            ApiError::SequenceAlreadyRunning => "SEQUENCE_ALREADY_RUNNING".to_string(),
+            ApiError::SequenceArmRefused(index) => {
+                format!("SEQUENCE_ARM_REFUSED: step {} (two-step arming is required)", index)
+            }
+            ApiError::CountdownAlreadyRunning => "COUNTDOWN_ALREADY_RUNNING".to_string(),
+            ApiError::InvalidCountdown(t_minus_s) => {
+                format!("INVALID_COUNTDOWN: t_minus_s {} (max {})", t_minus_s, MAX_T_MINUS_S)
+            }
+            ApiError::InvalidScript(line, reason) => {
+                format!("INVALID_SCRIPT line {}: {}", line, reason)
+            }
+            ApiError::ScriptAlreadyRunning => "SCRIPT_ALREADY_RUNNING".to_string(),
+            ApiError::SystemNotArmed => "SYSTEM_NOT_ARMED".to_string(),
+            ApiError::InvalidPortName => "INVALID_PORT_NAME".to_string(),
+            ApiError::InvalidBaudRate(baud) => format!("INVALID_BAUD_RATE: {}", baud),
+            ApiError::RateLimited => "RATE_LIMITED".to_string(),
+            ApiError::DutyCycleExceeded => "DUTY_CYCLE_EXCEEDED".to_string(),
+            ApiError::TooManyRequests => "TOO_MANY_REQUESTS".to_string(),
+            ApiError::UnknownBoard(id) => format!("UNKNOWN_BOARD: {}", id),
+            ApiError::UnknownGroup(name) => format!("UNKNOWN_GROUP: {}", name),
+            ApiError::UnknownPulse(id) => format!("UNKNOWN_PULSE: {}", id),
+            ApiError::UnknownSnapshot(id) => format!("UNKNOWN_SNAPSHOT: {}", id),
+            ApiError::InvalidCalibration => "INVALID_CALIBRATION".to_string(),
+            ApiError::InvalidTelemetry => "INVALID_TELEMETRY".to_string(),
+            ApiError::PortEnumerationFailed(e) => format!("PORT_ENUMERATION_FAILED: {}", e),
+            ApiError::TwoStepArmRequired => "TWO_STEP_ARM_REQUIRED".to_string(),
+            ApiError::NoArmIntent => "NO_ARM_INTENT".to_string(),
+            ApiError::ArmIntentExpired => "ARM_INTENT_EXPIRED".to_string(),
+            ApiError::InvalidArmToken => "INVALID_ARM_TOKEN".to_string(),
+            ApiError::RawCommandTooLong(len) => {
+                format!("RAW_COMMAND_TOO_LONG: {} bytes (max {})", len, MAX_PAYLOAD_LEN)
+            }
+            ApiError::InvalidRawCommand => "INVALID_RAW_COMMAND".to_string(),
+            ApiError::InvalidConfig(reason) => format!("INVALID_CONFIG: {}", reason),
+            ApiError::DuplicateGroup(name) => format!("DUPLICATE_GROUP: {}", name),
+            ApiError::UnknownInterlock(index) => format!("UNKNOWN_INTERLOCK: {}", index),
+            ApiError::ConfigSaveFailed(e) => format!("CONFIG_SAVE_FAILED: {}", e),
+            ApiError::UnknownSession(token) => format!("UNKNOWN_SESSION: {}", token),
+            ApiError::InvalidArmTransition(state) => format!("INVALID_ARM_TRANSITION: {}", state),
+            ApiError::NoClusterLeader => "NO_CLUSTER_LEADER".to_string(),
+            ApiError::ClusterProxyFailed(e) => format!("CLUSTER_PROXY_FAILED: {}", e),
+            ApiError::SolenoidAlreadyOn(channel) => format!("SOLENOID_ALREADY_ON: {}", channel),
+            ApiError::InterlockViolation { channel, blocked_by } => format!(
+                "INTERLOCK_VIOLATION: channel {} blocked by channel {}",
+                channel, blocked_by
+            ),
+        }
+    }
+}
+
+impl From<CommandError> for ApiError {
+    fn from(e: CommandError) -> Self {
+        match e {
+            CommandError::InvalidChannel(ch) => ApiError::InvalidChannel(ch),
+            CommandError::InvalidState(st) => ApiError::InvalidState(st),
+        }
+    }
+}
+
+impl From<ArmError> for ApiError {
+    fn from(e: ArmError) -> Self {
+        ApiError::InvalidArmTransition(e.state)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        match &self {
+            // Batch failures list every rejected entry, so they are reported as JSON.
+            ApiError::InvalidBatch(failed) => {
+                let failed: Vec<_> = failed
+                    .iter()
+                    .map(|(channel, err)| json!({ "channel": channel, "error": err.body() }))
+                    .collect();
+                let body = json!({ "error": "INVALID_BATCH", "failed": failed });
+                (self.status(), Json(body)).respond_to(req)
+            }
+            _ => (self.status(), self.body()).respond_to(req),
+        }
+    }
+}
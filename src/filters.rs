@@ -0,0 +1,142 @@
+// src/filters.rs
+
+//! Calibration and smoothing of noisy ADC readings before they reach the shared telemetry.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::Telemetry;
+
+/// Default number of samples the voltage filters average over.
+pub const DEFAULT_FILTER_WINDOW: usize = 10;
+
+/// An N-sample rolling mean. Until N samples have been seen it averages what it has.
+pub struct BatteryFilter {
+    samples: VecDeque<f32>,
+    window: usize,
+}
+
+impl BatteryFilter {
+    /// A filter over the last `window` samples (at least one).
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        BatteryFilter {
+            samples: VecDeque::with_capacity(window),
+            window,
+        }
+    }
+
+    /// Adds a reading and returns the mean of the current window.
+    pub fn push(&mut self, value: f32) -> f32 {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+        self.samples.iter().sum::<f32>() / self.samples.len() as f32
+    }
+}
+
+/// A linear correction of the battery reading for measurement circuit tolerances:
+/// `calibrated = raw * scale + offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct VoltageCalibration {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for VoltageCalibration {
+    fn default() -> Self {
+        VoltageCalibration { scale: 1.0, offset: 0.0 }
+    }
+}
+
+impl VoltageCalibration {
+    pub fn apply(&self, raw: f32) -> f32 {
+        raw * self.scale + self.offset
+    }
+}
+
+/// The battery calibration, set by POST /calibrate/battery and read by the serial loop.
+pub type SharedCalibration = Arc<Mutex<VoltageCalibration>>;
+
+/// The filters applied to each parsed sample. Lives with the serial loop, so the windows
+/// carry over across reconnects.
+pub struct TelemetryFilters {
+    battery: BatteryFilter,
+    arming: BatteryFilter,
+    battery_calibration: SharedCalibration,
+}
+
+impl TelemetryFilters {
+    /// Filters with the default (identity) battery calibration.
+    pub fn new(window: usize) -> Self {
+        TelemetryFilters {
+            battery: BatteryFilter::new(window),
+            arming: BatteryFilter::new(window),
+            battery_calibration: SharedCalibration::default(),
+        }
+    }
+
+    /// Uses `calibration` for the battery reading, picking up changes as they are made.
+    pub fn with_battery_calibration(mut self, calibration: SharedCalibration) -> Self {
+        self.battery_calibration = calibration;
+        self
+    }
+
+    /// Replaces the battery and arming sense voltages with their filtered values; the battery
+    /// reading is calibrated first. The raw battery reading is kept in `battery_raw`.
+    pub fn apply(&mut self, tel: &mut Telemetry) {
+        tel.battery_raw = tel.battery;
+        let calibrated = self.battery_calibration.lock().unwrap().apply(tel.battery);
+        tel.battery = self.battery.push(calibrated);
+        tel.arming = self.arming.push(tel.arming);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_mean_of_three() {
+        let mut filter = BatteryFilter::new(3);
+        assert_eq!(filter.push(3.0), 3.0);
+        assert_eq!(filter.push(5.0), 4.0);
+        assert_eq!(filter.push(4.0), 4.0);
+    }
+
+    #[test]
+    fn oldest_sample_leaves_the_window() {
+        let mut filter = BatteryFilter::new(3);
+        for v in [3.0, 5.0, 4.0] {
+            filter.push(v);
+        }
+        assert_eq!(filter.push(9.0), 6.0);
+    }
+
+    #[test]
+    fn apply_keeps_raw_battery() {
+        let mut filters = TelemetryFilters::new(2);
+        let mut tel = Telemetry { battery: 12.0, ..Telemetry::default() };
+        filters.apply(&mut tel);
+        let mut tel = Telemetry { battery: 11.0, arming: 2.0, ..Telemetry::default() };
+        filters.apply(&mut tel);
+        assert_eq!(tel.battery_raw, 11.0);
+        assert_eq!(tel.battery, 11.5);
+        assert_eq!(tel.arming, 1.0);
+    }
+
+    #[test]
+    fn battery_is_calibrated_before_filtering() {
+        let calibration = SharedCalibration::default();
+        let mut filters = TelemetryFilters::new(1).with_battery_calibration(calibration.clone());
+        *calibration.lock().unwrap() = VoltageCalibration { scale: 2.0, offset: 0.5 };
+        let mut tel = Telemetry { battery: 6.0, ..Telemetry::default() };
+        filters.apply(&mut tel);
+        assert_eq!(tel.battery_raw, 6.0);
+        assert_eq!(tel.battery, 12.5);
+    }
+}
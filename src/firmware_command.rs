@@ -0,0 +1,83 @@
+// src/firmware_command.rs
+
+//! POST /firmware/command: sends a raw line to the Arduino, for trying out firmware commands
+//! the server has no endpoint for yet. It bypasses every check the solenoid endpoints make
+//! (arming interlock, rate limit, channel mask), so it is only mounted in a debug build
+//! started with `--dev-mode`; release builds refuse the flag.
+//!
+//! The payload is written as-is, plus the terminating newline. It must be at most
+//! `MAX_PAYLOAD_LEN` bytes, with no control characters, so one request is always exactly
+//! one line for the firmware.
+
+use rocket::serde::Deserialize;
+use rocket::State;
+
+use crate::auth::SignedJson;
+use crate::error::ApiError;
+use crate::latency::RequestStart;
+use crate::{AppState, QueuedCommand};
+
+/// The longest payload accepted, in bytes (without the newline).
+pub const MAX_PAYLOAD_LEN: usize = 64;
+
+/// Body of POST /firmware/command.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RawCommand {
+    payload: String,
+}
+
+/// Checks that `payload` is one line the firmware can take.
+fn validate(payload: &str) -> Result<(), ApiError> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(ApiError::RawCommandTooLong(payload.len()));
+    }
+    if payload.is_empty() || payload.chars().any(char::is_control) {
+        return Err(ApiError::InvalidRawCommand);
+    }
+    Ok(())
+}
+
+/// POST /firmware/command queues `payload` for the primary board's serial port verbatim.
+#[post("/firmware/command", data = "<command>")]
+pub fn send(
+    command: SignedJson<RawCommand>,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let payload = command.into_inner().payload;
+    validate(&payload)?;
+    state.send_command(QueuedCommand::new(payload, start))?;
+    Ok("OK")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_long_empty_and_control_payloads() {
+        assert_eq!(validate("s31"), Ok(()));
+        assert_eq!(validate(&"x".repeat(MAX_PAYLOAD_LEN)), Ok(()));
+        assert_eq!(
+            validate(&"x".repeat(MAX_PAYLOAD_LEN + 1)),
+            Err(ApiError::RawCommandTooLong(MAX_PAYLOAD_LEN + 1))
+        );
+        for payload in [
+            "",
+            "s31\ns50",
+            "s31\r",
+            "a\0",
+            "\x1b[2J",
+            "tab\there",
+            "\u{7f}",
+        ] {
+            assert_eq!(
+                validate(payload),
+                Err(ApiError::InvalidRawCommand),
+                "{:?}",
+                payload
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// src/firmware_reset.rs
+
+//! POST /firmware/reset: reboots the Arduino the way the IDE does before an upload, by
+//! dropping the port's DTR line for `DTR_LOW_TIME`. It is only mounted with
+//! `--allow-firmware-reset`.
+//!
+//! The handler throws away the queued commands (a freshly booted board should not get them)
+//! and the last known telemetry, then asks the serial loop to toggle DTR, since the loop
+//! owns the port. Links without control lines (`--simulate`, `--hil`, UDP, CAN) cannot be
+//! reset; the serial loop logs that instead.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rocket::State;
+use serialport::SerialPort;
+use tracing::{info, warn};
+
+use crate::auth::Authenticated;
+use crate::state_machine::ArmState;
+use crate::{AppState, Telemetry};
+
+/// How long DTR is held low; the Arduino resets when it goes high again.
+const DTR_LOW_TIME: Duration = Duration::from_millis(100);
+
+/// A reset requested by POST /firmware/reset, picked up by the serial loop.
+#[derive(Debug, Default)]
+pub struct FirmwareReset {
+    requested: AtomicBool,
+    /// When the last reset was requested, for GET /status.
+    last: Mutex<Option<SystemTime>>,
+}
+
+/// The reset request, shared between the handler and the serial loop.
+pub type SharedFirmwareReset = Arc<FirmwareReset>;
+
+impl FirmwareReset {
+    fn request(&self, now: SystemTime) {
+        *self.last.lock().unwrap() = Some(now);
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Takes the pending request, if any.
+    pub fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn last(&self) -> Option<SystemTime> {
+        *self.last.lock().unwrap()
+    }
+}
+
+/// Resets the board on `port` by toggling DTR. Without a port (a link with no control
+/// lines) there is nothing to toggle.
+pub fn toggle_dtr(port: &mut Option<Box<dyn SerialPort>>) {
+    let Some(port) = port else {
+        warn!("Firmware reset requested, but this link has no DTR line to toggle");
+        return;
+    };
+    let toggled = port.write_data_terminal_ready(false).and_then(|()| {
+        thread::sleep(DTR_LOW_TIME);
+        port.write_data_terminal_ready(true)
+    });
+    match toggled {
+        Ok(()) => info!("Firmware reset: DTR toggled"),
+        Err(e) => warn!(error = %e, "Firmware reset: could not toggle DTR"),
+    }
+}
+
+/// POST /firmware/reset discards the queued commands and the last known telemetry, and has
+/// the serial loop reset the primary board. GET /status shows when it was last asked for.
+#[post("/firmware/reset")]
+pub fn reset(_auth: Authenticated, state: &State<AppState>) -> &'static str {
+    let discarded = state.command_tx.drain();
+    *state.telemetry.write().unwrap() = Telemetry::default();
+    // The board comes back disarmed.
+    *state.arm_state.lock().unwrap() = ArmState::default();
+    state.firmware_reset.request(SystemTime::now());
+    warn!(discarded, "Firmware reset requested");
+    "RESETTING"
+}
@@ -0,0 +1,109 @@
+// src/flight_log.rs
+
+//! The flight-event log: every arm, disarm, solenoid and emergency-stop command written to
+//! the Arduino, with the wall-clock time of the write and the telemetry timestamp at that
+//! moment, for lining events up against ignition in post-flight debriefs.
+//!
+//! The serial loop appends to it; GET /flight_log reads it and DELETE /flight_log clears it.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A shared flight log (appended by the serial loop, read by the handlers).
+pub type SharedFlightLog = Arc<Mutex<FlightLog>>;
+
+/// What a logged command did.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum EventType {
+    Arm,
+    Disarm,
+    Solenoid { channel: u8, state: bool },
+    /// The disarm-and-close-everything batch from POST /emergency_stop.
+    EmergencyStop,
+}
+
+impl EventType {
+    /// Recognises one command line ("a", "d" or "s<channel><state>").
+    pub fn from_command(cmd: &str) -> Option<EventType> {
+        match cmd.trim() {
+            "a" => Some(EventType::Arm),
+            "d" => Some(EventType::Disarm),
+            cmd => {
+                let rest = cmd.strip_prefix('s')?;
+                let (channel, state) = rest.split_at(rest.len().checked_sub(1)?);
+                let state = match state {
+                    "0" => false,
+                    "1" => true,
+                    _ => return None,
+                };
+                Some(EventType::Solenoid { channel: channel.parse().ok()?, state })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FlightEvent {
+    /// When the command was written, serialized as milliseconds since the Unix epoch.
+    #[serde(serialize_with = "serialize_epoch_ms")]
+    pub wall_clock: SystemTime,
+    /// The Arduino timestamp of the latest telemetry at that moment.
+    pub telemetry_ts: u64,
+    pub event_type: EventType,
+}
+
+pub fn serialize_epoch_ms<S: Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    let ms = time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    s.serialize_u64(ms)
+}
+
+/// `serialize_epoch_ms` for an optional time; `None` is `null`.
+pub fn serialize_opt_epoch_ms<S: Serializer>(
+    time: &Option<SystemTime>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_epoch_ms(time, s),
+        None => s.serialize_none(),
+    }
+}
+
+/// Reads a time written by `serialize_epoch_ms`.
+pub fn deserialize_epoch_ms<'de, D: Deserializer<'de>>(d: D) -> Result<SystemTime, D::Error> {
+    let ms = u64::deserialize(d)?;
+    Ok(UNIX_EPOCH + Duration::from_millis(ms))
+}
+
+#[derive(Debug, Default)]
+pub struct FlightLog {
+    events: Vec<FlightEvent>,
+}
+
+impl FlightLog {
+    pub fn record(&mut self, telemetry_ts: u64, event_type: EventType) {
+        self.events.push(FlightEvent {
+            wall_clock: SystemTime::now(),
+            telemetry_ts,
+            event_type,
+        });
+    }
+
+    /// Records each recognised line of a written command (batches are several lines).
+    pub fn record_command(&mut self, telemetry_ts: u64, text: &str) {
+        for event_type in text.lines().filter_map(EventType::from_command) {
+            self.record(telemetry_ts, event_type);
+        }
+    }
+
+    pub fn events(&self) -> &[FlightEvent] {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
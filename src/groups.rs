@@ -0,0 +1,163 @@
+// src/groups.rs
+
+//! Named solenoid groups (`[[group]]` in the config), such as a purge group or a vent
+//! sequence, actuated with a single request instead of one per valve.
+//!
+//! POST /group/<name>/open and /close send every channel's command in one serial write,
+//! with the same checks as POST /solenoids/batch (arming, `[[interlock]]`s, duty cycles and
+//! rate limit). GET /groups
+//! reports each group's state from the latest telemetry. Groups can be added and removed
+//! while the server runs through /config/groups (see `runtime_config`).
+//!
+//! Open and closed are the valves' physical states: opening a group energizes its normally
+//! closed solenoids and de-energizes its normally open ones (see `[solenoid_directions]`).
+
+use std::collections::HashMap;
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::auth::Authenticated;
+use crate::command::CommandBuilder;
+use crate::config::{SolenoidDirection, SolenoidInfo};
+use crate::error::ApiError;
+use crate::latency::RequestStart;
+use crate::{AppState, QueuedCommand};
+
+/// The state of a group's solenoids.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum GroupState {
+    Open,
+    Closed,
+    /// Some open, some closed.
+    Mixed,
+}
+
+impl GroupState {
+    /// The state of the valves on `channels` (validated, 1-16), given which solenoids are
+    /// energized according to telemetry.
+    fn of(channels: &[u8], solenoids: &[bool], info: &HashMap<u8, SolenoidInfo>) -> GroupState {
+        let open = channels
+            .iter()
+            .filter(|&&ch| {
+                let energized = solenoids.get(ch as usize - 1).copied().unwrap_or(false);
+                direction(info, ch).is_open(energized)
+            })
+            .count();
+        match open {
+            0 => GroupState::Closed,
+            n if n == channels.len() => GroupState::Open,
+            _ => GroupState::Mixed,
+        }
+    }
+}
+
+/// An entry of GET /groups.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GroupInfo {
+    name: String,
+    channels: Vec<u8>,
+    state: GroupState,
+}
+
+/// GET /groups lists the configured groups and whether each is open, closed or mixed.
+#[get("/groups")]
+pub fn list_groups(state: &State<AppState>) -> Json<Vec<GroupInfo>> {
+    let tel = state.telemetry.read().unwrap();
+    let runtime = state.runtime_config.lock().unwrap();
+    let info = runtime.solenoid_info();
+    let groups = runtime
+        .groups
+        .iter()
+        .map(|group| GroupInfo {
+            name: group.name.clone(),
+            channels: group.channels.clone(),
+            state: GroupState::of(&group.channels, &tel.solenoids, &info),
+        })
+        .collect();
+    Json(groups)
+}
+
+fn direction(info: &HashMap<u8, SolenoidInfo>, channel: u8) -> SolenoidDirection {
+    info.get(&channel)
+        .map(|info| info.direction)
+        .unwrap_or_default()
+}
+
+/// Opens or closes every valve of the group `name` in one write.
+fn actuate(
+    name: &str,
+    open: bool,
+    start: RequestStart,
+    state: &AppState,
+) -> Result<&'static str, ApiError> {
+    // Not held while sending: the interlock check in `send_solenoid_command` locks it again.
+    let (group, info) = {
+        let runtime = state.runtime_config.lock().unwrap();
+        let group = runtime
+            .groups
+            .iter()
+            .find(|group| group.name == name)
+            .cloned()
+            .ok_or_else(|| ApiError::UnknownGroup(name.to_string()))?;
+        (group, runtime.solenoid_info())
+    };
+    let mut builder = CommandBuilder::new();
+    for &channel in &group.channels {
+        builder.solenoid(channel, direction(&info, channel).energized(open))?;
+    }
+    let cmd = QueuedCommand::new(builder.build_joined(), start);
+    state.send_solenoid_command(cmd, &group.channels)?;
+    Ok("OK")
+}
+
+/// POST /group/<name>/open opens every valve of the group.
+#[post("/group/<name>/open")]
+pub fn open(
+    name: &str,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    actuate(name, true, start, state)
+}
+
+/// POST /group/<name>/close closes every valve of the group.
+#[post("/group/<name>/close")]
+pub fn close(
+    name: &str,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    actuate(name, false, start, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_state_from_telemetry() {
+        let mut info = crate::config::solenoid_info(&HashMap::new(), &HashMap::new());
+        let mut solenoids = vec![false; 16];
+        assert_eq!(
+            GroupState::of(&[1, 2], &solenoids, &info),
+            GroupState::Closed
+        );
+        solenoids[0] = true;
+        assert_eq!(
+            GroupState::of(&[1, 2], &solenoids, &info),
+            GroupState::Mixed
+        );
+        solenoids[1] = true;
+        assert_eq!(GroupState::of(&[1, 2], &solenoids, &info), GroupState::Open);
+        info.get_mut(&2).unwrap().direction = SolenoidDirection::NormallyOpen;
+        assert_eq!(
+            GroupState::of(&[1, 2], &solenoids, &info),
+            GroupState::Mixed
+        );
+    }
+}
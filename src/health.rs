@@ -0,0 +1,146 @@
+// src/health.rs
+
+//! GET /health: a single JSON summary of whether the server is doing its job (serial link
+//! up, telemetry arriving, commands not piling up), plus what build is running. GET /version
+//! reports just the build.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::{AppState, ConnectionStatus};
+
+/// The window `telemetry_parse_rate_hz` is averaged over.
+pub const PARSE_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// When telemetry samples were parsed, over the last `PARSE_RATE_WINDOW`.
+#[derive(Debug, Default)]
+pub struct ParseRate {
+    times: VecDeque<Instant>,
+    last: Option<Instant>,
+}
+
+/// The parse times, shared between the serial loop and GET /health.
+pub type SharedParseRate = Arc<Mutex<ParseRate>>;
+
+impl ParseRate {
+    /// Records a sample parsed at `now`.
+    pub fn record(&mut self, now: Instant) {
+        self.times.push_back(now);
+        self.last = Some(now);
+        self.expire(now);
+    }
+
+    /// When the last sample was parsed, if any was.
+    pub fn last(&self) -> Option<Instant> {
+        self.last
+    }
+
+    /// Samples per second over the `PARSE_RATE_WINDOW` before `now`.
+    pub fn rate_hz(&mut self, now: Instant) -> f32 {
+        self.expire(now);
+        self.times.len() as f32 / PARSE_RATE_WINDOW.as_secs_f32()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&oldest) = self.times.front() {
+            if now.saturating_duration_since(oldest) < PARSE_RATE_WINDOW {
+                break;
+            }
+            self.times.pop_front();
+        }
+    }
+}
+
+/// What was built, from `build.rs`.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BuildInfo {
+    version: &'static str,
+    /// The short commit hash, "unknown" outside a git checkout.
+    git_sha: &'static str,
+    /// UTC, as YYYY-MM-DD.
+    build_date: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_sha: env!("GIT_SHA"),
+    build_date: env!("BUILD_DATE"),
+};
+
+/// Response body for GET /version.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// The short commit hash, "unknown" outside a git checkout.
+    pub git_sha: &'static str,
+    /// UTC, as YYYY-MM-DDTHH:MM:SSZ.
+    pub build_timestamp: &'static str,
+}
+
+pub const VERSION_INFO: VersionInfo = VersionInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_sha: env!("GIT_SHA"),
+    build_timestamp: env!("BUILD_TIMESTAMP"),
+};
+
+/// GET /version reports which build is running.
+#[get("/version")]
+pub fn get_version() -> Json<&'static VersionInfo> {
+    Json(&VERSION_INFO)
+}
+
+/// Response body for GET /health.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HealthStatus {
+    serial_connected: bool,
+    /// Since the last telemetry sample was parsed, or since startup if none has been.
+    last_telemetry_age_ms: u64,
+    /// Over the last `PARSE_RATE_WINDOW`.
+    telemetry_parse_rate_hz: f32,
+    /// Commands queued for the serial loop and not written yet.
+    command_queue_depth: u32,
+    uptime_s: u64,
+    build_info: &'static BuildInfo,
+}
+
+/// GET /health reports the serial link, telemetry rate, command backlog and build.
+#[get("/health")]
+pub fn get_health(state: &State<AppState>) -> Json<HealthStatus> {
+    let now = Instant::now();
+    let mut parse_rate = state.parse_rate.lock().unwrap();
+    let last = parse_rate.last().unwrap_or(state.started_at);
+    Json(HealthStatus {
+        serial_connected: *state.connection_status.lock().unwrap() == ConnectionStatus::Connected,
+        last_telemetry_age_ms: now.saturating_duration_since(last).as_millis() as u64,
+        telemetry_parse_rate_hz: parse_rate.rate_hz(now),
+        command_queue_depth: state.command_tx.pending() as u32,
+        uptime_s: now.saturating_duration_since(state.started_at).as_secs(),
+        build_info: &BUILD_INFO,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_is_averaged_over_the_window() {
+        let t0 = Instant::now();
+        let mut rate = ParseRate::default();
+        assert_eq!(rate.rate_hz(t0), 0.0);
+        for i in 0..20 {
+            rate.record(t0 + Duration::from_millis(100 * i));
+        }
+        assert_eq!(rate.rate_hz(t0 + Duration::from_secs(2)), 2.0);
+        // The first 10 samples are more than 10 s old by now.
+        assert_eq!(rate.rate_hz(t0 + Duration::from_millis(10_950)), 1.0);
+        assert_eq!(rate.last(), Some(t0 + Duration::from_millis(1900)));
+    }
+}
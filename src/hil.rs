@@ -0,0 +1,128 @@
+// src/hil.rs
+
+//! Hardware-in-the-loop test mode (`--hil`): the serial loop talks to a fake Arduino over a
+//! real OS socket pair instead of a serial port, to exercise the whole
+//! command → response → telemetry cycle without hardware.
+//!
+//! Unlike `--simulate` the fake firmware is deterministic. `armed` only changes with "a"/"d"
+//! commands and the solenoids only with "sXY" commands. Every recognised command is ACKed
+//! and answered with a telemetry sample straight away, on top of the usual 100 ms stream;
+//! anything else is NACKed.
+//! Only Unix-like systems are supported (there is no named-pipe variant for Windows).
+
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::simulator::FakeArduino;
+use crate::telemetry::TelemetryFormat;
+
+/// How often the fake firmware sends telemetry on its own.
+const TELEMETRY_PERIOD: Duration = Duration::from_millis(100);
+
+/// The serial loop's end of the socket pair. Read timeouts surface as `TimedOut`, the way
+/// the serial port reports them, rather than the socket's `WouldBlock`.
+pub struct HilPort(UnixStream);
+
+impl Read for HilPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, e),
+            _ => e,
+        })
+    }
+}
+
+impl Write for HilPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Creates the socket pair and starts the fake firmware on one end. Returns the other end
+/// as a writer/reader pair for the serial loop, with reads timing out after `read_timeout`.
+pub fn spawn(format: TelemetryFormat, read_timeout: Duration) -> io::Result<(HilPort, HilPort)> {
+    let (port, firmware) = UnixStream::pair()?;
+    port.set_read_timeout(Some(read_timeout))?;
+    firmware.set_read_timeout(Some(TELEMETRY_PERIOD))?;
+    let writer = HilPort(port.try_clone()?);
+    thread::spawn(move || {
+        // An error means the serial loop closed its end; the firmware just stops.
+        let _ = run_firmware(format, firmware);
+    });
+    Ok((writer, HilPort(port)))
+}
+
+fn run_firmware(format: TelemetryFormat, mut socket: UnixStream) -> io::Result<()> {
+    let mut arduino = FakeArduino::new();
+    let sample = |arduino: &FakeArduino| match format {
+        TelemetryFormat::Ascii => arduino.telemetry_line().into_bytes(),
+        TelemetryFormat::Binary => arduino.telemetry_frame(),
+    };
+    let mut last_sample = Instant::now();
+    let mut buf = [0u8; 256];
+    loop {
+        match socket.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => arduino.receive(&buf[..n]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e),
+        }
+        while let Some(cmd) = arduino.next_command() {
+            let executed = arduino.apply(&cmd);
+            if format == TelemetryFormat::Ascii {
+                let reply = if executed { "ACK" } else { "NACK" };
+                socket.write_all(format!("{}:{}\r\n", reply, cmd).as_bytes())?;
+            }
+            if !executed {
+                continue;
+            }
+            socket.write_all(&sample(&arduino))?;
+            last_sample = Instant::now();
+        }
+        if last_sample.elapsed() >= TELEMETRY_PERIOD {
+            socket.write_all(&sample(&arduino))?;
+            last_sample = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn commands_are_acked_and_reflected_in_telemetry() {
+        let (mut writer, reader) = spawn(TelemetryFormat::Ascii, Duration::from_secs(2)).unwrap();
+        let mut reader = BufReader::new(reader);
+        writer.write_all(b"a\ns99\ns31\n").unwrap();
+        let mut replies = Vec::new();
+        let mut line = String::new();
+        let tel = loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if line.contains("ACK:") {
+                replies.push(line.trim().to_string());
+                continue;
+            }
+            let tel = crate::telemetry::parse_telemetry_line(line.trim()).unwrap();
+            if replies.len() == 3 {
+                break tel;
+            }
+        };
+        assert_eq!(replies, ["ACK:a", "NACK:s99", "ACK:s31"]);
+        assert!(tel.armed);
+        assert!(tel.solenoids[2]);
+        assert!(!tel.solenoids[0]);
+    }
+}
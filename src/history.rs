@@ -0,0 +1,377 @@
+// src/history.rs
+
+//! The telemetry history ring buffer. At 10 samples a second most consecutive frames differ
+//! only in their timestamp and a voltage or two, so the buffer keeps every
+//! `SNAPSHOT_INTERVAL`th sample whole and, in between, only what changed since the sample
+//! before. Reading a sample replays the changes from the snapshot before it.
+
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::{AppState, Telemetry};
+
+/// Default number of samples kept when `--history-size` is not given.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+/// Every this many samples one is stored whole.
+pub const SNAPSHOT_INTERVAL: usize = 10;
+
+/// The telemetry history, shared between the serial loop (which pushes) and the handlers.
+pub type SharedHistory = Arc<Mutex<TelemetryHistory>>;
+
+/// One field of a sample that differs from the sample before. Changes that cannot be said
+/// this briefly (a different number of channels, or other `extra` readings) make the sample
+/// a snapshot instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldChange {
+    Armed(bool),
+    Battery(f32),
+    BatteryRaw(f32),
+    Arming(f32),
+    /// A solenoid (by index) switched.
+    Solenoid(u8, bool),
+    /// A pyro channel's continuity (by index) changed.
+    PyroContinuity(u8, bool),
+}
+
+/// A sample stored as its changes from the sample before it.
+#[derive(Debug)]
+struct TelemetryDelta {
+    timestamp: u64,
+    changed_fields: Vec<FieldChange>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    Snapshot(Box<Telemetry>),
+    Delta(TelemetryDelta),
+}
+
+impl Entry {
+    fn timestamp(&self) -> u64 {
+        match self {
+            Entry::Snapshot(tel) => tel.timestamp,
+            Entry::Delta(delta) => delta.timestamp,
+        }
+    }
+}
+
+/// A fixed-capacity ring buffer of the most recently parsed telemetry samples.
+/// Once full, pushing a new sample drops the oldest one.
+pub struct TelemetryHistory {
+    /// The first entry is always a snapshot.
+    entries: VecDeque<Entry>,
+    /// The newest sample, whole, for computing the next delta.
+    newest: Option<Telemetry>,
+    /// Deltas pushed since the last snapshot.
+    since_snapshot: usize,
+    capacity: usize,
+}
+
+impl TelemetryHistory {
+    pub fn new(capacity: usize) -> Self {
+        TelemetryHistory {
+            entries: VecDeque::with_capacity(capacity),
+            newest: None,
+            since_snapshot: 0,
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Appends a sample, evicting the oldest one if the buffer is full.
+    pub fn push(&mut self, tel: Telemetry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.evict_oldest();
+        }
+        let changes = match &self.newest {
+            Some(previous) if self.since_snapshot + 1 < SNAPSHOT_INTERVAL => {
+                changes(previous, &tel)
+            }
+            _ => None,
+        };
+        match changes {
+            Some(changed_fields) => {
+                self.since_snapshot += 1;
+                self.entries.push_back(Entry::Delta(TelemetryDelta {
+                    timestamp: tel.timestamp,
+                    changed_fields,
+                }));
+            }
+            None => {
+                self.since_snapshot = 0;
+                self.entries
+                    .push_back(Entry::Snapshot(Box::new(tel.clone())));
+            }
+        }
+        self.newest = Some(tel);
+    }
+
+    /// Drops the first entry. If the next one is a delta it becomes a snapshot, as there is
+    /// nothing left to apply it to.
+    fn evict_oldest(&mut self) {
+        let Some(Entry::Snapshot(mut base)) = self.entries.pop_front() else {
+            unreachable!("the first entry is always a snapshot");
+        };
+        if let Some(next) = self.entries.front_mut() {
+            if let Entry::Delta(delta) = next {
+                apply(&mut base, delta);
+                *next = Entry::Snapshot(base);
+            }
+        }
+        if self.entries.is_empty() {
+            self.newest = None;
+        }
+    }
+
+    /// The sample at `idx` (0 is the oldest). Panics if `idx` is not below `len()`.
+    pub fn reconstruct(&self, idx: usize) -> Telemetry {
+        let Some(start) = (0..=idx)
+            .rev()
+            .find(|&i| matches!(self.entries[i], Entry::Snapshot(_)))
+        else {
+            unreachable!("the first entry is always a snapshot");
+        };
+        let mut tel = self.samples_from(start).nth(idx - start);
+        tel.take().expect("idx is in range")
+    }
+
+    /// The samples from `start` (an index of a snapshot) on, oldest first.
+    fn samples_from(&self, start: usize) -> impl Iterator<Item = Telemetry> + '_ {
+        let mut current: Option<Telemetry> = None;
+        self.entries.range(start..).map(move |entry| {
+            let tel = match (entry, current.take()) {
+                (Entry::Snapshot(tel), _) => (**tel).clone(),
+                (Entry::Delta(delta), Some(mut tel)) => {
+                    apply(&mut tel, delta);
+                    tel
+                }
+                (Entry::Delta(_), None) => unreachable!("deltas follow a snapshot"),
+            };
+            current = Some(tel.clone());
+            tel
+        })
+    }
+
+    /// The newest sample taken at or before `timestamp`, if the buffer reaches back that far.
+    pub fn at(&self, timestamp: u64) -> Option<Telemetry> {
+        let idx = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.timestamp() <= timestamp)?;
+        Some(self.reconstruct(idx))
+    }
+
+    /// The samples taken at or after `timestamp`, oldest first.
+    pub fn since(&self, timestamp: u64) -> Vec<Telemetry> {
+        self.samples_from(0)
+            .filter(|tel| tel.timestamp >= timestamp)
+            .collect()
+    }
+
+    /// Returns the last `limit` samples (oldest first). The limit is clamped to what is stored.
+    pub fn latest(&self, limit: usize) -> Vec<Telemetry> {
+        let skip = self.entries.len().saturating_sub(limit);
+        self.samples_from(0).skip(skip).collect()
+    }
+
+    /// Roughly how much memory the stored samples take: the entries and what they own.
+    pub fn bytes(&self) -> usize {
+        let entries: usize = self
+            .entries
+            .iter()
+            .map(|entry| {
+                size_of::<Entry>()
+                    + match entry {
+                        Entry::Snapshot(tel) => telemetry_bytes(tel),
+                        Entry::Delta(delta) => {
+                            delta.changed_fields.capacity() * size_of::<FieldChange>()
+                        }
+                    }
+            })
+            .sum();
+        entries + self.newest.as_ref().map_or(0, telemetry_bytes)
+    }
+
+    /// Roughly how much memory the stored samples would take if each were kept whole.
+    pub fn uncompressed_bytes(&self) -> usize {
+        self.samples_from(0).map(|tel| telemetry_bytes(&tel)).sum()
+    }
+}
+
+/// A whole sample and what it owns.
+fn telemetry_bytes(tel: &Telemetry) -> usize {
+    size_of::<Telemetry>()
+        + tel.solenoids.capacity()
+        + tel.pyro_continuity.capacity()
+        + tel.extra.capacity() * size_of::<(String, f64)>()
+        + tel.extra.keys().map(String::capacity).sum::<usize>()
+}
+
+/// What changed between two samples, or `None` if that takes a snapshot to store.
+fn changes(previous: &Telemetry, tel: &Telemetry) -> Option<Vec<FieldChange>> {
+    if tel.solenoids.len() != previous.solenoids.len()
+        || tel.pyro_continuity.len() != previous.pyro_continuity.len()
+        || tel.solenoids.len() > usize::from(u8::MAX)
+        || tel.extra != previous.extra
+    {
+        return None;
+    }
+    let mut changes = Vec::new();
+    if tel.armed != previous.armed {
+        changes.push(FieldChange::Armed(tel.armed));
+    }
+    let voltages = [
+        (
+            previous.battery,
+            tel.battery,
+            FieldChange::Battery as fn(f32) -> FieldChange,
+        ),
+        (
+            previous.battery_raw,
+            tel.battery_raw,
+            FieldChange::BatteryRaw,
+        ),
+        (previous.arming, tel.arming, FieldChange::Arming),
+    ];
+    for (old, new, change) in voltages {
+        // By bits, so NaN readings are kept exactly too.
+        if old.to_bits() != new.to_bits() {
+            changes.push(change(new));
+        }
+    }
+    let channels = [
+        (
+            &previous.solenoids,
+            &tel.solenoids,
+            FieldChange::Solenoid as fn(u8, bool) -> FieldChange,
+        ),
+        (
+            &previous.pyro_continuity,
+            &tel.pyro_continuity,
+            FieldChange::PyroContinuity,
+        ),
+    ];
+    for (old, new, change) in channels {
+        for (i, (&old, &new)) in old.iter().zip(new).enumerate() {
+            if old != new {
+                changes.push(change(i as u8, new));
+            }
+        }
+    }
+    changes.shrink_to_fit();
+    Some(changes)
+}
+
+/// Turns the sample before `delta` into the sample it stores.
+fn apply(tel: &mut Telemetry, delta: &TelemetryDelta) {
+    tel.timestamp = delta.timestamp;
+    for change in &delta.changed_fields {
+        match *change {
+            FieldChange::Armed(armed) => tel.armed = armed,
+            FieldChange::Battery(v) => tel.battery = v,
+            FieldChange::BatteryRaw(v) => tel.battery_raw = v,
+            FieldChange::Arming(v) => tel.arming = v,
+            FieldChange::Solenoid(i, on) => tel.solenoids[usize::from(i)] = on,
+            FieldChange::PyroContinuity(i, ok) => tel.pyro_continuity[usize::from(i)] = ok,
+        }
+    }
+}
+
+/// Response body for GET /metrics/ring_buffer_bytes.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RingBufferBytes {
+    pub samples: usize,
+    /// What the history takes as stored.
+    pub bytes: usize,
+    /// What it would take with every sample stored whole.
+    pub uncompressed_bytes: usize,
+}
+
+/// GET /metrics/ring_buffer_bytes reports the (estimated) memory the history buffer uses.
+#[get("/metrics/ring_buffer_bytes")]
+pub fn ring_buffer_bytes(state: &State<AppState>) -> Json<RingBufferBytes> {
+    let history = state.history.lock().unwrap();
+    Json(RingBufferBytes {
+        samples: history.len(),
+        bytes: history.bytes(),
+        uncompressed_bytes: history.uncompressed_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Ten samples a second of a board with a noisy battery, switching a solenoid now and
+    /// then, and with a thermocouple reading changing every second.
+    fn samples(count: u64) -> Vec<Telemetry> {
+        (0..count)
+            .map(|i| {
+                let mut solenoids = vec![false; 16];
+                solenoids[(i / 37 % 16) as usize] = i % 74 < 37;
+                Telemetry {
+                    timestamp: i * 100,
+                    armed: i > 20,
+                    battery: 12.6 - (i % 7) as f32 * 0.01,
+                    battery_raw: 12.6 - (i % 5) as f32 * 0.01,
+                    arming: if i > 20 { 12.5 } else { 0.0 },
+                    solenoids,
+                    pyro_continuity: vec![true, i % 50 < 25, true, false],
+                    extra: HashMap::from([("TC1".to_string(), (i / 10) as f64)]),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_sample_reconstructs() {
+        let samples = samples(250);
+        let mut history = TelemetryHistory::new(100);
+        for tel in &samples {
+            history.push(tel.clone());
+        }
+        let kept = &samples[150..];
+        assert_eq!(history.len(), kept.len());
+        let json = |tel: &Telemetry| rocket::serde::json::to_string(tel).unwrap();
+        for (idx, tel) in kept.iter().enumerate() {
+            assert_eq!(json(&history.reconstruct(idx)), json(tel), "sample {}", idx);
+        }
+        assert_eq!(history.latest(3).len(), 3);
+        assert_eq!(json(&history.latest(1)[0]), json(&samples[249]));
+        assert_eq!(history.at(20_050).unwrap().timestamp, 20_000);
+        assert!(history.at(14_999).is_none());
+        assert_eq!(history.since(24_000).len(), 10);
+    }
+
+    #[test]
+    fn deltas_take_at_least_40_percent_less_memory() {
+        let mut history = TelemetryHistory::new(DEFAULT_HISTORY_CAPACITY);
+        for tel in samples(DEFAULT_HISTORY_CAPACITY as u64 + 500) {
+            history.push(tel);
+        }
+        let (bytes, whole) = (history.bytes(), history.uncompressed_bytes());
+        assert!(
+            bytes * 10 <= whole * 6,
+            "{} bytes vs {} whole",
+            bytes,
+            whole
+        );
+    }
+}
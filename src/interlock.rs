@@ -0,0 +1,210 @@
+// src/interlock.rs
+
+//! Solenoid interlocks (`[[interlock]]` in the config): pairs of valves that must never be
+//! open at the same time, such as an oxidizer valve and a fuel valve.
+//!
+//! Every solenoid command for the primary board goes through a `SolenoidGuard`, which refuses
+//! to open a valve while the other valve of any of its interlocks is open (409), and to switch
+//! on a solenoid over its duty cycle (429). Open and closed are the valves' physical states,
+//! as in `groups`: what the latest telemetry reports, through `[solenoid_directions]`, with
+//! every solenoid of the command applied, so one batch cannot open both valves of a pair.
+//! Closing a valve is never refused. /config/interlocks changes the interlocks at runtime
+//! (see `runtime_config`).
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::config::{Interlock, SolenoidInfo};
+use crate::error::ApiError;
+use crate::flight_log::EventType;
+use crate::runtime_config::SharedRuntimeConfig;
+use crate::safety::SharedDutyCycle;
+use crate::SharedTelemetry;
+
+/// What a solenoid command is checked against: the primary board's telemetry, the runtime
+/// interlocks and the duty cycles. Cheap to clone, so background senders (sequences, scripts,
+/// the countdown) hold their own.
+#[derive(Clone, Default)]
+pub struct SolenoidGuard {
+    telemetry: SharedTelemetry,
+    runtime_config: SharedRuntimeConfig,
+    duty_cycle: SharedDutyCycle,
+}
+
+impl SolenoidGuard {
+    pub fn new(
+        telemetry: SharedTelemetry,
+        runtime_config: SharedRuntimeConfig,
+        duty_cycle: SharedDutyCycle,
+    ) -> Self {
+        SolenoidGuard {
+            telemetry,
+            runtime_config,
+            duty_cycle,
+        }
+    }
+
+    /// Checks the solenoid commands in `text` (one per line) against the latest telemetry.
+    pub fn check(&self, text: &str) -> Result<(), ApiError> {
+        let solenoids = self.telemetry.read().unwrap().solenoids.clone();
+        self.check_against(text, &solenoids)
+    }
+
+    /// Checks the solenoid commands in `text` given which solenoids are energized now. For
+    /// callers already holding the telemetry lock.
+    pub fn check_against(&self, text: &str, solenoids: &[bool]) -> Result<(), ApiError> {
+        let changes: Vec<(u8, bool)> = text
+            .lines()
+            .filter_map(EventType::from_command)
+            .filter_map(|event| match event {
+                EventType::Solenoid { channel, state } if (1..=16).contains(&channel) => {
+                    Some((channel, state))
+                }
+                _ => None,
+            })
+            .collect();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut after = solenoids.to_vec();
+        after.resize(16, false);
+        for &(channel, energized) in &changes {
+            after[usize::from(channel) - 1] = energized;
+        }
+        {
+            let runtime = self.runtime_config.lock().unwrap();
+            let info = runtime.solenoid_info();
+            for &(channel, energized) in &changes {
+                check(&runtime.interlocks, channel, energized, &after, &info)?;
+            }
+        }
+        let mut duty_cycle = self.duty_cycle.lock().unwrap();
+        let now = Instant::now();
+        for &(channel, _) in changes.iter().filter(|&&(_, energized)| energized) {
+            if !duty_cycle.may_switch_on(channel, now) {
+                return Err(ApiError::DutyCycleExceeded);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks commanding the solenoid on `channel` to `energized` against `rules`, given which
+/// solenoids are energized according to telemetry.
+pub fn check(
+    rules: &[Interlock],
+    channel: u8,
+    energized: bool,
+    solenoids: &[bool],
+    info: &HashMap<u8, SolenoidInfo>,
+) -> Result<(), ApiError> {
+    let is_open = |ch: u8, energized: bool| {
+        let direction = info.get(&ch).map(|i| i.direction).unwrap_or_default();
+        direction.is_open(energized)
+    };
+    if !is_open(channel, energized) {
+        return Ok(());
+    }
+    // `Config::load` checked that every rule has two channels.
+    let others = rules.iter().filter_map(|rule| match *rule.prevent {
+        [a, b] if a == channel => Some(b),
+        [a, b] if b == channel => Some(a),
+        _ => None,
+    });
+    for other in others {
+        let energized = solenoids.get(other as usize - 1).copied().unwrap_or(false);
+        if is_open(other, energized) {
+            return Err(ApiError::InterlockViolation {
+                channel,
+                blocked_by: other,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{solenoid_info, SolenoidDirection};
+
+    fn rules(pairs: &[[u8; 2]]) -> Vec<Interlock> {
+        pairs
+            .iter()
+            .map(|pair| Interlock {
+                prevent: pair.to_vec(),
+            })
+            .collect()
+    }
+
+    fn energized(channels: &[u8]) -> Vec<bool> {
+        (1..=16).map(|ch| channels.contains(&ch)).collect()
+    }
+
+    #[test]
+    fn blocks_opening_either_valve_of_a_pair() {
+        let info = solenoid_info(&HashMap::new(), &HashMap::new());
+        let rules = rules(&[[3, 7]]);
+        let blocked = |channel, blocked_by| {
+            Err(ApiError::InterlockViolation {
+                channel,
+                blocked_by,
+            })
+        };
+        assert_eq!(
+            check(&rules, 7, true, &energized(&[3]), &info),
+            blocked(7, 3)
+        );
+        assert_eq!(
+            check(&rules, 3, true, &energized(&[7]), &info),
+            blocked(3, 7)
+        );
+        assert_eq!(check(&rules, 7, true, &energized(&[]), &info), Ok(()));
+        // Unrelated channels and closing are never blocked.
+        assert_eq!(check(&rules, 5, true, &energized(&[3, 7]), &info), Ok(()));
+        assert_eq!(check(&rules, 7, false, &energized(&[3, 7]), &info), Ok(()));
+    }
+
+    #[test]
+    fn every_rule_of_a_channel_is_checked() {
+        let info = solenoid_info(&HashMap::new(), &HashMap::new());
+        let rules = rules(&[[3, 7], [7, 9]]);
+        let result = check(&rules, 7, true, &energized(&[9]), &info);
+        assert_eq!(
+            result,
+            Err(ApiError::InterlockViolation {
+                channel: 7,
+                blocked_by: 9
+            })
+        );
+        assert_eq!(check(&rules, 3, true, &energized(&[9]), &info), Ok(()));
+    }
+
+    #[test]
+    fn follows_the_valve_direction() {
+        let mut info = solenoid_info(&HashMap::new(), &HashMap::new());
+        info.get_mut(&3).unwrap().direction = SolenoidDirection::NormallyOpen;
+        let rules = rules(&[[3, 7]]);
+        // Valve 3 is open while its solenoid is off...
+        assert!(check(&rules, 7, true, &energized(&[]), &info).is_err());
+        assert_eq!(check(&rules, 7, true, &energized(&[3]), &info), Ok(()));
+        // ...and opening it means de-energizing it.
+        assert!(check(&rules, 3, false, &energized(&[3, 7]), &info).is_err());
+        assert_eq!(check(&rules, 3, true, &energized(&[7]), &info), Ok(()));
+    }
+
+    #[test]
+    fn guard_checks_the_state_after_the_whole_command() {
+        let guard = SolenoidGuard::default();
+        guard.runtime_config.lock().unwrap().interlocks = rules(&[[3, 7]]);
+        let blocked = Err(ApiError::InterlockViolation {
+            channel: 3,
+            blocked_by: 7,
+        });
+        assert_eq!(guard.check("s31\ns71"), blocked);
+        assert_eq!(guard.check_against("s30\ns71", &energized(&[3])), Ok(()));
+        assert!(guard.check_against("s71", &energized(&[3])).is_err());
+        // Arming and closing are never refused.
+        assert_eq!(guard.check_against("a\ns70", &energized(&[3, 7])), Ok(()));
+    }
+}
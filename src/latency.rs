@@ -0,0 +1,141 @@
+// src/latency.rs
+
+//! Command latency tracking: the time from an HTTP command request arriving to its bytes
+//! being written to the serial port.
+//!
+//! The fairing stamps each command request on arrival (`RequestStart`), the handler forwards
+//! that stamp with the queued command, and the serial loop reports back a `CommandAck` once
+//! the write completes. The fairing collects those acks into a `LatencyTracker`.
+
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::serde::Serialize;
+use rocket::Data;
+
+use crate::session::{SessionId, SESSION_HEADER};
+
+/// Number of recent commands the latency statistics are computed over.
+pub const LATENCY_WINDOW: usize = 100;
+
+/// When a command request arrived, as stamped by `CommandLatencyFairing`, and the operator
+/// session it names (`X-Session-Token`), if any.
+/// Used as a request guard; if the fairing is not attached the stamp is taken in the guard.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestStart {
+    pub at: Instant,
+    pub session: Option<SessionId>,
+}
+
+impl RequestStart {
+    /// Stamps `req` now.
+    fn of(req: &Request<'_>) -> Self {
+        RequestStart {
+            at: Instant::now(),
+            session: req.headers().get_one(SESSION_HEADER).and_then(|token| token.parse().ok()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestStart {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(*req.local_cache(|| RequestStart::of(req)))
+    }
+}
+
+/// Sent by the serial loop once a command has been written to the port.
+pub struct CommandAck {
+    pub received_at: Instant,
+    pub written_at: Instant,
+}
+
+/// The latencies of the last `LATENCY_WINDOW` commands.
+#[derive(Default)]
+pub struct LatencyTracker {
+    samples: VecDeque<Duration>,
+}
+
+/// A shared latency tracker (written by the fairing, read by GET /metrics/latency).
+pub type SharedLatency = Arc<Mutex<LatencyTracker>>;
+
+/// Min/max/mean latency in milliseconds; the values are `null` until a command has been sent.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LatencyStats {
+    pub count: usize,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub mean_ms: Option<f64>,
+}
+
+impl LatencyTracker {
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let ms: Vec<f64> = self.samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        let count = ms.len();
+        LatencyStats {
+            count,
+            min_ms: ms.iter().copied().reduce(f64::min),
+            max_ms: ms.iter().copied().reduce(f64::max),
+            mean_ms: (count > 0).then(|| ms.iter().sum::<f64>() / count as f64),
+        }
+    }
+}
+
+/// Stamps command requests (POST /arm, /disarm, /solenoid/*) on arrival and collects
+/// the serial loop's write acknowledgements into the shared `LatencyTracker`.
+pub struct CommandLatencyFairing {
+    acks: Mutex<mpsc::Receiver<CommandAck>>,
+    latencies: SharedLatency,
+}
+
+impl CommandLatencyFairing {
+    pub fn new(acks: mpsc::Receiver<CommandAck>, latencies: SharedLatency) -> Self {
+        CommandLatencyFairing { acks: Mutex::new(acks), latencies }
+    }
+
+    /// Moves any acknowledgements that arrived since the last request into the tracker.
+    fn collect_acks(&self) {
+        let acks = self.acks.lock().unwrap();
+        let mut latencies = self.latencies.lock().unwrap();
+        while let Ok(ack) = acks.try_recv() {
+            latencies.record(ack.written_at.saturating_duration_since(ack.received_at));
+        }
+    }
+}
+
+/// Whether this request is one of the timed command endpoints.
+fn is_command_request(req: &Request<'_>) -> bool {
+    let path = req.uri().path();
+    req.method() == Method::Post
+        && (path == "/arm" || path == "/disarm" || path.starts_with("/solenoid/"))
+}
+
+#[rocket::async_trait]
+impl Fairing for CommandLatencyFairing {
+    fn info(&self) -> Info {
+        Info { name: "Command Latency", kind: Kind::Request }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        if is_command_request(req) {
+            req.local_cache(|| RequestStart::of(req));
+        }
+        // Acks arrive after the response has gone out, so pick them up on the next request
+        // (including the GET /metrics/latency that wants to read them).
+        self.collect_acks();
+    }
+}
@@ -0,0 +1,4343 @@
+// src/lib.rs
+
+//! The ground control server: the serial loop talking to the Arduino and the Rocket
+//! application serving its telemetry and accepting commands. `main.rs` only launches
+//! `rocket()`; `rocket_with_link` runs the same server over any reader and writer, for the
+//! integration tests.
+
+#[macro_use] extern crate rocket;
+
+mod ack;
+mod alerts;
+mod arm_audit;
+mod argon2;
+mod auth;
+mod base64;
+mod basic_auth;
+mod board;
+mod cluster;
+#[cfg(feature = "can")]
+mod can_link;
+mod command;
+mod command_queue;
+mod config;
+mod continuity;
+mod countdown;
+mod cors;
+mod csv_log;
+#[cfg(feature = "sqlite")]
+mod db;
+mod diagram;
+mod error;
+mod filters;
+mod firmware_command;
+mod firmware_reset;
+mod flight_log;
+mod groups;
+mod health;
+#[cfg(unix)]
+mod hil;
+mod history;
+mod interlock;
+mod latency;
+mod logging;
+mod metrics;
+mod msgpack;
+mod raw_log;
+mod reconcile;
+mod regex;
+mod replay;
+mod request_limit;
+mod safety;
+mod script;
+mod pulse;
+mod runtime_config;
+mod self_test;
+mod sequence;
+mod serial_ports;
+mod session;
+mod sha256;
+mod shutdown;
+mod snapshot;
+mod state_machine;
+mod state_file;
+mod simulator;
+mod stats;
+mod telemetry;
+#[cfg(feature = "templates")]
+mod template;
+mod udp_link;
+mod udp_relay;
+mod verifier;
+#[cfg(feature = "webhook")]
+mod webhook;
+mod ws;
+
+use ack::{AckEvent, AckKind, AckStats, PendingCommands, SharedAckLog};
+use arm_audit::{ArmAction, ArmAuditLog, ArmEvent, SharedArmAudit, SourceIp};
+use config::{Config, FilterConfig, SerialConfig, DEFAULT_CONFIG_PATH};
+use countdown::Countdown;
+use auth::{Authenticated, SignedJson};
+use basic_auth::BasicAuthFairing;
+use board::BoardState;
+use cluster::{Cluster, ClusterProxyFairing, LeaderOnly, SharedCluster};
+use command_queue::{CommandReceiver, CommandSender};
+use cors::CorsFairing;
+use csv_log::{CsvLog, SharedCsvLog};
+use error::ApiError;
+use filters::{SharedCalibration, TelemetryFilters, VoltageCalibration};
+use firmware_reset::SharedFirmwareReset;
+use flight_log::{EventType, FlightEvent, SharedFlightLog};
+use health::SharedParseRate;
+use history::{SharedHistory, TelemetryHistory};
+use logging::LogFormat;
+use latency::{
+    CommandAck, CommandLatencyFairing, LatencyStats, LatencyTracker, RequestStart, SharedLatency,
+};
+use metrics::Metrics;
+use raw_log::{RawTap, SharedRawLog};
+use reconcile::Reconciler;
+use replay::{ReplayStatus, SharedReplayStatus};
+use request_limit::{RateLimitFairing, RequestLimiter, DEFAULT_BURST, DEFAULT_REFILL_PER_S};
+use rocket::response::content::{RawHtml, RawJson, RawText};
+use rocket::response::status;
+use rocket::http::{Accept, ContentType, Header, Status};
+use rocket::response::stream::{Event, EventStream, TextStream};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast;
+use rocket::{Build, Rocket, Shutdown, State};
+use session::{SessionId, SharedSessions};
+use state_machine::{ArmEvent as ArmLifecycle, SharedArmState};
+use verifier::{CommandVerifier, SharedVerifier};
+use safety::{
+    ArmConfirmError, ArmIntent, DutyCycleTracker, OpenTimers, RateLimiter, SharedDutyCycle,
+    SharedOpenTimers, DEFAULT_ARM_CONFIRM_WINDOW_MS, DEFAULT_MIN_INTERVAL_MS,
+};
+use alerts::SharedActiveAlerts;
+use command::CommandBuilder;
+use pulse::{PulseInfo, PulseRunner};
+use interlock::SolenoidGuard;
+use runtime_config::{RuntimeConfig, SharedRuntimeConfig};
+use diagram::DiagramLayout;
+use self_test::SelfTestFairing;
+use shutdown::{ShutdownFairing, ShutdownSignal};
+use snapshot::SharedSnapshots;
+use state_file::StateFile;
+use script::ScriptRunner;
+use sequence::{SequenceCommand, SequenceRunner, SequenceStatus, SequenceStep};
+use stats::{TelemetryStats, DEFAULT_STATS_WINDOW_S};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::Path;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, info_span, warn};
+use telemetry::{
+    parse_telemetry_line, solenoids_to_mask, BinaryFrameReader, LineFormat, ParseError,
+    TelemetryDiff, TelemetryFormat,
+};
+use ws::{TelemetryStream, WebSocketKey};
+
+/// The telemetry structure matching the Arduino telemetry format.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+struct Telemetry {
+    timestamp: u64,
+    armed: bool,
+    /// Battery voltage, smoothed by a rolling mean (see `filters`).
+    battery: f32,
+    /// The unfiltered battery reading.
+    battery_raw: f32,
+    /// Arming sense voltage, smoothed like `battery`.
+    arming: f32,
+    /// For simplicity we keep the solenoid states as a vector of booleans (length 16).
+    solenoids: Vec<bool>,
+    /// Pyro channel continuity (length 4, `true` = OK).
+    /// All `false` when the firmware doesn't report it.
+    pyro_continuity: Vec<bool>,
+    /// Readings of extra sensors by name (the `EXT:` segment), e.g. "TC1" for a thermocouple.
+    /// Left out of the JSON when there are none.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    extra: HashMap<String, f64>,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry {
+            timestamp: 0,
+            armed: false,
+            battery: 0.0,
+            battery_raw: 0.0,
+            arming: 0.0,
+            solenoids: vec![false; 16],
+            pyro_continuity: vec![false; 4],
+            extra: HashMap::new(),
+        }
+    }
+}
+
+/// Highest voltage either the battery or the arming sense line can plausibly read.
+const MAX_PLAUSIBLE_VOLTS: f32 = 30.0;
+
+impl Telemetry {
+    /// Physical plausibility of a parsed sample: voltages within 0–30 V, a non-zero
+    /// timestamp and all 16 solenoids. Parsing only checks the structure of the line.
+    fn is_valid(&self) -> bool {
+        let volts = 0.0..=MAX_PLAUSIBLE_VOLTS;
+        volts.contains(&self.battery)
+            && volts.contains(&self.arming)
+            && self.timestamp != 0
+            && self.solenoids.len() == 16
+    }
+}
+
+/// The latest telemetry. Only the serial loop (and POST /telemetry/inject) writes it, so
+/// readers share the lock.
+type SharedTelemetry = Arc<RwLock<Telemetry>>;
+
+/// Baud rate used when `--baud` is not given.
+const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// The standard rates accepted by `--baud`.
+const SUPPORTED_BAUD_RATES: &[u32] = &[
+    300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 38400, 57600, 115200, 230400, 460800, 921600,
+];
+
+/// Lowest port `--http-port` accepts; the ones below need root.
+const MIN_HTTP_PORT: u16 = 1024;
+
+/// Consecutive serial read errors before the port is considered lost
+/// (overridden by `--reconnect-threshold`).
+const DEFAULT_ERROR_THRESHOLD: u32 = 5;
+
+/// How many unsent updates a slow WebSocket client may fall behind
+/// before it starts skipping samples.
+const TELEMETRY_BROADCAST_CAPACITY: usize = 16;
+
+/// How often GET /events sends a keepalive comment.
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// A command string queued for the serial loop, stamped with the arrival time of the
+/// HTTP request that produced it so the write latency can be measured, and with the
+/// operator session that request named, so it is logged for that session too.
+struct QueuedCommand {
+    text: String,
+    received_at: Instant,
+    session: Option<SessionId>,
+}
+
+impl QueuedCommand {
+    fn new(text: impl Into<String>, start: RequestStart) -> Self {
+        QueuedCommand { text: text.into(), received_at: start.at, session: start.session }
+    }
+
+    /// A command generated by the server itself (e.g. a sequence step), timed from now.
+    fn immediate(text: impl Into<String>) -> Self {
+        QueuedCommand { text: text.into(), received_at: Instant::now(), session: None }
+    }
+}
+
+/// Our application state now holds both the telemetry and a command sender.
+/// When a button is pressed, the corresponding command string (e.g. "a", "d", or "s51")
+/// is sent via this channel to the serial loop thread.
+struct AppState {
+    telemetry: SharedTelemetry,
+    /// The most recent telemetry samples, newest last.
+    history: SharedHistory,
+    /// Every newly-parsed telemetry sample is broadcast here for push clients (WebSockets).
+    telemetry_tx: broadcast::Sender<Telemetry>,
+    /// Commands for the serial loop, which writes them in order.
+    command_tx: CommandSender,
+    /// Latencies of recent commands, filled in by `CommandLatencyFairing`.
+    latencies: SharedLatency,
+    /// Commands written to the Arduino that have not been ACKed yet.
+    pending_commands: PendingCommands,
+    /// Write-to-ACK round trips of recently acknowledged commands.
+    ack_round_trips: SharedLatency,
+    /// The latest ACK/NACK lines from the Arduino, and the last NACK.
+    ack_log: SharedAckLog,
+    /// The last bytes received from the serial port, for GET /serial/raw.
+    raw_log: SharedRawLog,
+    /// Priority channel for emergency stops, drained by the serial loop before `command_tx`.
+    emergency_tx: mpsc::SyncSender<String>,
+    /// Serial link state, maintained by the serial loop.
+    connection_status: SharedConnectionStatus,
+    /// Asks the serial loop to re-open on another port (POST /serial/reconnect).
+    port_switch: Arc<PortSwitch>,
+    /// The desired solenoid states, checked against telemetry after a reconnect.
+    reconciler: Arc<Reconciler>,
+    /// How long each time-limited solenoid has been on, for GET /solenoid/timers.
+    open_timers: SharedOpenTimers,
+    /// How much of the last minute each solenoid was on, for GET /solenoid/duty.
+    duty_cycle: SharedDutyCycle,
+    /// Every command written to the Arduino, for post-flight debriefs.
+    flight_log: SharedFlightLog,
+    /// Every arm, disarm and emergency stop requested, for GET /audit/arm (append-only).
+    arm_audit: SharedArmAudit,
+    /// Correction applied to the raw battery reading, set by POST /calibrate/battery.
+    battery_calibration: SharedCalibration,
+    /// The timed command sequence started by POST /sequence, if any.
+    sequence: SequenceRunner,
+    /// The test script started by POST /script/run, if any.
+    script: ScriptRunner,
+    /// Timed pulses started by POST /solenoid/<channel>/pulse.
+    pulses: PulseRunner,
+    /// The latest snapshots taken by POST /snapshot.
+    snapshots: SharedSnapshots,
+    /// Where the telemetry is saved on shutdown (`--state-file`), if anywhere.
+    state_file: Option<StateFile>,
+    /// The `[[alert]]` rules firing now.
+    active_alerts: SharedActiveAlerts,
+    /// Counters exported at GET /metrics.
+    metrics: Arc<Metrics>,
+    /// When telemetry was parsed recently, for GET /health.
+    parse_rate: SharedParseRate,
+    /// When the server started, for GET /health.
+    started_at: Instant,
+    /// Path of the CSV telemetry log, if `--log-file` was given.
+    log_path: Option<String>,
+    /// Path of the SQLite telemetry database, if `--db` was given.
+    db_path: Option<String>,
+    /// Progress of `--replay`, which replaces the primary board's serial loop.
+    replay: Option<SharedReplayStatus>,
+    /// Refuse solenoid commands unless the latest telemetry reports armed.
+    require_armed: bool,
+    /// Refuse POST /solenoid/all/open|close unless the latest telemetry reports armed.
+    require_armed_for_all: bool,
+    /// Refuse single-step POST /arm; arming goes through /arm/intent and /arm/confirm.
+    require_two_step: bool,
+    /// The outstanding POST /arm/intent, if any.
+    arm_intent: Mutex<ArmIntent>,
+    /// Where the board is in its arm/disarm lifecycle; the arm endpoints follow it.
+    arm_state: SharedArmState,
+    /// Commands are logged instead of written to the port (`--dry-run`).
+    dry_run: bool,
+    /// The other servers of a `[cluster]`, and which of them leads.
+    cluster: SharedCluster,
+    /// Mount POST /telemetry/inject (`--allow-inject`).
+    allow_inject: bool,
+    /// Mount POST /firmware/command (`--dev-mode`, debug builds only).
+    dev_mode: bool,
+    /// Mount POST /firmware/reset (`--allow-firmware-reset`).
+    allow_firmware_reset: bool,
+    /// A POST /firmware/reset waiting for the serial loop, and when the last one came.
+    firmware_reset: SharedFirmwareReset,
+    /// Refuses solenoid commands that come too soon after the previous one per channel.
+    rate_limiter: Mutex<RateLimiter>,
+    /// POST budgets per client IP, spent in `RateLimitFairing`.
+    request_limiter: Mutex<RequestLimiter>,
+    /// Origins that get CORS headers (`"*"` for any).
+    allowed_origins: Vec<String>,
+    /// Solenoid labels and directions, the named groups of POST /group/<name>/open|close
+    /// and the interlocks of POST /solenoid/<channel>/<state>, editable through /config/...
+    runtime_config: SharedRuntimeConfig,
+    /// Where GET /diagram draws the valves and pipes.
+    diagram: DiagramLayout,
+    /// Shared secret POST requests must be signed with; `None` disables the check.
+    auth_secret: Option<Vec<u8>>,
+    /// Username and password every request must carry; `None` disables basic auth.
+    basic_auth: Option<basic_auth::Credentials>,
+    /// Set by the watchdog when telemetry was lost and it disarmed; cleared by POST /arm.
+    watchdog_tripped: Arc<AtomicBool>,
+    /// Set while the battery drains faster than `max_drain_rate_v_per_s`.
+    battery_draining: Arc<AtomicBool>,
+    /// Operator sessions and their command logs.
+    sessions: SharedSessions,
+    /// Solenoid commands whose effect telemetry has yet to show, and those it never did.
+    verifier: SharedVerifier,
+    /// The launch countdown, with the `[countdown]` commands for T-0. Shared with the
+    /// watchdog, which aborts it.
+    countdown: Arc<Countdown>,
+    /// The ID of the board driven by the fields above: the first `[[board]]`, or 0.
+    board_id: u8,
+    /// The other `[[board]]`s of a multi-board stand, each with its own serial loop.
+    secondary_boards: Vec<BoardState>,
+    /// Tells the serial loops to drain, disarm and exit when Rocket shuts down.
+    shutdown: ShutdownSignal,
+}
+
+/// The serial loop's ends of the channels in `AppState`.
+struct SerialEndpoints {
+    commands: CommandReceiver,
+    emergency: mpsc::Receiver<String>,
+    /// Acknowledges each written command for latency tracking.
+    acks: mpsc::Sender<CommandAck>,
+    /// Written commands are recorded here until the Arduino ACKs them.
+    pending_commands: PendingCommands,
+    ack_round_trips: SharedLatency,
+    /// Every ACK/NACK line is appended here.
+    ack_log: SharedAckLog,
+    /// Every chunk read from the port is copied here (the raw log collector), if set.
+    raw_bytes: Option<mpsc::SyncSender<Vec<u8>>>,
+    /// Checked at the top of the serial loop for a requested port change.
+    port_switch: Arc<PortSwitch>,
+    /// Updated from every solenoid command; corrects the board's state once per session.
+    reconciler: Arc<Reconciler>,
+    /// Checks the reconciler's corrections like the API's commands; `None` for secondaries.
+    solenoid_guard: Option<SolenoidGuard>,
+    /// Started and stopped by every solenoid command written.
+    open_timers: SharedOpenTimers,
+    /// Every solenoid command written is recorded here too.
+    duty_cycle: SharedDutyCycle,
+    /// Each successful command write is appended here.
+    flight_log: SharedFlightLog,
+    /// Commands tagged with a session are also appended to its log.
+    sessions: SharedSessions,
+    /// Expects the effect of every solenoid command written; checked against each frame.
+    verifier: SharedVerifier,
+    /// Told whether each frame reports the board armed.
+    arm_state: SharedArmState,
+    /// Set by POST /firmware/reset; the loop toggles the port's DTR line.
+    firmware_reset: SharedFirmwareReset,
+    /// Applied to every battery reading before filtering.
+    battery_calibration: SharedCalibration,
+    /// Set when the server shuts down: the loop drains the commands, disarms and exits.
+    shutdown: ShutdownSignal,
+}
+
+impl AppState {
+    /// Creates the application state with default telemetry and no serial link yet.
+    /// Also returns the channel ends for the serial loop, and the receiving end of its
+    /// command acknowledgements (for `CommandLatencyFairing`).
+    fn new(
+        history_size: usize,
+        log_path: Option<String>,
+    ) -> (AppState, SerialEndpoints, mpsc::Receiver<CommandAck>) {
+        // Create a channel for sending command strings to the serial loop.
+        let (command_tx, commands) = command_queue::channel();
+        // The emergency channel only ever needs to hold one pending stop.
+        let (emergency_tx, emergency) = mpsc::sync_channel::<String>(1);
+        // The serial loop acknowledges each written command here so latency can be measured.
+        let (acks, ack_rx) = mpsc::channel::<CommandAck>();
+        // And a broadcast channel carrying parsed telemetry out to push clients.
+        let (telemetry_tx, _) = broadcast::channel::<Telemetry>(TELEMETRY_BROADCAST_CAPACITY);
+        let pending_commands = PendingCommands::default();
+        let ack_round_trips = SharedLatency::default();
+        let ack_log = SharedAckLog::default();
+        let raw_log = SharedRawLog::default();
+        let raw_bytes = raw_log::spawn_collector(raw_log.clone());
+        let port_switch = Arc::new(PortSwitch::default());
+        let reconciler = Arc::new(Reconciler::default());
+        let open_timers = SharedOpenTimers::default();
+        let duty_cycle = SharedDutyCycle::default();
+        let flight_log = SharedFlightLog::default();
+        let sessions = SharedSessions::default();
+        let verifier = SharedVerifier::default();
+        let arm_state = SharedArmState::default();
+        let firmware_reset = SharedFirmwareReset::default();
+        let battery_calibration = SharedCalibration::default();
+        let shutdown = ShutdownSignal::default();
+
+        let state = AppState {
+            telemetry: SharedTelemetry::default(),
+            history: Arc::new(Mutex::new(TelemetryHistory::new(history_size))),
+            telemetry_tx,
+            command_tx,
+            latencies: Arc::new(Mutex::new(LatencyTracker::default())),
+            pending_commands: pending_commands.clone(),
+            ack_round_trips: ack_round_trips.clone(),
+            ack_log: ack_log.clone(),
+            raw_log,
+            emergency_tx,
+            connection_status: Arc::new(Mutex::new(ConnectionStatus::Reconnecting(0))),
+            port_switch: port_switch.clone(),
+            reconciler: reconciler.clone(),
+            open_timers: open_timers.clone(),
+            duty_cycle: duty_cycle.clone(),
+            flight_log: flight_log.clone(),
+            arm_audit: SharedArmAudit::default(),
+            battery_calibration: battery_calibration.clone(),
+            sequence: SequenceRunner::default(),
+            script: ScriptRunner::default(),
+            pulses: PulseRunner::default(),
+            snapshots: SharedSnapshots::default(),
+            state_file: None,
+            active_alerts: SharedActiveAlerts::default(),
+            metrics: Arc::new(Metrics::default()),
+            parse_rate: SharedParseRate::default(),
+            started_at: Instant::now(),
+            log_path,
+            db_path: None,
+            replay: None,
+            require_armed: false,
+            require_armed_for_all: true,
+            require_two_step: false,
+            arm_intent: Mutex::new(ArmIntent::new(Duration::from_millis(
+                DEFAULT_ARM_CONFIRM_WINDOW_MS,
+            ))),
+            arm_state: arm_state.clone(),
+            dry_run: false,
+            cluster: SharedCluster::default(),
+            allow_inject: false,
+            dev_mode: false,
+            allow_firmware_reset: false,
+            firmware_reset: firmware_reset.clone(),
+            rate_limiter: Mutex::new(RateLimiter::new(Duration::from_millis(
+                DEFAULT_MIN_INTERVAL_MS,
+            ))),
+            request_limiter: Mutex::new(RequestLimiter::new(DEFAULT_BURST, DEFAULT_REFILL_PER_S)),
+            allowed_origins: Vec::new(),
+            runtime_config: SharedRuntimeConfig::default(),
+            diagram: DiagramLayout::default(),
+            auth_secret: None,
+            basic_auth: None,
+            watchdog_tripped: Arc::new(AtomicBool::new(false)),
+            battery_draining: Arc::new(AtomicBool::new(false)),
+            sessions: sessions.clone(),
+            verifier: verifier.clone(),
+            countdown: Arc::default(),
+            board_id: 0,
+            secondary_boards: Vec::new(),
+            shutdown: shutdown.clone(),
+        };
+        let endpoints = SerialEndpoints {
+            commands,
+            emergency,
+            acks,
+            pending_commands,
+            ack_round_trips,
+            ack_log,
+            raw_bytes: Some(raw_bytes),
+            port_switch,
+            reconciler,
+            solenoid_guard: None,
+            open_timers,
+            duty_cycle,
+            flight_log,
+            sessions,
+            verifier,
+            arm_state,
+            firmware_reset,
+            battery_calibration,
+            shutdown,
+        };
+        (state, endpoints, ack_rx)
+    }
+
+    /// Queues a command for the primary board's serial loop.
+    fn send_command(&self, cmd: QueuedCommand) -> Result<(), ApiError> {
+        self.primary_board().send_command(cmd)
+    }
+
+    /// Queues a solenoid command for the primary board (see `Board::send_solenoid_command`).
+    fn send_solenoid_command(&self, cmd: QueuedCommand, channels: &[u8]) -> Result<(), ApiError> {
+        self.primary_board().send_solenoid_command(cmd, channels)
+    }
+
+    /// Whether the latest telemetry already shows the primary board the way a command would
+    /// leave it, so the command need not be sent. That is only trusted while the link is up
+    /// and nothing is queued that could still change the state. Skips are counted.
+    fn already_in_state(&self, matches: impl FnOnce(&Telemetry) -> bool) -> bool {
+        let connected = *self.connection_status.lock().unwrap() == ConnectionStatus::Connected;
+        let unchanged = connected
+            && self.command_tx.pending() == 0
+            && matches(&self.telemetry.read().unwrap());
+        if unchanged {
+            self.metrics.dedup_skips.fetch_add(1, Ordering::Relaxed);
+        }
+        unchanged
+    }
+
+    /// Takes the arm lifecycle through `events` (see `ArmPath::drive`).
+    fn drive_arm<T>(
+        &self,
+        events: &[ArmLifecycle],
+        send: impl FnOnce() -> Result<T, ApiError>,
+    ) -> Result<T, ApiError> {
+        self.arm_path().drive(events, send)
+    }
+
+    /// The arm lifecycle and audit, for arming and disarming off the request threads.
+    fn arm_path(&self) -> ArmPath {
+        ArmPath {
+            arm_state: self.arm_state.clone(),
+            arm_audit: self.arm_audit.clone(),
+            watchdog_tripped: self.watchdog_tripped.clone(),
+            require_two_step: self.require_two_step,
+            board_id: self.board_id,
+        }
+    }
+
+    /// Adds an event to the arm audit log.
+    fn audit_arm(&self, action: ArmAction, source: SourceIp, board: u8) {
+        self.arm_audit.lock().unwrap().record(action, source.0, board);
+    }
+
+    /// What the primary board's solenoid commands are checked against.
+    fn solenoid_guard(&self) -> SolenoidGuard {
+        SolenoidGuard::new(
+            self.telemetry.clone(),
+            self.runtime_config.clone(),
+            self.duty_cycle.clone(),
+        )
+    }
+}
+
+/// The primary board's arm lifecycle and audit, for arming and disarming on the server's own
+/// behalf (the countdown, sequences and scripts) the way POST /arm and /disarm do. Cheap to
+/// clone, so background threads hold their own.
+#[derive(Clone, Default)]
+struct ArmPath {
+    arm_state: SharedArmState,
+    arm_audit: SharedArmAudit,
+    watchdog_tripped: Arc<AtomicBool>,
+    require_two_step: bool,
+    board_id: u8,
+}
+
+impl ArmPath {
+    /// Takes the arm lifecycle through `events` if it allows them and `send` succeeds;
+    /// otherwise it stays where it was. A refused event is a 409 with the current state.
+    fn drive<T>(
+        &self,
+        events: &[ArmLifecycle],
+        send: impl FnOnce() -> Result<T, ApiError>,
+    ) -> Result<T, ApiError> {
+        let mut arm_state = self.arm_state.lock().unwrap();
+        let next = events
+            .iter()
+            .try_fold(*arm_state, |current, &event| state_machine::try_transition(current, event))?;
+        let sent = send()?;
+        *arm_state = next;
+        Ok(sent)
+    }
+
+    /// Queues an arm on `command_tx` like POST /arm, audited with no source IP. Refused with
+    /// `require_two_step`, since nobody is there to confirm it, and when the lifecycle does
+    /// not allow it.
+    fn arm(&self, command_tx: &CommandSender) -> Result<(), ApiError> {
+        if self.require_two_step {
+            return Err(ApiError::TwoStepArmRequired);
+        }
+        self.drive(&[ArmLifecycle::Intend, ArmLifecycle::Confirm], || {
+            let arm = CommandBuilder::new().arm().build_joined();
+            command_tx
+                .send(QueuedCommand::immediate(arm))
+                .map_err(|_| ApiError::SerialSendFailed)?;
+            self.arm_audit.lock().unwrap().record(ArmAction::Arm, None, self.board_id);
+            self.watchdog_tripped.store(false, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    /// Queues a disarm on `command_tx` like POST /disarm, audited with no source IP.
+    fn disarm(&self, command_tx: &CommandSender) -> Result<(), ApiError> {
+        self.drive(&[ArmLifecycle::Disarm], || {
+            let disarm = CommandBuilder::new().disarm().build_joined();
+            command_tx
+                .send(QueuedCommand::immediate(disarm))
+                .map_err(|_| ApiError::SerialSendFailed)?;
+            self.arm_audit.lock().unwrap().record(ArmAction::Disarm, None, self.board_id);
+            Ok(())
+        })
+    }
+}
+
+/// Response body for GET /status.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SystemStatus {
+    connection: ConnectionStatus,
+    /// The telemetry watchdog disarmed the system and nobody has re-armed since.
+    watchdog_tripped: bool,
+    /// Commands are logged but never written to the port (`--dry-run`).
+    dry_run: bool,
+    /// When POST /firmware/reset was last called, in milliseconds since the Unix epoch.
+    #[serde(serialize_with = "flight_log::serialize_opt_epoch_ms")]
+    last_firmware_reset: Option<SystemTime>,
+}
+
+/// Response body for GET /solenoid/<channel>.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SolenoidState {
+    channel: u8,
+    state: bool,
+    /// Timestamp of the telemetry sample the state was taken from.
+    timestamp: u64,
+}
+
+/// GET /solenoid/labels returns the display name ("Solenoid N" unless `[solenoid_labels]`
+/// names it) and valve direction of every solenoid, keyed by channel.
+#[get("/solenoid/labels")]
+fn get_solenoid_labels(state: &State<AppState>) -> Json<HashMap<u8, config::SolenoidInfo>> {
+    Json(state.runtime_config.lock().unwrap().solenoid_info())
+}
+
+/// Response body for GET /solenoid/mask, and request body for POST /solenoid/mask
+/// (which only reads `mask`).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct SolenoidMask {
+    /// Bit N is solenoid N+1.
+    mask: u16,
+    #[serde(default)]
+    timestamp: u64,
+}
+
+/// Response body for POST /solenoid/mask.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SolenoidMaskResult {
+    /// How many solenoid commands were needed to reach the requested mask.
+    sent_commands: u8,
+}
+
+/// Response body for GET /log/path.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct LogPath {
+    log_file: Option<String>,
+}
+
+/// Response of GET /telemetry, in the format the client prefers.
+#[derive(Responder)]
+enum TelemetryResponse {
+    Json(Json<Telemetry>),
+    MessagePack((ContentType, Vec<u8>)),
+}
+
+/// GET /telemetry returns the current telemetry: as MessagePack if the client prefers
+/// `application/msgpack` (about half the size, for high-rate pollers), otherwise as JSON.
+#[get("/telemetry")]
+fn get_telemetry(
+    accept: Option<&Accept>,
+    state: &State<AppState>,
+) -> Result<TelemetryResponse, Status> {
+    let tel = state.telemetry.read().unwrap().clone();
+    if !accept.is_some_and(|accept| accept.preferred().is_msgpack()) {
+        return Ok(TelemetryResponse::Json(Json(tel)));
+    }
+    let body = msgpack::to_vec(&tel).map_err(|e| {
+        error!(error = %e, "Could not encode telemetry as MessagePack");
+        Status::InternalServerError
+    })?;
+    Ok(TelemetryResponse::MessagePack((ContentType::MsgPack, body)))
+}
+
+/// GET /telemetry/extra returns just the extra sensor readings of the current sample, by
+/// name (empty when the firmware sends none).
+#[get("/telemetry/extra")]
+fn get_telemetry_extra(state: &State<AppState>) -> Json<HashMap<String, f64>> {
+    Json(state.telemetry.read().unwrap().extra.clone())
+}
+
+/// POST /telemetry/inject makes the posted sample the current telemetry, for frontend work
+/// and tests without a board; it is only mounted with `--allow-inject`. The sample becomes
+/// what the GET endpoints, the arming and solenoid checks and the watchdog read (it starts
+/// and feeds the watchdog like a real frame). It is added to the history and broadcast to
+/// WebSocket and SSE clients, the alerts, the low-battery webhook and the battery drain
+/// monitor. It skips the serial read path: it is not filtered, written to the CSV log or
+/// the database, relayed over UDP or counted in the parse rate, and the command verifier,
+/// the arm lifecycle and the reconciler never see it. A sample the serial loop would drop
+/// (see `Telemetry::is_valid`, and four pyro channels) is a 400.
+#[post("/telemetry/inject", data = "<tel>")]
+fn inject_telemetry(
+    tel: SignedJson<Telemetry>,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let tel = tel.into_inner();
+    if !tel.is_valid() || tel.pyro_continuity.len() != 4 {
+        return Err(ApiError::InvalidTelemetry);
+    }
+    state.history.lock().unwrap().push(tel.clone());
+    // Sending only fails when nobody is subscribed, which is fine.
+    let _ = state.telemetry_tx.send(tel.clone());
+    *state.telemetry.write().unwrap() = tel;
+    Ok("OK")
+}
+
+/// GET /status reports the state of the serial link so the UI can flag lost comms.
+#[get("/status")]
+fn get_status(state: &State<AppState>) -> Json<SystemStatus> {
+    Json(SystemStatus {
+        connection: *state.connection_status.lock().unwrap(),
+        watchdog_tripped: state.watchdog_tripped.load(Ordering::SeqCst),
+        dry_run: state.dry_run,
+        last_firmware_reset: state.firmware_reset.last(),
+    })
+}
+
+/// GET /pyro returns just the pyro continuity flags (channels 1-4) for go/no-go indicators.
+#[get("/pyro")]
+fn get_pyro(state: &State<AppState>) -> Json<Vec<bool>> {
+    Json(state.telemetry.read().unwrap().pyro_continuity.clone())
+}
+
+/// GET /solenoid/<channel> returns the last reported state of one solenoid (1-16).
+/// Out-of-range channels are a 404.
+#[get("/solenoid/<channel>")]
+fn get_solenoid(channel: u8, state: &State<AppState>) -> Option<Json<SolenoidState>> {
+    let tel = state.telemetry.read().unwrap();
+    let index = (channel as usize).checked_sub(1)?;
+    Some(Json(SolenoidState {
+        channel,
+        state: *tel.solenoids.get(index)?,
+        timestamp: tel.timestamp,
+    }))
+}
+
+/// An entry of GET /solenoid/timers.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct SolenoidTimer {
+    channel: u8,
+    /// Until the solenoid is turned off automatically; 0 while that is under way.
+    remaining_ms: u64,
+}
+
+/// GET /solenoid/timers lists the solenoids that are on and have a `[solenoid_max_open_ms]`
+/// limit, with the time each has left.
+#[get("/solenoid/timers")]
+fn get_solenoid_timers(state: &State<AppState>) -> Json<Vec<SolenoidTimer>> {
+    let remaining = state.open_timers.lock().unwrap().remaining(Instant::now());
+    Json(
+        remaining
+            .into_iter()
+            .map(|(channel, left)| SolenoidTimer { channel, remaining_ms: left.as_millis() as u64 })
+            .collect(),
+    )
+}
+
+/// GET /solenoid/duty returns the share of the last minute (in percent) each solenoid was
+/// on, by channel.
+#[get("/solenoid/duty")]
+fn get_solenoid_duty(state: &State<AppState>) -> Json<[f32; 16]> {
+    Json(state.duty_cycle.lock().unwrap().duty(Instant::now()))
+}
+
+/// GET /solenoid/mask returns all 16 solenoid states packed into one u16.
+#[get("/solenoid/mask")]
+fn get_solenoid_mask(state: &State<AppState>) -> Json<SolenoidMask> {
+    let tel = state.telemetry.read().unwrap();
+    Json(SolenoidMask {
+        mask: solenoids_to_mask(&tel.solenoids),
+        timestamp: tel.timestamp,
+    })
+}
+
+/// GET /ack/stats reports how many commands the Arduino has acknowledged and how long
+/// the round trip (serial write to "ACK:<cmd>" line) took.
+#[get("/ack/stats")]
+fn get_ack_stats(state: &State<AppState>) -> Json<AckStats> {
+    Json(AckStats {
+        pending: state.pending_commands.lock().unwrap().len(),
+        unmatched: state.metrics.unmatched_acks.load(Ordering::Relaxed),
+        round_trip: state.ack_round_trips.lock().unwrap().stats(),
+    })
+}
+
+/// GET /ack/log returns the last `ack::ACK_LOG_SIZE` ACK/NACK lines from the Arduino,
+/// oldest first.
+#[get("/ack/log")]
+fn get_ack_log(state: &State<AppState>) -> Json<Vec<AckEvent>> {
+    Json(state.ack_log.lock().unwrap().events().cloned().collect())
+}
+
+/// GET /ack/last returns the most recent NACK (404 if the Arduino never sent one).
+#[get("/ack/last")]
+fn get_last_nack(state: &State<AppState>) -> Option<Json<AckEvent>> {
+    state.ack_log.lock().unwrap().last_nack().cloned().map(Json)
+}
+
+/// GET /flight_log returns every logged command event, oldest first.
+#[get("/flight_log")]
+fn get_flight_log(state: &State<AppState>) -> Json<Vec<FlightEvent>> {
+    Json(state.flight_log.lock().unwrap().events().to_vec())
+}
+
+/// GET /audit/arm returns every arm, disarm and emergency stop requested, oldest first,
+/// including those in `--audit-log` from earlier runs. There is no way to clear it.
+#[get("/audit/arm")]
+fn get_arm_audit(state: &State<AppState>) -> Json<Vec<ArmEvent>> {
+    Json(state.arm_audit.lock().unwrap().events().to_vec())
+}
+
+/// DELETE /flight_log clears the flight log (e.g. before the next test).
+#[delete("/flight_log")]
+fn clear_flight_log(_auth: Authenticated, state: &State<AppState>) -> &'static str {
+    state.flight_log.lock().unwrap().clear();
+    "CLEARED"
+}
+
+/// GET /ws/telemetry upgrades to a WebSocket and pushes each new telemetry sample
+/// as a JSON text frame. Every connected client receives every update.
+#[get("/ws/telemetry")]
+fn ws_telemetry(key: WebSocketKey, state: &State<AppState>) -> TelemetryStream {
+    TelemetryStream::new(key, state.telemetry_tx.subscribe())
+}
+
+/// GET /events is a server-sent event stream with one `telemetry` event (the sample as JSON)
+/// per new sample: a lighter, read-only alternative to /ws/telemetry for clients that can't do
+/// WebSockets. Idle connections get a keepalive comment every `SSE_KEEPALIVE`. The stream
+/// ends when the client goes away or the server shuts down.
+#[get("/events")]
+fn events(state: &State<AppState>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut rx = state.telemetry_tx.subscribe();
+    EventStream! {
+        loop {
+            let tel = select! {
+                received = rx.recv() => match received {
+                    Ok(tel) => tel,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            yield Event::json(&tel).event("telemetry");
+        }
+    }
+    .heartbeat(SSE_KEEPALIVE)
+}
+
+/// GET /telemetry/stream?interval_ms=N streams one line of JSON per new sample
+/// (newline-delimited JSON) over a chunked HTTP/1.1 response, for clients that can do
+/// neither WebSockets nor SSE. With `interval_ms`, samples arriving less than N ms after the
+/// last line sent are skipped. The stream ends when the client goes away (dropping its
+/// broadcast receiver) or the server shuts down.
+#[get("/telemetry/stream?<interval_ms>")]
+fn telemetry_stream(
+    interval_ms: Option<u64>,
+    state: &State<AppState>,
+    mut shutdown: Shutdown,
+) -> (ContentType, TextStream![String]) {
+    let mut rx = state.telemetry_tx.subscribe();
+    let interval = Duration::from_millis(interval_ms.unwrap_or(0));
+    let mut last_sent: Option<Instant> = None;
+    let stream = TextStream! {
+        loop {
+            let tel = select! {
+                received = rx.recv() => match received {
+                    Ok(tel) => tel,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            if last_sent.is_some_and(|sent| sent.elapsed() < interval) {
+                continue;
+            }
+            match rocket::serde::json::to_string(&tel) {
+                Ok(line) => {
+                    last_sent = Some(Instant::now());
+                    yield line + "\n";
+                }
+                Err(e) => error!(error = %e, "Could not serialize telemetry for the stream"),
+            }
+        }
+    };
+    (ContentType::new("application", "x-ndjson"), stream)
+}
+
+/// GET /telemetry/diff?since=<timestamp> returns only the fields that changed between the
+/// sample at (or just before) `since` and the current one. If `since` is older than the
+/// history buffer, or omitted, every field is returned.
+#[get("/telemetry/diff?<since>")]
+fn get_telemetry_diff(since: Option<u64>, state: &State<AppState>) -> Json<TelemetryDiff> {
+    let current = state.telemetry.read().unwrap().clone();
+    let history = state.history.lock().unwrap();
+    let previous = since.and_then(|ts| history.at(ts));
+    Json(telemetry::diff(previous.as_ref(), &current))
+}
+
+/// GET /telemetry/history?limit=N returns the last N samples (oldest first).
+/// Without `limit`, the whole buffer is returned; larger limits are clamped to the buffer capacity.
+#[get("/telemetry/history?<limit>")]
+fn get_telemetry_history(limit: Option<usize>, state: &State<AppState>) -> Json<Vec<Telemetry>> {
+    let history = state.history.lock().unwrap();
+    let limit = limit.unwrap_or(history.capacity()).min(history.capacity());
+    Json(history.latest(limit))
+}
+
+/// Response of GET /export/json: a JSON body downloaded as a file.
+#[derive(Responder)]
+struct JsonDownload {
+    body: RawJson<String>,
+    disposition: Header<'static>,
+}
+
+/// GET /export/json downloads the whole history buffer (oldest first) as a pretty-printed
+/// JSON array, named after the current Unix time, to hand to the analysis team.
+#[get("/export/json")]
+fn export_json(state: &State<AppState>) -> Result<JsonDownload, Status> {
+    let samples = state.history.lock().unwrap().latest(usize::MAX);
+    let body = rocket::serde::json::serde_json::to_string_pretty(&samples).map_err(|e| {
+        error!(error = %e, "Could not serialize the telemetry export");
+        Status::InternalServerError
+    })?;
+    let unix_s = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let filename = format!("attachment; filename=\"telemetry_{}.json\"", unix_s);
+    Ok(JsonDownload {
+        body: RawJson(body),
+        disposition: Header::new("Content-Disposition", filename),
+    })
+}
+
+/// Response of GET /telemetry/csv: a CSV body downloaded as a file.
+#[derive(Responder)]
+struct CsvDownload {
+    body: (ContentType, String),
+    disposition: Header<'static>,
+}
+
+/// GET /telemetry/csv downloads the history buffer (oldest first) as CSV, one row per
+/// sample, for tools that import CSV more easily than JSON.
+#[get("/telemetry/csv")]
+fn get_telemetry_csv(state: &State<AppState>) -> CsvDownload {
+    let samples = state.history.lock().unwrap().latest(usize::MAX);
+    let unix_s = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let filename = format!("attachment; filename=\"telemetry_{}.csv\"", unix_s);
+    CsvDownload {
+        body: (ContentType::CSV, csv_log::history_csv(&samples)),
+        disposition: Header::new("Content-Disposition", filename),
+    }
+}
+
+/// GET /telemetry/stats?window_s=N returns battery and arming sense min/max/mean and
+/// per-solenoid toggle counts over the last N seconds (by Arduino timestamp) of the history
+/// buffer. The window defaults to 60 s and cannot reach back further than the buffer.
+#[get("/telemetry/stats?<window_s>")]
+fn get_telemetry_stats(window_s: Option<u64>, state: &State<AppState>) -> Json<TelemetryStats> {
+    let window_ms = window_s.unwrap_or(DEFAULT_STATS_WINDOW_S).saturating_mul(1000);
+    let newest = state.telemetry.read().unwrap().timestamp;
+    let history = state.history.lock().unwrap();
+    Json(TelemetryStats::compute(&history.since(newest.saturating_sub(window_ms))))
+}
+
+/// GET /log/path reports where telemetry is being logged (`null` when logging is disabled).
+#[get("/log/path")]
+fn get_log_path(state: &State<AppState>) -> Json<LogPath> {
+    Json(LogPath { log_file: state.log_path.clone() })
+}
+
+/// GET /replay/status reports how far `--replay` has got (404 when not replaying).
+#[get("/replay/status")]
+fn get_replay_status(state: &State<AppState>) -> Option<Json<ReplayStatus>> {
+    let status = state.replay.as_ref()?.lock().unwrap().clone();
+    Some(Json(status))
+}
+
+/// GET /metrics serves the telemetry gauges and server counters in Prometheus text format.
+#[get("/metrics")]
+fn get_metrics(state: &State<AppState>) -> RawText<String> {
+    let tel = state.telemetry.read().unwrap().clone();
+    RawText(metrics::render(&tel, &state.metrics))
+}
+
+/// GET /diagnostics/parse_errors counts the serial lines that failed to parse as telemetry,
+/// by why (see `ParseError::KINDS`). Bad binary frames are not included.
+#[get("/diagnostics/parse_errors")]
+fn get_parse_errors(state: &State<AppState>) -> Json<BTreeMap<&'static str, u64>> {
+    let counts = &state.metrics.parse_errors_by_kind;
+    Json(
+        ParseError::KINDS
+            .into_iter()
+            .zip(counts.iter().map(|count| count.load(Ordering::Relaxed)))
+            .collect(),
+    )
+}
+
+/// GET /metrics/latency returns min/max/mean command latency (HTTP request to serial write)
+/// over the last 100 commands.
+#[get("/metrics/latency")]
+fn get_latency_metrics(state: &State<AppState>) -> Json<LatencyStats> {
+    Json(state.latencies.lock().unwrap().stats())
+}
+
+/// GET /calibrate/battery returns the battery calibration in use.
+#[get("/calibrate/battery")]
+fn get_battery_calibration(state: &State<AppState>) -> Json<VoltageCalibration> {
+    Json(*state.battery_calibration.lock().unwrap())
+}
+
+/// POST /calibrate/battery sets the battery calibration: from the next sample on,
+/// `battery` is filtered from `battery_raw * scale + offset`. It survives reconnects but not
+/// a server restart.
+#[post("/calibrate/battery", data = "<calibration>")]
+fn set_battery_calibration(
+    calibration: SignedJson<VoltageCalibration>,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let calibration = calibration.into_inner();
+    if !calibration.scale.is_finite() || !calibration.offset.is_finite() {
+        return Err(ApiError::InvalidCalibration);
+    }
+    *state.battery_calibration.lock().unwrap() = calibration;
+    Ok("OK")
+}
+
+/// POST /arm sends an "arm" command (the Arduino expects "a") and clears a tripped watchdog.
+/// Refused with `require_two_step`, and while the board is not disarmed (see
+/// `state_machine`). Answers "NO_CHANGE" without sending anything if the system already
+/// reports armed (see `AppState::already_in_state`).
+#[post("/arm")]
+fn arm(
+    _auth: Authenticated,
+    start: RequestStart,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    arm_primary(start, source, state)
+}
+
+/// POST /arm, also reached through POST /board/<id>/arm with the primary board's ID.
+fn arm_primary(
+    start: RequestStart,
+    source: SourceIp,
+    state: &AppState,
+) -> Result<&'static str, ApiError> {
+    if state.require_two_step {
+        return Err(ApiError::TwoStepArmRequired);
+    }
+    let events = [ArmLifecycle::Intend, ArmLifecycle::Confirm];
+    state.drive_arm(&events, || send_arm(start, source, state))
+}
+
+fn send_arm(
+    start: RequestStart,
+    source: SourceIp,
+    state: &AppState,
+) -> Result<&'static str, ApiError> {
+    if state.already_in_state(|tel| tel.armed) {
+        return Ok("NO_CHANGE");
+    }
+    state.send_command(QueuedCommand::new(CommandBuilder::new().arm().build_joined(), start))?;
+    state.audit_arm(ArmAction::Arm, source, state.board_id);
+    state.watchdog_tripped.store(false, Ordering::SeqCst);
+    Ok("OK")
+}
+
+/// Response body for POST /arm/intent.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ArmIntentToken {
+    /// Pass this to POST /arm/confirm.
+    token: String,
+    /// How long the token is valid for.
+    expires_in_ms: u64,
+}
+
+/// POST /arm/intent is the first step of two-step arming: it returns a one-time token that
+/// POST /arm/confirm must present within the confirm window. A new intent replaces the last.
+/// Refused while the board is armed or disarming.
+#[post("/arm/intent")]
+fn arm_intent(
+    _auth: Authenticated,
+    state: &State<AppState>,
+) -> Result<Json<ArmIntentToken>, ApiError> {
+    state.drive_arm(&[ArmLifecycle::Intend], || {
+        let mut intent = state.arm_intent.lock().unwrap();
+        Ok(Json(ArmIntentToken {
+            token: intent.issue(Instant::now()),
+            expires_in_ms: intent.window().as_millis() as u64,
+        }))
+    })
+}
+
+/// Request body for POST /arm/confirm.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ArmConfirm {
+    token: String,
+}
+
+/// POST /arm/confirm arms (like POST /arm) if `token` is the one POST /arm/intent issued and
+/// the window has not run out. Every attempt clears the intent: a wrong token is a 403, an
+/// expired intent or none at all a 409.
+#[post("/arm/confirm", data = "<body>")]
+fn arm_confirm(
+    body: SignedJson<ArmConfirm>,
+    start: RequestStart,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let confirmed = state.arm_intent.lock().unwrap().confirm(&body.token, Instant::now());
+    if confirmed.is_err() {
+        // The intent is gone. Refused unless the lifecycle was still waiting for it.
+        let _ = state.drive_arm(&[ArmLifecycle::IntentLapsed], || Ok(()));
+    }
+    match confirmed {
+        Ok(()) => state.drive_arm(&[ArmLifecycle::Confirm], || send_arm(start, source, state)),
+        Err(ArmConfirmError::NoIntent) => Err(ApiError::NoArmIntent),
+        Err(ArmConfirmError::Expired) => Err(ApiError::ArmIntentExpired),
+        Err(ArmConfirmError::WrongToken) => {
+            warn!("Arm confirm with the wrong token; intent cleared");
+            Err(ApiError::InvalidArmToken)
+        }
+    }
+}
+
+/// POST /disarm sends a "disarm" command (the Arduino expects "d"), or answers "NO_CHANGE"
+/// if the system already reports disarmed. Never refused by the arm lifecycle.
+#[post("/disarm")]
+fn disarm(
+    _auth: Authenticated,
+    start: RequestStart,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    disarm_primary(start, source, state)
+}
+
+/// POST /disarm, also reached through POST /board/<id>/disarm with the primary board's ID.
+fn disarm_primary(
+    start: RequestStart,
+    source: SourceIp,
+    state: &AppState,
+) -> Result<&'static str, ApiError> {
+    state.countdown.abort();
+    state.drive_arm(&[ArmLifecycle::Disarm], || {
+        if state.already_in_state(|tel| !tel.armed) {
+            return Ok("NO_CHANGE");
+        }
+        let disarm = CommandBuilder::new().disarm().build_joined();
+        state.send_command(QueuedCommand::new(disarm, start))?;
+        state.audit_arm(ArmAction::Disarm, source, state.board_id);
+        Ok("OK")
+    })
+}
+
+/// POST /emergency_stop disarms and closes all 16 solenoids in a single serial write, on
+/// every board. It uses the priority channel and never blocks: if a stop is already queued,
+/// that one carries the same sequence, so this request is already covered.
+/// Every board is sent the stop even if one of them fails. A running countdown or script is
+/// aborted.
+#[post("/emergency_stop")]
+fn emergency_stop(
+    _auth: Authenticated,
+    source: SourceIp,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    state.countdown.abort();
+    state.script.abort();
+    state.drive_arm(&[ArmLifecycle::EmergencyStop], || {
+        let results: Vec<_> = state
+            .boards()
+            .map(|b| (b.id, b.send_emergency(emergency_stop_sequence())))
+            .collect();
+        for (id, result) in &results {
+            if result.is_ok() {
+                state.audit_arm(ArmAction::EmergencyStop, source, *id);
+            }
+        }
+        results.into_iter().try_for_each(|(_, result)| result)?;
+        Ok("ESTOP_SENT")
+    })
+}
+
+/// One entry of a POST /solenoids/batch request.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct SolenoidCommand {
+    channel: u8,
+    state: u8,
+}
+
+/// POST /solenoids/batch actuates several solenoids at once.
+/// Every entry is validated first; if any is invalid, nothing is sent and the failures are
+/// listed in a 400 response. Otherwise all commands go to the serial port in a single write.
+/// The `[[interlock]]`s are checked with every entry applied, so a batch cannot open both
+/// valves of a pair.
+#[post("/solenoids/batch", data = "<batch>")]
+fn solenoid_batch(
+    batch: SignedJson<Vec<SolenoidCommand>>,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let mut builder = CommandBuilder::new();
+    let mut channels = Vec::with_capacity(batch.len());
+    let mut failed = Vec::new();
+    for entry in batch.iter() {
+        match builder.solenoid_state(entry.channel, entry.state) {
+            Ok(_) => channels.push(entry.channel),
+            Err(e) => failed.push((entry.channel, e.into())),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(ApiError::InvalidBatch(failed));
+    }
+    if !builder.is_empty() {
+        // The serial loop appends the final newline, so this goes out as one write.
+        let cmd = QueuedCommand::new(builder.build_joined(), start);
+        state.send_solenoid_command(cmd, &channels)?;
+    }
+    Ok("OK")
+}
+
+/// Response body for POST /solenoid/all/open and /solenoid/all/close.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct AllSolenoidsResponse {
+    sent: u8,
+}
+
+/// "s<ch>1" for all 16 solenoids, newline-separated (the serial loop adds the last newline),
+/// so the batch goes out in one write. Written out rather than built on every request.
+const ALL_OPEN_COMMAND: &str = "s11\ns21\ns31\ns41\ns51\ns61\ns71\ns81\ns91\n\
+    s101\ns111\ns121\ns131\ns141\ns151\ns161";
+
+/// Like `ALL_OPEN_COMMAND`, with every solenoid set to 0.
+const ALL_CLOSED_COMMAND: &str = "s10\ns20\ns30\ns40\ns50\ns60\ns70\ns80\ns90\n\
+    s100\ns110\ns120\ns130\ns140\ns150\ns160";
+
+fn all_solenoids_command(on: bool) -> &'static str {
+    if on {
+        ALL_OPEN_COMMAND
+    } else {
+        ALL_CLOSED_COMMAND
+    }
+}
+
+/// Queues `all_solenoids_command(on)` for the primary board. These are emergency
+/// overrides: the rate limit, interlocks and duty cycle are not checked, only (with
+/// `require_armed_for_all`, the default) that the system reports armed.
+fn send_all_solenoids(
+    on: bool,
+    start: RequestStart,
+    state: &AppState,
+) -> Result<Json<AllSolenoidsResponse>, ApiError> {
+    if state.require_armed_for_all && !state.telemetry.read().unwrap().armed {
+        return Err(ApiError::SystemNotArmed);
+    }
+    state.send_command(QueuedCommand::new(all_solenoids_command(on), start))?;
+    warn!(on, "All solenoids commanded");
+    Ok(Json(AllSolenoidsResponse { sent: 16 }))
+}
+
+/// POST /solenoid/all/open energizes all 16 solenoids at once, e.g. to vent everything.
+/// 403 SYSTEM_NOT_ARMED while disarmed, unless `require_armed_for_all` is off.
+#[post("/solenoid/all/open")]
+fn open_all_solenoids(
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<Json<AllSolenoidsResponse>, ApiError> {
+    send_all_solenoids(true, start, state)
+}
+
+/// POST /solenoid/all/close de-energizes all 16 solenoids at once (see
+/// /solenoid/all/open).
+#[post("/solenoid/all/close")]
+fn close_all_solenoids(
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<Json<AllSolenoidsResponse>, ApiError> {
+    send_all_solenoids(false, start, state)
+}
+
+/// POST /sequence starts a timed command sequence. Each step waits `delay_ms` after the
+/// previous one and then sends its command. All steps are validated before the sequence
+/// starts; only one sequence may run at a time. With `require_two_step`, a sequence that
+/// arms is refused with 409 SEQUENCE_ARM_REFUSED, since nobody is there to confirm it.
+#[post("/sequence", data = "<steps>")]
+fn start_sequence(
+    steps: SignedJson<Vec<SequenceStep>>,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let mut commands = Vec::with_capacity(steps.len());
+    for (index, step) in steps.iter().enumerate() {
+        let mut builder = CommandBuilder::new();
+        match &step.command {
+            SequenceCommand::Arm if state.require_two_step => {
+                return Err(ApiError::SequenceArmRefused(index))
+            }
+            SequenceCommand::Arm => builder.arm(),
+            SequenceCommand::Disarm => builder.disarm(),
+            SequenceCommand::Solenoid { channel, state } => builder
+                .solenoid_state(*channel, *state)
+                .map_err(|e| ApiError::InvalidSequenceStep(index, Box::new(e.into())))?,
+        };
+        commands.push((Duration::from_millis(step.delay_ms), builder.build_joined()));
+    }
+    if state.sequence.start(commands, state.command_tx.clone(), state.solenoid_guard()) {
+        Ok("OK")
+    } else {
+        Err(ApiError::SequenceAlreadyRunning)
+    }
+}
+
+/// GET /sequence/status reports whether a sequence is pending, running, completed or aborted.
+#[get("/sequence/status")]
+fn get_sequence_status(state: &State<AppState>) -> Json<SequenceStatus> {
+    Json(state.sequence.status())
+}
+
+/// POST /sequence/abort stops the running sequence before its next step is sent.
+#[post("/sequence/abort")]
+fn abort_sequence(_auth: Authenticated, state: &State<AppState>) -> &'static str {
+    if state.sequence.abort() {
+        "ABORTED"
+    } else {
+        "NOT_RUNNING"
+    }
+}
+
+/// Body of POST /serial/reconnect.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReconnectRequest {
+    port: String,
+    /// Keeps the current baud rate if omitted.
+    baud: Option<u32>,
+}
+
+/// POST /serial/reconnect closes the current serial port and opens `port` instead
+/// (e.g. after the cable came back as /dev/ttyUSB1). GET /status shows the progress.
+#[post("/serial/reconnect", data = "<request>")]
+fn serial_reconnect(
+    request: SignedJson<ReconnectRequest>,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let ReconnectRequest { port, baud } = request.into_inner();
+    if port.trim().is_empty() {
+        return Err(ApiError::InvalidPortName);
+    }
+    if let Some(baud) = baud.filter(|b| !SUPPORTED_BAUD_RATES.contains(b)) {
+        return Err(ApiError::InvalidBaudRate(baud));
+    }
+    state.port_switch.request(port, baud);
+    Ok("RECONNECTING")
+}
+
+/// POST /solenoid/mask sets all 16 solenoids to the states in `mask`. Only channels whose
+/// reported state differs are commanded, all in a single serial write.
+#[post("/solenoid/mask", data = "<request>")]
+fn set_solenoid_mask(
+    request: SignedJson<SolenoidMask>,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<Json<SolenoidMaskResult>, ApiError> {
+    let current = solenoids_to_mask(&state.telemetry.read().unwrap().solenoids);
+    let changed = current ^ request.mask;
+    let mut builder = CommandBuilder::new();
+    let mut channels = Vec::with_capacity(16);
+    for bit in (0..16).filter(|bit| changed & (1 << bit) != 0) {
+        let channel = bit + 1;
+        builder.solenoid(channel, request.mask & (1 << bit) != 0)?;
+        channels.push(channel);
+    }
+    if !channels.is_empty() {
+        let cmd = QueuedCommand::new(builder.build_joined(), start);
+        state.send_solenoid_command(cmd, &channels)?;
+    }
+    Ok(Json(SolenoidMaskResult {
+        sent_commands: channels.len() as u8,
+    }))
+}
+
+/// POST /solenoid/<channel>/<sstate> sends a solenoid actuation command.
+/// For example, POST /solenoid/5/1 sends "s51" (channel 5 → state 1).
+/// If telemetry already shows the solenoid in that state, nothing is sent ("NO_CHANGE").
+/// With `require_armed_for_solenoid` set, this is a 403 while the system is disarmed.
+/// Commands within `min_interval_ms` of the previous one for the channel are a 429.
+/// Opening a valve while the other valve of one of its `[[interlock]]`s is open is a 409.
+/// Switching on a solenoid that was on for more than `max_duty_pct` of the last minute is a
+/// 429.
+#[post("/solenoid/<channel>/<sstate>")]
+fn solenoid(
+    channel: u8,
+    sstate: u8,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let cmd = CommandBuilder::new().solenoid_state(channel, sstate)?.build_joined();
+    let index = usize::from(channel) - 1;
+    if state.already_in_state(|tel| tel.solenoids.get(index) == Some(&(sstate == 1))) {
+        return Ok("NO_CHANGE");
+    }
+    state.send_solenoid_command(QueuedCommand::new(cmd, start), &[channel])?;
+    Ok("OK")
+}
+
+/// POST /solenoid/<channel>/pulse?duration_ms=<N> opens the solenoid, then closes it again
+/// after `duration_ms` (clamped to 1-5000). The open is checked like POST /solenoid; the
+/// close is queued by a background thread, so this returns 202 with the pulse ID at once.
+#[post("/solenoid/<channel>/pulse?<duration_ms>")]
+fn pulse_solenoid(
+    channel: u8,
+    duration_ms: u64,
+    _auth: Authenticated,
+    start: RequestStart,
+    state: &State<AppState>,
+) -> Result<status::Accepted<Json<PulseInfo>>, ApiError> {
+    let open = CommandBuilder::new().solenoid(channel, true)?.build_joined();
+    let close = CommandBuilder::new().solenoid(channel, false)?.build_joined();
+    let pulse = state.pulses.add(channel, duration_ms);
+    if let Err(e) = state.send_solenoid_command(QueuedCommand::new(open, start), &[channel]) {
+        state.pulses.discard(pulse.pulse_id);
+        return Err(e);
+    }
+    let pulse = state.pulses.start(pulse.pulse_id, close, state.command_tx.clone());
+    Ok(status::Accepted(Json(pulse)))
+}
+
+/// GET /solenoid/pulse/<pulse_id> reports whether a pulse is pending, active, complete or
+/// aborted.
+#[get("/solenoid/pulse/<pulse_id>")]
+fn get_pulse(pulse_id: u64, state: &State<AppState>) -> Result<Json<PulseInfo>, ApiError> {
+    state.pulses.get(pulse_id).map(Json).ok_or(ApiError::UnknownPulse(pulse_id))
+}
+
+/// DELETE /solenoid/pulse/<pulse_id> cuts a running pulse short, closing the solenoid now.
+/// A finished pulse is left as it was.
+#[delete("/solenoid/pulse/<pulse_id>")]
+fn abort_pulse(
+    pulse_id: u64,
+    _auth: Authenticated,
+    state: &State<AppState>,
+) -> Result<Json<PulseInfo>, ApiError> {
+    state.pulses.abort(pulse_id).map(Json).ok_or(ApiError::UnknownPulse(pulse_id))
+}
+
+/// The main page, kept in `static/index.html` so it can be edited as HTML.
+const INDEX_HTML: &str = include_str!("../static/index.html");
+const _: () = assert!(!INDEX_HTML.is_empty(), "static/index.html is empty");
+
+/// GET / serves the main HTML page.
+/// The page creates buttons for all 16 solenoids and for arm/disarm,
+/// and it listens on /ws/telemetry to update the UI.
+#[cfg(not(feature = "templates"))]
+#[get("/")]
+fn index() -> RawHtml<&'static str> {
+    RawHtml(INDEX_HTML)
+}
+
+/// GET / renders `templates/index.html.tera` with the current telemetry (see `template`),
+/// or serves the built-in page if there is no `templates` directory. A template that fails
+/// to render is logged and the built-in page served, so the controls stay reachable.
+#[cfg(feature = "templates")]
+#[get("/")]
+fn index(state: &State<AppState>) -> RawHtml<String> {
+    // Through the JSON text, so the f32 voltages print as GET /telemetry shows them.
+    use rocket::serde::json::{self, Value};
+    let telemetry: Value = json::to_string(&*state.telemetry.read().unwrap())
+        .and_then(|text| json::from_str(&text))
+        .unwrap_or_default();
+    let context = json::json!({ "telemetry": telemetry });
+    match template::render_index(&context) {
+        Some(Ok(page)) => RawHtml(page),
+        Some(Err(e)) => {
+            error!(error = %e, "Dashboard template failed, serving the built-in page");
+            RawHtml(INDEX_HTML.to_string())
+        }
+        None => RawHtml(INDEX_HTML.to_string()),
+    }
+}
+
+/// Everything a freshly parsed telemetry sample is published to.
+struct TelemetrySinks {
+    telemetry: SharedTelemetry,
+    history: SharedHistory,
+    broadcast: broadcast::Sender<Telemetry>,
+    csv_log: Option<SharedCsvLog>,
+    /// The SQLite writer thread, if `--db` was given.
+    db: Option<mpsc::Sender<Telemetry>>,
+    /// The UDP relay thread, if `--udp-out` was given.
+    udp_relay: Option<mpsc::SyncSender<Telemetry>>,
+    /// Every sample is counted here for the parse rate.
+    parse_rate: SharedParseRate,
+}
+
+impl TelemetrySinks {
+    /// Appends the sample to the CSV log and database (if any) and the history buffer, hands
+    /// it to the UDP relay (if any), broadcasts it to push clients, and finally makes it the
+    /// current shared telemetry.
+    fn publish(&self, new_telemetry: Telemetry) {
+        self.parse_rate.lock().unwrap().record(Instant::now());
+        if let Some(log) = &self.csv_log {
+            if let Ok(mut log) = log.lock() {
+                if let Err(e) = log.write_record(&new_telemetry) {
+                    error!(error = %e, "Error writing telemetry log");
+                }
+            }
+        }
+        if let Some(db) = &self.db {
+            // The writer thread only exits if its statement could not be prepared.
+            let _ = db.send(new_telemetry.clone());
+        }
+        if let Some(relay) = &self.udp_relay {
+            // Dropped when the relay is behind, rather than holding up the serial loop.
+            let _ = relay.try_send(new_telemetry.clone());
+        }
+        if let Ok(mut hist) = self.history.lock() {
+            hist.push(new_telemetry.clone());
+        }
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.broadcast.send(new_telemetry.clone());
+        if let Ok(mut tel) = self.telemetry.write() {
+            *tel = new_telemetry;
+        }
+    }
+}
+
+/// The command sequence sent by POST /emergency_stop: disarm, then close all 16 solenoids.
+/// Each command is already newline-terminated so the whole batch goes out in one write.
+fn emergency_stop_sequence() -> String {
+    let mut builder = CommandBuilder::new();
+    builder.disarm();
+    for ch in 1..=16 {
+        builder.solenoid(ch, false).expect("valid channel");
+    }
+    builder.build_lines()
+}
+
+/// Serial port parameters for the serial loop.
+struct SerialSettings {
+    port_name: String,
+    baud_rate: u32,
+    /// How long a read waits for data before timing out.
+    read_timeout: Duration,
+    /// Samples the battery and arming sense filters average over.
+    filter_window: usize,
+    /// Whether the firmware sends text lines or binary frames.
+    format: TelemetryFormat,
+    /// Consecutive read errors after which the port is considered lost and re-opened.
+    error_threshold: u32,
+    /// Talk to a simulated Arduino instead of opening `port_name`.
+    simulate: bool,
+    /// Talk to the hardware-in-the-loop fake Arduino over a socket pair instead.
+    hil: bool,
+    /// Log commands instead of writing them (see `DryRunWriter`).
+    dry_run: bool,
+    /// Parse text lines with this `[telemetry.format]` instead of the built-in format.
+    line_format: Option<Arc<LineFormat>>,
+    /// Queued commands still written when the server shuts down, before the final disarm.
+    shutdown_drain: usize,
+    /// `require_armed_for_solenoid`, for the reconciler's corrections.
+    require_armed: bool,
+}
+
+impl SerialSettings {
+    /// The settings for the port in `[serial]`.
+    fn new(serial: &SerialConfig, filters: &FilterConfig) -> Self {
+        SerialSettings {
+            port_name: serial.port.clone(),
+            baud_rate: serial.baud,
+            read_timeout: Duration::from_millis(serial.timeout_ms),
+            filter_window: filters.battery_window,
+            format: serial.format,
+            error_threshold: serial.reconnect_threshold,
+            simulate: serial.simulate,
+            hil: serial.hil,
+            dry_run: serial.dry_run,
+            line_format: None,
+            shutdown_drain: serial.shutdown_drain,
+            require_armed: false,
+        }
+    }
+}
+
+/// The state of the serial link, as reported by GET /status.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", tag = "state", content = "attempt")]
+enum ConnectionStatus {
+    Connected,
+    /// Waiting to (re-)open the port; carries the reconnect attempt number
+    /// (0 while the first connection is being made).
+    Reconnecting(u32),
+    /// The serial loop hit an unrecoverable error and stopped.
+    Failed,
+}
+
+/// A shared connection status (written by the serial loop, read by GET /status).
+type SharedConnectionStatus = Arc<Mutex<ConnectionStatus>>;
+
+/// A port change requested by POST /serial/reconnect, picked up by the serial loop.
+#[derive(Default)]
+struct PortSwitch {
+    /// Set when a new target is waiting in `target`.
+    requested: AtomicBool,
+    /// The port to open next, and the baud rate to switch to (if changing).
+    target: Mutex<(String, Option<u32>)>,
+}
+
+impl PortSwitch {
+    fn request(&self, port: String, baud: Option<u32>) {
+        *self.target.lock().unwrap() = (port, baud);
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Takes the pending request, if any.
+    fn take(&self) -> Option<(String, Option<u32>)> {
+        if self.requested.swap(false, Ordering::SeqCst) {
+            Some(self.target.lock().unwrap().clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// Longest wait between reconnect attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Backoff before reconnect attempt `attempt` (1-based): 100 ms, 200 ms, 400 ms, ... capped at 5 s.
+fn reconnect_backoff(attempt: u32) -> Duration {
+    let exp = attempt.saturating_sub(1).min(16);
+    (Duration::from_millis(100) * 2u32.pow(exp)).min(MAX_RECONNECT_BACKOFF)
+}
+
+/// Why a serial session ended.
+enum SessionEnd {
+    /// The port stopped responding; try to open it again.
+    Disconnected,
+    /// Something went wrong that re-opening won't fix.
+    Fatal,
+    /// POST /serial/reconnect asked for a different port.
+    PortChanged,
+    /// The server is shutting down; the board was disarmed.
+    Shutdown,
+}
+
+/// An open link to the Arduino: a writer for commands and a line reader for telemetry.
+struct SerialLink {
+    writer: Box<dyn Write + Send>,
+    reader: Box<dyn BufRead + Send>,
+    /// The port's control lines, for POST /firmware/reset; `None` for links without them.
+    control: Option<Box<dyn serialport::SerialPort>>,
+}
+
+/// Stands in for the port's write half in `--dry-run` mode: every command line is logged
+/// and dropped, while telemetry is still read from the real port.
+struct DryRunWriter;
+
+impl Write for DryRunWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in String::from_utf8_lossy(buf).lines().filter(|l| !l.trim().is_empty()) {
+            info!(command = line.trim(), "[DRY RUN] Command not sent");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens the serial port described by `settings` or, with `--simulate`, starts a simulated
+/// Arduino instead. On failure, the error says whether trying again makes sense.
+fn open_link(settings: &SerialSettings) -> Result<SerialLink, SessionEnd> {
+    if settings.simulate {
+        let (writer, reader) = simulator::spawn(settings.format);
+        return Ok(SerialLink {
+            writer: Box::new(writer),
+            reader: Box::new(BufReader::new(reader)),
+            control: None,
+        });
+    }
+    #[cfg(unix)]
+    if settings.hil {
+        return match hil::spawn(settings.format, settings.read_timeout) {
+            Ok((writer, reader)) => Ok(SerialLink {
+                writer: Box::new(writer),
+                reader: Box::new(BufReader::new(reader)),
+                control: None,
+            }),
+            Err(e) => {
+                error!(error = %e, "Failed to start the HIL fake Arduino");
+                Err(SessionEnd::Fatal)
+            }
+        };
+    }
+    let port_result = serialport::new(settings.port_name.clone(), settings.baud_rate)
+        .timeout(settings.read_timeout)
+        .open();
+    let port = match port_result {
+        Ok(p) => p,
+        Err(e) => {
+            warn!(error = %e, "Failed to open serial port");
+            return Err(SessionEnd::Disconnected);
+        }
+    };
+    // Clone the port for reading (most serialport implementations allow cloning for read/write).
+    let port_clone = match port.try_clone() {
+        Ok(p) => p,
+        Err(e) => {
+            error!(error = %e, "Failed to clone serial port");
+            return Err(SessionEnd::Fatal);
+        }
+    };
+    Ok(SerialLink {
+        control: port.try_clone().ok(),
+        writer: port,
+        reader: Box::new(BufReader::new(port_clone)),
+    })
+}
+
+/// `open_link` for a `[cluster]` member: waits until this server leads (or shuts down),
+/// and ends the session once it no longer does so the new leader can open the port.
+fn open_as_leader(
+    cluster: &SharedCluster,
+    shutdown: &ShutdownSignal,
+    settings: &SerialSettings,
+) -> Result<SerialLink, SessionEnd> {
+    while !cluster.is_leader() {
+        if shutdown.is_set() {
+            return Err(SessionEnd::Shutdown);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let link = open_link(settings)?;
+    Ok(SerialLink {
+        reader: Box::new(LeaderOnly::new(link.reader, cluster.clone())),
+        ..link
+    })
+}
+
+/// This thread opens the serial link with `open` (normally `open_link`, using the provided
+/// settings) and runs a serial session on it. Whenever the session ends because the port was
+/// lost (or the port cannot be opened), it waits with exponential backoff and re-opens it,
+/// keeping `status` up to date.
+fn spawn_serial_loop(
+    sinks: TelemetrySinks,
+    endpoints: SerialEndpoints,
+    mut settings: SerialSettings,
+    status: SharedConnectionStatus,
+    metrics: Arc<Metrics>,
+    mut open: impl FnMut(&SerialSettings) -> Result<SerialLink, SessionEnd>,
+) {
+    let set_status = |s: ConnectionStatus| *status.lock().unwrap() = s;
+    let mut filters = TelemetryFilters::new(settings.filter_window)
+        .with_battery_calibration(endpoints.battery_calibration.clone());
+    let mut attempt = 0;
+    loop {
+        if endpoints.shutdown.is_set() {
+            warn!(port = %settings.port_name, "Shutting down without a serial link to disarm");
+            return;
+        }
+        if let Some((port, baud)) = endpoints.port_switch.take() {
+            settings.port_name = port;
+            settings.baud_rate = baud.unwrap_or(settings.baud_rate);
+            info!(port = %settings.port_name, baud = settings.baud_rate, "Switching serial port");
+            attempt = 0;
+        }
+        let _span = info_span!("serial", port = %settings.port_name).entered();
+        let end = match open(&settings) {
+            Ok(link) => {
+                info!(baud = settings.baud_rate, "Serial port connected");
+                set_status(ConnectionStatus::Connected);
+                attempt = 0;
+                let link = if settings.dry_run {
+                    SerialLink { writer: Box::new(DryRunWriter), ..link }
+                } else {
+                    link
+                };
+                let link = match &endpoints.raw_bytes {
+                    Some(tx) => SerialLink {
+                        reader: Box::new(RawTap::new(link.reader, tx.clone())),
+                        ..link
+                    },
+                    None => link,
+                };
+                run_serial_session(link, &sinks, &endpoints, &settings, &metrics, &mut filters)
+            }
+            Err(end) => end,
+        };
+        match end {
+            SessionEnd::Disconnected => {
+                if attempt == 0 {
+                    warn!("Serial port lost, reconnecting");
+                }
+            }
+            SessionEnd::Fatal => {
+                error!("Giving up on the serial port");
+                set_status(ConnectionStatus::Failed);
+                return;
+            }
+            SessionEnd::PortChanged => {
+                set_status(ConnectionStatus::Reconnecting(0));
+                continue;
+            }
+            SessionEnd::Shutdown => return,
+        }
+        attempt += 1;
+        metrics.serial_reconnect_attempts.fetch_add(1, Ordering::Relaxed);
+        set_status(ConnectionStatus::Reconnecting(attempt));
+        // Back off, but cut the wait short if a new port is requested meanwhile.
+        let backoff = reconnect_backoff(attempt);
+        debug!(attempt, backoff_ms = backoff.as_millis() as u64, "Reconnect scheduled");
+        let retry_at = Instant::now() + backoff;
+        while Instant::now() < retry_at
+            && !endpoints.port_switch.is_requested()
+            && !endpoints.shutdown.is_set()
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Filters and publishes a freshly parsed sample, unless it fails `Telemetry::is_valid`:
+/// an implausible sample is counted and dropped, so it never reaches the filters or the
+/// shared state. Returns whether the sample was published.
+fn accept_telemetry(
+    mut new_telemetry: Telemetry,
+    sinks: &TelemetrySinks,
+    metrics: &Metrics,
+    filters: &mut TelemetryFilters,
+) -> bool {
+    if !new_telemetry.is_valid() {
+        metrics.telemetry_invalid.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            timestamp = new_telemetry.timestamp,
+            battery = new_telemetry.battery,
+            arming = new_telemetry.arming,
+            "Dropping implausible telemetry"
+        );
+        return false;
+    }
+    filters.apply(&mut new_telemetry);
+    sinks.publish(new_telemetry);
+    true
+}
+
+/// Parses a telemetry line in `line_format`, or the built-in format if none is configured.
+fn parse_line(line: &str, line_format: Option<&LineFormat>) -> Result<Telemetry, ParseError> {
+    match line_format {
+        Some(format) => format.parse(line).ok_or(ParseError::FormatMismatch),
+        None => parse_telemetry_line(line),
+    }
+}
+
+/// Handles one line from the Arduino: an "ACK:<cmd>" or "NACK:<cmd>" for a written
+/// command, which is logged, or otherwise a telemetry line (in `line_format`, if given),
+/// which is filtered and published. Returns whether a telemetry sample was published.
+fn handle_line(
+    line: &str,
+    line_format: Option<&LineFormat>,
+    sinks: &TelemetrySinks,
+    endpoints: &SerialEndpoints,
+    metrics: &Metrics,
+    filters: &mut TelemetryFilters,
+) -> bool {
+    let pending = &endpoints.pending_commands;
+    match ack::handle_ack_line(line, pending, &endpoints.ack_round_trips) {
+        Some((message, matched)) => {
+            if !matched {
+                debug!(line, "ACK/NACK for a command that was not sent");
+                metrics.unmatched_acks.fetch_add(1, Ordering::Relaxed);
+            }
+            if message.kind == AckKind::Nack {
+                warn!(command = %message.command, "Arduino failed to execute command");
+            }
+            let ts = sinks.telemetry.read().unwrap().timestamp;
+            endpoints.ack_log.lock().unwrap().record(ts, message);
+            false
+        }
+        None => match parse_line(line, line_format) {
+            Ok(new_telemetry) => accept_telemetry(new_telemetry, sinks, metrics, filters),
+            Err(e) if !line.is_empty() => {
+                debug!(line, kind = e.kind(), error = %e, "Unparseable telemetry line");
+                metrics.telemetry_parse_errors.fetch_add(1, Ordering::Relaxed);
+                metrics.parse_errors_by_kind[e.kind_index()].fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Err(_) => false,
+        },
+    }
+}
+
+/// Writes newline-terminated `commands` to the port and records them as sent (for ACK
+/// matching and the flight log). Returns when the write finished.
+fn write_commands(
+    port: &mut dyn Write,
+    commands: &str,
+    sinks: &TelemetrySinks,
+    endpoints: &SerialEndpoints,
+    settings: &SerialSettings,
+) -> io::Result<Instant> {
+    port.write_all(commands.as_bytes())?;
+    let written_at = Instant::now();
+    // Nothing was sent in a dry run, so no ACK is coming and nothing will switch.
+    if !settings.dry_run {
+        ack::record_sent(&endpoints.pending_commands, commands, written_at);
+        endpoints.verifier.lock().unwrap().record(commands, written_at);
+    }
+    let ts = sinks.telemetry.read().unwrap().timestamp;
+    endpoints.flight_log.lock().unwrap().record_command(ts, commands);
+    Ok(written_at)
+}
+
+/// Writes up to `settings.shutdown_drain` of the commands still queued, then a disarm. The
+/// rest of the queue is dropped.
+fn shut_down(
+    port: &mut dyn Write,
+    sinks: &TelemetrySinks,
+    endpoints: &SerialEndpoints,
+    settings: &SerialSettings,
+) {
+    let mut drained = 0;
+    while drained < settings.shutdown_drain {
+        let Ok(cmd) = endpoints.commands.try_recv() else { break };
+        let cmd_with_newline = cmd.text + "\n";
+        if let Err(e) = write_commands(port, &cmd_with_newline, sinks, endpoints, settings) {
+            error!(error = %e, command = cmd_with_newline.trim_end(), "Error writing command");
+        }
+        drained += 1;
+    }
+    let mut dropped = 0;
+    while endpoints.commands.try_recv().is_ok() {
+        dropped += 1;
+    }
+    if dropped > 0 {
+        warn!(drained, dropped, "Shutting down: dropped queued commands");
+    }
+    let disarm = CommandBuilder::new().disarm().build_lines();
+    match write_commands(port, &disarm, sinks, endpoints, settings).and_then(|_| port.flush()) {
+        Ok(()) => info!(drained, "Shutting down: queued commands sent, board disarmed"),
+        Err(e) => error!(error = %e, "Shutting down: could not disarm the board"),
+    }
+}
+
+/// Runs on an open link until it is lost: continuously
+/// (a) checks for command strings from the channels and writes them to the port (with a newline)
+/// and (b) reads telemetry lines from the Arduino, parses them, and publishes them to `sinks`.
+/// "ACK:<cmd>" and "NACK:<cmd>" lines are matched against the pending commands instead.
+/// With the binary format, fixed-size frames are read instead of lines (and there are no ACKs).
+/// The emergency channel is always drained before the normal command channel.
+/// Every successfully written command is acknowledged for latency tracking.
+fn run_serial_session(
+    link: SerialLink,
+    sinks: &TelemetrySinks,
+    endpoints: &SerialEndpoints,
+    settings: &SerialSettings,
+    metrics: &Metrics,
+    filters: &mut TelemetryFilters,
+) -> SessionEnd {
+    let SerialLink { writer: mut port, mut reader, mut control } = link;
+    let mut frames = BinaryFrameReader::default();
+    let mut consecutive_errors = 0;
+    // The board may have missed commands (or reset) while the link was down.
+    endpoints.reconciler.request();
+
+    loop {
+        if endpoints.port_switch.is_requested() {
+            return SessionEnd::PortChanged;
+        }
+        if endpoints.firmware_reset.take() {
+            firmware_reset::toggle_dtr(&mut control);
+        }
+        // Priority batches (emergency stops, watchdog disarms) are pre-formatted
+        // (newline-terminated) and go out in a single write.
+        while let Ok(batch) = endpoints.emergency.try_recv() {
+            endpoints.reconciler.record(&batch);
+            endpoints.open_timers.lock().unwrap().record(&batch, Instant::now());
+            endpoints.duty_cycle.lock().unwrap().record(&batch, Instant::now());
+            match port.write_all(batch.as_bytes()) {
+                Ok(()) => {
+                    // Nothing was sent in a dry run, so no ACK is coming.
+                    if !settings.dry_run {
+                        ack::record_sent(&endpoints.pending_commands, &batch, Instant::now());
+                    }
+                    let ts = sinks.telemetry.read().unwrap().timestamp;
+                    let mut flight_log = endpoints.flight_log.lock().unwrap();
+                    if batch == emergency_stop_sequence() {
+                        warn!("Emergency stop sent");
+                        flight_log.record(ts, EventType::EmergencyStop);
+                    } else {
+                        info!(commands = ?batch, "Priority commands sent");
+                        flight_log.record_command(ts, &batch);
+                    }
+                }
+                Err(e) => error!(error = %e, commands = ?batch, "Error writing priority commands"),
+            }
+        }
+        if endpoints.shutdown.is_set() {
+            shut_down(&mut port, sinks, endpoints, settings);
+            return SessionEnd::Shutdown;
+        }
+        // If any commands have been sent (via the Rocket endpoints), write them now.
+        while let Ok(cmd) = endpoints.commands.try_recv() {
+            endpoints.reconciler.record(&cmd.text);
+            endpoints.open_timers.lock().unwrap().record(&cmd.text, Instant::now());
+            endpoints.duty_cycle.lock().unwrap().record(&cmd.text, Instant::now());
+            let cmd_with_newline = cmd.text + "\n";
+            match write_commands(&mut port, &cmd_with_newline, sinks, endpoints, settings) {
+                Ok(written_at) => {
+                    debug!(command = cmd_with_newline.trim_end(), "Command sent");
+                    if let Some(session) = cmd.session {
+                        let ts = sinks.telemetry.read().unwrap().timestamp;
+                        let mut sessions = endpoints.sessions.lock().unwrap();
+                        sessions.record_command(session, written_at, ts, &cmd_with_newline);
+                    }
+                    let _ = endpoints.acks.send(CommandAck {
+                        received_at: cmd.received_at,
+                        written_at,
+                    });
+                }
+                Err(e) => {
+                    let command = cmd_with_newline.trim_end();
+                    error!(error = %e, command, "Error writing command");
+                }
+            }
+        }
+        // Try to read a line (or some binary frames) of telemetry.
+        let mut published = false;
+        let read = match settings.format {
+            TelemetryFormat::Ascii => {
+                let mut line = String::new();
+                let read = reader.read_line(&mut line);
+                if let Ok(n) = read {
+                    if n > 0 {
+                        let line_format = settings.line_format.as_deref();
+                        published = handle_line(
+                            line.trim(), line_format, sinks, endpoints, metrics, filters,
+                        );
+                    }
+                }
+                read
+            }
+            TelemetryFormat::Binary => {
+                let mut chunk = [0u8; 64];
+                let read = reader.read(&mut chunk);
+                if let Ok(n) = read {
+                    frames.push(&chunk[..n]);
+                    while let Some(new_telemetry) = frames.next_frame() {
+                        published |= accept_telemetry(new_telemetry, sinks, metrics, filters);
+                    }
+                    let bad_frames = frames.take_bad_frames();
+                    metrics.telemetry_parse_errors.fetch_add(bad_frames, Ordering::Relaxed);
+                }
+                read
+            }
+        };
+        if published {
+            let (armed, solenoids) = {
+                let telemetry = sinks.telemetry.read().unwrap();
+                (telemetry.armed, telemetry.solenoids.clone())
+            };
+            verifier::check_frame(&endpoints.verifier, armed, &solenoids);
+            state_machine::observe(&endpoints.arm_state, armed);
+            let fix = endpoints.reconciler.take_fix(&solenoids).and_then(|fix| {
+                let guard = endpoints.solenoid_guard.as_ref();
+                let refused = |command: &str| {
+                    reconcile::refused(command, armed, settings.require_armed, guard, &solenoids)
+                };
+                let (refused, allowed): (Vec<_>, Vec<_>) = fix.lines().partition(|c| refused(c));
+                if !refused.is_empty() {
+                    warn!(commands = ?refused, "Solenoids are off against the commanded state, \
+                          but switching them on would be refused now; not correcting");
+                }
+                (!allowed.is_empty()).then(|| allowed.join("\n"))
+            });
+            if let Some(fix) = fix {
+                warn!(commands = ?fix, "Solenoids differ from the commanded state; correcting");
+                let fix = fix + "\n";
+                endpoints.open_timers.lock().unwrap().record(&fix, Instant::now());
+                endpoints.duty_cycle.lock().unwrap().record(&fix, Instant::now());
+                if let Err(e) = write_commands(&mut port, &fix, sinks, endpoints, settings) {
+                    error!(error = %e, commands = ?fix, "Error writing reconciliation commands");
+                }
+            }
+        }
+        match read {
+            Ok(n) if n > 0 => {
+                consecutive_errors = 0;
+            },
+            Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+                // No (or incomplete) data was available within the read timeout. Sockets and
+                // pipes report a timeout as `WouldBlock`.
+            }
+            _ => {
+                // A real read error, or end-of-file (the device went away).
+                consecutive_errors += 1;
+                if let Err(e) = &read {
+                    metrics.serial_read_errors.fetch_add(1, Ordering::Relaxed);
+                    // Warn once per run of errors; the rest would only repeat it.
+                    if consecutive_errors == 1 {
+                        warn!(error = %e, "Serial read error");
+                    } else {
+                        debug!(error = %e, consecutive_errors, "Serial read error");
+                    }
+                }
+                if consecutive_errors >= settings.error_threshold {
+                    return SessionEnd::Disconnected;
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Options read from the command line. Everything except `--config` overrides the
+/// corresponding config file value when given.
+struct CliArgs {
+    /// `--config <path>`: the TOML config file (default `gcs.toml`, if present).
+    config: Option<String>,
+    /// The serial port name (first positional argument).
+    port_name: Option<String>,
+    /// `--baud <rate>`: serial baud rate, one of `SUPPORTED_BAUD_RATES`.
+    baud_rate: Option<u32>,
+    /// `--reconnect-threshold <N>`: consecutive read errors before the port is re-opened.
+    error_threshold: Option<u32>,
+    /// `--log-file <path>`: append parsed telemetry to this CSV file.
+    log_file: Option<String>,
+    /// `--db <path>`: also store parsed telemetry in this SQLite database.
+    db_file: Option<String>,
+    /// `--audit-log <path>`: append arm/disarm events to this file.
+    audit_log: Option<String>,
+    /// `--state-file <path>`: keep the last known telemetry here across restarts.
+    state_file: Option<String>,
+    /// `--history-size <N>`: number of samples kept for GET /telemetry/history.
+    history_size: Option<usize>,
+    /// `--simulate`: run against a simulated Arduino instead of a serial port.
+    simulate: bool,
+    /// `--hil`: run against the hardware-in-the-loop fake Arduino instead of a serial port.
+    hil: bool,
+    /// `--replay <path>`: serve telemetry recorded in this log instead of a serial port.
+    replay: Option<String>,
+    /// `--require-armed`: refuse solenoid commands while disarmed.
+    require_armed: bool,
+    /// `--dry-run`: log commands instead of writing them to the port.
+    dry_run: bool,
+    /// `--format binary|ascii`: the telemetry wire format.
+    format: Option<TelemetryFormat>,
+    /// `--log-format pretty|json`: how the server's log lines are written.
+    log_format: Option<LogFormat>,
+    /// `--udp-out <host:port>[,<host:port>...]`: relay telemetry to these UDP destinations.
+    udp_out: Option<Vec<String>>,
+    /// `--allow-inject`: mount POST /telemetry/inject.
+    allow_inject: bool,
+    /// `--dev-mode`: mount POST /firmware/command.
+    dev_mode: bool,
+    /// `--allow-firmware-reset`: mount POST /firmware/reset.
+    allow_firmware_reset: bool,
+    /// `--udp-in <host:port>`: receive telemetry as UDP datagrams instead of over serial.
+    udp_in: Option<String>,
+    /// `--udp-command <host:port>`: where commands are sent with `--udp-in`.
+    udp_command: Option<String>,
+    /// `--can <interface>`: read telemetry from a SocketCAN interface instead of serial.
+    can: Option<String>,
+    /// `--http-port <N>`: the port the HTTP server listens on (`[server] port`).
+    http_port: Option<u16>,
+    /// `--cluster-peers <host:port>[,<host:port>...]`: the other servers of a cluster.
+    cluster_peers: Option<Vec<String>>,
+}
+
+impl CliArgs {
+    /// Applies the flags that were given on top of `config`.
+    fn apply_to(self, config: &mut Config) {
+        if let Some(port) = self.port_name {
+            config.serial.port = port;
+        }
+        if let Some(baud) = self.baud_rate {
+            config.serial.baud = baud;
+        }
+        if let Some(threshold) = self.error_threshold {
+            config.serial.reconnect_threshold = threshold;
+        }
+        if self.simulate {
+            config.serial.simulate = true;
+        }
+        if self.hil {
+            config.serial.hil = true;
+        }
+        if let Some(path) = self.replay {
+            config.serial.replay = Some(path);
+        }
+        if let Some(path) = self.log_file {
+            config.logging.log_file = Some(path);
+        }
+        if let Some(path) = self.db_file {
+            config.logging.db_file = Some(path);
+        }
+        if let Some(path) = self.audit_log {
+            config.logging.audit_log = Some(path);
+        }
+        if let Some(path) = self.state_file {
+            config.logging.state_file = Some(path);
+        }
+        if let Some(size) = self.history_size {
+            config.logging.ring_buffer_size = size;
+        }
+        if self.require_armed {
+            config.safety.require_armed_for_solenoid = true;
+        }
+        if self.dry_run {
+            config.serial.dry_run = true;
+        }
+        if let Some(format) = self.format {
+            config.serial.format = format;
+        }
+        if let Some(format) = self.log_format {
+            config.logging.format = format;
+        }
+        if let Some(destinations) = self.udp_out {
+            config.relay.udp_out = destinations;
+        }
+        if self.allow_inject {
+            config.server.allow_inject = true;
+        }
+        if self.dev_mode {
+            config.server.dev_mode = true;
+        }
+        if self.allow_firmware_reset {
+            config.server.allow_firmware_reset = true;
+        }
+        if let Some(addr) = self.udp_in {
+            config.serial.udp_in = Some(addr);
+        }
+        if let Some(addr) = self.udp_command {
+            config.serial.udp_command = Some(addr);
+        }
+        if let Some(interface) = self.can {
+            config.serial.can = Some(interface);
+        }
+        if let Some(port) = self.http_port {
+            config.server.port = Some(port);
+        }
+        if let Some(peers) = self.cluster_peers {
+            config.cluster.peers = peers;
+        }
+    }
+}
+
+/// Parses the command line. The first positional argument is the port name.
+fn parse_args() -> CliArgs {
+    let mut config = None;
+    let mut port_name = None;
+    let mut baud_rate = None;
+    let mut error_threshold = None;
+    let mut log_file = None;
+    let mut db_file = None;
+    let mut audit_log = None;
+    let mut state_file = None;
+    let mut history_size = None;
+    let mut simulate = false;
+    let mut hil = false;
+    let mut replay = None;
+    let mut require_armed = false;
+    let mut dry_run = false;
+    let mut format = None;
+    let mut log_format = None;
+    let mut udp_out = None;
+    let mut allow_inject = false;
+    let mut dev_mode = false;
+    let mut allow_firmware_reset = false;
+    let mut udp_in = None;
+    let mut udp_command = None;
+    let mut can = None;
+    let mut http_port = None;
+    let mut cluster_peers = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => match args.next() {
+                Some(path) => config = Some(path),
+                None => exit_with_usage("--config requires a path"),
+            },
+            "--baud" => match args.next() {
+                Some(rate) => baud_rate = Some(parse_baud_rate(&rate)),
+                None => exit_with_usage("--baud requires a rate"),
+            },
+            "--reconnect-threshold" => match args.next().map(|n| n.parse::<u32>()) {
+                Some(Ok(n)) if n > 0 => error_threshold = Some(n),
+                _ => exit_with_usage("--reconnect-threshold requires a positive error count"),
+            },
+            "--log-file" => match args.next() {
+                Some(path) => log_file = Some(path),
+                None => exit_with_usage("--log-file requires a path"),
+            },
+            "--db" => match args.next() {
+                Some(path) => db_file = Some(path),
+                None => exit_with_usage("--db requires a path"),
+            },
+            "--audit-log" => match args.next() {
+                Some(path) => audit_log = Some(path),
+                None => exit_with_usage("--audit-log requires a path"),
+            },
+            "--state-file" => match args.next() {
+                Some(path) => state_file = Some(path),
+                None => exit_with_usage("--state-file requires a path"),
+            },
+            "--history-size" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => history_size = Some(n),
+                _ => exit_with_usage("--history-size requires a sample count"),
+            },
+            "--simulate" => simulate = true,
+            "--hil" => hil = true,
+            "--replay" => match args.next() {
+                Some(path) => replay = Some(path),
+                None => exit_with_usage("--replay requires a log file"),
+            },
+            "--require-armed" => require_armed = true,
+            "--dry-run" => dry_run = true,
+            "--format" => match args.next().as_deref() {
+                Some("ascii") => format = Some(TelemetryFormat::Ascii),
+                Some("binary") => format = Some(TelemetryFormat::Binary),
+                _ => exit_with_usage("--format must be 'ascii' or 'binary'"),
+            },
+            "--log-format" => match args.next().as_deref() {
+                Some("pretty") => log_format = Some(LogFormat::Pretty),
+                Some("json") => log_format = Some(LogFormat::Json),
+                _ => exit_with_usage("--log-format must be 'pretty' or 'json'"),
+            },
+            "--udp-out" => match args.next() {
+                Some(list) => {
+                    udp_out = Some(list.split(',').map(|d| d.trim().to_string()).collect())
+                }
+                None => exit_with_usage("--udp-out requires <host:port>[,<host:port>...]"),
+            },
+            "--allow-inject" => allow_inject = true,
+            "--dev-mode" => dev_mode = true,
+            "--allow-firmware-reset" => allow_firmware_reset = true,
+            "--udp-in" => match args.next() {
+                Some(addr) => udp_in = Some(addr),
+                None => exit_with_usage("--udp-in requires <host:port>"),
+            },
+            "--can" => match args.next() {
+                Some(interface) => can = Some(interface),
+                None => exit_with_usage("--can requires <interface>"),
+            },
+            "--udp-command" => match args.next() {
+                Some(addr) => udp_command = Some(addr),
+                None => exit_with_usage("--udp-command requires <host:port>"),
+            },
+            "--http-port" => match args.next().map(|n| n.parse::<u16>()) {
+                Some(Ok(port)) if port >= MIN_HTTP_PORT => http_port = Some(port),
+                Some(Ok(port)) => exit_with_usage(&format!(
+                    "--http-port {} is a privileged port; use {}-65535",
+                    port, MIN_HTTP_PORT
+                )),
+                _ => exit_with_usage(&format!(
+                    "--http-port requires a port number ({}-65535)",
+                    MIN_HTTP_PORT
+                )),
+            },
+            "--cluster-peers" => match args.next() {
+                Some(list) => {
+                    cluster_peers = Some(list.split(',').map(|p| p.trim().to_string()).collect())
+                }
+                None => exit_with_usage("--cluster-peers requires <host:port>[,<host:port>...]"),
+            },
+            flag if flag.starts_with("--") => {
+                exit_with_usage(&format!("Unknown option '{}'", flag))
+            }
+            _ if port_name.is_none() => port_name = Some(arg),
+            _ => exit_with_usage(&format!("Unexpected argument '{}'", arg)),
+        }
+    }
+    CliArgs {
+        config,
+        port_name,
+        baud_rate,
+        error_threshold,
+        log_file,
+        db_file,
+        audit_log,
+        state_file,
+        history_size,
+        simulate,
+        hil,
+        replay,
+        require_armed,
+        dry_run,
+        format,
+        log_format,
+        udp_out,
+        allow_inject,
+        dev_mode,
+        allow_firmware_reset,
+        udp_in,
+        udp_command,
+        can,
+        http_port,
+        cluster_peers,
+    }
+}
+
+/// Loads the config file named by `--config`, or `gcs.toml` if it exists, or the defaults.
+/// Also returns the path of the file, if one was read.
+/// Exits if the file cannot be read or is invalid.
+fn load_config(path: Option<&str>) -> (Config, Option<&str>) {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH,
+        None => return (Config::default(), None),
+    };
+    match Config::load(path) {
+        Ok(config) => (config, Some(path)),
+        Err(errors) => {
+            for e in errors.lines() {
+                eprintln!("Invalid config: {}", e);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parses a `--baud` value, exiting with the list of valid rates if it is not supported.
+fn parse_baud_rate(value: &str) -> u32 {
+    match value.parse::<u32>() {
+        Ok(rate) if SUPPORTED_BAUD_RATES.contains(&rate) => rate,
+        _ => {
+            let valid: Vec<String> = SUPPORTED_BAUD_RATES.iter().map(|r| r.to_string()).collect();
+            eprintln!("Unsupported baud rate '{}'. Valid rates: {}", value, valid.join(", "));
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Prints an argument error plus usage and exits before Rocket starts.
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!(
+        "Usage: telemetry_server generate-password\n       \
+         telemetry_server [PORT] [--config <path>] [--baud <rate>] \
+         [--reconnect-threshold <N>] [--log-file <path>] [--db <path>] [--audit-log <path>] \
+         [--state-file <path>] \
+         [--history-size <N>] [--simulate] [--hil] [--replay <log_file>] [--require-armed] \
+         [--dry-run] \
+         [--format ascii|binary] [--log-format pretty|json] [--udp-out <host:port>,...] \
+         [--allow-inject] [--dev-mode] [--allow-firmware-reset] \
+         [--udp-in <host:port>] [--udp-command <host:port>] [--can <interface>] \
+         [--http-port <N>] [--cluster-peers <host:port>,...]"
+    );
+    std::process::exit(2);
+}
+
+/// The server as configured by the config file and then the command line, with its serial
+/// loop (or replay) already running and every endpoint mounted.
+pub fn rocket() -> Rocket<Build> {
+    if env::args().nth(1).as_deref() == Some("generate-password") {
+        basic_auth::generate_password();
+    }
+    let mut args = parse_args();
+    let config_path = args.config.take();
+    let (mut config, loaded_from) = load_config(config_path.as_deref());
+    args.apply_to(&mut config);
+    logging::init(config.logging.format);
+    let build = &health::VERSION_INFO;
+    info!(version = build.version, git_sha = build.git_sha, built = build.build_timestamp,
+          "Ground control server");
+    if let Some(path) = loaded_from {
+        info!(path, "Loaded config");
+    }
+    #[cfg(debug_assertions)]
+    match toml::to_string(&config) {
+        Ok(text) => debug!(config = %text, "Resolved config"),
+        Err(e) => warn!(error = %e, "Could not print the resolved config"),
+    }
+    let serial = config.serial;
+    let resolve_udp = |addr: &str| {
+        udp_link::resolve(addr).unwrap_or_else(|e| {
+            error!(error = %e, "Invalid UDP address");
+            std::process::exit(1);
+        })
+    };
+    let mut udp_link = None;
+    if let Some(path) = &serial.replay {
+        info!(path, "Replaying telemetry (commands will not be sent)");
+    } else if serial.simulate {
+        info!("Simulating the Arduino (no serial port will be opened)");
+    } else if serial.hil {
+        if cfg!(not(unix)) {
+            error!("--hil is only supported on Unix-like systems");
+            std::process::exit(1);
+        }
+        info!("Hardware-in-the-loop mode: fake Arduino on a local socket pair");
+    } else if let Some(interface) = &serial.can {
+        if cfg!(not(feature = "can")) {
+            error!("--can needs a build with the `can` feature");
+            std::process::exit(1);
+        }
+        if config.can.signals.is_empty() {
+            error!("--can needs at least one [[can.signal]] in the config");
+            std::process::exit(1);
+        }
+        info!(interface, "Receiving telemetry over CAN");
+    } else if let Some(addr) = &serial.udp_in {
+        let command_to = serial.udp_command.as_deref().map(resolve_udp);
+        udp_link = Some((resolve_udp(addr), command_to));
+        match command_to {
+            Some(command_to) => info!(addr, %command_to, "Receiving telemetry over UDP"),
+            None => info!(addr, "Receiving telemetry over UDP (replying to the sender)"),
+        }
+    } else {
+        info!(port = %serial.port, baud = serial.baud, "Using serial port");
+    }
+    // A follower hands the serial port over by closing it, which the other links and the
+    // secondary boards' loops have no way to do.
+    let clustered = !config.cluster.peers.is_empty();
+    let other_link = serial.replay.is_some() || serial.can.is_some() || serial.udp_in.is_some();
+    if clustered && (other_link || config.boards.len() > 1) {
+        error!("[cluster] peers need a single board on a serial port (or --simulate / --hil)");
+        std::process::exit(1);
+    }
+    let log_file = config.logging.log_file;
+
+    // Open the CSV log up front so a bad path is reported before anything else starts.
+    let csv_log: Option<SharedCsvLog> = log_file.as_ref().map(|path| {
+        match CsvLog::open(path) {
+            Ok(log) => {
+                info!(path, "Logging telemetry to CSV");
+                Arc::new(Mutex::new(log))
+            }
+            Err(e) => {
+                error!(path, error = %e, "Failed to open log file");
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let db = config.logging.db_file.as_ref().map(|path| open_db(path));
+
+    let udp_relay = if config.relay.udp_out.is_empty() {
+        None
+    } else {
+        match udp_relay::spawn(&config.relay.udp_out) {
+            Ok(relay) => {
+                info!(destinations = ?config.relay.udp_out, "Relaying telemetry over UDP");
+                Some(relay)
+            }
+            Err(e) => {
+                error!(error = %e, "Invalid --udp-out destination");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let (mut app_state, mut endpoints, ack_rx) =
+        AppState::new(config.logging.ring_buffer_size, log_file);
+    app_state.db_path = config.logging.db_file;
+    if let Some(path) = &config.logging.audit_log {
+        match ArmAuditLog::open(path) {
+            Ok(log) => {
+                info!(path, earlier_events = log.events().len(), "Appending to the arm audit log");
+                app_state.arm_audit = Arc::new(Mutex::new(log));
+            }
+            Err(e) => {
+                error!(path, error = %e, "Failed to open the arm audit log");
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = config.logging.state_file {
+        let state_file = StateFile::new(path, app_state.telemetry.clone());
+        match state_file.load() {
+            Ok(Some(tel)) => {
+                info!(path = state_file.path(), telemetry_ts = tel.timestamp,
+                      "Restored the last known state");
+                *app_state.telemetry.write().unwrap() = tel;
+            }
+            Ok(None) => {}
+            Err(e) => warn!(path = state_file.path(), error = %e, "Ignoring the state file"),
+        }
+        app_state.state_file = Some(state_file);
+    }
+    app_state.require_armed = config.safety.require_armed_for_solenoid;
+    app_state.require_armed_for_all = config.safety.require_armed_for_all;
+    app_state.require_two_step = config.safety.require_two_step;
+    let confirm_window = Duration::from_millis(config.safety.arm_confirm_window_ms);
+    app_state.arm_intent = Mutex::new(ArmIntent::new(confirm_window));
+    let min_interval = Duration::from_millis(config.safety.min_interval_ms);
+    app_state.rate_limiter = Mutex::new(RateLimiter::new(min_interval));
+    let limit = &config.rate_limit;
+    app_state.request_limiter = Mutex::new(RequestLimiter::new(limit.burst, limit.refill_per_s));
+    app_state.allowed_origins = config.cors.allowed_origins;
+    app_state.diagram = DiagramLayout::new(&config.diagram);
+    app_state.countdown = Arc::new(Countdown::new(&config.countdown));
+    app_state.runtime_config = Arc::new(Mutex::new(RuntimeConfig {
+        labels: config.solenoid_labels,
+        directions: config.solenoid_directions,
+        groups: config.groups,
+        interlocks: config.interlocks,
+        path: loaded_from.unwrap_or(DEFAULT_CONFIG_PATH).to_string(),
+    }));
+    endpoints.solenoid_guard = Some(app_state.solenoid_guard());
+    app_state.basic_auth = config.auth.basic_credentials();
+    app_state.auth_secret = auth::resolve_secret(config.auth.secret);
+    if app_state.auth_secret.is_some() {
+        info!(header = auth::SIGNATURE_HEADER, "POST requests must be signed");
+    }
+    if let Some(credentials) = &app_state.basic_auth {
+        info!(username = %credentials.username, "All requests require basic auth");
+    }
+    if app_state.require_armed {
+        info!("Solenoid commands require the system to be armed");
+    }
+    if app_state.require_two_step {
+        info!(window_ms = config.safety.arm_confirm_window_ms, "Arming requires intent + confirm");
+    }
+    app_state.dry_run = serial.dry_run;
+    app_state.allow_inject = config.server.allow_inject;
+    if app_state.allow_inject {
+        warn!("POST /telemetry/inject is enabled: anyone who can reach the server can fake \
+               telemetry");
+    }
+    if config.server.dev_mode {
+        if cfg!(not(debug_assertions)) {
+            error!("--dev-mode is only available in debug builds");
+            std::process::exit(1);
+        }
+        app_state.dev_mode = true;
+        warn!("Dev mode: POST /firmware/command sends raw lines to the Arduino, bypassing \
+               every safety check");
+    }
+    app_state.allow_firmware_reset = config.server.allow_firmware_reset;
+    if app_state.allow_firmware_reset {
+        warn!("POST /firmware/reset is enabled: anyone who can reach the server can reboot \
+               the Arduino");
+    }
+    if app_state.dry_run {
+        warn!("Dry run: commands are logged but not written to the serial port");
+    }
+    let limits = config::solenoid_max_open(&config.solenoid_max_open_ms);
+    *app_state.open_timers.lock().unwrap() = OpenTimers::new(limits);
+    *app_state.duty_cycle.lock().unwrap() = DutyCycleTracker::new(config.safety.max_duty_pct);
+    let verify_timeout = Duration::from_millis(config.safety.verify_timeout_ms);
+    *app_state.verifier.lock().unwrap() = CommandVerifier::new(verify_timeout);
+    if app_state.open_timers.lock().unwrap().any_limits() {
+        safety::spawn_open_timers(app_state.open_timers.clone(), app_state.emergency_tx.clone());
+    }
+    if config.safety.watchdog_timeout_s > 0 {
+        let countdown = app_state.countdown.clone();
+        safety::spawn_watchdog(
+            Duration::from_secs(config.safety.watchdog_timeout_s),
+            app_state.telemetry.clone(),
+            app_state.emergency_tx.clone(),
+            app_state.watchdog_tripped.clone(),
+            app_state.arm_audit.clone(),
+            app_state.board_id,
+            move || {
+                countdown.abort();
+            },
+        );
+    }
+    if config.safety.max_drain_rate_v_per_s > 0.0 {
+        safety::spawn_battery_rate_monitor(
+            config.safety.max_drain_rate_v_per_s,
+            app_state.telemetry_tx.subscribe(),
+            app_state.battery_draining.clone(),
+        );
+    }
+    let mut figment = rocket::Config::figment();
+    if let Some(address) = config.server.address {
+        figment = figment.merge(("address", address));
+    }
+    if let Some(port) = config.server.port {
+        figment = figment.merge(("port", port));
+    }
+    if let Some(tls) = config.server.tls {
+        figment = figment.merge(("tls.certs", tls.certs)).merge(("tls.key", tls.key));
+        // Without Rocket's `tls` feature the settings above are silently ignored; refuse to
+        // serve plain HTTP when HTTPS was asked for.
+        if !rocket::Config::from(&figment).tls_enabled() {
+            error!("[server.tls] is configured, but this build has no TLS support; \
+                    terminate TLS in a reverse proxy instead");
+            std::process::exit(1);
+        }
+    }
+    if clustered {
+        let bound = rocket::Config::from(&figment);
+        let http_addr = SocketAddr::new(bound.address, bound.port);
+        let peers = cluster::resolve_peers(&config.cluster.peers).unwrap_or_else(|e| {
+            error!(error = %e, "Invalid [cluster] peer");
+            std::process::exit(1);
+        });
+        let cluster = Arc::new(Cluster::new(peers, http_addr, app_state.auth_secret.clone()));
+        let heartbeats = UdpSocket::bind(http_addr)
+            .and_then(|socket| cluster.spawn(socket, &app_state.shutdown));
+        if let Err(e) = heartbeats {
+            error!(%http_addr, error = %e, "Failed to open the cluster heartbeat socket");
+            std::process::exit(1);
+        }
+        info!(peers = ?config.cluster.peers, "Clustered: following until a leader is heard from");
+        app_state.cluster = cluster;
+    }
+
+    // Spawn the serial loop thread.
+    let sinks = TelemetrySinks {
+        telemetry: app_state.telemetry.clone(),
+        history: app_state.history.clone(),
+        broadcast: app_state.telemetry_tx.clone(),
+        csv_log,
+        db,
+        udp_relay,
+        parse_rate: app_state.parse_rate.clone(),
+    };
+    let line_format = config.telemetry.format.as_ref().map(|format| {
+        info!(regex = %format.regex, "Parsing telemetry with a custom line format");
+        Arc::new(LineFormat::new(&format.regex).expect("checked by Config::load"))
+    });
+    let require_armed = app_state.require_armed;
+    let serial_settings = || SerialSettings {
+        line_format: line_format.clone(),
+        require_armed,
+        ..SerialSettings::new(&serial, &config.filters)
+    };
+    // The other boards of a multi-board stand each get their own serial loop.
+    let board_settings = |port: &str, baud: u32| SerialSettings {
+        port_name: port.to_string(),
+        baud_rate: baud,
+        ..serial_settings()
+    };
+    if let Some(primary) = config.boards.first() {
+        app_state.board_id = primary.id;
+    }
+    for board in config.boards.iter().skip(1) {
+        info!(board = board.id, port = %board.port, "Secondary board");
+        let settings = board_settings(&board.port, board.baud.unwrap_or(serial.baud));
+        app_state.secondary_boards.push(BoardState::spawn(
+            board.id,
+            settings,
+            RateLimiter::new(min_interval),
+            endpoints.acks.clone(),
+            endpoints.ack_round_trips.clone(),
+            app_state.metrics.clone(),
+            &app_state.shutdown,
+        ));
+    }
+
+    #[cfg(feature = "webhook")]
+    if let Err(e) =
+        webhook::spawn_low_battery_watcher(&config.webhook, app_state.telemetry_tx.subscribe())
+    {
+        error!(error = %e, "Invalid webhook config");
+        std::process::exit(1);
+    }
+    #[cfg(not(feature = "webhook"))]
+    if config.webhook.url.is_some() {
+        warn!("Built without the `webhook` feature; low-battery alerts are disabled");
+    }
+    if let Err(e) = alerts::spawn(config.alerts, app_state.telemetry_tx.subscribe(),
+        app_state.active_alerts.clone())
+    {
+        error!(error = %e, "Invalid alert webhook");
+        std::process::exit(1);
+    }
+
+    if let Some(path) = &serial.replay {
+        match replay::spawn(path, sinks, endpoints) {
+            Ok(status) => app_state.replay = Some(status),
+            Err(e) => {
+                error!(path, error = %e, "Failed to open replay log");
+                std::process::exit(1);
+            }
+        }
+        *app_state.connection_status.lock().unwrap() = ConnectionStatus::Connected;
+    } else if let Some(interface) = serial.can.clone() {
+        #[cfg(feature = "can")]
+        {
+            let settings = serial_settings();
+            let status = app_state.connection_status.clone();
+            let metrics = app_state.metrics.clone();
+            let can = config.can;
+            app_state.shutdown.spawn(move || {
+                can_link::spawn_can_loop(
+                    sinks, endpoints, settings, interface, can, status, metrics,
+                );
+            });
+        }
+        #[cfg(not(feature = "can"))]
+        unreachable!("refused above: {}", interface);
+    } else if let Some((bind, command_to)) = udp_link {
+        let settings = serial_settings();
+        let status = app_state.connection_status.clone();
+        let metrics = app_state.metrics.clone();
+        app_state.shutdown.spawn(move || {
+            udp_link::spawn_udp_loop(sinks, endpoints, settings, bind, command_to, status, metrics);
+        });
+    } else if clustered {
+        let settings = serial_settings();
+        let status = app_state.connection_status.clone();
+        let metrics = app_state.metrics.clone();
+        let cluster = app_state.cluster.clone();
+        let shutdown = app_state.shutdown.clone();
+        app_state.shutdown.spawn(move || {
+            let open = move |settings: &SerialSettings| {
+                open_as_leader(&cluster, &shutdown, settings)
+            };
+            spawn_serial_loop(sinks, endpoints, settings, status, metrics, open);
+        });
+    } else {
+        let settings = serial_settings();
+        let status = app_state.connection_status.clone();
+        let metrics = app_state.metrics.clone();
+        app_state.shutdown.spawn(move || {
+            spawn_serial_loop(sinks, endpoints, settings, status, metrics, open_link);
+        });
+    }
+
+    let self_test = (!app_state.dry_run).then(|| {
+        SelfTestFairing::new(
+            app_state.replay.is_some(),
+            app_state.basic_auth.is_some(),
+            app_state.dev_mode,
+        )
+    });
+    let rocket = build_rocket(app_state, ack_rx).configure(figment);
+    match self_test {
+        Some(self_test) => rocket.attach(self_test),
+        None => rocket,
+    }
+}
+
+/// The server with default settings, its serial loop talking to `writer` and `reader` instead
+/// of a serial port: commands are written to `writer`, telemetry lines are read from
+/// `reader`. Reads should time out the way a serial port's do (e.g. a socket with a read
+/// timeout), or commands only go out when a line arrives. The link is used for one session;
+/// once it ends the serial loop gives up.
+pub fn rocket_with_link<W, R>(writer: W, reader: R) -> Rocket<Build>
+where
+    W: Write + Send + 'static,
+    R: Read + Send + 'static,
+{
+    let config = Config::default();
+    let (app_state, endpoints, ack_rx) =
+        AppState::new(config.logging.ring_buffer_size, None);
+    let sinks = TelemetrySinks {
+        telemetry: app_state.telemetry.clone(),
+        history: app_state.history.clone(),
+        broadcast: app_state.telemetry_tx.clone(),
+        csv_log: None,
+        db: None,
+        udp_relay: None,
+        parse_rate: app_state.parse_rate.clone(),
+    };
+    let settings = SerialSettings::new(&config.serial, &config.filters);
+    let status = app_state.connection_status.clone();
+    let metrics = app_state.metrics.clone();
+    let mut link = Some(SerialLink {
+        writer: Box::new(writer),
+        reader: Box::new(BufReader::new(reader)),
+        control: None,
+    });
+    app_state.shutdown.spawn(move || {
+        let open = move |_: &SerialSettings| link.take().ok_or(SessionEnd::Fatal);
+        spawn_serial_loop(sinks, endpoints, settings, status, metrics, open);
+    });
+    build_rocket(app_state, ack_rx)
+}
+
+/// Opens the `--db` database and starts its writer thread, exiting on failure.
+#[cfg(feature = "sqlite")]
+fn open_db(path: &str) -> mpsc::Sender<Telemetry> {
+    match db::spawn_writer(path) {
+        Ok(tx) => {
+            info!(path, "Storing telemetry in database");
+            tx
+        }
+        Err(e) => {
+            error!(path, error = %e, "Failed to open database");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_db(_path: &str) -> mpsc::Sender<Telemetry> {
+    error!("--db needs a build with the `sqlite` feature");
+    std::process::exit(1);
+}
+
+/// Builds the Rocket instance around an application state: manages it, attaches the
+/// fairings and mounts all endpoints. Starting the serial loop is left to the caller.
+fn build_rocket(app_state: AppState, ack_rx: mpsc::Receiver<CommandAck>) -> Rocket<Build> {
+    let latencies = app_state.latencies.clone();
+    let cors = CorsFairing::new(app_state.allowed_origins.clone());
+    let basic_auth = app_state.basic_auth.clone().map(BasicAuthFairing::new);
+    let allow_inject = app_state.allow_inject;
+    let dev_mode = app_state.dev_mode && cfg!(debug_assertions);
+    let allow_firmware_reset = app_state.allow_firmware_reset;
+    let shutdown = ShutdownFairing::new(app_state.shutdown.clone(), app_state.state_file.clone());
+    let rocket = rocket::build()
+        .manage(app_state)
+        .register("/", catchers![auth::unauthorized])
+        .attach(CommandLatencyFairing::new(ack_rx, latencies))
+        .attach(cors)
+        .attach(RateLimitFairing)
+        .attach(shutdown)
+        .mount(
+            "/",
+            routes![
+                index,
+                get_telemetry,
+                get_telemetry_extra,
+                get_telemetry_history,
+                get_telemetry_diff,
+                get_telemetry_stats,
+                export_json,
+                get_telemetry_csv,
+                get_status,
+                get_pyro,
+                get_solenoid,
+                get_solenoid_mask,
+                get_solenoid_timers,
+                get_solenoid_duty,
+                get_solenoid_labels,
+                health::get_health,
+                health::get_version,
+                ws_telemetry,
+                events,
+                telemetry_stream,
+                get_log_path,
+                get_battery_calibration,
+                set_battery_calibration,
+                get_replay_status,
+                get_metrics,
+                get_latency_metrics,
+                get_parse_errors,
+                get_ack_stats,
+                get_ack_log,
+                get_last_nack,
+                get_flight_log,
+                clear_flight_log,
+                snapshot::capture,
+                alerts::active,
+                alerts::battery_drain,
+                runtime_config::get_labels,
+                runtime_config::add_labels,
+                runtime_config::replace_labels,
+                runtime_config::delete_label,
+                runtime_config::get_groups,
+                runtime_config::add_group,
+                runtime_config::delete_group,
+                runtime_config::get_interlocks,
+                runtime_config::add_interlock,
+                runtime_config::delete_interlock,
+                runtime_config::save,
+                diagram::get,
+                continuity::test_solenoid,
+                session::create,
+                session::log,
+                verifier::failures,
+                history::ring_buffer_bytes,
+                countdown::get,
+                countdown::start,
+                countdown::abort,
+                snapshot::get,
+                get_arm_audit,
+                command_queue::get_pending,
+                command_queue::clear_pending,
+                reconcile::reconcile,
+                arm,
+                arm_intent,
+                arm_confirm,
+                disarm,
+                emergency_stop,
+                solenoid,
+                solenoid_batch,
+                open_all_solenoids,
+                close_all_solenoids,
+                groups::list_groups,
+                groups::open,
+                groups::close,
+                set_solenoid_mask,
+                pulse_solenoid,
+                get_pulse,
+                abort_pulse,
+                start_sequence,
+                get_sequence_status,
+                abort_sequence,
+                script::run,
+                script::abort,
+                script::status,
+                serial_reconnect,
+                serial_ports::list_ports,
+                raw_log::get_raw,
+                raw_log::get_raw_text,
+                board::list_boards,
+                board::get_all_telemetry,
+                board::get_telemetry,
+                board::get_status,
+                board::get_solenoid,
+                board::arm,
+                board::disarm,
+                board::solenoid,
+                cors::preflight,
+                basic_auth::challenge,
+                request_limit::rejected,
+                cluster::status,
+                cluster::proxy,
+            ],
+        );
+    #[cfg(feature = "sqlite")]
+    let rocket = rocket.mount("/", routes![db::query]);
+    let rocket = match allow_inject {
+        true => rocket.mount("/", routes![inject_telemetry]),
+        false => rocket,
+    };
+    let rocket = match dev_mode {
+        true => rocket.mount("/", routes![firmware_command::send]),
+        false => rocket,
+    };
+    let rocket = match allow_firmware_reset {
+        true => rocket.mount("/", routes![firmware_reset::reset]),
+        false => rocket,
+    };
+    let rocket = match basic_auth {
+        Some(basic_auth) => rocket.attach(basic_auth),
+        None => rocket,
+    };
+    // After the fairings that turn requests away, so a follower only proxies the rest.
+    rocket.attach(ClusterProxyFairing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{AlertField, AlertOp, AlertRule, SolenoidGroup};
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+
+    /// A test client around a fresh application state, plus the serial loop's channel ends
+    /// so tests can see exactly what would have been written to the port.
+    fn client() -> (Client, SerialEndpoints) {
+        client_with(|_| {})
+    }
+
+    /// Like `client()`, with `configure` applied to the state first.
+    fn client_with(configure: impl FnOnce(&mut AppState)) -> (Client, SerialEndpoints) {
+        let (mut state, endpoints, ack_rx) = AppState::new(10, None);
+        configure(&mut state);
+        let client = Client::tracked(build_rocket(state, ack_rx)).expect("valid rocket");
+        (client, endpoints)
+    }
+
+    fn post_batch(client: &Client, body: &str) -> Status {
+        client
+            .post("/solenoids/batch")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn all_invalid_batch_sends_nothing() {
+        let (client, endpoints) = client();
+        let status = post_batch(
+            &client,
+            r#"[{"channel":0,"state":1},{"channel":17,"state":0},{"channel":3,"state":2}]"#,
+        );
+        assert_eq!(status, Status::BadRequest);
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn batch_with_one_invalid_entry_sends_nothing() {
+        let (client, endpoints) = client();
+        let status = post_batch(&client, r#"[{"channel":3,"state":1},{"channel":99,"state":1}]"#);
+        assert_eq!(status, Status::BadRequest);
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn valid_batch_is_queued_as_one_write() {
+        let (client, endpoints) = client();
+        let status = post_batch(&client, r#"[{"channel":3,"state":1},{"channel":7,"state":0}]"#);
+        assert_eq!(status, Status::Ok);
+        let queued = endpoints.commands.try_recv().expect("batch queued");
+        assert_eq!(queued.text, "s31\ns70");
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn get_solenoid_reports_one_channel() {
+        let (client, _endpoints) = client();
+        let state = client.rocket().state::<AppState>().unwrap();
+        {
+            let mut tel = state.telemetry.write().unwrap();
+            tel.timestamp = 42;
+            tel.solenoids[4] = true;
+        }
+        let response = client.get("/solenoid/5").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"{"channel":5,"state":true,"timestamp":42}"#
+        );
+    }
+
+    #[test]
+    fn get_solenoid_out_of_range_is_not_found() {
+        let (client, _endpoints) = client();
+        assert_eq!(client.get("/solenoid/0").dispatch().status(), Status::NotFound);
+        assert_eq!(client.get("/solenoid/17").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn require_armed_blocks_solenoids_while_disarmed() {
+        let (client, endpoints) = client_with(|state| state.require_armed = true);
+        let response = client.post("/solenoid/3/1").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.into_string().unwrap(), "SYSTEM_NOT_ARMED");
+        assert_eq!(post_batch(&client, r#"[{"channel":3,"state":1}]"#), Status::Forbidden);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().armed = true;
+        assert_eq!(client.post("/solenoid/3/1").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s31");
+    }
+
+    #[test]
+    fn all_solenoid_commands_match_the_builder() {
+        for on in [true, false] {
+            let mut builder = CommandBuilder::new();
+            for ch in 1..=16 {
+                builder.solenoid(ch, on).unwrap();
+            }
+            assert_eq!(all_solenoids_command(on), builder.build_joined());
+        }
+    }
+
+    #[test]
+    fn all_solenoids_override_the_interlocks_but_not_the_arming() {
+        let (client, endpoints) = client_with(|state| {
+            state.runtime_config.lock().unwrap().interlocks =
+                vec![config::Interlock { prevent: vec![3, 7] }];
+        });
+        let response = client.post("/solenoid/all/open").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().armed = true;
+        for uri in ["/solenoid/all/open", "/solenoid/all/close", "/solenoid/all/open"] {
+            let response = client.post(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_string().unwrap(), r#"{"sent":16}"#);
+        }
+        let open = endpoints.commands.try_recv().unwrap().text;
+        assert_eq!(open.lines().count(), 16);
+        assert!(open.starts_with("s11\ns21\n") && open.ends_with("\ns161"));
+        assert!(endpoints.commands.try_recv().unwrap().text.lines().all(|l| l.ends_with('0')));
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, open);
+    }
+
+    #[test]
+    fn interlocks_keep_paired_valves_from_opening_together() {
+        let (client, endpoints) = client_with(|state| {
+            state.runtime_config.lock().unwrap().interlocks =
+                vec![config::Interlock { prevent: vec![3, 7] }];
+            state.telemetry.write().unwrap().solenoids[2] = true;
+        });
+        for uri in ["/solenoid/7/1", "/board/0/solenoid/7/1"] {
+            let response = client.post(uri).dispatch();
+            assert_eq!(response.status(), Status::Conflict);
+            assert_eq!(
+                response.into_string().unwrap(),
+                "INTERLOCK_VIOLATION: channel 7 blocked by channel 3"
+            );
+        }
+        assert!(endpoints.commands.try_recv().is_err());
+        assert_eq!(client.post("/solenoid/3/0").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30");
+
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().solenoids[2] = false;
+        assert_eq!(client.post("/solenoid/7/1").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s71");
+    }
+
+    #[test]
+    fn interlocks_apply_to_batches_masks_groups_and_sequences() {
+        let (client, endpoints) = client_with(|state| {
+            let mut runtime = state.runtime_config.lock().unwrap();
+            runtime.interlocks = vec![config::Interlock { prevent: vec![3, 7] }];
+            runtime.groups =
+                vec![SolenoidGroup { name: "feed".to_string(), channels: vec![3, 7] }];
+        });
+        let post_json = |uri: &'static str, body: &str| {
+            client.post(uri).header(ContentType::JSON).body(body).dispatch()
+        };
+        // Both valves of the pair in one command.
+        let both = r#"[{"channel":3,"state":1},{"channel":7,"state":1}]"#;
+        for response in [
+            post_json("/solenoids/batch", both),
+            post_json("/solenoid/mask", r#"{"mask":68}"#),
+            client.post("/group/feed/open").dispatch(),
+        ] {
+            assert_eq!(response.status(), Status::Conflict);
+            assert!(response.into_string().unwrap().starts_with("INTERLOCK_VIOLATION"));
+        }
+        assert!(endpoints.commands.try_recv().is_err());
+
+        // The rules see the state after the whole command: closing 3 while opening 7 is fine.
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().solenoids[2] = true;
+        let swap = r#"[{"channel":3,"state":0},{"channel":7,"state":1}]"#;
+        assert_eq!(post_json("/solenoids/batch", swap).status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30\ns71");
+
+        let step = r#"[{"delay_ms":0,"command":{"type":"solenoid","channel":7,"state":1}}]"#;
+        assert_eq!(post_json("/sequence", step).status(), Status::Ok);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !matches!(state.sequence.status(), SequenceStatus::Failed { .. }) {
+            assert!(Instant::now() < deadline, "sequence did not fail");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn solenoids_over_their_duty_cycle_cannot_be_switched_on() {
+        let (client, endpoints) = client_with(|state| {
+            let minute_ago = Instant::now() - Duration::from_secs(60);
+            state.duty_cycle.lock().unwrap().record("s31", minute_ago);
+        });
+        let duty: Vec<f32> = client.get("/solenoid/duty").dispatch().into_json().unwrap();
+        assert_eq!(duty.len(), 16);
+        assert_eq!((duty[2], duty[3]), (100.0, 0.0));
+        let response = client.post("/solenoid/3/1").dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.into_string().unwrap(), "DUTY_CYCLE_EXCEEDED");
+        assert!(endpoints.commands.try_recv().is_err());
+        assert_eq!(client.post("/solenoid/3/0").dispatch().status(), Status::Ok);
+        assert_eq!(client.post("/solenoid/4/1").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn pulse_opens_then_closes_the_solenoid() {
+        let (client, endpoints) = client();
+        let recv = || {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            loop {
+                if let Ok(cmd) = endpoints.commands.try_recv() {
+                    return cmd.text;
+                }
+                assert!(Instant::now() < deadline, "no command queued");
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        let response = client.post("/solenoid/5/pulse?duration_ms=20").dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        let pulse: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(pulse["status"], "active");
+        assert_eq!(recv(), "s51");
+        assert_eq!(recv(), "s50");
+        thread::sleep(Duration::from_millis(20));
+        let status = client.get(format!("/solenoid/pulse/{}", pulse["pulse_id"])).dispatch();
+        let status: rocket::serde::json::Value = status.into_json().unwrap();
+        assert_eq!(status["status"], "complete");
+
+        // A long pulse is clamped to 5 s and can be cut short.
+        let response = client.post("/solenoid/6/pulse?duration_ms=900000").dispatch();
+        let pulse: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(pulse["duration_ms"], 5000);
+        assert_eq!(recv(), "s61");
+        let aborted = client.delete(format!("/solenoid/pulse/{}", pulse["pulse_id"])).dispatch();
+        let aborted: rocket::serde::json::Value = aborted.into_json().unwrap();
+        assert_eq!(aborted["status"], "aborted");
+        assert_eq!(recv(), "s60");
+
+        assert_eq!(client.get("/solenoid/pulse/99").dispatch().status(), Status::NotFound);
+        let invalid = client.post("/solenoid/17/pulse?duration_ms=20").dispatch();
+        assert_eq!(invalid.status(), Status::BadRequest);
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn serial_reconnect_validates_and_requests_switch() {
+        let (client, _endpoints) = client();
+        let post = |body: &str| {
+            client
+                .post("/serial/reconnect")
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+                .status()
+        };
+        assert_eq!(post(r#"{"port":"/dev/ttyUSB1","baud":1234}"#), Status::BadRequest);
+        assert_eq!(post(r#"{"port":" "}"#), Status::BadRequest);
+        let state = client.rocket().state::<AppState>().unwrap();
+        assert!(!state.port_switch.is_requested());
+
+        assert_eq!(post(r#"{"port":"/dev/ttyUSB1","baud":9600}"#), Status::Ok);
+        let target = state.port_switch.take();
+        assert_eq!(target, Some(("/dev/ttyUSB1".to_string(), Some(9600))));
+    }
+
+    #[test]
+    fn repeated_solenoid_command_is_rate_limited() {
+        let (client, endpoints) = client();
+        assert_eq!(client.post("/solenoid/3/1").dispatch().status(), Status::Ok);
+        let response = client.post("/solenoid/3/0").dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.into_string().unwrap(), "RATE_LIMITED");
+        assert_eq!(client.post("/solenoid/4/1").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s31");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s41");
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn set_solenoid_mask_sends_only_changed_channels() {
+        let (client, endpoints) = client();
+        let state = client.rocket().state::<AppState>().unwrap();
+        {
+            let mut tel = state.telemetry.write().unwrap();
+            tel.solenoids[0] = true;
+            tel.solenoids[1] = true;
+        }
+        // Keep 1 on, turn 2 off, turn 16 on.
+        let response = client
+            .post("/solenoid/mask")
+            .header(ContentType::JSON)
+            .body(r#"{"mask":32769}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"sent_commands":2}"#);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s20\ns161");
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn cors_headers_for_allowed_origins_only() {
+        let (client, _endpoints) = client_with(|state| {
+            state.allowed_origins = vec!["http://localhost:3000".into()];
+        });
+        let response = client
+            .options("/solenoid/3/1")
+            .header(Header::new("Origin", "http://localhost:3000"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let headers = response.headers();
+        assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some("http://localhost:3000"));
+        assert!(headers.get_one("Access-Control-Allow-Methods").unwrap().contains("POST"));
+
+        let response = client
+            .get("/telemetry")
+            .header(Header::new("Origin", "http://evil.example"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one("Access-Control-Allow-Origin").is_none());
+    }
+
+    #[test]
+    fn board_routes_reach_the_primary_board_by_id() {
+        let (client, endpoints) = client_with(|state| state.board_id = 2);
+        let response = client.post("/board/2/solenoid/4/1").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s41");
+
+        let response = client.post("/board/3/solenoid/4/1").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.into_string().unwrap(), "UNKNOWN_BOARD: 3");
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let response = client.get("/boards").dispatch();
+        assert_eq!(
+            response.into_string().unwrap(),
+            r#"[{"id":2,"primary":true,"connection":{"state":"Reconnecting","attempt":0}}]"#
+        );
+    }
+
+    #[test]
+    fn signed_posts_only_when_a_secret_is_set() {
+        let (client, endpoints) = client_with(|state| state.auth_secret = Some(b"s3cret".to_vec()));
+        let response = client.post("/arm").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.into_string().unwrap(), "UNAUTHORIZED");
+        let bad = Header::new(auth::SIGNATURE_HEADER, auth::sign(b"wrong", "POST", "/arm", b""));
+        assert_eq!(client.post("/arm").header(bad).dispatch().status(), Status::Unauthorized);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let sig = Header::new(auth::SIGNATURE_HEADER, auth::sign(b"s3cret", "POST", "/arm", b""));
+        assert_eq!(client.post("/arm").header(sig).dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "a");
+
+        let body = r#"[{"channel":3,"state":1}]"#;
+        let sig = auth::sign(b"s3cret", "POST", "/solenoids/batch", body.as_bytes());
+        let response = client
+            .post("/solenoids/batch")
+            .header(ContentType::JSON)
+            .header(Header::new(auth::SIGNATURE_HEADER, sig.clone()))
+            .body(r#"[{"channel":3,"state":0}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .post("/solenoids/batch")
+            .header(ContentType::JSON)
+            .header(Header::new(auth::SIGNATURE_HEADER, sig))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s31");
+
+        let delete = |uri: &'static str| {
+            let sig = auth::sign(b"s3cret", "DELETE", uri, b"");
+            assert_eq!(client.delete(uri).dispatch().status(), Status::Unauthorized);
+            let signed = client.delete(uri).header(Header::new(auth::SIGNATURE_HEADER, sig));
+            assert_eq!(signed.dispatch().status(), Status::Ok);
+        };
+        delete("/commands/pending");
+        delete("/flight_log");
+
+        // Monitoring stays open.
+        assert_eq!(client.get("/telemetry").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn basic_auth_guards_every_route() {
+        let (client, endpoints) = client_with(|state| {
+            let params = argon2::Params { memory_kib: 64, passes: 1, lanes: 1 };
+            state.basic_auth = Some(basic_auth::Credentials {
+                username: "op".to_string(),
+                password_hash: argon2::PasswordHash::generate("pw", params),
+            })
+        });
+        let basic = |credentials: &str| {
+            let encoded = base64::encode(credentials.as_bytes());
+            Header::new("Authorization", format!("Basic {}", encoded))
+        };
+        let response = client.get("/telemetry").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.headers().get_one("WWW-Authenticate"), Some(r#"Basic realm="GCS""#));
+        let response = client.post("/arm").header(basic("op:wrong")).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let response = client.get("/telemetry").header(basic("op:pw")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.post("/arm").header(basic("op:pw")).dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "a");
+    }
+
+    #[test]
+    fn two_step_arming_needs_a_fresh_token() {
+        let (client, endpoints) = client_with(|state| state.require_two_step = true);
+        assert_eq!(client.post("/arm").dispatch().status(), Status::Forbidden);
+        assert_eq!(client.post("/board/0/arm").dispatch().status(), Status::Forbidden);
+        let steps = r#"[{"delay_ms":0,"command":{"type":"disarm"}},
+                        {"delay_ms":100,"command":{"type":"arm"}}]"#;
+        let response = client.post("/sequence").header(ContentType::JSON).body(steps).dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(
+            response.into_string().unwrap(),
+            "SEQUENCE_ARM_REFUSED: step 1 (two-step arming is required)"
+        );
+        let state = client.rocket().state::<AppState>().unwrap();
+        assert_eq!(state.sequence.status(), SequenceStatus::Idle);
+        let confirm = |token: &str| {
+            client
+                .post("/arm/confirm")
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"token":"{}"}}"#, token))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(confirm("none"), Status::Conflict);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let intent = |client: &Client| {
+            let body: rocket::serde::json::Value =
+                client.post("/arm/intent").dispatch().into_json().unwrap();
+            assert_eq!(body["expires_in_ms"], 5000);
+            body["token"].as_str().unwrap().to_string()
+        };
+        let token = intent(&client);
+        assert_eq!(confirm("wrong"), Status::Forbidden);
+        assert_eq!(confirm(&token), Status::Conflict);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let token = intent(&client);
+        assert_eq!(confirm(&token), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "a");
+        assert_eq!(confirm(&token), Status::Conflict);
+    }
+
+    #[test]
+    fn cluster_followers_proxy_writes_and_serve_reads() {
+        let (client, _endpoints) = client();
+        let response = client.get("/cluster/status").dispatch();
+        let status: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(status["role"], "standalone");
+
+        // A follower that has yet to hear from a leader has nowhere to send writes.
+        let (client, endpoints) = client_with(|state| {
+            let peer = SocketAddr::from(([127, 0, 0, 1], 9));
+            let http_addr = SocketAddr::from(([127, 0, 0, 1], 8000));
+            state.cluster = Arc::new(Cluster::new(vec![peer], http_addr, None));
+        });
+        let response = client.get("/cluster/status").dispatch();
+        let status: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(status["role"], "follower");
+        assert_eq!(status["leader_addr"], rocket::serde::json::Value::Null);
+        assert_eq!(client.get("/telemetry").dispatch().status(), Status::Ok);
+        let response = client.post("/arm").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        assert_eq!(response.into_string().unwrap(), "NO_CLUSTER_LEADER");
+        assert!(endpoints.commands.try_recv().is_err());
+        // The proxy endpoint is only reached through the fairing.
+        assert_eq!(client.post("/cluster/proxy").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn emergency_stop_and_disarm_abort_the_countdown() {
+        let (client, _endpoints) = client();
+        let start = |t_minus_s: u64| {
+            let body = format!(r#"{{"t_minus_s":{}}}"#, t_minus_s);
+            client.post("/countdown/start").header(ContentType::JSON).body(body).dispatch()
+        };
+        let countdown = || {
+            let response = client.get("/countdown").dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<rocket::serde::json::Value>().unwrap()["state"].clone()
+        };
+        let response = start(u64::MAX);
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response.into_string().unwrap().starts_with("INVALID_COUNTDOWN"));
+        assert_eq!(countdown(), "idle");
+
+        for stop in ["/emergency_stop", "/disarm"] {
+            assert_eq!(start(600).status(), Status::Ok);
+            assert_eq!(countdown(), "running");
+            assert_eq!(client.post(stop).dispatch().status(), Status::Ok);
+            assert_eq!(countdown(), "idle", "after {}", stop);
+        }
+    }
+
+    #[test]
+    fn arm_endpoints_follow_the_arm_lifecycle() {
+        let (client, endpoints) = client();
+        let post = |uri: &str| {
+            let response = client.post(uri.to_string()).dispatch();
+            (response.status(), response.into_string().unwrap())
+        };
+        let refused =
+            |state: &str| (Status::Conflict, format!("INVALID_ARM_TRANSITION: {}", state));
+        let arm_state = &client.rocket().state::<AppState>().unwrap().arm_state;
+        // A disarm is never refused; it waits for telemetry like any other.
+        assert_eq!(post("/disarm"), (Status::Ok, "OK".to_string()));
+        assert_eq!(post("/arm"), refused("disarming"));
+        state_machine::observe(arm_state, false);
+        assert_eq!(post("/arm"), (Status::Ok, "OK".to_string()));
+        assert_eq!(post("/arm"), refused("armed"));
+        assert_eq!(post("/arm/intent").1, "INVALID_ARM_TRANSITION: armed");
+        assert_eq!(post("/disarm"), (Status::Ok, "OK".to_string()));
+        // Until telemetry shows the board disarmed.
+        assert_eq!(post("/arm"), refused("disarming"));
+        assert_eq!(post("/disarm").0, Status::Ok);
+        let sent: Vec<_> = std::iter::from_fn(|| endpoints.commands.try_recv().ok()).collect();
+        assert_eq!(sent.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), ["d", "a", "d", "d"]);
+
+        state_machine::observe(arm_state, false);
+        assert_eq!(post("/arm").0, Status::Ok);
+        assert_eq!(post("/emergency_stop"), (Status::Ok, "ESTOP_SENT".to_string()));
+        assert_eq!(post("/emergency_stop").0, Status::Ok);
+        // The primary board's ID takes the same path.
+        assert_eq!(post("/board/0/arm"), refused("disarming"));
+        assert_eq!(post("/board/0/disarm").0, Status::Ok);
+    }
+
+    #[test]
+    fn arm_audit_records_who_armed_and_cannot_be_cleared() {
+        let (client, _endpoints) = client();
+        let operator: std::net::SocketAddr = "10.0.0.5:40000".parse().unwrap();
+        assert_eq!(client.post("/arm").remote(operator).dispatch().status(), Status::Ok);
+        assert_eq!(client.post("/board/0/disarm").remote(operator).dispatch().status(), Status::Ok);
+        assert_eq!(client.post("/emergency_stop").dispatch().status(), Status::Ok);
+        // Refused commands are not audited.
+        assert_eq!(client.post("/board/7/arm").dispatch().status(), Status::NotFound);
+        assert_eq!(client.delete("/audit/arm").dispatch().status(), Status::NotFound);
+
+        let events: rocket::serde::json::Value =
+            client.get("/audit/arm").dispatch().into_json().unwrap();
+        let summary: Vec<_> = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["action"].as_str().unwrap(), e["source_ip"].clone(), e["board"].clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("arm", "10.0.0.5".into(), 0.into()),
+                ("disarm", "10.0.0.5".into(), 0.into()),
+                ("emergency_stop", rocket::serde::json::Value::Null, 0.into()),
+            ]
+        );
+        assert!(events[0]["wall_clock"].as_u64().unwrap() > 1_600_000_000_000);
+    }
+
+    #[test]
+    fn groups_actuate_all_their_channels_at_once() {
+        let (client, endpoints) = client_with(|state| {
+            let mut runtime = state.runtime_config.lock().unwrap();
+            runtime.groups =
+                vec![SolenoidGroup { name: "purge".to_string(), channels: vec![3, 9] }];
+            state.telemetry.write().unwrap().solenoids[2] = true;
+            // Valve 9 is open while its solenoid is off.
+            runtime.directions.insert("9".to_string(), config::SolenoidDirection::NormallyOpen);
+        });
+        let groups: rocket::serde::json::Value =
+            client.get("/groups").dispatch().into_json().unwrap();
+        assert_eq!(groups[0]["name"], "purge");
+        assert_eq!(groups[0]["state"], "open");
+
+        assert_eq!(client.post("/group/purge/close").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30\ns91");
+        assert_eq!(client.post("/group/vent/open").dispatch().status(), Status::NotFound);
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn solenoid_timers_list_open_limited_channels() {
+        let (client, _endpoints) = client_with(|state| {
+            let mut limits = [None; 16];
+            limits[6] = Some(Duration::from_secs(30));
+            let mut timers = OpenTimers::new(limits);
+            timers.record("s71\ns81", Instant::now());
+            *state.open_timers.lock().unwrap() = timers;
+        });
+        let timers: rocket::serde::json::Value =
+            client.get("/solenoid/timers").dispatch().into_json().unwrap();
+        let timers = timers.as_array().unwrap();
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0]["channel"], 7);
+        let remaining = timers[0]["remaining_ms"].as_u64().unwrap();
+        assert!(remaining > 29_000 && remaining <= 30_000, "{}", remaining);
+    }
+
+    #[test]
+    fn posts_beyond_the_request_limit_are_429() {
+        let (client, _endpoints) = client_with(|state| {
+            state.request_limiter = Mutex::new(RequestLimiter::new(2, 0.001));
+        });
+        let remote = "10.0.0.7:40000".parse().unwrap();
+        let post = || client.post("/arm/intent").remote(remote).dispatch();
+        assert_eq!(post().status(), Status::Ok);
+        assert_eq!(post().status(), Status::Ok);
+        let response = post();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.into_string().unwrap(), "TOO_MANY_REQUESTS");
+        assert_eq!(client.get("/status").remote(remote).dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn telemetry_inject_needs_allow_inject() {
+        let tel = Telemetry { timestamp: 77, ..Telemetry::default() };
+        let body = rocket::serde::json::to_string(&tel).unwrap();
+        let (client, _endpoints) = client();
+        let response = client.post("/telemetry/inject").header(ContentType::JSON).body(&body);
+        assert_eq!(response.dispatch().status(), Status::NotFound);
+
+        let (client, _endpoints) = client_with(|state| state.allow_inject = true);
+        let response = client.post("/telemetry/inject").header(ContentType::JSON).body(&body);
+        assert_eq!(response.dispatch().status(), Status::Ok);
+        let current: Telemetry = client.get("/telemetry").dispatch().into_json().unwrap();
+        assert_eq!(current.timestamp, 77);
+
+        for bad in [
+            Telemetry { timestamp: 78, solenoids: vec![true; 2], ..Telemetry::default() },
+            Telemetry { timestamp: 78, pyro_continuity: Vec::new(), ..Telemetry::default() },
+            Telemetry { timestamp: 78, battery: 31.0, ..Telemetry::default() },
+        ] {
+            let body = rocket::serde::json::to_string(&bad).unwrap();
+            let response = client.post("/telemetry/inject").header(ContentType::JSON).body(&body);
+            assert_eq!(response.dispatch().into_string().unwrap(), "INVALID_TELEMETRY");
+        }
+        let current: Telemetry = client.get("/telemetry").dispatch().into_json().unwrap();
+        assert_eq!(current.timestamp, 77);
+    }
+
+    #[test]
+    fn telemetry_is_sent_as_msgpack_when_preferred() {
+        let (client, _endpoints) = client_with(|state| {
+            *state.telemetry.write().unwrap() = Telemetry {
+                timestamp: 123_456,
+                armed: true,
+                battery: 12.6,
+                battery_raw: 12.55,
+                arming: 12.4,
+                solenoids: vec![false; 16],
+                pyro_continuity: vec![true; 4],
+                ..Telemetry::default()
+            };
+        });
+
+        let response = client.get("/telemetry").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let json = response.into_bytes().unwrap();
+        let response = client.get("/telemetry").header(Accept::JSON).dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert_eq!(response.into_bytes().unwrap(), json);
+
+        let response = client.get("/telemetry").header(Accept::MsgPack).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::MsgPack));
+        let packed = response.into_bytes().unwrap();
+        // A map of 7 fields, keys sorted: "armed": true first, "timestamp": 123456 last.
+        assert_eq!(packed[..8], *b"\x87\xa5armed\xc3");
+        assert!(packed.ends_with(b"\xa9timestamp\xce\x00\x01\xe2\x40"));
+        assert!(packed.len() * 10 <= json.len() * 7, "{} vs {} bytes", packed.len(), json.len());
+    }
+
+    #[test]
+    fn export_downloads_the_history_as_a_json_file() {
+        let (client, _endpoints) = client_with(|state| {
+            let mut history = state.history.lock().unwrap();
+            for timestamp in 1..=3 {
+                history.push(Telemetry { timestamp, ..Telemetry::default() });
+            }
+        });
+        let response = client.get("/export/json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let disposition = response.headers().get_one("Content-Disposition").unwrap();
+        assert!(disposition.starts_with("attachment; filename=\"telemetry_"), "{}", disposition);
+        assert!(disposition.ends_with(".json\""), "{}", disposition);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("\n  {"), "pretty-printed");
+        let samples: Vec<Telemetry> = rocket::serde::json::from_str(&body).unwrap();
+        let timestamps: Vec<u64> = samples.iter().map(|tel| tel.timestamp).collect();
+        assert_eq!(timestamps, [1, 2, 3]);
+    }
+
+    #[test]
+    fn csv_export_has_a_row_per_sample() {
+        let (client, _endpoints) = client_with(|state| {
+            let mut history = state.history.lock().unwrap();
+            let mut solenoids = vec![false; 16];
+            solenoids[2] = true;
+            history.push(Telemetry { timestamp: 1, battery: 12.5, ..Telemetry::default() });
+            let armed = true;
+            history.push(Telemetry { timestamp: 2, armed, solenoids, ..Telemetry::default() });
+        });
+        let response = client.get("/telemetry/csv").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSV));
+        let disposition = response.headers().get_one("Content-Disposition").unwrap();
+        assert!(disposition.starts_with("attachment; filename=\"telemetry_"), "{}", disposition);
+        let body = response.into_string().unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("timestamp,armed,battery,arming,sol1,sol2,"), "{}", lines[0]);
+        assert!(lines[0].ends_with(",sol16"), "{}", lines[0]);
+        assert_eq!(lines[1], format!("1,0,12.5,0{}", ",0".repeat(16)));
+        assert_eq!(lines[2], format!("2,1,0,0,0,0,1{}", ",0".repeat(13)));
+    }
+
+    #[test]
+    fn script_runs_until_a_check_fails() {
+        let (client, endpoints) = client();
+        let run = |script: &str| {
+            let body = rocket::serde::json::json!({ "script": script }).to_string();
+            client.post("/script/run").header(ContentType::JSON).body(body).dispatch()
+        };
+        let response = run("OPEN_SOL 3\nWAIT x");
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(
+            response.into_string().unwrap(),
+            "INVALID_SCRIPT line 2: invalid duration 'x' (expected milliseconds)"
+        );
+
+        let script = "OPEN_SOL 3\nWAIT 200\nCLOSE_SOL 3\nARMED_CHECK\nDISARM";
+        assert_eq!(run(script).status(), Status::Ok);
+        assert_eq!(run("ARM").status(), Status::Conflict);
+        let status = || -> rocket::serde::json::Value {
+            client.get("/script/status").dispatch().into_json().unwrap()
+        };
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while status()["state"] == "running" && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let status = status();
+        assert_eq!(status["state"], "failed");
+        assert_eq!(status["line"], 4);
+        assert_eq!(status["reason"], "not armed");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s31");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30");
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn scripts_arm_through_the_lifecycle_and_stop_on_emergency_stop() {
+        let (client, endpoints) = client();
+        let run = |script: &str| {
+            let body = rocket::serde::json::json!({ "script": script }).to_string();
+            client.post("/script/run").header(ContentType::JSON).body(body).dispatch()
+        };
+        let state_of = || -> rocket::serde::json::Value {
+            client.get("/script/status").dispatch().into_json().unwrap()
+        };
+        let wait_until_done = || {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while state_of()["state"] == "running" && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        assert_eq!(run("ARM\nWAIT 60000\nOPEN_SOL 3").status(), Status::Ok);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(state_of()["line"], 2);
+        assert_eq!(client.post("/emergency_stop").dispatch().status(), Status::Ok);
+        wait_until_done();
+        assert_eq!(state_of(), rocket::serde::json::json!({"state": "aborted", "line": 2}));
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "a");
+        assert!(endpoints.commands.try_recv().is_err());
+        let audit: rocket::serde::json::Value =
+            client.get("/audit/arm").dispatch().into_json().unwrap();
+        assert_eq!(audit[0]["action"], "arm");
+        assert_eq!(client.post("/script/abort").dispatch().into_string().unwrap(), "NOT_RUNNING");
+
+        // Disarming, so the lifecycle refuses the arm.
+        assert_eq!(run("ARM").status(), Status::Ok);
+        wait_until_done();
+        assert_eq!(state_of()["reason"], "INVALID_ARM_TRANSITION: disarming");
+
+        let (client, _endpoints) = client_with(|state| state.require_two_step = true);
+        let body = rocket::serde::json::json!({ "script": "WAIT 1\nARM" }).to_string();
+        let response = client.post("/script/run").header(ContentType::JSON).body(body).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn firmware_command_needs_dev_mode() {
+        let post = |client: &Client, payload: &str| {
+            let body = rocket::serde::json::json!({ "payload": payload }).to_string();
+            let request = client.post("/firmware/command").header(ContentType::JSON).body(body);
+            request.dispatch().status()
+        };
+        let (client, endpoints) = client();
+        assert_eq!(post(&client, "s31"), Status::NotFound);
+        assert!(endpoints.commands.try_recv().is_err());
+
+        let (client, endpoints) = client_with(|state| state.dev_mode = true);
+        assert_eq!(post(&client, &"x".repeat(65)), Status::BadRequest);
+        assert_eq!(post(&client, "s31\ns50"), Status::BadRequest);
+        assert_eq!(post(&client, "a\0"), Status::BadRequest);
+        assert!(endpoints.commands.try_recv().is_err());
+        assert_eq!(post(&client, "X42 debug"), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "X42 debug");
+    }
+
+    #[test]
+    fn firmware_reset_clears_telemetry_and_queue() {
+        let (client, _endpoints) = client();
+        assert_eq!(client.post("/firmware/reset").dispatch().status(), Status::NotFound);
+
+        let (client, endpoints) = client_with(|state| state.allow_firmware_reset = true);
+        let status = |client: &Client| {
+            let response = client.get("/status").dispatch();
+            response.into_json::<rocket::serde::json::Value>().unwrap()
+        };
+        assert_eq!(status(&client)["last_firmware_reset"], rocket::serde::json::Value::Null);
+        assert_eq!(client.post("/arm").dispatch().status(), Status::Ok);
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().timestamp = 1234;
+
+        let response = client.post("/firmware/reset").dispatch();
+        assert_eq!(response.into_string().unwrap(), "RESETTING");
+        assert!(endpoints.commands.try_recv().is_err(), "queued arm discarded");
+        assert!(endpoints.firmware_reset.take());
+        assert_eq!(state.telemetry.read().unwrap().timestamp, 0);
+        assert!(status(&client)["last_firmware_reset"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn health_reports_queue_depth_and_build() {
+        let (client, _endpoints) = client_with(|_| {});
+        assert_eq!(client.post("/arm").dispatch().status(), Status::Ok);
+        let response = client.get("/health").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let health: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(health["serial_connected"], false);
+        assert_eq!(health["telemetry_parse_rate_hz"], 0.0);
+        assert_eq!(health["command_queue_depth"], 1);
+        assert_eq!(health["build_info"]["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn snapshots_capture_telemetry_with_the_flight_log() {
+        let (client, _endpoints) = client_with(|state| {
+            state.telemetry.write().unwrap().timestamp = 4200;
+            state.flight_log.lock().unwrap().record_command(4100, "a\ns31\n");
+        });
+        let response = client.post("/snapshot").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let first: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(first["id"], 1);
+        assert_eq!(first["telemetry"]["timestamp"], 4200);
+        assert_eq!(first["event_count"], 2);
+        assert_eq!(first["last_command"]["event_type"]["channel"], 3);
+        assert!(first["captured_at"].as_u64().unwrap() > 0);
+
+        let second: rocket::serde::json::Value =
+            client.post("/snapshot").dispatch().into_json().unwrap();
+        assert_eq!(second["id"], 2);
+        let fetched: rocket::serde::json::Value =
+            client.get("/snapshot/1").dispatch().into_json().unwrap();
+        assert_eq!(fetched, first);
+        assert_eq!(client.get("/snapshot/3").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn alerts_fire_once_per_crossing() {
+        let rules = vec![AlertRule {
+            field: AlertField::Battery,
+            op: AlertOp::Lt,
+            threshold: 11.0,
+            webhook: "http://127.0.0.1:9/alerts".to_string(),
+        }];
+        let (client, _endpoints) = client_with(|state| {
+            alerts::spawn(rules, state.telemetry_tx.subscribe(), state.active_alerts.clone())
+                .unwrap();
+            for (timestamp, battery) in [(1, 12.0), (2, 10.8), (3, 10.5)] {
+                let tel = Telemetry { timestamp, battery, ..Telemetry::default() };
+                state.telemetry_tx.send(tel).unwrap();
+            }
+        });
+        let active = || -> rocket::serde::json::Value {
+            for _ in 0..200 {
+                let active: rocket::serde::json::Value =
+                    client.get("/alerts/active").dispatch().into_json().unwrap();
+                if active.as_array().unwrap().first().is_some_and(|a| a["since"] == 2) {
+                    return active;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("the alert never fired");
+        };
+        let active = active();
+        assert_eq!(active.as_array().unwrap().len(), 1);
+        assert_eq!(active[0]["field"], "battery");
+        assert_eq!(active[0]["op"], "lt");
+        assert_eq!(active[0]["rule"], 0);
+    }
+
+    #[test]
+    fn continuity_test_confirms_both_switches() {
+        let mut telemetry = None;
+        let (client, endpoints) = client_with(|state| telemetry = Some(state.telemetry.clone()));
+        let telemetry = telemetry.unwrap();
+        assert_eq!(client.post("/test/solenoid/4").dispatch().status(), Status::Forbidden);
+
+        telemetry.write().unwrap().armed = true;
+        let shared = telemetry.clone();
+        // Plays a board that applies each command as it arrives.
+        let board = std::thread::spawn(move || {
+            for expected in ["s41", "s40"] {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                let cmd = loop {
+                    if let Ok(cmd) = endpoints.commands.try_recv() {
+                        break cmd;
+                    }
+                    assert!(Instant::now() < deadline, "no {}", expected);
+                    std::thread::sleep(Duration::from_millis(1));
+                };
+                assert_eq!(cmd.text, expected);
+                telemetry.write().unwrap().solenoids[3] = expected == "s41";
+            }
+        });
+        let result: rocket::serde::json::Value =
+            client.post("/test/solenoid/4").dispatch().into_json().unwrap();
+        board.join().unwrap();
+        assert_eq!(result["channel"], 4);
+        assert_eq!(result["open_confirmed"], true);
+        assert_eq!(result["close_confirmed"], true);
+        assert!(result["latency_ms"].as_u64().unwrap() < 250);
+        assert_eq!(client.post("/test/solenoid/17").dispatch().status(), Status::BadRequest);
+
+        // Already on, so switching it on cannot be confirmed.
+        shared.write().unwrap().solenoids[3] = true;
+        let response = client.post("/test/solenoid/4").dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(response.into_string().unwrap(), "SOLENOID_ALREADY_ON: 4");
+    }
+
+    #[test]
+    fn session_commands_are_logged_per_session() {
+        let (client, endpoints) = client_with(|_| ());
+        let created: rocket::serde::json::Value =
+            client.post("/session/create").dispatch().into_json().unwrap();
+        let token = created["token"].as_str().unwrap().to_string();
+
+        let tagged = Header::new(session::SESSION_HEADER, token.clone());
+        let response = client.post("/solenoid/3/1").header(tagged).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        client.post("/arm").dispatch();
+        let cmd = endpoints.commands.try_recv().unwrap();
+        assert_eq!(cmd.session, Some(token.parse().unwrap()));
+        // What the serial loop does once the tagged command is written.
+        let now = Instant::now();
+        endpoints.sessions.lock().unwrap().record_command(cmd.session.unwrap(), now, 0, &cmd.text);
+        assert_eq!(endpoints.commands.try_recv().unwrap().session, None);
+
+        let log: rocket::serde::json::Value =
+            client.get(format!("/session/{}/log", token)).dispatch().into_json().unwrap();
+        assert_eq!(log.as_array().unwrap().len(), 1);
+        assert_eq!(log[0]["event_type"]["channel"], 3);
+        let unknown = client.get("/session/00000000-0000-4000-8000-000000000000/log").dispatch();
+        assert_eq!(unknown.status(), Status::NotFound);
+        assert_eq!(client.get("/session/nope/log").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn fast_battery_drain_is_flagged() {
+        let (client, _endpoints) = client_with(|state| {
+            safety::spawn_battery_rate_monitor(
+                1.0,
+                state.telemetry_tx.subscribe(),
+                state.battery_draining.clone(),
+            );
+            for (timestamp, battery) in [(100, 12.6), (200, 12.3)] {
+                let tel = Telemetry { timestamp, battery, ..Telemetry::default() };
+                state.telemetry_tx.send(tel).unwrap();
+            }
+        });
+        for _ in 0..200 {
+            let drain: rocket::serde::json::Value =
+                client.get("/alerts/battery_drain").dispatch().into_json().unwrap();
+            if drain["draining"] == true {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("the drain was never flagged");
+    }
+
+    #[test]
+    fn commands_matching_the_reported_state_are_not_sent() {
+        let (client, endpoints) = client_with(|state| {
+            *state.connection_status.lock().unwrap() = ConnectionStatus::Connected;
+            let mut tel = state.telemetry.write().unwrap();
+            tel.armed = true;
+            tel.solenoids[2] = true;
+        });
+        let post = |uri: &str| client.post(uri.to_string()).dispatch().into_string().unwrap();
+        assert_eq!(post("/solenoid/3/1"), "NO_CHANGE");
+        assert_eq!(post("/arm"), "NO_CHANGE");
+        assert!(endpoints.commands.try_recv().is_err());
+
+        assert_eq!(post("/solenoid/3/0"), "OK");
+        // With s30 still queued, the reported state may be about to change.
+        assert_eq!(post("/solenoid/4/0"), "OK");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s40");
+        assert_eq!(post("/disarm"), "OK");
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "d");
+
+        let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+        assert!(metrics.contains("gcs_dedup_skips_total 2\n"), "{}", metrics);
+    }
+
+    #[test]
+    fn shutdown_saves_the_state_file() {
+        let path = env::temp_dir().join(format!("gcs_shutdown_state_{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (client, _endpoints) = client_with(|state| {
+            state.telemetry.write().unwrap().solenoids[4] = true;
+            state.state_file = Some(StateFile::new(path.clone(), state.telemetry.clone()));
+        });
+        client.terminate();
+        let saved = StateFile::new(path.clone(), SharedTelemetry::default()).load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(saved.unwrap().solenoids[4]);
+    }
+
+    #[test]
+    fn runtime_config_changes_apply_immediately() {
+        use rocket::http::Method;
+        use rocket::serde::json::json;
+
+        let path = env::temp_dir().join(format!("gcs_runtime_api_{}.toml", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (client, endpoints) = client_with(|state| {
+            state.runtime_config.lock().unwrap().path = path.clone();
+            state.telemetry.write().unwrap().solenoids[2] = true;
+        });
+        let json = |response: rocket::local::blocking::LocalResponse| {
+            response.into_json::<rocket::serde::json::Value>().unwrap()
+        };
+        let send = |method: Method, uri: &str, body: &str| {
+            client.req(method, uri.to_string()).body(body).dispatch()
+        };
+
+        let labels = json(send(Method::Post, "/config/labels", r#"{"7":"LOX Main Valve"}"#));
+        assert_eq!(labels, json!({"7": "LOX Main Valve"}));
+        let info = json(client.get("/solenoid/labels").dispatch());
+        assert_eq!(info["7"]["label"], "LOX Main Valve");
+        let bad = send(Method::Put, "/config/labels", r#"{"17":"Nothing"}"#);
+        assert_eq!(bad.status(), Status::BadRequest);
+        assert_eq!(json(send(Method::Delete, "/config/labels/7", "")), json!({}));
+
+        let group = r#"{"name":"purge","channels":[3,9]}"#;
+        assert_eq!(json(send(Method::Post, "/config/groups", group))[0]["name"], "purge");
+        assert_eq!(send(Method::Post, "/config/groups", group).status(), Status::Conflict);
+        assert_eq!(client.post("/group/purge/close").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s30\ns90");
+
+        let interlock = r#"{"prevent":[3,7]}"#;
+        let interlocks = json(send(Method::Post, "/config/interlocks", interlock));
+        assert_eq!(interlocks, json!([{"prevent": [3, 7]}]));
+        assert_eq!(client.post("/solenoid/7/1").dispatch().status(), Status::Conflict);
+        let bad = send(Method::Post, "/config/interlocks", r#"{"prevent":[3,3]}"#);
+        assert_eq!(bad.status(), Status::BadRequest);
+
+        assert_eq!(client.post("/config/save").dispatch().into_string().unwrap(), "SAVED");
+        let saved = Config::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.groups[0].channels, [3, 9]);
+        assert_eq!(saved.interlocks[0].prevent, [3, 7]);
+
+        assert_eq!(json(send(Method::Delete, "/config/interlocks/0", "")), json!([]));
+        assert_eq!(send(Method::Delete, "/config/interlocks/0", "").status(), Status::NotFound);
+        assert_eq!(client.post("/solenoid/7/1").dispatch().status(), Status::Ok);
+        assert_eq!(json(send(Method::Delete, "/config/groups/purge", "")), json!([]));
+    }
+
+    #[test]
+    fn version_reports_the_build() {
+        let (client, _endpoints) = client_with(|_| {});
+        let response = client.get("/version").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let version: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(version["git_sha"], env!("GIT_SHA"));
+        let timestamp = version["build_timestamp"].as_str().unwrap();
+        assert_eq!(timestamp.len(), "YYYY-MM-DDTHH:MM:SSZ".len());
+        assert!(timestamp.starts_with(env!("BUILD_DATE")) && timestamp.ends_with('Z'));
+    }
+
+    #[test]
+    fn diagram_shows_the_reported_valve_states() {
+        let (client, _endpoints) = client_with(|state| {
+            state.telemetry.write().unwrap().solenoids[4] = true;
+        });
+        let response = client.get("/diagram").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::SVG));
+        let svg = response.into_string().unwrap();
+        assert!(svg.contains("<g id=\"valve-5\" class=\"open\">"));
+        assert_eq!(svg.matches("class=\"closed\"").count(), 15);
+    }
+
+    #[test]
+    fn solenoid_labels_default_to_channel_numbers() {
+        let labels = HashMap::from([("7".to_string(), "LOX Main Valve".to_string())]);
+        let directions =
+            HashMap::from([("8".to_string(), config::SolenoidDirection::NormallyOpen)]);
+        let (client, _endpoints) = client_with(|state| {
+            let mut runtime = state.runtime_config.lock().unwrap();
+            (runtime.labels, runtime.directions) = (labels, directions);
+        });
+        let response = client.get("/solenoid/labels").dispatch();
+        let info: rocket::serde::json::Value = response.into_json().unwrap();
+        assert_eq!(info.as_object().unwrap().len(), 16);
+        assert_eq!(info["7"]["label"], "LOX Main Valve");
+        assert_eq!(info["7"]["direction"], "normally_closed");
+        assert_eq!(info["8"]["label"], "Solenoid 8");
+        assert_eq!(info["8"]["direction"], "normally_open");
+    }
+
+    #[rocket::async_test]
+    async fn events_stream_pushes_telemetry() {
+        use rocket::local::asynchronous::Client;
+        use rocket::tokio::io::AsyncReadExt;
+
+        let (state, _endpoints, ack_rx) = AppState::new(10, None);
+        let telemetry_tx = state.telemetry_tx.clone();
+        let client = Client::tracked(build_rocket(state, ack_rx)).await.unwrap();
+        let mut response = client.get("/events").dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+
+        let tel = Telemetry { timestamp: 1500, ..Telemetry::default() };
+        telemetry_tx.send(tel).unwrap();
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        while !received.ends_with("\n\n") {
+            let n = response.read(&mut buf).await.unwrap();
+            assert!(n > 0, "stream ended early");
+            received.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+        }
+        assert!(received.starts_with("event:telemetry\n"), "{}", received);
+        assert!(received.contains(r#""timestamp":1500"#), "{}", received);
+    }
+
+    #[rocket::async_test]
+    async fn telemetry_stream_sends_throttled_json_lines() {
+        use rocket::local::asynchronous::Client;
+        use rocket::tokio::io::AsyncReadExt;
+        use rocket::tokio::time::timeout;
+
+        let (state, _endpoints, ack_rx) = AppState::new(10, None);
+        let telemetry_tx = state.telemetry_tx.clone();
+        let client = Client::tracked(build_rocket(state, ack_rx)).await.unwrap();
+        let mut response = client.get("/telemetry/stream?interval_ms=60000").dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+
+        for timestamp in [1500, 1600] {
+            telemetry_tx.send(Telemetry { timestamp, ..Telemetry::default() }).unwrap();
+        }
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        while !received.ends_with('\n') {
+            let n = response.read(&mut buf).await.unwrap();
+            assert!(n > 0, "stream ended early");
+            received.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+        }
+        let line: Telemetry = rocket::serde::json::from_str(&received).unwrap();
+        assert_eq!(line.timestamp, 1500);
+        // 1600 came within the interval.
+        let more = timeout(Duration::from_millis(100), response.read(&mut buf)).await;
+        assert!(more.is_err(), "unexpected data: {:?}", more);
+    }
+
+    #[test]
+    fn implausible_telemetry_is_counted_and_dropped() {
+        let good = Telemetry { timestamp: 1500, battery: 12.4, ..Telemetry::default() };
+        assert!(good.is_valid());
+        assert!(!Telemetry { battery: 31.0, ..good.clone() }.is_valid());
+        assert!(!Telemetry { arming: -0.5, ..good.clone() }.is_valid());
+        assert!(!Telemetry { battery: f32::NAN, ..good.clone() }.is_valid());
+        assert!(!Telemetry { timestamp: 0, ..good.clone() }.is_valid());
+        assert!(!Telemetry { solenoids: vec![false; 15], ..good.clone() }.is_valid());
+
+        let (state, _endpoints, _acks) = AppState::new(8, None);
+        let sinks = TelemetrySinks {
+            telemetry: state.telemetry.clone(),
+            history: state.history.clone(),
+            broadcast: state.telemetry_tx.clone(),
+            csv_log: None,
+            db: None,
+            udp_relay: None,
+            parse_rate: state.parse_rate.clone(),
+        };
+        let mut filters = TelemetryFilters::new(1);
+        let bad = Telemetry { battery: 99.0, ..good.clone() };
+        accept_telemetry(bad, &sinks, &state.metrics, &mut filters);
+        assert_eq!(state.metrics.telemetry_invalid.load(Ordering::Relaxed), 1);
+        assert_eq!(state.telemetry.read().unwrap().timestamp, 0);
+        accept_telemetry(good, &sinks, &state.metrics, &mut filters);
+        assert_eq!(state.telemetry.read().unwrap().timestamp, 1500);
+    }
+
+    #[test]
+    fn shutdown_drains_at_most_the_limit_then_disarms() {
+        let (state, endpoints, _acks) = AppState::new(8, None);
+        let sinks = TelemetrySinks {
+            telemetry: state.telemetry.clone(),
+            history: state.history.clone(),
+            broadcast: state.telemetry_tx.clone(),
+            csv_log: None,
+            db: None,
+            udp_relay: None,
+            parse_rate: state.parse_rate.clone(),
+        };
+        for channel in 1..=4 {
+            state.command_tx.send(QueuedCommand::immediate(format!("s{}1", channel))).unwrap();
+        }
+        let config = Config::default();
+        let settings = SerialSettings {
+            shutdown_drain: 3,
+            ..SerialSettings::new(&config.serial, &config.filters)
+        };
+        let mut port = Vec::new();
+        shut_down(&mut port, &sinks, &endpoints, &settings);
+        assert_eq!(String::from_utf8(port).unwrap(), "s11\ns21\ns31\nd\n");
+        assert!(endpoints.commands.try_recv().is_err(), "the rest is dropped");
+        let events = state.flight_log.lock().unwrap().events().to_vec();
+        assert!(matches!(events.last().unwrap().event_type, EventType::Disarm));
+    }
+}
@@ -0,0 +1,377 @@
+// src/logging.rs
+
+//! The server's own log: `tracing` events (serial link, commands, parse failures, the
+//! watchdog, ...) written to stderr, one line each, by the subscriber below.
+//!
+//! `--log-format pretty` (the default, also `[logging] format`) prints human-readable lines;
+//! `json` prints one JSON object per line, ready for Loki, Datadog and the like:
+//!
+//! ```text
+//! 12:00:01.250  WARN serial{port=/dev/ttyACM0}: telemetry_server: Serial port lost, reconnecting
+//! {"fields":{"message":"Serial port lost, reconnecting"},"level":"WARN","spans":[{"name":"serial","port":"/dev/ttyACM0"}],"target":"telemetry_server","timestamp_ms":1700000001250}
+//! ```
+//!
+//! Rocket's request log is separate and keeps its own format.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::serde::json::serde_json::Map;
+use rocket::serde::json::{self, Value};
+use rocket::serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// How log lines are written (`--log-format`, `[logging] format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `HH:MM:SS.mmm LEVEL spans: target: message key=value ...` (UTC).
+    #[default]
+    Pretty,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Installs the logger for the whole process. Events before this (argument and config
+/// errors) are printed directly.
+pub fn init(format: LogFormat) {
+    let logger = Logger::new(format, Level::INFO, io::stderr());
+    // Only fails if a logger is already installed, which is fine.
+    let _ = tracing::subscriber::set_global_default(logger);
+}
+
+/// The fields of an event or span, in the order they were recorded.
+type Fields = Vec<(&'static str, Value)>;
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl FieldVisitor<'_> {
+    fn set(&mut self, field: &Field, value: Value) {
+        match self.0.iter_mut().find(|(name, _)| *name == field.name()) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((field.name(), value)),
+        }
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // f32 fields (the voltages) arrive widened; print them as short as the f32 is.
+        let narrow = value as f32;
+        let value = match narrow.to_string().parse::<f64>() {
+            Ok(short) if narrow as f64 == value => short,
+            _ => value,
+        };
+        self.set(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    fields: Fields,
+    /// Handles to the span that are still open; it is dropped at zero.
+    refs: usize,
+}
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A `tracing` subscriber writing every event at or above `max_level` to `out`.
+pub struct Logger<W> {
+    format: LogFormat,
+    max_level: Level,
+    out: Mutex<W>,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+impl<W: Write> Logger<W> {
+    pub fn new(format: LogFormat, max_level: Level, out: W) -> Self {
+        Logger {
+            format,
+            max_level,
+            out: Mutex::new(out),
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Formats one event; `spans` are the entered spans, outermost first.
+    fn format_line(
+        &self,
+        now_ms: u64,
+        metadata: &Metadata<'_>,
+        fields: &Fields,
+        spans: &[(&'static str, Fields)],
+    ) -> String {
+        match self.format {
+            LogFormat::Pretty => {
+                let secs = now_ms / 1000;
+                let mut line = format!(
+                    "{:02}:{:02}:{:02}.{:03} {:>5} ",
+                    secs / 3600 % 24,
+                    secs / 60 % 60,
+                    secs % 60,
+                    now_ms % 1000,
+                    metadata.level()
+                );
+                for (name, span_fields) in spans {
+                    line.push_str(name);
+                    if !span_fields.is_empty() {
+                        line.push('{');
+                        line.push_str(&pretty_fields(span_fields));
+                        line.push('}');
+                    }
+                    line.push_str(": ");
+                }
+                line.push_str(metadata.target());
+                line.push(':');
+                if let Some((_, message)) = fields.iter().find(|(name, _)| *name == "message") {
+                    line.push(' ');
+                    line.push_str(&pretty_value(message));
+                }
+                let others: Fields = fields
+                    .iter()
+                    .filter(|(name, _)| *name != "message")
+                    .cloned()
+                    .collect();
+                if !others.is_empty() {
+                    line.push(' ');
+                    line.push_str(&pretty_fields(&others));
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp_ms".into(), Value::from(now_ms));
+                object.insert("level".into(), Value::from(metadata.level().as_str()));
+                object.insert("target".into(), Value::from(metadata.target()));
+                object.insert("fields".into(), json_object(fields));
+                if !spans.is_empty() {
+                    let spans = spans.iter().map(|(name, span_fields)| {
+                        let mut span = Map::new();
+                        span.insert("name".into(), Value::from(*name));
+                        if let Value::Object(span_fields) = json_object(span_fields) {
+                            span.extend(span_fields);
+                        }
+                        Value::Object(span)
+                    });
+                    object.insert("spans".into(), Value::Array(spans.collect()));
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+}
+
+fn json_object(fields: &Fields) -> Value {
+    let object = fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()));
+    Value::Object(object.collect())
+}
+
+fn pretty_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `key=value` pairs. Strings are printed without quotes unless they contain spaces.
+fn pretty_fields(fields: &Fields) -> String {
+    let pairs: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| match value {
+            Value::String(s) if s.contains(char::is_whitespace) => {
+                format!("{}={}", name, json::to_string(s).unwrap_or_default())
+            }
+            _ => format!("{}={}", name, pretty_value(value)),
+        })
+        .collect();
+    pairs.join(" ")
+}
+
+impl<W: Write + Send + 'static> Subscriber for Logger<W> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.max_level
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(self.max_level))
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Fields::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        let data = SpanData {
+            name: span.metadata().name(),
+            fields,
+            refs: 1,
+        };
+        self.spans.lock().unwrap().insert(id, data);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut FieldVisitor(&mut data.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        let spans: Vec<(&'static str, Fields)> = {
+            let spans = self.spans.lock().unwrap();
+            ENTERED.with(|entered| {
+                entered
+                    .borrow()
+                    .iter()
+                    .filter_map(|id| spans.get(id))
+                    .map(|data| (data.name, data.fields.clone()))
+                    .collect()
+            })
+        };
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let line = self.format_line(now_ms, event.metadata(), &fields, &spans);
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line);
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(pos) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(pos);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let id = span.into_u64();
+        let closed = match spans.get_mut(&id) {
+            Some(data) => {
+                data.refs -= 1;
+                data.refs == 0
+            }
+            None => false,
+        };
+        if closed {
+            spans.remove(&id);
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A `Write` the test can read back.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_lines(format: LogFormat) -> Vec<String> {
+        let buffer = Buffer::default();
+        let logger = Logger::new(format, Level::INFO, buffer.clone());
+        tracing::subscriber::with_default(logger, || {
+            let _span = tracing::info_span!("serial", port = "/dev/ttyACM0").entered();
+            tracing::warn!(
+                attempt = 3,
+                volts = 12.6f32,
+                error = "no such device",
+                "Serial port lost"
+            );
+            tracing::debug!("filtered out");
+        });
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn writes_pretty_lines() {
+        let lines = log_lines(LogFormat::Pretty);
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].ends_with(
+                "  WARN serial{port=/dev/ttyACM0}: telemetry_server::logging::tests: \
+                 Serial port lost attempt=3 volts=12.6 error=\"no such device\""
+            ),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let lines = log_lines(LogFormat::Json);
+        assert_eq!(lines.len(), 1);
+        let line: Value = json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["fields"]["message"], "Serial port lost");
+        assert_eq!(line["fields"]["attempt"], 3);
+        assert_eq!(line["fields"]["volts"], 12.6);
+        assert_eq!(line["spans"][0]["name"], "serial");
+        assert_eq!(line["spans"][0]["port"], "/dev/ttyACM0");
+        assert!(line["timestamp_ms"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,106 @@
+// src/metrics.rs
+
+//! Counters and the Prometheus text exposition served at GET /metrics.
+//! The format is simple enough to write by hand.
+
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::telemetry::ParseError;
+use crate::Telemetry;
+
+/// Server-wide counters. Plain atomics so the serial loop can bump them and
+/// scrapes can read them without taking any lock.
+#[derive(Default)]
+pub struct Metrics {
+    /// Non-empty serial lines that did not parse as telemetry.
+    pub telemetry_parse_errors: AtomicU64,
+    /// The same lines by why they did not parse, indexed like `ParseError::KINDS`.
+    pub parse_errors_by_kind: [AtomicU64; ParseError::KINDS.len()],
+    /// Parsed samples dropped by `Telemetry::is_valid`.
+    pub telemetry_invalid: AtomicU64,
+    /// Serial reads that failed with an error other than a timeout.
+    pub serial_read_errors: AtomicU64,
+    /// Attempts to (re-)open the serial port after it was lost or failed to open.
+    pub serial_reconnect_attempts: AtomicU64,
+    /// "ACK:<cmd>" lines that did not match a pending command.
+    pub unmatched_acks: AtomicU64,
+    /// Commands not sent because telemetry already showed the requested state.
+    pub dedup_skips: AtomicU64,
+}
+
+/// Renders the current telemetry and counters in the Prometheus text format.
+pub fn render(tel: &Telemetry, metrics: &Metrics) -> String {
+    let mut out = String::new();
+    gauge(&mut out, "gcs_battery_volts", "Battery voltage.", tel.battery);
+    gauge(
+        &mut out,
+        "gcs_arming_sense_volts",
+        "Arming sense voltage.",
+        tel.arming,
+    );
+    gauge(
+        &mut out,
+        "gcs_armed_state",
+        "1 if the system reports armed.",
+        tel.armed as u8,
+    );
+
+    header(&mut out, "gcs_solenoid_state", "gauge", "1 if the solenoid is on.");
+    for (i, &on) in tel.solenoids.iter().enumerate() {
+        let _ = writeln!(out, "gcs_solenoid_state{{channel=\"{}\"}} {}", i + 1, on as u8);
+    }
+
+    counter(
+        &mut out,
+        "gcs_telemetry_parse_errors_total",
+        "Serial lines that failed to parse as telemetry.",
+        &metrics.telemetry_parse_errors,
+    );
+    counter(
+        &mut out,
+        "gcs_telemetry_invalid_total",
+        "Parsed telemetry samples dropped as physically implausible.",
+        &metrics.telemetry_invalid,
+    );
+    counter(
+        &mut out,
+        "gcs_serial_read_errors_total",
+        "Serial reads that failed with an error other than a timeout.",
+        &metrics.serial_read_errors,
+    );
+    counter(
+        &mut out,
+        "gcs_serial_reconnect_attempts_total",
+        "Attempts to re-open the serial port.",
+        &metrics.serial_reconnect_attempts,
+    );
+    counter(
+        &mut out,
+        "gcs_unmatched_acks_total",
+        "Command acknowledgements that matched no pending command.",
+        &metrics.unmatched_acks,
+    );
+    counter(
+        &mut out,
+        "gcs_dedup_skips_total",
+        "Commands not sent because telemetry already showed the requested state.",
+        &metrics.dedup_skips,
+    );
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl Display) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
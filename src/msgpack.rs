@@ -0,0 +1,135 @@
+// src/msgpack.rs
+
+//! MessagePack encoding of anything serializable, by way of its JSON value. Only the
+//! encoder: clients ask for it, nothing here reads it. Floats that are exactly an `f32`
+//! (all of the telemetry's) take the 5-byte float32 form.
+
+use rocket::serde::json::{self, Value};
+use rocket::serde::Serialize;
+
+/// Encodes `value` as MessagePack. Fails only if it cannot be represented as JSON.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    let value = json::to_value(value).map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    encode(&value, &mut out);
+    Ok(out)
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                encode_uint(u, out);
+            } else if let Some(i) = n.as_i64() {
+                encode_int(i, out);
+            } else {
+                let f = n.as_f64().unwrap_or(f64::NAN);
+                if f64::from(f as f32) == f {
+                    out.push(0xca);
+                    out.extend_from_slice(&(f as f32).to_be_bytes());
+                } else {
+                    out.push(0xcb);
+                    out.extend_from_slice(&f.to_be_bytes());
+                }
+            }
+        }
+        Value::String(s) => {
+            encode_len(s.len(), [0xa0, 0xd9, 0xda, 0xdb], 31, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            encode_len(items.len(), [0x90, 0, 0xdc, 0xdd], 15, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(fields) => {
+            encode_len(fields.len(), [0x80, 0, 0xde, 0xdf], 15, out);
+            for (key, item) in fields {
+                encode(&Value::String(key.clone()), out);
+                encode(item, out);
+            }
+        }
+    }
+}
+
+/// Writes a length header: the fix form up to `fix_max`, then the 8-bit (strings only;
+/// a 0 marker means there is none), 16-bit and 32-bit forms.
+fn encode_len(len: usize, markers: [u8; 4], fix_max: usize, out: &mut Vec<u8>) {
+    let [fix, b8, b16, b32] = markers;
+    if len <= fix_max {
+        out.push(fix | len as u8);
+    } else if b8 != 0 && len <= usize::from(u8::MAX) {
+        out.extend_from_slice(&[b8, len as u8]);
+    } else if len <= usize::from(u16::MAX) {
+        out.push(b16);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(b32);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn encode_uint(u: u64, out: &mut Vec<u8>) {
+    if u < 0x80 {
+        out.push(u as u8);
+    } else if u <= u64::from(u8::MAX) {
+        out.extend_from_slice(&[0xcc, u as u8]);
+    } else if u <= u64::from(u16::MAX) {
+        out.push(0xcd);
+        out.extend_from_slice(&(u as u16).to_be_bytes());
+    } else if u <= u64::from(u32::MAX) {
+        out.push(0xce);
+        out.extend_from_slice(&(u as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&u.to_be_bytes());
+    }
+}
+
+/// Negative integers only (`as_u64` takes the rest).
+fn encode_int(i: i64, out: &mut Vec<u8>) {
+    if i >= -32 {
+        out.push(i as u8);
+    } else if i >= i64::from(i8::MIN) {
+        out.extend_from_slice(&[0xd0, i as u8]);
+    } else if i >= i64::from(i16::MIN) {
+        out.push(0xd1);
+        out.extend_from_slice(&(i as i16).to_be_bytes());
+    } else if i >= i64::from(i32::MIN) {
+        out.push(0xd2);
+        out.extend_from_slice(&(i as i32).to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&i.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::json;
+
+    #[test]
+    fn encodes_the_spec_examples() {
+        // {"compact": true, "schema": 0} from msgpack.org.
+        let value = json!({"compact": true, "schema": 0});
+        let mut expected = vec![0x82, 0xa7];
+        expected.extend_from_slice(b"compact");
+        expected.extend_from_slice(&[0xc3, 0xa6]);
+        expected.extend_from_slice(b"schema");
+        expected.push(0x00);
+        assert_eq!(to_vec(&value).unwrap(), expected);
+
+        assert_eq!(to_vec(&json!(-1)).unwrap(), [0xff]);
+        assert_eq!(to_vec(&json!(-33)).unwrap(), [0xd0, 0xdf]);
+        assert_eq!(to_vec(&json!(300)).unwrap(), [0xcd, 0x01, 0x2c]);
+        assert_eq!(to_vec(&12.5f32).unwrap(), [0xca, 0x41, 0x48, 0x00, 0x00]);
+        assert_eq!(to_vec(&0.1f64).unwrap()[0], 0xcb);
+        assert_eq!(to_vec(&"x".repeat(40)).unwrap()[..2], [0xd9, 40]);
+        assert_eq!(to_vec(&vec![false; 16]).unwrap()[..3], [0xdc, 0x00, 0x10]);
+        assert_eq!(to_vec(&Option::<u8>::None).unwrap(), [0xc0]);
+    }
+}
@@ -0,0 +1,212 @@
+// src/pulse.rs
+
+//! Timed solenoid pulses (POST /solenoid/<channel>/pulse): open a valve, hold it open for
+//! `duration_ms`, then close it again, e.g. to vent for exactly 200 ms.
+//!
+//! Each pulse runs on its own thread, like a sequence. DELETE /solenoid/pulse/<id> wakes the
+//! thread early, which closes the valve straight away. Only the most recent
+//! `MAX_KEPT_PULSES` finished pulses can still be looked up.
+
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rocket::serde::Serialize;
+use tracing::error;
+
+use crate::command_queue::CommandSender;
+use crate::QueuedCommand;
+
+/// Shortest pulse; shorter requests are lengthened to this.
+pub const MIN_PULSE_MS: u64 = 1;
+/// Longest pulse; longer requests are cut to this.
+pub const MAX_PULSE_MS: u64 = 5000;
+
+/// Finished pulses kept for GET /solenoid/pulse/<id>.
+const MAX_KEPT_PULSES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum PulseStatus {
+    /// Accepted; the open command is being queued.
+    Pending,
+    /// The open command is queued and the pulse is timing.
+    Active,
+    /// The pulse ran its full duration and the close command was queued.
+    Complete,
+    /// Cut short by DELETE; the close command is queued right away.
+    Aborted,
+}
+
+/// Response body for the pulse endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PulseInfo {
+    pub pulse_id: u64,
+    pub channel: u8,
+    /// After clamping to `MIN_PULSE_MS..=MAX_PULSE_MS`.
+    pub duration_ms: u64,
+    pub status: PulseStatus,
+}
+
+struct Pulse {
+    info: PulseInfo,
+    /// Wakes the pulse's thread to cut it short.
+    abort_tx: mpsc::Sender<()>,
+    /// Taken by the thread once the pulse starts.
+    abort_rx: Option<mpsc::Receiver<()>>,
+}
+
+/// Every pulse started since the server came up, by ID.
+#[derive(Default)]
+pub struct PulseRunner {
+    pulses: Arc<Mutex<BTreeMap<u64, Pulse>>>,
+    next_id: Mutex<u64>,
+}
+
+impl PulseRunner {
+    /// Registers a pending pulse of `channel` (validated) for `duration_ms`, clamped.
+    pub fn add(&self, channel: u8, duration_ms: u64) -> PulseInfo {
+        let pulse_id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let info = PulseInfo {
+            pulse_id,
+            channel,
+            duration_ms: duration_ms.clamp(MIN_PULSE_MS, MAX_PULSE_MS),
+            status: PulseStatus::Pending,
+        };
+        let (abort_tx, abort_rx) = mpsc::channel();
+        let mut pulses = self.pulses.lock().unwrap();
+        pulses.insert(
+            pulse_id,
+            Pulse {
+                info,
+                abort_tx,
+                abort_rx: Some(abort_rx),
+            },
+        );
+        if pulses.len() > MAX_KEPT_PULSES {
+            let oldest_finished = pulses
+                .iter()
+                .find(|(_, p)| !is_running(p.info.status))
+                .map(|(id, _)| *id);
+            if let Some(id) = oldest_finished {
+                pulses.remove(&id);
+            }
+        }
+        info
+    }
+
+    /// Forgets a pending pulse whose open command was refused.
+    pub fn discard(&self, pulse_id: u64) {
+        self.pulses.lock().unwrap().remove(&pulse_id);
+    }
+
+    /// Starts timing a pulse whose open command is queued: after its duration, or as soon as
+    /// it is aborted, `close` is queued on `command_tx`.
+    pub fn start(&self, pulse_id: u64, close: String, command_tx: CommandSender) -> PulseInfo {
+        let (info, abort_rx) = {
+            let mut pulses = self.pulses.lock().unwrap();
+            let pulse = pulses.get_mut(&pulse_id).expect("pulse was added");
+            if pulse.info.status == PulseStatus::Pending {
+                pulse.info.status = PulseStatus::Active;
+            }
+            (
+                pulse.info,
+                pulse.abort_rx.take().expect("pulse started once"),
+            )
+        };
+        let pulses = self.pulses.clone();
+        thread::spawn(move || {
+            let _ = abort_rx.recv_timeout(Duration::from_millis(info.duration_ms));
+            if command_tx.send(QueuedCommand::immediate(close)).is_err() {
+                error!(
+                    channel = info.channel,
+                    "Pulse could not queue the close command; the solenoid may still be open"
+                );
+            }
+            if let Some(pulse) = pulses.lock().unwrap().get_mut(&pulse_id) {
+                if pulse.info.status == PulseStatus::Active {
+                    pulse.info.status = PulseStatus::Complete;
+                }
+            }
+        });
+        info
+    }
+
+    pub fn get(&self, pulse_id: u64) -> Option<PulseInfo> {
+        self.pulses.lock().unwrap().get(&pulse_id).map(|p| p.info)
+    }
+
+    /// Cuts a pulse short if it is still running. Returns its status afterwards, or `None`
+    /// for an unknown ID.
+    pub fn abort(&self, pulse_id: u64) -> Option<PulseInfo> {
+        let mut pulses = self.pulses.lock().unwrap();
+        let pulse = pulses.get_mut(&pulse_id)?;
+        if is_running(pulse.info.status) {
+            pulse.info.status = PulseStatus::Aborted;
+            let _ = pulse.abort_tx.send(());
+        }
+        Some(pulse.info)
+    }
+}
+
+fn is_running(status: PulseStatus) -> bool {
+    matches!(status, PulseStatus::Pending | PulseStatus::Active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::command_queue;
+
+    #[test]
+    fn pulse_closes_after_its_duration_or_when_aborted() {
+        let runner = PulseRunner::default();
+        let (command_tx, command_rx) = command_queue::channel();
+
+        let long = runner.add(3, 60_000);
+        assert_eq!(long.duration_ms, MAX_PULSE_MS);
+        runner.start(long.pulse_id, "s30".into(), command_tx.clone());
+        let short = runner.add(4, 0);
+        assert_eq!(short.duration_ms, MIN_PULSE_MS);
+        let started = Instant::now();
+        runner.start(short.pulse_id, "s40".into(), command_tx);
+
+        let recv = || loop {
+            if let Ok(cmd) = command_rx.try_recv() {
+                return cmd.text;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(2),
+                "no close command"
+            );
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(recv(), "s40");
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            runner.get(short.pulse_id).unwrap().status,
+            PulseStatus::Complete
+        );
+        assert_eq!(
+            runner.get(long.pulse_id).unwrap().status,
+            PulseStatus::Active
+        );
+
+        let aborted = runner.abort(long.pulse_id).unwrap();
+        assert_eq!(aborted.status, PulseStatus::Aborted);
+        assert_eq!(recv(), "s30");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // Aborting a finished pulse changes nothing.
+        let completed = runner.abort(short.pulse_id).unwrap();
+        assert_eq!(completed.status, PulseStatus::Complete);
+        assert_eq!(runner.abort(99), None);
+    }
+}
@@ -0,0 +1,184 @@
+// src/raw_log.rs
+
+//! The last bytes received from the primary board's serial port, exactly as they arrived,
+//! for debugging the serial protocol: GET /serial/raw (Base64 in JSON) and GET
+//! /serial/raw/text (as text, for a browser).
+//!
+//! The serial loop's reader is wrapped in a `RawTap`, which copies every chunk it reads onto
+//! a bounded channel without waiting; a collector thread appends them to the ring buffer.
+//! When the collector falls behind, chunks are dropped rather than slowing the reader.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use rocket::response::content::RawHtml;
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::{base64, AppState};
+
+/// Bytes kept in the ring buffer.
+pub const RAW_LOG_SIZE: usize = 8192;
+
+/// Chunks waiting for the collector thread before new ones are dropped.
+const QUEUE_SIZE: usize = 256;
+
+/// The raw log, shared between the collector thread and the handlers.
+pub type SharedRawLog = Arc<Mutex<RawLog>>;
+
+/// A ring buffer of the last `RAW_LOG_SIZE` bytes received.
+#[derive(Debug, Default)]
+pub struct RawLog {
+    bytes: VecDeque<u8>,
+    /// Every byte ever received, including those evicted since.
+    total: u64,
+}
+
+impl RawLog {
+    /// Appends `chunk`, evicting the oldest bytes beyond `RAW_LOG_SIZE`.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.total += chunk.len() as u64;
+        self.bytes.extend(chunk);
+        let excess = self.bytes.len().saturating_sub(RAW_LOG_SIZE);
+        self.bytes.drain(..excess);
+    }
+
+    /// The buffered bytes, oldest first.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.iter().copied().collect()
+    }
+}
+
+/// Starts the collector thread filling `log`. Send it chunks with `try_send`; it exits once
+/// every sender is gone.
+pub fn spawn_collector(log: SharedRawLog) -> mpsc::SyncSender<Vec<u8>> {
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_SIZE);
+    thread::spawn(move || {
+        for chunk in rx {
+            log.lock().unwrap().push(&chunk);
+        }
+    });
+    tx
+}
+
+/// A reader that copies everything read through it to the raw log collector.
+pub struct RawTap<R> {
+    inner: R,
+    tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl<R> RawTap<R> {
+    pub fn new(inner: R, tx: mpsc::SyncSender<Vec<u8>>) -> Self {
+        RawTap { inner, tx }
+    }
+
+    fn capture(&self, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            // Full (collector behind) or disconnected: the chunk is skipped.
+            let _ = self.tx.try_send(bytes.to_vec());
+        }
+    }
+}
+
+impl<R: BufRead> Read for RawTap<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.capture(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for RawTap<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Bytes count as received once the caller consumes them. The buffer still holds
+        // them, so this `fill_buf` does no I/O.
+        if amt > 0 {
+            if let Ok(buf) = self.inner.fill_buf() {
+                let consumed = &buf[..amt.min(buf.len())];
+                let _ = self.tx.try_send(consumed.to_vec());
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Response body for GET /serial/raw.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RawBytes {
+    /// The buffered bytes, oldest first, Base64-encoded.
+    data: String,
+    /// Bytes received since startup, including those no longer buffered.
+    total_bytes: u64,
+}
+
+/// GET /serial/raw returns the last `RAW_LOG_SIZE` bytes received from the serial port.
+#[get("/serial/raw")]
+pub fn get_raw(state: &State<AppState>) -> Json<RawBytes> {
+    let log = state.raw_log.lock().unwrap();
+    Json(RawBytes {
+        data: base64::encode(&log.bytes()),
+        total_bytes: log.total,
+    })
+}
+
+/// GET /serial/raw/text shows the same bytes as (lossy) UTF-8 in a `<pre>` block.
+#[get("/serial/raw/text")]
+pub fn get_raw_text(state: &State<AppState>) -> RawHtml<String> {
+    let bytes = state.raw_log.lock().unwrap().bytes();
+    let text = String::from_utf8_lossy(&bytes);
+    RawHtml(format!("<pre>{}</pre>", escape_html(&text)))
+}
+
+/// Escapes text for HTML (or SVG) element content.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn keeps_the_last_bytes_and_counts_all() {
+        let mut log = RawLog::default();
+        log.push(&[1; RAW_LOG_SIZE]);
+        log.push(b"TS:1\r\n");
+        assert_eq!(log.total, RAW_LOG_SIZE as u64 + 6);
+        let bytes = log.bytes();
+        assert_eq!(bytes.len(), RAW_LOG_SIZE);
+        assert!(bytes.ends_with(b"\x01TS:1\r\n"));
+    }
+
+    #[test]
+    fn tap_captures_lines_and_reads() {
+        let (tx, rx) = mpsc::sync_channel(8);
+        let input: &[u8] = b"TS:1 | ARM:0\r\nTS:2\r\n\x00\x01";
+        let mut tap = RawTap::new(BufReader::new(input), tx);
+        let mut line = String::new();
+        tap.read_line(&mut line).unwrap();
+        tap.read_line(&mut line).unwrap();
+        let mut rest = Vec::new();
+        tap.read_to_end(&mut rest).unwrap();
+        drop(tap);
+        let captured: Vec<u8> = rx.iter().flatten().collect();
+        assert_eq!(captured, input);
+        assert_eq!(escape_html("<a & b>"), "&lt;a &amp; b&gt;");
+    }
+}
@@ -0,0 +1,158 @@
+// src/reconcile.rs
+
+//! Solenoid state reconciliation. Commands written while the port is going away are lost,
+//! and an Arduino that reset comes back with every solenoid off, so after a reconnect the
+//! board may not be in the state the operators last asked for. The serial loop keeps that
+//! desired state, taken from every solenoid command it dequeues (whether or not the write
+//! succeeded), separately from the telemetry the board reports.
+//!
+//! ```text
+//!              session starts,
+//!              POST /reconcile
+//!   ┌──────┐ ─────────────────▶ ┌─────────┐
+//!   │ Idle │                    │ Pending │
+//!   └──────┘ ◀───────────────── └─────────┘
+//!            first valid telemetry frame:
+//!            write "s<ch><state>" for every
+//!            channel it contradicts (if any)
+//! ```
+//!
+//! Only channels that were commanded since startup are compared; the others have no desired
+//! state. Reconciliation commands go straight to the port, past the rate limit the commands
+//! they repeat already passed once. Switching a solenoid back on is still checked (see
+//! `refused`): the board may have disarmed or another valve opened since, so a command
+//! POST /solenoid would refuse now is logged and not sent.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use rocket::State;
+
+use crate::auth::Authenticated;
+use crate::command::CommandBuilder;
+use crate::flight_log::EventType;
+use crate::interlock::SolenoidGuard;
+use crate::AppState;
+
+/// The desired solenoid states, and whether a comparison is pending.
+#[derive(Debug, Default)]
+pub struct Reconciler {
+    /// `None` for channels never commanded.
+    desired: Mutex<[Option<bool>; 16]>,
+    pending: AtomicBool,
+}
+
+impl Reconciler {
+    /// Takes the desired states from the solenoid commands in `text` (one per line).
+    pub fn record(&self, text: &str) {
+        let mut desired = self.desired.lock().unwrap();
+        for event in text.lines().filter_map(EventType::from_command) {
+            if let EventType::Solenoid {
+                channel: channel @ 1..=16,
+                state,
+            } = event
+            {
+                desired[channel as usize - 1] = Some(state);
+            }
+        }
+    }
+
+    /// Compares against the next valid telemetry frame.
+    pub fn request(&self) {
+        self.pending.store(true, Ordering::SeqCst);
+    }
+
+    /// If a comparison is pending, clears it and returns the commands (newline-separated)
+    /// that bring `solenoids` to the desired state, or `None` if they already match.
+    pub fn take_fix(&self, solenoids: &[bool]) -> Option<String> {
+        if !self.pending.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let desired = self.desired.lock().unwrap();
+        let mut builder = CommandBuilder::new();
+        for ((desired, &actual), channel) in desired.iter().zip(solenoids).zip(1..=16u8) {
+            if let Some(on) = desired.filter(|&on| on != actual) {
+                builder.solenoid(channel, on).expect("valid channel");
+            }
+        }
+        (!builder.is_empty()).then(|| builder.build_joined())
+    }
+}
+
+/// Whether POST /solenoid would refuse the correction `command` now: it switches a solenoid
+/// on while the board is disarmed under `require_armed`, or against `guard`'s interlocks and
+/// duty cycles. Switching off is never refused.
+pub fn refused(
+    command: &str,
+    armed: bool,
+    require_armed: bool,
+    guard: Option<&SolenoidGuard>,
+    solenoids: &[bool],
+) -> bool {
+    let switches_on = matches!(
+        EventType::from_command(command),
+        Some(EventType::Solenoid { state: true, .. })
+    );
+    let guarded = guard.is_some_and(|guard| guard.check_against(command, solenoids).is_err());
+    switches_on && (require_armed && !armed || guarded)
+}
+
+/// POST /reconcile compares the next telemetry frame against the desired solenoid states
+/// and corrects any mismatch, as happens after every reconnect.
+#[post("/reconcile")]
+pub fn reconcile(_auth: Authenticated, state: &State<AppState>) -> &'static str {
+    state.reconciler.request();
+    "OK"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Interlock;
+    use crate::runtime_config::SharedRuntimeConfig;
+
+    #[test]
+    fn fixes_only_commanded_channels_that_differ() {
+        let reconciler = Reconciler::default();
+        reconciler.record("s31\ns50");
+        reconciler.record("a\ns71\ns50");
+        let mut solenoids = vec![false; 16];
+        solenoids[4] = true; // 5 should be off
+        solenoids[6] = true; // 7 is on, as desired
+        solenoids[9] = true; // 10 was never commanded
+        assert_eq!(reconciler.take_fix(&solenoids), None, "nothing pending");
+
+        reconciler.request();
+        assert_eq!(reconciler.take_fix(&solenoids).as_deref(), Some("s31\ns50"));
+        assert_eq!(
+            reconciler.take_fix(&solenoids),
+            None,
+            "request was consumed"
+        );
+
+        reconciler.request();
+        solenoids[2] = true;
+        solenoids[4] = false;
+        assert_eq!(reconciler.take_fix(&solenoids), None);
+    }
+
+    #[test]
+    fn refuses_switching_on_what_the_api_would_refuse() {
+        let mut solenoids = vec![false; 16];
+        let guard = SolenoidGuard::default();
+        assert!(!refused("s31", false, false, None, &solenoids));
+        assert!(refused("s31", false, true, Some(&guard), &solenoids));
+        assert!(!refused("s31", true, true, Some(&guard), &solenoids));
+        assert!(!refused("s30", false, true, Some(&guard), &solenoids));
+
+        // Channel 7 may not open while 3 is.
+        let runtime = SharedRuntimeConfig::default();
+        runtime.lock().unwrap().interlocks = vec![Interlock {
+            prevent: vec![3, 7],
+        }];
+        let guard = SolenoidGuard::new(Default::default(), runtime, Default::default());
+        solenoids[2] = true;
+        assert!(refused("s71", true, false, Some(&guard), &solenoids));
+        assert!(!refused("s30", true, false, Some(&guard), &solenoids));
+    }
+}
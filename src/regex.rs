@@ -0,0 +1,593 @@
+// src/regex.rs
+
+//! A small backtracking regular expression engine for `[telemetry.format]`. Small enough to
+//! keep in-tree.
+//!
+//! It supports the usual syntax for parsing lines: literals, `.`, `^`, `$`, classes such as
+//! `[0-9.]` and `[^,]`, the escapes `\d \w \s \D \W \S \t \r \n` (ASCII digits and word
+//! characters), groups `(...)`, `(?:...)`, named groups `(?P<name>...)` / `(?<name>...)`,
+//! alternation, and the greedy and lazy quantifiers `* + ? {n} {n,} {n,m}`. There are no
+//! flags, look-around or backreferences. Matching backtracks, so a pattern with nested
+//! unbounded repeats can take exponential time on a line it doesn't match.
+
+use std::collections::HashMap;
+
+/// The biggest `n`/`m` accepted in `{n,m}`.
+const MAX_REPEAT: u32 = 1000;
+
+/// A compiled pattern.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    root: Node,
+    /// Capture slots: 0 is the whole match, then one per group.
+    slots: usize,
+    names: HashMap<String, usize>,
+}
+
+/// The groups of a match, by name.
+#[derive(Debug)]
+pub struct Captures<'t> {
+    text: &'t str,
+    slots: Vec<Option<(usize, usize)>>,
+    names: &'t HashMap<String, usize>,
+}
+
+impl<'t> Captures<'t> {
+    /// The text the group `name` matched, if it took part in the match.
+    pub fn name(&self, name: &str) -> Option<&'t str> {
+        let (start, end) = (*self.slots.get(*self.names.get(name)?)?)?;
+        Some(&self.text[start..end])
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    /// `.`: anything but a newline.
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// A group and, if it captures, its slot.
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClassItem {
+    Range(char, char),
+    /// `\d`, `\w` or `\s` (`true` for the negated `\D`, `\W`, `\S`).
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(self, c: char) -> bool {
+        match self {
+            ClassItem::Range(low, high) => (low..=high).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_ascii_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
+    }
+}
+
+impl Regex {
+    /// Compiles `pattern`, or says what is wrong with it.
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            slots: 1,
+            names: HashMap::new(),
+        };
+        let root = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unmatched ')' at {}", parser.pos));
+        }
+        Ok(Regex {
+            root,
+            slots: parser.slots,
+            names: parser.names,
+        })
+    }
+
+    /// The names of the named groups.
+    pub fn capture_names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+
+    /// The leftmost match in `text`, if any.
+    pub fn captures<'t>(&'t self, text: &'t str) -> Option<Captures<'t>> {
+        let mut offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        offsets.push(text.len());
+        let matcher = Matcher {
+            chars: text.chars().collect(),
+        };
+        let mut slots = vec![None; self.slots];
+        for start in 0..offsets.len() {
+            slots.iter_mut().for_each(|slot| *slot = None);
+            let matched = matcher.node(&self.root, start, &mut slots, &mut |end, slots| {
+                slots[0] = Some((start, end));
+                true
+            });
+            if matched {
+                let slots = slots
+                    .into_iter()
+                    .map(|slot| slot.map(|(start, end)| (offsets[start], offsets[end])))
+                    .collect();
+                return Some(Captures {
+                    text,
+                    slots,
+                    names: &self.names,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Capture positions, in chars.
+type Slots = Vec<Option<(usize, usize)>>;
+
+struct Matcher {
+    chars: Vec<char>,
+}
+
+impl Matcher {
+    /// Matches `node` at `pos`, then calls `next` with the position after it; backtracks
+    /// into `node` for as long as `next` fails.
+    fn node(
+        &self,
+        node: &Node,
+        pos: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        let current = self.chars.get(pos).copied();
+        match node {
+            Node::Empty => next(pos, slots),
+            Node::Char(c) => current == Some(*c) && next(pos + 1, slots),
+            Node::Any => current.is_some_and(|c| c != '\n') && next(pos + 1, slots),
+            Node::Class(class) => current.is_some_and(|c| class.matches(c)) && next(pos + 1, slots),
+            Node::Start => pos == 0 && next(pos, slots),
+            Node::End => pos == self.chars.len() && next(pos, slots),
+            Node::Group(inner, None) => self.node(inner, pos, slots, next),
+            Node::Group(inner, Some(slot)) => {
+                let slot = *slot;
+                self.node(inner, pos, slots, &mut |end, slots| {
+                    let previous = slots[slot].replace((pos, end));
+                    next(end, slots) || {
+                        slots[slot] = previous;
+                        false
+                    }
+                })
+            }
+            Node::Concat(nodes) => self.sequence(nodes, pos, slots, next),
+            Node::Alternate(alternatives) => alternatives
+                .iter()
+                .any(|alternative| self.node(alternative, pos, slots, &mut *next)),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                let repeat = Repeat {
+                    node,
+                    min: *min,
+                    max: *max,
+                    greedy: *greedy,
+                };
+                self.repeat(&repeat, 0, pos, slots, next)
+            }
+        }
+    }
+
+    fn sequence(
+        &self,
+        nodes: &[Node],
+        pos: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(pos, slots),
+            Some((first, rest)) => self.node(first, pos, slots, &mut |pos, slots| {
+                self.sequence(rest, pos, slots, &mut *next)
+            }),
+        }
+    }
+
+    /// Matches the rest of `repeat` after `count` repetitions ending at `pos`.
+    fn repeat(
+        &self,
+        repeat: &Repeat,
+        count: u32,
+        pos: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        let enough = count >= repeat.min;
+        // Greedy repeats try one more repetition before stopping, lazy ones the opposite.
+        if !repeat.greedy && enough && next(pos, slots) {
+            return true;
+        }
+        self.repeat_once(repeat, count, pos, slots, next)
+            || (repeat.greedy && enough && next(pos, slots))
+    }
+
+    fn repeat_once(
+        &self,
+        repeat: &Repeat,
+        count: u32,
+        pos: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        if repeat.max.is_some_and(|max| count >= max) {
+            return false;
+        }
+        self.node(repeat.node, pos, slots, &mut |end, slots| {
+            // Past the minimum, an empty repetition would only loop forever.
+            (end != pos || count < repeat.min)
+                && self.repeat(repeat, count + 1, end, slots, &mut *next)
+        })
+    }
+}
+
+struct Repeat<'a> {
+    node: &'a Node,
+    min: u32,
+    max: Option<u32>,
+    greedy: bool,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    slots: usize,
+    names: HashMap<String, usize>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.concatenation()?];
+        while self.eat('|') {
+            alternatives.push(self.concatenation()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Node::Alternate(alternatives),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let at = self.pos;
+        let c = self.peek().ok_or("unexpected end of pattern")?;
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => self.group()?,
+            '[' => Node::Class(self.class()?),
+            '\\' => match self.escape()? {
+                Escape::Char(c) => Node::Char(c),
+                Escape::Class(item) => Node::Class(Class {
+                    negated: false,
+                    items: vec![item],
+                }),
+            },
+            '*' | '+' | '?' | '{' => return Err(format!("nothing to repeat at {}", at)),
+            c => Node::Char(c),
+        })
+    }
+
+    /// After the `(`.
+    fn group(&mut self) -> Result<Node, String> {
+        let slot = if self.eat('?') {
+            if self.eat(':') {
+                None
+            } else {
+                self.eat('P');
+                if !self.eat('<') {
+                    return Err(format!("unsupported group syntax at {}", self.pos));
+                }
+                let name = self.group_name()?;
+                let slot = self.slots;
+                if self.names.insert(name.clone(), slot).is_some() {
+                    return Err(format!("duplicate group name '{}'", name));
+                }
+                self.slots += 1;
+                Some(slot)
+            }
+        } else {
+            self.slots += 1;
+            Some(self.slots - 1)
+        };
+        let inner = self.alternation()?;
+        if !self.eat(')') {
+            return Err("unclosed group".to_string());
+        }
+        Ok(Node::Group(Box::new(inner), slot))
+    }
+
+    /// After the `<`, up to and including the `>`.
+    fn group_name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        loop {
+            match self.peek() {
+                Some('>') => break,
+                Some(c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err(format!("invalid group name at {}", self.pos)),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!("invalid group name '{}'", name));
+        }
+        Ok(name)
+    }
+
+    /// After the `[`, up to and including the `]`.
+    fn class(&mut self) -> Result<Class, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or("unclosed character class")?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = match c {
+                '\\' => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                },
+                c => c,
+            };
+            // A '-' before the closing ']' is literal.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let high = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        match self.escape()? {
+                            Escape::Char(c) => c,
+                            Escape::Class(_) => return Err("invalid class range".to_string()),
+                        }
+                    }
+                    Some(c) => {
+                        self.pos += 1;
+                        c
+                    }
+                    None => return Err("unclosed character class".to_string()),
+                };
+                if high < low {
+                    return Err(format!("invalid class range {}-{}", low, high));
+                }
+                items.push(ClassItem::Range(low, high));
+            } else {
+                items.push(ClassItem::Range(low, low));
+            }
+        }
+        Ok(Class { negated, items })
+    }
+
+    /// After the `\`.
+    fn escape(&mut self) -> Result<Escape, String> {
+        let c = self.peek().ok_or("pattern ends with '\\'")?;
+        self.pos += 1;
+        Ok(match c {
+            'd' | 'D' => Escape::Class(ClassItem::Digit(c == 'D')),
+            'w' | 'W' => Escape::Class(ClassItem::Word(c == 'W')),
+            's' | 'S' => Escape::Class(ClassItem::Space(c == 'S')),
+            't' => Escape::Char('\t'),
+            'r' => Escape::Char('\r'),
+            'n' => Escape::Char('\n'),
+            c if c.is_ascii_punctuation() || c == ' ' => Escape::Char(c),
+            c => return Err(format!("unsupported escape '\\{}'", c)),
+        })
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let at = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let min = self.number()?;
+                let max = if self.eat(',') {
+                    match self.peek() {
+                        Some('}') => None,
+                        _ => Some(self.number()?),
+                    }
+                } else {
+                    Some(min)
+                };
+                if self.peek() != Some('}') {
+                    return Err(format!("unclosed repetition at {}", at));
+                }
+                if max.is_some_and(|max| max < min) {
+                    return Err(format!("invalid repetition at {}", at));
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            return Err(format!("nothing to repeat at {}", at));
+        }
+        let greedy = !self.eat('?');
+        let node = Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        };
+        match self.peek() {
+            Some('*' | '+' | '?' | '{') => Err(format!("nothing to repeat at {}", self.pos)),
+            _ => Ok(node),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse::<u32>() {
+            Ok(n) if n <= MAX_REPEAT => Ok(n),
+            Ok(_) => Err(format!("repetition above {} at {}", MAX_REPEAT, start)),
+            Err(_) => Err(format!("expected a number at {}", start)),
+        }
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(ClassItem),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'t>(regex: &'t Regex, text: &'t str) -> Option<&'t str> {
+        let captures = regex.captures(text)?;
+        let (start, end) = captures.slots[0].unwrap();
+        Some(&text[start..end])
+    }
+
+    #[test]
+    fn finds_the_leftmost_match() {
+        let regex = Regex::new(r"\d+(\.\d*)?").unwrap();
+        assert_eq!(find(&regex, "V=12.34V"), Some("12.34"));
+        assert_eq!(find(&regex, "V=12V"), Some("12"));
+        assert_eq!(find(&regex, "V=V"), None);
+        let lazy = Regex::new(r"<.+?>").unwrap();
+        assert_eq!(find(&lazy, "<a><b>"), Some("<a>"));
+        let anchored = Regex::new(r"^a|b$").unwrap();
+        assert_eq!(find(&anchored, "cab"), Some("b"));
+        assert_eq!(find(&anchored, "abc"), Some("a"));
+        assert_eq!(find(&anchored, "cac"), None);
+    }
+
+    #[test]
+    fn classes_and_counted_repeats() {
+        let regex = Regex::new(r"^[01]{16}$").unwrap();
+        assert!(regex.captures("1000100000000001").is_some());
+        assert!(regex.captures("100010000000001").is_none());
+        assert!(regex.captures("10001000000000012").is_none());
+        let regex = Regex::new(r"^[^,\s]+,[a-c-]{2,}\W$").unwrap();
+        assert!(regex.captures("x.y,a-c!").is_some());
+        assert!(regex.captures("x y,a-c!").is_none());
+        assert!(regex.captures("xy,a!").is_none());
+        let regex = Regex::new(r"^(ab)*$").unwrap();
+        assert!(regex.captures("ababab").is_some());
+        assert!(regex.captures("ababa").is_none());
+        let regex = Regex::new(r"^(a*)*b$").unwrap();
+        assert!(regex.captures("aab").is_some());
+    }
+
+    #[test]
+    fn named_groups_capture_their_last_repetition() {
+        let regex = Regex::new(r"^T=(?P<ts>\d+);(?:(?<key>[A-Z]+)=\d+;)*(?P<tail>x)?$").unwrap();
+        let mut names: Vec<&str> = regex.capture_names().collect();
+        names.sort();
+        assert_eq!(names, ["key", "tail", "ts"]);
+        let captures = regex.captures("T=42;AB=1;CD=2;").unwrap();
+        assert_eq!(captures.name("ts"), Some("42"));
+        assert_eq!(captures.name("key"), Some("CD"));
+        assert_eq!(captures.name("tail"), None);
+        assert_eq!(captures.name("missing"), None);
+        let captures = regex.captures("T=7;x").unwrap();
+        assert_eq!(captures.name("key"), None);
+        assert_eq!(captures.name("tail"), Some("x"));
+        // Byte offsets for text that isn't ASCII.
+        let regex = Regex::new(r"(?P<t>°\w)").unwrap();
+        assert_eq!(regex.captures("35°C").unwrap().name("t"), Some("°C"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in [
+            "(a",
+            "a)",
+            "[a",
+            "[z-a]",
+            "*a",
+            "a**",
+            "a{2",
+            "a{3,1}",
+            "a{9999}",
+            r"\q",
+            "(?=a)",
+            "(?P<1x>a)",
+            "(?P<a>x)(?P<a>y)",
+            r"a\",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// src/replay.rs
+
+//! Replay mode (`--replay <log_file>`): serves telemetry recorded earlier, at the original
+//! speed, for operator training and UI testing without hardware.
+//!
+//! The log can be a CSV file written by `--log-file` or a capture of the raw serial lines.
+//! Samples are published in order, each after the gap between its `timestamp` and the
+//! previous one, as if they had just arrived from the Arduino. Commands are accepted and
+//! logged (console and flight log) but go nowhere.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::serde::Serialize;
+use tracing::{error, info};
+
+use crate::latency::CommandAck;
+use crate::telemetry::parse_telemetry_line;
+use crate::{SerialEndpoints, Telemetry, TelemetrySinks};
+
+/// Longest pause between two samples; a gap in the recording (e.g. a reconnect) is cut
+/// short rather than replayed in full.
+const MAX_SAMPLE_GAP: Duration = Duration::from_secs(5);
+
+/// How often commands are drained while waiting for the next sample.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Progress of a replay, as reported by GET /replay/status.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReplayStatus {
+    /// The log file being replayed.
+    pub file: String,
+    /// Line number (1-based) of the last published sample; 0 before the first.
+    pub line: usize,
+    /// Arduino timestamp of the last published sample.
+    pub timestamp: u64,
+    /// Every line of the log has been replayed.
+    pub complete: bool,
+}
+
+/// Replay progress shared between the replay thread and GET /replay/status.
+pub type SharedReplayStatus = Arc<Mutex<ReplayStatus>>;
+
+/// Parses one row written by `CsvLog`:
+/// `wall_clock_ms,timestamp,armed,battery,arming,sol1..sol16,pyro1..pyro4`.
+/// The recorded battery is already filtered, so it is also used as the raw reading.
+fn parse_csv_record(line: &str) -> Option<Telemetry> {
+    let fields: Vec<&str> = line.trim().split(',').collect();
+    if fields.len() != 5 + 16 + 4 {
+        return None;
+    }
+    let flag = |field: &str| match field {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    };
+    let battery: f32 = fields[3].parse().ok()?;
+    Some(Telemetry {
+        timestamp: fields[1].parse().ok()?,
+        armed: flag(fields[2])?,
+        battery,
+        battery_raw: battery,
+        arming: fields[4].parse().ok()?,
+        solenoids: fields[5..21]
+            .iter()
+            .map(|f| flag(f))
+            .collect::<Option<_>>()?,
+        pyro_continuity: fields[21..]
+            .iter()
+            .map(|f| flag(f))
+            .collect::<Option<_>>()?,
+        ..Telemetry::default()
+    })
+}
+
+/// Parses a log line in either format. Headers, ACKs and other lines give `None`.
+fn parse_log_line(line: &str) -> Option<Telemetry> {
+    parse_csv_record(line).or_else(|| parse_telemetry_line(line.trim()).ok())
+}
+
+/// Opens `path` and starts the replay thread, which stands in for the serial loop: it
+/// publishes the recorded samples to `sinks` and drains the command channels.
+pub fn spawn(
+    path: &str,
+    sinks: TelemetrySinks,
+    endpoints: SerialEndpoints,
+) -> io::Result<SharedReplayStatus> {
+    let reader = BufReader::new(File::open(path)?);
+    let status = SharedReplayStatus::new(Mutex::new(ReplayStatus {
+        file: path.to_string(),
+        ..ReplayStatus::default()
+    }));
+    let progress = status.clone();
+    thread::spawn(move || {
+        let mut previous: Option<u64> = None;
+        for (index, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error!(error = %e, "Error reading replay log");
+                    break;
+                }
+            };
+            let Some(tel) = parse_log_line(&line) else {
+                continue;
+            };
+            let gap = previous.map_or(0, |prev| tel.timestamp.saturating_sub(prev));
+            wait_draining_commands(
+                Duration::from_millis(gap).min(MAX_SAMPLE_GAP),
+                &sinks,
+                &endpoints,
+            );
+            previous = Some(tel.timestamp);
+            {
+                let mut progress = progress.lock().unwrap();
+                progress.line = index + 1;
+                progress.timestamp = tel.timestamp;
+            }
+            sinks.publish(tel);
+        }
+        progress.lock().unwrap().complete = true;
+        info!(file = %progress.lock().unwrap().file, "Replay complete");
+        loop {
+            wait_draining_commands(Duration::from_secs(1), &sinks, &endpoints);
+        }
+    });
+    Ok(status)
+}
+
+/// Waits for `duration`, accepting the commands that arrive in the meantime.
+fn wait_draining_commands(duration: Duration, sinks: &TelemetrySinks, endpoints: &SerialEndpoints) {
+    let deadline = Instant::now() + duration;
+    loop {
+        drain_commands(sinks, endpoints);
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        thread::sleep(COMMAND_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Logs (instead of writing) every queued command.
+fn drain_commands(sinks: &TelemetrySinks, endpoints: &SerialEndpoints) {
+    let ts = || sinks.telemetry.read().unwrap().timestamp;
+    while let Ok(batch) = endpoints.emergency.try_recv() {
+        info!(commands = ?batch, "Replay: not sending priority commands");
+        endpoints
+            .flight_log
+            .lock()
+            .unwrap()
+            .record_command(ts(), &batch);
+    }
+    while let Ok(cmd) = endpoints.commands.try_recv() {
+        info!(command = %cmd.text, "Replay: not sending command");
+        endpoints
+            .flight_log
+            .lock()
+            .unwrap()
+            .record_command(ts(), &cmd.text);
+        let _ = endpoints.acks.send(CommandAck {
+            received_at: cmd.received_at,
+            written_at: Instant::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_csv_rows_and_serial_lines() {
+        let csv = "1700000000000,1500,1,12.5,11.9,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,1,1,1,0,1";
+        let tel = parse_log_line(csv).unwrap();
+        assert_eq!(
+            (tel.timestamp, tel.armed, tel.battery, tel.arming),
+            (1500, true, 12.5, 11.9)
+        );
+        assert!(tel.solenoids[0] && tel.solenoids[15] && !tel.solenoids[1]);
+        assert_eq!(tel.pyro_continuity, [true, true, false, true]);
+
+        let sol: Vec<String> = (1..=16).map(|ch| format!("{}:OFF", ch)).collect();
+        let line = format!(
+            "TS:2000 | ARM:0 | BATT:12.4V | ARM_SENSE:0.0V | SOL:{}\r",
+            sol.join(",")
+        );
+        assert_eq!(parse_log_line(&line).unwrap().timestamp, 2000);
+
+        assert!(parse_log_line("wall_clock_ms,timestamp,armed,battery,arming").is_none());
+        assert!(parse_log_line("ACK:s51").is_none());
+    }
+}
@@ -0,0 +1,154 @@
+// src/request_limit.rs
+
+//! A per-client limit on POST requests (`[rate_limit]`), so a script on the LAN can't flood
+//! the command endpoints. Every client IP has a token bucket of `burst` tokens that refills
+//! at `refill_per_s`; each POST takes one. GETs (and CORS preflights) are free.
+//!
+//! Buckets refill from the time elapsed since they were last used, so there is no
+//! background ticker. Like `basic_auth`, the fairing cannot answer a request itself: it
+//! reroutes a request over the limit to `rejected`, which answers `429`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::Method;
+use rocket::{Data, Request};
+use tracing::warn;
+
+use crate::error::ApiError;
+use crate::AppState;
+
+/// Where requests over the limit are rerouted to.
+const REJECTED_PATH: &str = "/rate_limit/rejected";
+
+/// Default bucket size: POSTs a client can make in a burst.
+pub const DEFAULT_BURST: u32 = 100;
+
+/// Default refill rate, in tokens per second.
+pub const DEFAULT_REFILL_PER_S: f64 = 10.0;
+
+/// Past this many clients, buckets that have refilled completely are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The token buckets of every client seen.
+#[derive(Debug)]
+pub struct RequestLimiter {
+    burst: u32,
+    refill_per_s: f64,
+    buckets: HashMap<IpAddr, TokenBucket>,
+}
+
+impl RequestLimiter {
+    /// A limiter with `burst` tokens per client refilling at `refill_per_s`; a `burst` of 0
+    /// disables it.
+    pub fn new(burst: u32, refill_per_s: f64) -> Self {
+        RequestLimiter {
+            burst,
+            refill_per_s,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from `ip`'s bucket at `now`; `false` if it is empty.
+    pub fn try_take(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.burst == 0 {
+            return true;
+        }
+        let burst = self.burst as f64;
+        let refill_per_s = self.refill_per_s;
+        if self.buckets.len() >= MAX_TRACKED_CLIENTS && !self.buckets.contains_key(&ip) {
+            self.buckets.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * refill_per_s < burst
+            });
+        }
+        let bucket = self.buckets.entry(ip).or_insert(TokenBucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_s).min(burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Reroutes POSTs from a client whose bucket is empty to `rejected`.
+pub struct RateLimitFairing;
+
+#[rocket::async_trait]
+impl Fairing for RateLimitFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request rate limit",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        if req.method() != Method::Post {
+            return;
+        }
+        let (Some(ip), Some(state)) = (req.client_ip(), req.rocket().state::<AppState>()) else {
+            return;
+        };
+        let allowed = state.request_limiter.lock().unwrap().try_take(ip, Instant::now());
+        if allowed {
+            return;
+        }
+        warn!(client = %ip, uri = %req.uri(), "Request rate limit exceeded");
+        req.set_method(Method::Get);
+        req.set_uri(Origin::parse(REJECTED_PATH).expect("valid path"));
+    }
+}
+
+/// GET /rate_limit/rejected: where the fairing sends requests over the limit. Always a `429`.
+#[get("/rate_limit/rejected")]
+pub fn rejected() -> ApiError {
+    ApiError::TooManyRequests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let mut limiter = RequestLimiter::new(2, 10.0);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        let t0 = Instant::now();
+        assert!(limiter.try_take(a, t0));
+        assert!(limiter.try_take(a, t0));
+        assert!(!limiter.try_take(a, t0));
+        assert!(limiter.try_take(b, t0), "each client has its own bucket");
+        assert!(!limiter.try_take(a, t0 + Duration::from_millis(50)));
+        assert!(limiter.try_take(a, t0 + Duration::from_millis(100)));
+        // Never more than `burst`, however long the client was idle.
+        let later = t0 + Duration::from_secs(60);
+        assert!(limiter.try_take(a, later));
+        assert!(limiter.try_take(a, later));
+        assert!(!limiter.try_take(a, later));
+    }
+
+    #[test]
+    fn zero_burst_disables_the_limit() {
+        let mut limiter = RequestLimiter::new(0, 10.0);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!((0..1000).all(|_| limiter.try_take(ip, Instant::now())));
+    }
+}
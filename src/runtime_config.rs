@@ -0,0 +1,378 @@
+// src/runtime_config.rs
+
+//! Solenoid labels, groups and interlocks that can be changed while the server runs, so a
+//! mission-control UI can rename or regroup valves between test runs without a restart.
+//! They start out as the `[solenoid_labels]`, `[[group]]` and `[[interlock]]` sections of
+//! the config file. Changes apply from the next request on, and POST /config/save writes
+//! them back to that file.
+//!
+//! The bodies are the JSON form of the TOML sections: `{"7": "LOX Main Valve"}` for labels,
+//! `{"name": "purge", "channels": [2, 3]}` for a group, `{"prevent": [3, 7]}` for an
+//! interlock. Each change responds with the whole section as it is afterwards.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::auth::{Authenticated, SignedJson};
+use crate::config::{
+    self, Interlock, SolenoidDirection, SolenoidGroup, SolenoidInfo, DEFAULT_CONFIG_PATH,
+};
+use crate::error::ApiError;
+use crate::AppState;
+
+/// The runtime config shared by the handlers.
+pub type SharedRuntimeConfig = Arc<Mutex<RuntimeConfig>>;
+
+pub struct RuntimeConfig {
+    /// `[solenoid_labels]`, keyed by channel as in the TOML.
+    pub labels: HashMap<String, String>,
+    /// `[solenoid_directions]`; not editable at runtime, but part of `solenoid_info`.
+    pub directions: HashMap<String, SolenoidDirection>,
+    pub groups: Vec<SolenoidGroup>,
+    pub interlocks: Vec<Interlock>,
+    /// The config file POST /config/save writes to.
+    pub path: String,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            labels: HashMap::new(),
+            directions: HashMap::new(),
+            groups: Vec::new(),
+            interlocks: Vec::new(),
+            path: DEFAULT_CONFIG_PATH.to_string(),
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Labels and directions of all 16 solenoids (see `config::solenoid_info`).
+    pub fn solenoid_info(&self) -> HashMap<u8, SolenoidInfo> {
+        config::solenoid_info(&self.labels, &self.directions)
+    }
+
+    /// Writes the labels, groups and interlocks into the config file, keeping its other
+    /// settings (but not its comments). The file is created if it does not exist yet.
+    pub fn save(&self) -> io::Result<()> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut table = match fs::read_to_string(&self.path) {
+            Ok(text) => text
+                .parse::<toml::Table>()
+                .map_err(|e| invalid(e.to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e),
+        };
+        let sections = [
+            (
+                "solenoid_labels",
+                toml::Value::try_from(&self.labels),
+                self.labels.is_empty(),
+            ),
+            (
+                "group",
+                toml::Value::try_from(&self.groups),
+                self.groups.is_empty(),
+            ),
+            (
+                "interlock",
+                toml::Value::try_from(&self.interlocks),
+                self.interlocks.is_empty(),
+            ),
+        ];
+        for (key, value, empty) in sections {
+            if empty {
+                table.remove(key);
+            } else {
+                table.insert(key.to_string(), value.map_err(|e| invalid(e.to_string()))?);
+            }
+        }
+        let text = toml::to_string(&table).map_err(|e| invalid(e.to_string()))?;
+        let tmp = format!("{}.tmp", self.path);
+        let mut file = File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// The channel (1-16) a `[solenoid_labels]` key names.
+fn label_channel(key: &str) -> Result<u8, ApiError> {
+    match key.parse::<u8>() {
+        Ok(channel @ 1..=16) => Ok(channel),
+        Ok(channel) => Err(ApiError::InvalidChannel(channel)),
+        Err(_) => Err(ApiError::InvalidConfig(format!(
+            "'{}' is not a channel (1-16)",
+            key
+        ))),
+    }
+}
+
+/// GET /config/labels returns the configured labels, keyed by channel.
+#[get("/config/labels")]
+pub fn get_labels(state: &State<AppState>) -> Json<HashMap<String, String>> {
+    Json(state.runtime_config.lock().unwrap().labels.clone())
+}
+
+/// POST /config/labels adds the given labels, replacing those of the same channels.
+#[post("/config/labels", data = "<labels>")]
+pub fn add_labels(
+    labels: SignedJson<HashMap<String, String>>,
+    state: &State<AppState>,
+) -> Result<Json<HashMap<String, String>>, ApiError> {
+    for key in labels.keys() {
+        label_channel(key)?;
+    }
+    let mut runtime = state.runtime_config.lock().unwrap();
+    for (key, label) in labels.into_inner() {
+        // Stored the way `config::solenoid_info` looks them up: "7", not "07".
+        runtime
+            .labels
+            .insert(label_channel(&key)?.to_string(), label);
+    }
+    Ok(Json(runtime.labels.clone()))
+}
+
+/// PUT /config/labels replaces every label; channels left out go back to "Solenoid N".
+#[put("/config/labels", data = "<labels>")]
+pub fn replace_labels(
+    labels: SignedJson<HashMap<String, String>>,
+    state: &State<AppState>,
+) -> Result<Json<HashMap<String, String>>, ApiError> {
+    let labels = labels
+        .into_inner()
+        .into_iter()
+        .map(|(key, label)| Ok((label_channel(&key)?.to_string(), label)))
+        .collect::<Result<HashMap<_, _>, ApiError>>()?;
+    let mut runtime = state.runtime_config.lock().unwrap();
+    runtime.labels = labels;
+    Ok(Json(runtime.labels.clone()))
+}
+
+/// DELETE /config/labels/<channel> removes a channel's label.
+#[delete("/config/labels/<channel>")]
+pub fn delete_label(
+    channel: u8,
+    _auth: Authenticated,
+    state: &State<AppState>,
+) -> Result<Json<HashMap<String, String>>, ApiError> {
+    let key = label_channel(&channel.to_string())?.to_string();
+    let mut runtime = state.runtime_config.lock().unwrap();
+    runtime.labels.remove(&key);
+    Ok(Json(runtime.labels.clone()))
+}
+
+/// GET /config/groups returns the `[[group]]`s.
+#[get("/config/groups")]
+pub fn get_groups(state: &State<AppState>) -> Json<Vec<SolenoidGroup>> {
+    Json(state.runtime_config.lock().unwrap().groups.clone())
+}
+
+/// POST /config/groups adds a group; its name must not be taken (409).
+#[post("/config/groups", data = "<group>")]
+pub fn add_group(
+    group: SignedJson<SolenoidGroup>,
+    state: &State<AppState>,
+) -> Result<Json<Vec<SolenoidGroup>>, ApiError> {
+    group.validate().map_err(ApiError::InvalidConfig)?;
+    let mut runtime = state.runtime_config.lock().unwrap();
+    if runtime.groups.iter().any(|other| other.name == group.name) {
+        return Err(ApiError::DuplicateGroup(group.name.clone()));
+    }
+    runtime.groups.push(group.into_inner());
+    Ok(Json(runtime.groups.clone()))
+}
+
+/// DELETE /config/groups/<name> removes a group.
+#[delete("/config/groups/<name>")]
+pub fn delete_group(
+    name: &str,
+    _auth: Authenticated,
+    state: &State<AppState>,
+) -> Result<Json<Vec<SolenoidGroup>>, ApiError> {
+    let mut runtime = state.runtime_config.lock().unwrap();
+    let index = runtime
+        .groups
+        .iter()
+        .position(|group| group.name == name)
+        .ok_or_else(|| ApiError::UnknownGroup(name.to_string()))?;
+    runtime.groups.remove(index);
+    Ok(Json(runtime.groups.clone()))
+}
+
+/// GET /config/interlocks returns the `[[interlock]]`s, in the order DELETE indexes them.
+#[get("/config/interlocks")]
+pub fn get_interlocks(state: &State<AppState>) -> Json<Vec<Interlock>> {
+    Json(state.runtime_config.lock().unwrap().interlocks.clone())
+}
+
+/// POST /config/interlocks adds an interlock. It applies to the next command, even if both
+/// of its valves are open already.
+#[post("/config/interlocks", data = "<interlock>")]
+pub fn add_interlock(
+    interlock: SignedJson<Interlock>,
+    state: &State<AppState>,
+) -> Result<Json<Vec<Interlock>>, ApiError> {
+    interlock.validate().map_err(ApiError::InvalidConfig)?;
+    let mut runtime = state.runtime_config.lock().unwrap();
+    runtime.interlocks.push(interlock.into_inner());
+    Ok(Json(runtime.interlocks.clone()))
+}
+
+/// DELETE /config/interlocks/<index> removes the interlock at `index` (from 0) of GET
+/// /config/interlocks.
+#[delete("/config/interlocks/<index>")]
+pub fn delete_interlock(
+    index: usize,
+    _auth: Authenticated,
+    state: &State<AppState>,
+) -> Result<Json<Vec<Interlock>>, ApiError> {
+    let mut runtime = state.runtime_config.lock().unwrap();
+    if index >= runtime.interlocks.len() {
+        return Err(ApiError::UnknownInterlock(index));
+    }
+    runtime.interlocks.remove(index);
+    Ok(Json(runtime.interlocks.clone()))
+}
+
+/// POST /config/save writes the current labels, groups and interlocks to the config file.
+#[post("/config/save")]
+pub fn save(_auth: Authenticated, state: &State<AppState>) -> Result<&'static str, ApiError> {
+    let runtime = state.runtime_config.lock().unwrap();
+    runtime
+        .save()
+        .map_err(|e| ApiError::ConfigSaveFailed(format!("{}: {}", runtime.path, e)))?;
+    Ok("SAVED")
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::{Client, LocalResponse};
+    use rocket::serde::json::{json, Value};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::{build_rocket, SerialEndpoints};
+
+    fn client() -> (Client, SerialEndpoints) {
+        let (state, endpoints, ack_rx) = AppState::new(10, None);
+        let client = Client::tracked(build_rocket(state, ack_rx)).expect("valid rocket");
+        (client, endpoints)
+    }
+
+    fn post<'c>(client: &'c Client, uri: &'static str, body: &str) -> LocalResponse<'c> {
+        client
+            .post(uri)
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch()
+    }
+
+    fn json(response: LocalResponse) -> Value {
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().unwrap()
+    }
+
+    #[test]
+    fn labels_are_added_and_deleted() {
+        let (client, _endpoints) = client();
+        let added = json(post(
+            &client,
+            "/config/labels",
+            r#"{"07":"LOX","9":"Fuel"}"#,
+        ));
+        assert_eq!(added, json!({"7": "LOX", "9": "Fuel"}));
+        let deleted = json(client.delete("/config/labels/7").dispatch());
+        assert_eq!(deleted, json!({"9": "Fuel"}));
+        let out_of_range = client.delete("/config/labels/17").dispatch();
+        assert_eq!(out_of_range.status(), Status::BadRequest);
+        let bad = post(&client, "/config/labels", r#"{"0":"Nothing","3":"Vent"}"#);
+        assert_eq!(bad.status(), Status::BadRequest);
+        assert_eq!(
+            json(client.get("/config/labels").dispatch()),
+            json!({"9": "Fuel"})
+        );
+    }
+
+    #[test]
+    fn groups_are_added_and_deleted() {
+        let (client, _endpoints) = client();
+        let purge = r#"{"name":"purge","channels":[2,3]}"#;
+        let added = json(post(&client, "/config/groups", purge));
+        assert_eq!(added, json!([{"name": "purge", "channels": [2, 3]}]));
+        let bad = post(
+            &client,
+            "/config/groups",
+            r#"{"name":"vent","channels":[17]}"#,
+        );
+        assert_eq!(bad.status(), Status::BadRequest);
+        assert_eq!(
+            json(client.delete("/config/groups/purge").dispatch()),
+            json!([])
+        );
+        let unknown = client.delete("/config/groups/purge").dispatch();
+        assert_eq!(unknown.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn interlocks_are_added_and_deleted_by_index() {
+        let (client, _endpoints) = client();
+        json(post(&client, "/config/interlocks", r#"{"prevent":[3,7]}"#));
+        let added = json(post(&client, "/config/interlocks", r#"{"prevent":[4,8]}"#));
+        assert_eq!(added, json!([{"prevent": [3, 7]}, {"prevent": [4, 8]}]));
+        let out_of_range = client.delete("/config/interlocks/2").dispatch();
+        assert_eq!(out_of_range.status(), Status::NotFound);
+        assert_eq!(out_of_range.into_string().unwrap(), "UNKNOWN_INTERLOCK: 2");
+        let deleted = json(client.delete("/config/interlocks/0").dispatch());
+        assert_eq!(deleted, json!([{"prevent": [4, 8]}]));
+    }
+
+    #[test]
+    fn an_interlock_added_at_runtime_is_enforced() {
+        let (client, endpoints) = client();
+        let state = client.rocket().state::<AppState>().unwrap();
+        state.telemetry.write().unwrap().solenoids[2] = true;
+        assert_eq!(client.post("/solenoid/7/1").dispatch().status(), Status::Ok);
+        assert_eq!(endpoints.commands.try_recv().unwrap().text, "s71");
+        state.telemetry.write().unwrap().solenoids[6] = false;
+
+        json(post(&client, "/config/interlocks", r#"{"prevent":[3,7]}"#));
+        let refused = client.post("/solenoid/7/1").dispatch();
+        assert_eq!(refused.status(), Status::Conflict);
+        assert!(endpoints.commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn save_keeps_the_other_settings() {
+        let path = std::env::temp_dir().join(format!("gcs_runtime_{}.toml", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        fs::write(
+            &path,
+            "[serial]\nbaud = 9600\n\n[[interlock]]\nprevent = [1, 2]\n",
+        )
+        .unwrap();
+        let runtime = RuntimeConfig {
+            labels: HashMap::from([("7".to_string(), "LOX Main Valve".to_string())]),
+            groups: vec![SolenoidGroup {
+                name: "purge".to_string(),
+                channels: vec![2, 3],
+            }],
+            path: path.clone(),
+            ..RuntimeConfig::default()
+        };
+        runtime.save().unwrap();
+        let config = Config::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.serial.baud, 9600);
+        assert_eq!(config.solenoid_labels["7"], "LOX Main Valve");
+        assert_eq!(config.groups[0].channels, [2, 3]);
+        assert!(config.interlocks.is_empty());
+    }
+}
@@ -0,0 +1,633 @@
+// src/safety.rs
+
+//! Protections for the valve hardware that go beyond validating a single command.
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::arm_audit::{ArmAction, SharedArmAudit};
+use crate::command::CommandBuilder;
+use crate::flight_log::EventType;
+use crate::{SharedTelemetry, Telemetry};
+
+/// Default minimum time between two commands to the same solenoid.
+pub const DEFAULT_MIN_INTERVAL_MS: u64 = 500;
+
+/// Default time without new telemetry after which the watchdog disarms.
+pub const DEFAULT_WATCHDOG_TIMEOUT_S: u64 = 5;
+
+/// Default time POST /arm/confirm has after POST /arm/intent.
+pub const DEFAULT_ARM_CONFIRM_WINDOW_MS: u64 = 5000;
+
+/// How often the watchdog thread looks at the telemetry.
+const WATCHDOG_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the open-duration thread looks at the timers.
+const OPEN_TIMER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default share of `DUTY_WINDOW` a solenoid may be on before it is refused.
+pub const DEFAULT_MAX_DUTY_PCT: f32 = 80.0;
+
+/// The sliding window duty cycles are measured over.
+const DUTY_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default battery drop, in volts per second, that counts as a short or load dump.
+pub const DEFAULT_MAX_DRAIN_RATE_V_PER_S: f32 = 1.0;
+
+/// Readings at a normal rate that clear the battery drain alert.
+const DRAIN_CLEAR_READINGS: u32 = 3;
+
+/// Refuses commands to a solenoid that was actuated less than `min_interval` ago,
+/// so a script (or a stuck button) can't chatter a valve.
+pub struct RateLimiter {
+    min_interval: Duration,
+    /// When each channel (index = channel - 1) was last actuated; `None` if never.
+    last_actuation: [Option<Instant>; 16],
+}
+
+impl RateLimiter {
+    /// A limiter with the given minimum interval; zero disables it.
+    pub fn new(min_interval: Duration) -> Self {
+        RateLimiter {
+            min_interval,
+            last_actuation: [None; 16],
+        }
+    }
+
+    /// Checks every channel (1..=16) against the limit. If all are allowed, records them as
+    /// actuated at `now` and returns `Ok`; otherwise records nothing and returns the
+    /// channels that are still inside their interval.
+    pub fn try_actuate(&mut self, channels: &[u8], now: Instant) -> Result<(), Vec<u8>> {
+        let limited: Vec<u8> = channels
+            .iter()
+            .copied()
+            .filter(|&ch| {
+                self.last_actuation[ch as usize - 1]
+                    .is_some_and(|last| now.saturating_duration_since(last) < self.min_interval)
+            })
+            .collect();
+        if !limited.is_empty() {
+            return Err(limited);
+        }
+        for &ch in channels {
+            self.last_actuation[ch as usize - 1] = Some(now);
+        }
+        Ok(())
+    }
+}
+
+/// Two-step arming: POST /arm/intent issues a one-time token, and only POST /arm/confirm
+/// with that token inside `window` arms. Any confirm attempt uses the intent up.
+pub struct ArmIntent {
+    window: Duration,
+    /// The outstanding token and when it was issued.
+    pending: Option<(String, Instant)>,
+}
+
+/// Why POST /arm/confirm was refused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArmConfirmError {
+    /// There is no outstanding intent.
+    NoIntent,
+    /// The intent is older than the window.
+    Expired,
+    /// Not the token the intent was issued with.
+    WrongToken,
+}
+
+impl ArmIntent {
+    pub fn new(window: Duration) -> Self {
+        ArmIntent {
+            window,
+            pending: None,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Records an intent to arm at `now` and returns its token. Replaces any earlier intent.
+    pub fn issue(&mut self, now: Instant) -> String {
+        let token = arm_token();
+        self.pending = Some((token.clone(), now));
+        token
+    }
+
+    /// Checks `token` against the outstanding intent and clears it, whatever the outcome.
+    pub fn confirm(&mut self, token: &str, now: Instant) -> Result<(), ArmConfirmError> {
+        let (expected, issued) = self.pending.take().ok_or(ArmConfirmError::NoIntent)?;
+        if now.saturating_duration_since(issued) > self.window {
+            Err(ArmConfirmError::Expired)
+        } else if token != expected {
+            Err(ArmConfirmError::WrongToken)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 128 bits from the standard library's randomly keyed hasher, as hex.
+fn arm_token() -> String {
+    (0..2u64)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// Limits how long a solenoid may stay on (`[solenoid_max_open_ms]`). A channel's timer
+/// starts with the first "s<ch>1" written to the port and stops with "s<ch>0"; repeating
+/// "s<ch>1" does not restart it. Channels without a limit have no timer.
+#[derive(Debug, Default)]
+pub struct OpenTimers {
+    /// Per channel (index = channel - 1).
+    limits: [Option<Duration>; 16],
+    opened_at: [Option<Instant>; 16],
+}
+
+/// The timers, shared between the serial loop (which starts and stops them), the thread
+/// enforcing them and GET /solenoid/timers.
+pub type SharedOpenTimers = Arc<Mutex<OpenTimers>>;
+
+impl OpenTimers {
+    pub fn new(limits: [Option<Duration>; 16]) -> Self {
+        OpenTimers {
+            limits,
+            opened_at: [None; 16],
+        }
+    }
+
+    /// Whether any channel has a limit.
+    pub fn any_limits(&self) -> bool {
+        self.limits.iter().any(Option::is_some)
+    }
+
+    /// Starts and stops timers for the solenoid commands in `text` (one per line), written
+    /// at `now`.
+    pub fn record(&mut self, text: &str, now: Instant) {
+        for event in text.lines().filter_map(EventType::from_command) {
+            if let EventType::Solenoid { channel: channel @ 1..=16, state } = event {
+                let i = channel as usize - 1;
+                if self.limits[i].is_none() {
+                    continue;
+                }
+                self.opened_at[i] = match state {
+                    true => self.opened_at[i].or(Some(now)),
+                    false => None,
+                };
+            }
+        }
+    }
+
+    /// The time left for each timed channel that is on, by channel; zero once expired.
+    pub fn remaining(&self, now: Instant) -> Vec<(u8, Duration)> {
+        self.limits
+            .iter()
+            .zip(&self.opened_at)
+            .zip(1..=16u8)
+            .filter_map(|((limit, opened_at), channel)| {
+                let elapsed = now.saturating_duration_since((*opened_at)?);
+                Some((channel, limit.unwrap_or_default().saturating_sub(elapsed)))
+            })
+            .collect()
+    }
+}
+
+/// Starts the thread enforcing `timers`: every `OPEN_TIMER_POLL_INTERVAL` it queues
+/// "s<ch>0" on the priority channel for each channel past its limit. The timer stops once
+/// the serial loop writes the close, so a close that could not be queued (the channel holds
+/// one batch) is retried on the next poll.
+pub fn spawn_open_timers(timers: SharedOpenTimers, emergency_tx: mpsc::SyncSender<String>) {
+    thread::spawn(move || loop {
+        thread::sleep(OPEN_TIMER_POLL_INTERVAL);
+        let now = Instant::now();
+        let expired: Vec<u8> = timers
+            .lock()
+            .unwrap()
+            .remaining(now)
+            .into_iter()
+            .filter(|(_, left)| left.is_zero())
+            .map(|(channel, _)| channel)
+            .collect();
+        if expired.is_empty() {
+            continue;
+        }
+        let mut builder = CommandBuilder::new();
+        for &channel in &expired {
+            builder.solenoid(channel, false).expect("valid channel");
+        }
+        let batch = builder.build_lines();
+        match emergency_tx.try_send(batch) {
+            Ok(()) => warn!(channels = ?expired, "Solenoids on for too long; closing"),
+            Err(mpsc::TrySendError::Full(_)) => {}
+            Err(mpsc::TrySendError::Disconnected(_)) => return,
+        }
+    });
+}
+
+/// Keeps solenoid coils from overheating: measures how much of the last `DUTY_WINDOW` each
+/// solenoid was on (energized, whatever the valve direction) from the commands written to
+/// the port. A channel above `max_duty_pct` may not be switched on (429).
+#[derive(Debug)]
+pub struct DutyCycleTracker {
+    max_duty_pct: f32,
+    /// Solenoid commands written within the window: when, channel (1-16) and state.
+    events: VecDeque<(Instant, u8, bool)>,
+    /// Which solenoids were on before the first event in `events`.
+    on_before: [bool; 16],
+}
+
+/// The tracker, shared between the serial loop (which records) and the handlers.
+pub type SharedDutyCycle = Arc<Mutex<DutyCycleTracker>>;
+
+impl Default for DutyCycleTracker {
+    fn default() -> Self {
+        DutyCycleTracker::new(DEFAULT_MAX_DUTY_PCT)
+    }
+}
+
+impl DutyCycleTracker {
+    pub fn new(max_duty_pct: f32) -> Self {
+        DutyCycleTracker {
+            max_duty_pct,
+            events: VecDeque::new(),
+            on_before: [false; 16],
+        }
+    }
+
+    /// Records the solenoid commands in `text` (one per line), written at `now`.
+    pub fn record(&mut self, text: &str, now: Instant) {
+        for event in text.lines().filter_map(EventType::from_command) {
+            if let EventType::Solenoid { channel: channel @ 1..=16, state } = event {
+                self.events.push_back((now, channel, state));
+            }
+        }
+        self.forget_before(now);
+    }
+
+    /// Folds the events that are out of the window at `now` into `on_before`.
+    fn forget_before(&mut self, now: Instant) {
+        let Some(start) = now.checked_sub(DUTY_WINDOW) else { return };
+        while let Some(&(at, channel, state)) = self.events.front() {
+            if at > start {
+                break;
+            }
+            self.on_before[channel as usize - 1] = state;
+            self.events.pop_front();
+        }
+    }
+
+    /// Each channel's on time as a percentage of the window ending at `now`.
+    pub fn duty(&mut self, now: Instant) -> [f32; 16] {
+        self.forget_before(now);
+        let start = now.checked_sub(DUTY_WINDOW);
+        let mut on_since: [Option<Instant>; 16] =
+            self.on_before.map(|on| on.then(|| start.unwrap_or(now)));
+        let mut on_time = [Duration::ZERO; 16];
+        for &(at, channel, state) in &self.events {
+            let i = channel as usize - 1;
+            match (on_since[i], state) {
+                (None, true) => on_since[i] = Some(at),
+                (Some(since), false) => {
+                    on_time[i] += at.saturating_duration_since(since);
+                    on_since[i] = None;
+                }
+                _ => {}
+            }
+        }
+        let mut duty = [0.0; 16];
+        for i in 0..16 {
+            if let Some(since) = on_since[i] {
+                on_time[i] += now.saturating_duration_since(since);
+            }
+            duty[i] = (on_time[i].as_secs_f32() / DUTY_WINDOW.as_secs_f32() * 100.0).min(100.0);
+        }
+        duty
+    }
+
+    /// Whether switching on `channel` (1-16) at `now` is allowed.
+    pub fn may_switch_on(&mut self, channel: u8, now: Instant) -> bool {
+        self.duty(now)[channel as usize - 1] <= self.max_duty_pct
+    }
+}
+
+/// Detects lost telemetry: the Arduino `timestamp` advances with every sample, so a
+/// timestamp that hasn't changed for `timeout` means nothing has been received. It only
+/// starts watching once the first sample has arrived, so a slow board start does not trip it.
+pub struct TelemetryWatchdog {
+    timeout: Duration,
+    /// `None` until the first sample.
+    last_timestamp: Option<u64>,
+    /// When `last_timestamp` last changed (or the watchdog was reset).
+    last_change: Instant,
+}
+
+impl TelemetryWatchdog {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        TelemetryWatchdog {
+            timeout,
+            last_timestamp: None,
+            last_change: now,
+        }
+    }
+
+    /// Feeds the timestamp of the latest telemetry (0 before the first sample); returns
+    /// whether it is stale.
+    pub fn is_stale(&mut self, timestamp: u64, now: Instant) -> bool {
+        if self.last_timestamp.is_none() && timestamp == 0 {
+            return false;
+        }
+        if self.last_timestamp != Some(timestamp) {
+            self.last_timestamp = Some(timestamp);
+            self.last_change = now;
+        }
+        now.saturating_duration_since(self.last_change) > self.timeout
+    }
+
+    /// Restarts the timeout from `now`, e.g. after the operator re-armed.
+    pub fn reset(&mut self, now: Instant) {
+        self.last_change = now;
+    }
+}
+
+/// Starts the watchdog thread. After the first sample, when no new telemetry has arrived for
+/// `timeout` it queues a disarm on the priority channel and sets `tripped`. It stays tripped
+/// (and quiet) until something clears `tripped`, which restarts the timeout. This runs
+/// independently of the serial loop, so it also fires when the port stops delivering bytes
+/// at all. Each disarm is added to `audit` (with no source IP) for `board`, and followed by
+/// `on_trip` (which aborts the countdown).
+pub fn spawn_watchdog(
+    timeout: Duration,
+    telemetry: SharedTelemetry,
+    emergency_tx: mpsc::SyncSender<String>,
+    tripped: Arc<AtomicBool>,
+    audit: SharedArmAudit,
+    board: u8,
+    on_trip: impl Fn() + Send + 'static,
+) {
+    thread::spawn(move || {
+        let mut watchdog = TelemetryWatchdog::new(timeout, Instant::now());
+        let mut was_tripped = false;
+        loop {
+            thread::sleep(WATCHDOG_POLL_INTERVAL);
+            let now = Instant::now();
+            let is_tripped = tripped.load(Ordering::SeqCst);
+            if was_tripped && !is_tripped {
+                watchdog.reset(now);
+            }
+            was_tripped = is_tripped;
+            let timestamp = telemetry.read().unwrap().timestamp;
+            if watchdog.is_stale(timestamp, now) && !is_tripped {
+                warn!(timeout_s = timeout.as_secs(), "Watchdog: no telemetry, disarming");
+                tripped.store(true, Ordering::SeqCst);
+                was_tripped = true;
+                // A full channel means an emergency stop (which disarms) is already queued.
+                if let Err(mpsc::TrySendError::Disconnected(_)) =
+                    emergency_tx.try_send(CommandBuilder::new().disarm().build_lines())
+                {
+                    return;
+                }
+                audit.lock().unwrap().record(ArmAction::Disarm, None, board);
+                on_trip();
+            }
+        }
+    });
+}
+
+/// Detects a battery draining fast, as with a short circuit or a load dump: the voltage
+/// falling by more than `max_drain_rate` volts per second between two readings. The alert
+/// clears after `DRAIN_CLEAR_READINGS` readings in a row at a normal rate.
+pub struct BatteryRateMonitor {
+    max_drain_rate: f32,
+    /// Arduino timestamp (ms) and voltage of the previous reading.
+    last: Option<(u64, f32)>,
+    draining: bool,
+    /// Normal readings since the last fast drop, while `draining`.
+    normal_readings: u32,
+}
+
+impl BatteryRateMonitor {
+    pub fn new(max_drain_rate_v_per_s: f32) -> Self {
+        BatteryRateMonitor {
+            max_drain_rate: max_drain_rate_v_per_s,
+            last: None,
+            draining: false,
+            normal_readings: 0,
+        }
+    }
+
+    /// Feeds a reading. Returns the new state when the alert is raised (`true`) or
+    /// cleared (`false`).
+    pub fn update(&mut self, timestamp: u64, battery: f32) -> Option<bool> {
+        let last = self.last.replace((timestamp, battery));
+        // A repeated sample, or a timestamp that went back because the Arduino restarted.
+        let (last_ts, last_battery) = last.filter(|&(ts, _)| ts < timestamp)?;
+        let dt = (timestamp - last_ts) as f32 / 1000.0;
+        let drain_rate = (last_battery - battery) / dt;
+        if drain_rate > self.max_drain_rate {
+            self.normal_readings = 0;
+            if self.draining {
+                return None;
+            }
+            self.draining = true;
+            return Some(true);
+        }
+        if self.draining {
+            self.normal_readings += 1;
+            if self.normal_readings >= DRAIN_CLEAR_READINGS {
+                self.draining = false;
+                self.normal_readings = 0;
+                return Some(false);
+            }
+        }
+        None
+    }
+}
+
+/// Starts the battery drain thread, which feeds every telemetry sample to a
+/// `BatteryRateMonitor` and keeps `draining` set while its alert is raised.
+pub fn spawn_battery_rate_monitor(
+    max_drain_rate_v_per_s: f32,
+    mut telemetry: broadcast::Receiver<Telemetry>,
+    draining: Arc<AtomicBool>,
+) {
+    let mut monitor = BatteryRateMonitor::new(max_drain_rate_v_per_s);
+    thread::spawn(move || loop {
+        let tel = match telemetry.blocking_recv() {
+            Ok(tel) => tel,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        match monitor.update(tel.timestamp, tel.battery) {
+            Some(true) => warn!(battery = tel.battery, max_drain_rate_v_per_s,
+                                "Battery draining fast; short circuit or load dump?"),
+            Some(false) => warn!(battery = tel.battery, "Battery drain back to normal"),
+            None => continue,
+        }
+        draining.store(monitor.draining, Ordering::SeqCst);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_channel_separately() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(500));
+        let t0 = Instant::now();
+        assert!(limiter.try_actuate(&[3], t0).is_ok());
+        assert!(limiter.try_actuate(&[4], t0).is_ok());
+        assert_eq!(limiter.try_actuate(&[3], t0 + Duration::from_millis(499)), Err(vec![3]));
+        assert!(limiter.try_actuate(&[3], t0 + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn arm_intent_is_used_up_by_any_confirm() {
+        let mut intent = ArmIntent::new(Duration::from_secs(5));
+        let t0 = Instant::now();
+        assert_eq!(intent.confirm("x", t0), Err(ArmConfirmError::NoIntent));
+
+        let token = intent.issue(t0);
+        assert_eq!(token.len(), 32);
+        assert_eq!(intent.confirm(&token, t0 + Duration::from_secs(1)), Ok(()));
+        assert_eq!(intent.confirm(&token, t0), Err(ArmConfirmError::NoIntent));
+
+        let token = intent.issue(t0);
+        assert_eq!(intent.confirm("wrong", t0), Err(ArmConfirmError::WrongToken));
+        assert_eq!(intent.confirm(&token, t0), Err(ArmConfirmError::NoIntent));
+
+        let token = intent.issue(t0);
+        let late = t0 + Duration::from_millis(5001);
+        assert_eq!(intent.confirm(&token, late), Err(ArmConfirmError::Expired));
+        assert_ne!(intent.issue(t0), token);
+    }
+
+    #[test]
+    fn rejected_batch_records_nothing() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(500));
+        let t0 = Instant::now();
+        limiter.try_actuate(&[1], t0).unwrap();
+        let t1 = t0 + Duration::from_millis(100);
+        assert_eq!(limiter.try_actuate(&[1, 2], t1), Err(vec![1]));
+        // Channel 2 was not actuated by the rejected batch.
+        assert!(limiter.try_actuate(&[2], t1).is_ok());
+    }
+
+    #[test]
+    fn zero_interval_disables_the_limit() {
+        let mut limiter = RateLimiter::new(Duration::ZERO);
+        let t0 = Instant::now();
+        assert!(limiter.try_actuate(&[5], t0).is_ok());
+        assert!(limiter.try_actuate(&[5], t0).is_ok());
+    }
+
+    #[test]
+    fn open_timers_run_from_the_first_open_to_the_close() {
+        let mut limits = [None; 16];
+        limits[4] = Some(Duration::from_millis(1000));
+        let mut timers = OpenTimers::new(limits);
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        timers.record("s51\ns61", t0);
+        // Channel 6 has no limit.
+        assert_eq!(timers.remaining(ms(400)), vec![(5, Duration::from_millis(600))]);
+        timers.record("s51", ms(500));
+        assert_eq!(timers.remaining(ms(1200)), vec![(5, Duration::ZERO)]);
+        timers.record("s50", ms(1300));
+        assert!(timers.remaining(ms(1300)).is_empty());
+    }
+
+    #[test]
+    fn duty_cycle_is_on_time_over_the_last_minute() {
+        let mut tracker = DutyCycleTracker::new(80.0);
+        let t0 = Instant::now();
+        let s = |n| t0 + Duration::from_secs(n);
+        tracker.record("s31\ns51", t0);
+        tracker.record("s30", s(30));
+        tracker.record("s31", s(45));
+        let duty = tracker.duty(s(60));
+        assert_eq!((duty[2], duty[4], duty[0]), (75.0, 100.0, 0.0));
+        assert!(tracker.may_switch_on(3, s(60)));
+        assert!(!tracker.may_switch_on(5, s(60)));
+        // Switching off is recorded like any command, and the window slides on.
+        tracker.record("s50", s(60));
+        assert_eq!(tracker.duty(s(90))[4], 50.0);
+        // Channel 3 has been on since 45 s, channel 5 off since 60 s.
+        let duty = tracker.duty(s(150));
+        assert_eq!((duty[2], duty[4]), (100.0, 0.0));
+        assert!(tracker.may_switch_on(5, s(150)));
+    }
+
+    #[test]
+    fn expired_solenoids_are_closed_on_the_priority_channel() {
+        let mut limits = [None; 16];
+        limits[2] = Some(Duration::from_millis(50));
+        limits[3] = Some(Duration::from_millis(50));
+        let timers = Arc::new(Mutex::new(OpenTimers::new(limits)));
+        timers.lock().unwrap().record("s31\ns41", Instant::now());
+        let (tx, rx) = mpsc::sync_channel(1);
+        spawn_open_timers(timers, tx);
+        let batch = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(batch, "s30\ns40\n");
+    }
+
+    #[test]
+    fn watchdog_goes_stale_when_the_timestamp_stops_advancing() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut watchdog = TelemetryWatchdog::new(Duration::from_secs(5), t0);
+        assert!(!watchdog.is_stale(100, ms(1000)));
+        assert!(!watchdog.is_stale(200, ms(4000)));
+        assert!(!watchdog.is_stale(200, ms(9000)));
+        assert!(watchdog.is_stale(200, ms(9001)));
+        watchdog.reset(ms(10_000));
+        assert!(!watchdog.is_stale(200, ms(12_000)));
+        assert!(!watchdog.is_stale(300, ms(16_000)));
+    }
+
+    #[test]
+    fn watchdog_waits_for_the_first_frame() {
+        let t0 = Instant::now();
+        let s = |n| t0 + Duration::from_secs(n);
+        let mut watchdog = TelemetryWatchdog::new(Duration::from_secs(5), t0);
+        assert!(!watchdog.is_stale(0, s(60)));
+        // The timeout runs from the first frame, not from startup.
+        assert!(!watchdog.is_stale(100, s(61)));
+        assert!(!watchdog.is_stale(100, s(66)));
+        assert!(watchdog.is_stale(100, s(67)));
+    }
+
+    #[test]
+    fn battery_drain_alert_clears_after_three_normal_readings() {
+        let mut monitor = BatteryRateMonitor::new(1.0);
+        assert_eq!(monitor.update(0, 12.6), None);
+        // 0.05 V in 100 ms is 0.5 V/s.
+        assert_eq!(monitor.update(100, 12.55), None);
+        // 0.2 V in 100 ms is 2 V/s.
+        assert_eq!(monitor.update(200, 12.35), Some(true));
+        assert_eq!(monitor.update(300, 12.2), None);
+        assert_eq!(monitor.update(400, 12.2), None);
+        assert_eq!(monitor.update(500, 12.2), None);
+        // Another fast drop starts the count again.
+        assert_eq!(monitor.update(600, 12.0), None);
+        assert_eq!(monitor.update(700, 12.0), None);
+        assert_eq!(monitor.update(800, 12.1), None);
+        assert_eq!(monitor.update(800, 5.0), None);
+        assert_eq!(monitor.update(900, 12.1), Some(false));
+        // After a restart the timestamps start over; no rate across the gap.
+        assert_eq!(monitor.update(50, 3.0), None);
+        assert_eq!(monitor.update(150, 2.95), None);
+    }
+}
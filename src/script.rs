@@ -0,0 +1,384 @@
+// src/script.rs
+
+//! Test scripts: automated sequences written as plain text, one statement per line, which
+//! test engineers can keep in files next to the test procedure (POST /script/run).
+//!
+//! ```text
+//! # Lines starting with '#' and blank lines are skipped.
+//! ARM
+//! WAIT 500          # milliseconds
+//! ARMED_CHECK       # fails the script unless the latest telemetry reports armed
+//! OPEN_SOL 3
+//! WAIT 2000
+//! CLOSE_SOL 3
+//! DISARM
+//! ```
+//!
+//! Keywords are case-insensitive. OPEN_SOL and CLOSE_SOL open and close the valve, so a
+//! normally open one is de-energized to open it (see `[solenoid_directions]`). WAIT is at most
+//! `MAX_WAIT_MS`. The whole script is parsed before it starts; it then runs on its own thread
+//! like a sequence, with its valve commands checked against the interlocks and duty cycles
+//! and queued on the normal command channel. ARM and DISARM go through the arm lifecycle and
+//! audit like POST /arm and /disarm, so a script that arms cannot run with
+//! `require_two_step`. POST /script/abort (and POST /emergency_stop) stops it, even in the
+//! middle of a WAIT.
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
+
+use crate::auth::{Authenticated, SignedJson};
+use crate::command::CommandBuilder;
+use crate::command_queue::CommandSender;
+use crate::config::SolenoidInfo;
+use crate::error::ApiError;
+use crate::interlock::SolenoidGuard;
+use crate::{AppState, ArmPath, QueuedCommand, SharedTelemetry};
+
+/// The longest WAIT a script may have: ten minutes.
+pub const MAX_WAIT_MS: u64 = 10 * 60 * 1000;
+
+/// One statement of a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Wait(Duration),
+    OpenSol(u8),
+    CloseSol(u8),
+    Arm,
+    Disarm,
+    /// Stop with an error unless the latest telemetry reports armed.
+    ArmedCheck,
+}
+
+/// A statement and the (1-based) line it is on.
+pub type Line = (usize, Statement);
+
+/// Parses `script`. A statement that does not parse fails the whole script, with its line
+/// number and what is wrong with it.
+pub fn parse(script: &str) -> Result<Vec<Line>, (usize, String)> {
+    let mut lines = Vec::new();
+    for (index, text) in script.lines().enumerate() {
+        let number = index + 1;
+        let text = text.split('#').next().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(|reason| (number, reason))?;
+        lines.push((number, statement));
+    }
+    Ok(lines)
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let mut words = text.split_whitespace();
+    let keyword = words.next().unwrap_or("").to_ascii_uppercase();
+    let argument = words.next();
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected '{}'", extra));
+    }
+    let channel = || -> Result<u8, String> {
+        let arg = argument.ok_or_else(|| format!("{} needs a channel", keyword))?;
+        match arg.parse::<u8>() {
+            Ok(channel @ 1..=16) => Ok(channel),
+            _ => Err(format!("invalid channel '{}' (expected 1-16)", arg)),
+        }
+    };
+    let no_argument = |statement: Statement| match argument {
+        Some(arg) => Err(format!("{} takes no argument, got '{}'", keyword, arg)),
+        None => Ok(statement),
+    };
+    match keyword.as_str() {
+        "WAIT" => {
+            let arg = argument.ok_or("WAIT needs a duration in milliseconds")?;
+            let ms = arg
+                .parse::<u64>()
+                .map_err(|_| format!("invalid duration '{}' (expected milliseconds)", arg))?;
+            if ms > MAX_WAIT_MS {
+                return Err(format!("WAIT {} is longer than {} ms", ms, MAX_WAIT_MS));
+            }
+            Ok(Statement::Wait(Duration::from_millis(ms)))
+        }
+        "OPEN_SOL" => Ok(Statement::OpenSol(channel()?)),
+        "CLOSE_SOL" => Ok(Statement::CloseSol(channel()?)),
+        "ARM" => no_argument(Statement::Arm),
+        "DISARM" => no_argument(Statement::Disarm),
+        "ARMED_CHECK" => no_argument(Statement::ArmedCheck),
+        _ => Err(format!("unknown statement '{}'", keyword)),
+    }
+}
+
+/// What the runner thread does for a statement.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Wait(Duration),
+    Send(String),
+    Arm,
+    Disarm,
+    ArmedCheck,
+}
+
+impl Step {
+    /// The step for `statement`, translating valve states with `info`.
+    fn of(statement: &Statement, info: &HashMap<u8, SolenoidInfo>) -> Step {
+        let valve = |channel: u8, open: bool| {
+            let direction = info.get(&channel).map(|i| i.direction).unwrap_or_default();
+            // Channels were validated by the parser.
+            let mut builder = CommandBuilder::new();
+            builder
+                .solenoid(channel, direction.energized(open))
+                .expect("valid channel");
+            Step::Send(builder.build_joined())
+        };
+        match *statement {
+            Statement::Wait(duration) => Step::Wait(duration),
+            Statement::OpenSol(channel) => valve(channel, true),
+            Statement::CloseSol(channel) => valve(channel, false),
+            Statement::Arm => Step::Arm,
+            Statement::Disarm => Step::Disarm,
+            Statement::ArmedCheck => Step::ArmedCheck,
+        }
+    }
+}
+
+/// Progress of the most recently started script, as reported by GET /script/status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", tag = "state", rename_all = "snake_case")]
+pub enum ScriptStatus {
+    /// No script has been run yet.
+    Idle,
+    /// Executing the statement on `line`.
+    Running {
+        line: usize,
+    },
+    Done,
+    Failed {
+        line: usize,
+        reason: String,
+    },
+    /// Stopped by POST /script/abort before the statement on `line`.
+    Aborted {
+        line: usize,
+    },
+}
+
+/// Runs at most one script at a time and tracks its status.
+pub struct ScriptRunner {
+    status: Arc<Mutex<ScriptStatus>>,
+    /// Wakes the running script's thread to abort it.
+    abort_tx: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl Default for ScriptRunner {
+    fn default() -> Self {
+        ScriptRunner {
+            status: Arc::new(Mutex::new(ScriptStatus::Idle)),
+            abort_tx: Mutex::new(None),
+        }
+    }
+}
+
+impl ScriptRunner {
+    pub fn status(&self) -> ScriptStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Starts running `steps` on a background thread, checking each valve command with
+    /// `guard` and arming and disarming through `arm`. Returns `false` without doing anything
+    /// if a script is already running.
+    fn start(
+        &self,
+        steps: Vec<(usize, Step)>,
+        command_tx: CommandSender,
+        telemetry: SharedTelemetry,
+        guard: SolenoidGuard,
+        arm: ArmPath,
+    ) -> bool {
+        let mut abort_slot = self.abort_tx.lock().unwrap();
+        {
+            let mut status = self.status.lock().unwrap();
+            if matches!(*status, ScriptStatus::Running { .. }) {
+                return false;
+            }
+            let line = steps.first().map_or(0, |&(line, _)| line);
+            *status = ScriptStatus::Running { line };
+        }
+        let (abort_tx, abort_rx) = mpsc::channel::<()>();
+        *abort_slot = Some(abort_tx);
+
+        let status = self.status.clone();
+        thread::spawn(move || {
+            let set_status = |s: ScriptStatus| *status.lock().unwrap() = s;
+            for (line, step) in steps {
+                set_status(ScriptStatus::Running { line });
+                // Sleep through a WAIT, unless an abort arrives first; check for one before
+                // every other statement.
+                let pause = match step {
+                    Step::Wait(duration) => duration,
+                    _ => Duration::ZERO,
+                };
+                if abort_rx.recv_timeout(pause) != Err(mpsc::RecvTimeoutError::Timeout) {
+                    set_status(ScriptStatus::Aborted { line });
+                    return;
+                }
+                let failure = match step {
+                    Step::Wait(_) => None,
+                    Step::Arm => arm.arm(&command_tx).err().map(|e| e.body()),
+                    Step::Disarm => arm.disarm(&command_tx).err().map(|e| e.body()),
+                    Step::Send(command) => match guard.check(&command) {
+                        Err(e) => Some(e.body()),
+                        Ok(()) => command_tx
+                            .send(QueuedCommand::immediate(command))
+                            .err()
+                            .map(|_| "the serial loop is not running".to_string()),
+                    },
+                    Step::ArmedCheck => {
+                        let armed = telemetry.read().unwrap().armed;
+                        (!armed).then(|| "not armed".to_string())
+                    }
+                };
+                if let Some(reason) = failure {
+                    set_status(ScriptStatus::Failed { line, reason });
+                    return;
+                }
+            }
+            set_status(ScriptStatus::Done);
+        });
+        true
+    }
+
+    /// Aborts the running script, if any. Returns whether there was one to abort.
+    pub fn abort(&self) -> bool {
+        let abort_tx = self.abort_tx.lock().unwrap().take();
+        match abort_tx {
+            Some(tx) if matches!(self.status(), ScriptStatus::Running { .. }) => {
+                tx.send(()).is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Body of POST /script/run.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScriptRequest {
+    script: String,
+}
+
+/// POST /script/run parses a script and starts running it; only one script may run at a
+/// time. Nothing is sent if any line fails to parse. A script with an ARM is a 403 with
+/// `require_two_step`.
+#[post("/script/run", data = "<request>")]
+pub fn run(
+    request: SignedJson<ScriptRequest>,
+    state: &State<AppState>,
+) -> Result<&'static str, ApiError> {
+    let lines =
+        parse(&request.script).map_err(|(line, reason)| ApiError::InvalidScript(line, reason))?;
+    let arm = state.arm_path();
+    if arm.require_two_step && lines.iter().any(|(_, s)| *s == Statement::Arm) {
+        return Err(ApiError::TwoStepArmRequired);
+    }
+    let info = state.runtime_config.lock().unwrap().solenoid_info();
+    let steps = lines
+        .iter()
+        .map(|(line, statement)| (*line, Step::of(statement, &info)))
+        .collect();
+    let telemetry = state.telemetry.clone();
+    let guard = state.solenoid_guard();
+    if state
+        .script
+        .start(steps, state.command_tx.clone(), telemetry, guard, arm)
+    {
+        Ok("OK")
+    } else {
+        Err(ApiError::ScriptAlreadyRunning)
+    }
+}
+
+/// POST /script/abort stops the running script before its next statement, or in the middle
+/// of a WAIT.
+#[post("/script/abort")]
+pub fn abort(_auth: Authenticated, state: &State<AppState>) -> &'static str {
+    if state.script.abort() {
+        "ABORTED"
+    } else {
+        "NOT_RUNNING"
+    }
+}
+
+/// GET /script/status reports the line the script is on, or how it ended.
+#[get("/script/status")]
+pub fn status(state: &State<AppState>) -> Json<ScriptStatus> {
+    Json(state.script.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolenoidDirection;
+
+    #[test]
+    fn parses_statements_with_line_numbers() {
+        let script = "# leak check\nWAIT 500\n\nopen_sol 3  # main valve\nCLOSE_SOL 16\n\
+                      ARM\nARMED_CHECK\nDISARM\n";
+        assert_eq!(
+            parse(script),
+            Ok(vec![
+                (2, Statement::Wait(Duration::from_millis(500))),
+                (4, Statement::OpenSol(3)),
+                (5, Statement::CloseSol(16)),
+                (6, Statement::Arm),
+                (7, Statement::ArmedCheck),
+                (8, Statement::Disarm),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_the_first_bad_line() {
+        let error = |script: &str| parse(script).unwrap_err();
+        assert_eq!(
+            error("ARM\nOPEN_SOL 17"),
+            (2, "invalid channel '17' (expected 1-16)".to_string())
+        );
+        assert_eq!(
+            error("WAIT soon"),
+            (
+                1,
+                "invalid duration 'soon' (expected milliseconds)".to_string()
+            )
+        );
+        assert_eq!(
+            error("\nCLOSE_SOL"),
+            (2, "CLOSE_SOL needs a channel".to_string())
+        );
+        assert_eq!(
+            error("DISARM now"),
+            (1, "DISARM takes no argument, got 'now'".to_string())
+        );
+        assert_eq!(
+            error("WAIT 18446744073709551615"),
+            (
+                1,
+                "WAIT 18446744073709551615 is longer than 600000 ms".to_string()
+            )
+        );
+        assert_eq!(error("WAIT 1 2"), (1, "unexpected '2'".to_string()));
+        assert_eq!(error("FIRE 1"), (1, "unknown statement 'FIRE'".to_string()));
+    }
+
+    #[test]
+    fn valve_statements_follow_the_solenoid_direction() {
+        let mut info = crate::config::solenoid_info(&HashMap::new(), &HashMap::new());
+        info.get_mut(&4).unwrap().direction = SolenoidDirection::NormallyOpen;
+        let send = |command: &str| Step::Send(command.to_string());
+        assert_eq!(Step::of(&Statement::OpenSol(3), &info), send("s31"));
+        assert_eq!(Step::of(&Statement::OpenSol(4), &info), send("s40"));
+        assert_eq!(Step::of(&Statement::CloseSol(4), &info), send("s41"));
+    }
+}
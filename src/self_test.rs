@@ -0,0 +1,189 @@
+// src/self_test.rs
+
+//! A startup self-test: once Rocket is listening, GET a few endpoints over the server's own
+//! socket, through every fairing, and warn if any answers with an unexpected status. That
+//! catches a broken route or fairing before the first operator does. It is skipped in
+//! `--dry-run` mode.
+//!
+//! Outside `--dev-mode` it also checks that POST /firmware/command is not mounted, since a
+//! production server must never write raw lines to the board.
+//!
+//! With basic auth configured every endpoint is expected to answer `401`, since the server
+//! never learns the plaintext password to log in with.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::task;
+use rocket::{Orbit, Rocket};
+use tracing::{info, warn};
+
+/// The whole self-test gives up after this long.
+const TIME_LIMIT: Duration = Duration::from_millis(500);
+
+/// The endpoints every server has.
+const PATHS: &[&str] = &[
+    "/",
+    "/telemetry",
+    "/telemetry/extra",
+    "/status",
+    "/health",
+    "/solenoid/labels",
+    "/boards",
+    "/commands/pending",
+    "/metrics",
+];
+
+/// A request the self-test makes: method, path and the status expected.
+type Check = (&'static str, &'static str, u16);
+
+/// Runs the self-test at liftoff.
+pub struct SelfTestFairing {
+    checks: Vec<Check>,
+}
+
+impl SelfTestFairing {
+    /// `replaying` adds GET /replay/status; `basic_auth` makes `401` the expected status;
+    /// without `dev_mode`, POST /firmware/command must be a `404`.
+    pub fn new(replaying: bool, basic_auth: bool, dev_mode: bool) -> Self {
+        let expected = if basic_auth { 401 } else { 200 };
+        let mut checks: Vec<Check> = PATHS.iter().map(|&p| ("GET", p, expected)).collect();
+        if replaying {
+            checks.push(("GET", "/replay/status", expected));
+        }
+        if !dev_mode {
+            let absent = if basic_auth { 401 } else { 404 };
+            checks.push(("POST", "/firmware/command", absent));
+        }
+        SelfTestFairing { checks }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SelfTestFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Startup self-test",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let config = rocket.config();
+        // A server listening on every interface is reached over loopback.
+        let ip = match config.address {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let addr = SocketAddr::new(ip, config.port);
+        let checks = self.checks.clone();
+        // Not awaited: liftoff shouldn't wait on requests to the server it is starting.
+        task::spawn_blocking(move || {
+            let started = Instant::now();
+            let failures = run(addr, &checks);
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            for (path, problem) in &failures {
+                warn!(path, problem = %problem, "Self-test: unexpected response");
+            }
+            if failures.is_empty() {
+                info!(endpoints = checks.len(), elapsed_ms, "Self-test passed");
+            } else {
+                warn!(
+                    failed = failures.len(),
+                    endpoints = checks.len(),
+                    elapsed_ms,
+                    "Self-test failed; check the routes and fairings"
+                );
+            }
+        });
+    }
+}
+
+/// Makes every check's request to the server at `addr`, within `TIME_LIMIT`. Returns the
+/// paths that failed, with what went wrong.
+fn run(addr: SocketAddr, checks: &[Check]) -> Vec<(String, String)> {
+    let deadline = Instant::now() + TIME_LIMIT;
+    let mut failures = Vec::new();
+    for &(method, path, expected) in checks {
+        match request_status(addr, method, path, deadline) {
+            Ok(status) if status == expected => {}
+            Ok(status) => failures.push((path.to_string(), format!("status {}", status))),
+            Err(e) => failures.push((path.to_string(), e.to_string())),
+        }
+    }
+    failures
+}
+
+/// The status code of `method path` (without a body) on the server at `addr`.
+fn request_status(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    deadline: Instant,
+) -> io::Result<u16> {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    if timeout.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "out of time"));
+    }
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        method, path, addr
+    );
+    stream.write_all(request.as_bytes())?;
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            let message = format!("bad status line {:?}", status_line.trim_end());
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn reports_unexpected_statuses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 512];
+                let n = stream.read(&mut request).unwrap();
+                let response = if request[..n].starts_with(b"GET / ") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+                } else if request[..n].starts_with(b"POST /firmware/command ") {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                } else {
+                    "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let checks = [
+            ("GET", "/", 200),
+            ("GET", "/telemetry", 200),
+            ("POST", "/firmware/command", 404),
+        ];
+        let failures = run(addr, &checks);
+        assert_eq!(
+            failures,
+            vec![("/telemetry".to_string(), "status 500".to_string())]
+        );
+    }
+}
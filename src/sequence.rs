@@ -0,0 +1,165 @@
+// src/sequence.rs
+
+//! Timed command sequences for automated test procedures (POST /sequence).
+//!
+//! A sequence runs on its own thread, sleeping `delay_ms` before each step and then
+//! queueing the step's command on the normal command channel. Solenoid steps are checked
+//! against the interlocks and duty cycles when they are due; a refused step fails the
+//! sequence. Aborting wakes the thread immediately, so no further steps are sent.
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rocket::serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::command_queue::CommandSender;
+use crate::interlock::SolenoidGuard;
+use crate::QueuedCommand;
+
+/// A command a sequence step can issue.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum SequenceCommand {
+    Arm,
+    Disarm,
+    Solenoid { channel: u8, state: u8 },
+}
+
+/// One step of a POST /sequence request:
+/// wait `delay_ms` after the previous step, then send `command`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SequenceStep {
+    pub delay_ms: u64,
+    pub command: SequenceCommand,
+}
+
+/// Progress of the most recently submitted sequence, as reported by GET /sequence/status.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", tag = "state", rename_all = "snake_case")]
+pub enum SequenceStatus {
+    /// No sequence has been submitted yet.
+    Idle,
+    /// Accepted, waiting for the first step's delay.
+    Pending {
+        total_steps: usize,
+    },
+    /// At least one step has been sent.
+    Running {
+        completed_steps: usize,
+        total_steps: usize,
+    },
+    Completed {
+        total_steps: usize,
+    },
+    Aborted {
+        completed_steps: usize,
+        total_steps: usize,
+    },
+    /// A step was refused by an interlock or duty cycle (logged), or the command channel
+    /// closed under the sequence (the serial loop is gone).
+    Failed {
+        completed_steps: usize,
+        total_steps: usize,
+    },
+}
+
+impl SequenceStatus {
+    fn is_active(&self) -> bool {
+        matches!(
+            self,
+            SequenceStatus::Pending { .. } | SequenceStatus::Running { .. }
+        )
+    }
+}
+
+/// Runs at most one sequence at a time and tracks its status.
+pub struct SequenceRunner {
+    status: Arc<Mutex<SequenceStatus>>,
+    /// Wakes the running sequence's thread to abort it.
+    abort_tx: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl Default for SequenceRunner {
+    fn default() -> Self {
+        SequenceRunner {
+            status: Arc::new(Mutex::new(SequenceStatus::Idle)),
+            abort_tx: Mutex::new(None),
+        }
+    }
+}
+
+impl SequenceRunner {
+    pub fn status(&self) -> SequenceStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Starts running `steps` (delay, command string) on a background thread, checking each
+    /// with `guard` before it is sent. Returns `false` without doing anything if a sequence is
+    /// already active.
+    pub fn start(
+        &self,
+        steps: Vec<(Duration, String)>,
+        command_tx: CommandSender,
+        guard: SolenoidGuard,
+    ) -> bool {
+        let mut abort_slot = self.abort_tx.lock().unwrap();
+        let total_steps = steps.len();
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.is_active() {
+                return false;
+            }
+            *status = SequenceStatus::Pending { total_steps };
+        }
+        let (abort_tx, abort_rx) = mpsc::channel::<()>();
+        *abort_slot = Some(abort_tx);
+
+        let status = self.status.clone();
+        thread::spawn(move || {
+            let set_status = |s: SequenceStatus| *status.lock().unwrap() = s;
+            for (completed_steps, (delay, command)) in steps.into_iter().enumerate() {
+                // Sleep for the step delay, unless an abort arrives first.
+                if abort_rx.recv_timeout(delay) != Err(mpsc::RecvTimeoutError::Timeout) {
+                    set_status(SequenceStatus::Aborted {
+                        completed_steps,
+                        total_steps,
+                    });
+                    return;
+                }
+                if let Err(e) = guard.check(&command) {
+                    error!(step = completed_steps, error = %e.body(), "Sequence step refused");
+                    set_status(SequenceStatus::Failed {
+                        completed_steps,
+                        total_steps,
+                    });
+                    return;
+                }
+                if command_tx.send(QueuedCommand::immediate(command)).is_err() {
+                    set_status(SequenceStatus::Failed {
+                        completed_steps,
+                        total_steps,
+                    });
+                    return;
+                }
+                set_status(SequenceStatus::Running {
+                    completed_steps: completed_steps + 1,
+                    total_steps,
+                });
+            }
+            set_status(SequenceStatus::Completed { total_steps });
+        });
+        true
+    }
+
+    /// Aborts the active sequence, if any. Returns whether there was one to abort.
+    pub fn abort(&self) -> bool {
+        let abort_tx = self.abort_tx.lock().unwrap().take();
+        match abort_tx {
+            Some(tx) if self.status().is_active() => tx.send(()).is_ok(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// src/serial_ports.rs
+
+//! GET /serial/ports: the serial ports present on the host, so the UI can offer the
+//! operator a device list for POST /serial/reconnect instead of a free-form path.
+
+use rocket::serde::{json::Json, Serialize};
+use serialport::SerialPortType;
+
+use crate::error::ApiError;
+
+/// A serial port as reported by `serialport::available_ports()`. The USB fields are only
+/// present for USB ports.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SerialPortInfo {
+    pub port_name: String,
+    /// `usb`, `pci`, `bluetooth` or `unknown`.
+    pub port_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_vid: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_pid: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+}
+
+impl From<serialport::SerialPortInfo> for SerialPortInfo {
+    fn from(port: serialport::SerialPortInfo) -> Self {
+        let mut info = SerialPortInfo {
+            port_name: port.port_name,
+            port_type: "unknown",
+            usb_vid: None,
+            usb_pid: None,
+            manufacturer: None,
+            product: None,
+            serial_number: None,
+        };
+        match port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                info.port_type = "usb";
+                info.usb_vid = Some(usb.vid);
+                info.usb_pid = Some(usb.pid);
+                info.manufacturer = usb.manufacturer;
+                info.product = usb.product;
+                info.serial_number = usb.serial_number;
+            }
+            SerialPortType::PciPort => info.port_type = "pci",
+            SerialPortType::BluetoothPort => info.port_type = "bluetooth",
+            SerialPortType::Unknown => {}
+        }
+        info
+    }
+}
+
+/// GET /serial/ports lists the serial ports available on the host, sorted by name.
+#[get("/serial/ports")]
+pub fn list_ports() -> Result<Json<Vec<SerialPortInfo>>, ApiError> {
+    let ports = serialport::available_ports()
+        .map_err(|e| ApiError::PortEnumerationFailed(e.to_string()))?;
+    let mut ports: Vec<SerialPortInfo> = ports.into_iter().map(SerialPortInfo::from).collect();
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    Ok(Json(ports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::UsbPortInfo;
+
+    #[test]
+    fn usb_details_only_for_usb_ports() {
+        let usb = serialport::SerialPortInfo {
+            port_name: "/dev/ttyACM0".to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x2341,
+                pid: 0x0043,
+                serial_number: Some("95635333".to_string()),
+                manufacturer: Some("Arduino (www.arduino.cc)".to_string()),
+                product: None,
+            }),
+        };
+        let info = SerialPortInfo::from(usb);
+        assert_eq!(info.port_type, "usb");
+        assert_eq!((info.usb_vid, info.usb_pid), (Some(0x2341), Some(0x0043)));
+        assert_eq!(info.serial_number.as_deref(), Some("95635333"));
+
+        let pci = serialport::SerialPortInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            port_type: SerialPortType::PciPort,
+        };
+        let json = rocket::serde::json::to_string(&SerialPortInfo::from(pci)).unwrap();
+        assert_eq!(json, r#"{"port_name":"/dev/ttyS0","port_type":"pci"}"#);
+    }
+}
@@ -0,0 +1,208 @@
+// src/session.rs
+
+//! Operator sessions, so several consoles can command the stand at once and each can see
+//! what it sent. POST /session/create hands out a token; requests that carry it in the
+//! `X-Session-Token` header have their commands logged in that session's own flight log as
+//! well as the main one, and GET /session/<token>/log reads it back.
+//!
+//! A session expires after `SESSION_TIMEOUT` without a command or a read of its log. A
+//! token the server does not know (or no longer knows) is ignored: the command is still
+//! sent and logged, just not for any session.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::auth::Authenticated;
+use crate::error::ApiError;
+use crate::flight_log::{FlightEvent, FlightLog};
+use crate::AppState;
+
+/// The request header naming the session a command belongs to.
+pub const SESSION_HEADER: &str = "X-Session-Token";
+
+/// How long a session lives without being used.
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A session token: a random (version 4) UUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(u128);
+
+impl SessionId {
+    /// 122 random bits from the standard library's randomly keyed hasher.
+    fn random() -> Self {
+        let half = |i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(i);
+            hasher.finish() as u128
+        };
+        let bits = half(0) << 64 | half(1);
+        // Version 4, variant 10.
+        SessionId(bits & !(0xf << 76) & !(0b11 << 62) | 0x4 << 76 | 0b10 << 62)
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl FromStr for SessionId {
+    type Err = ();
+
+    /// Reads the hyphenated form `Display` writes, in either case.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let lengths = groups.iter().map(|group| group.len());
+        if !lengths.eq([8, 4, 4, 4, 12]) {
+            return Err(());
+        }
+        let hex = groups.concat();
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(());
+        }
+        u128::from_str_radix(&hex, 16)
+            .map(SessionId)
+            .map_err(|_| ())
+    }
+}
+
+struct Session {
+    last_used: Instant,
+    log: FlightLog,
+}
+
+/// The live sessions. Expired ones are dropped whenever the store is used.
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: HashMap<SessionId, Session>,
+}
+
+/// The sessions, shared between the handlers and the serial loop (which logs the commands).
+pub type SharedSessions = Arc<Mutex<SessionStore>>;
+
+impl SessionStore {
+    /// Starts a session and returns its token.
+    pub fn create(&mut self, now: Instant) -> SessionId {
+        self.expire(now);
+        let id = SessionId::random();
+        self.sessions.insert(
+            id,
+            Session {
+                last_used: now,
+                log: FlightLog::default(),
+            },
+        );
+        id
+    }
+
+    /// Records a written command in the session's log, if the session is still live.
+    pub fn record_command(&mut self, id: SessionId, now: Instant, telemetry_ts: u64, text: &str) {
+        if let Some(session) = self.live(id, now) {
+            session.log.record_command(telemetry_ts, text);
+        }
+    }
+
+    /// The session's command history, oldest first; `None` for unknown or expired sessions.
+    pub fn events(&mut self, id: SessionId, now: Instant) -> Option<Vec<FlightEvent>> {
+        self.live(id, now)
+            .map(|session| session.log.events().to_vec())
+    }
+
+    /// The session, if it has not expired, with its inactivity timer restarted.
+    fn live(&mut self, id: SessionId, now: Instant) -> Option<&mut Session> {
+        self.expire(now);
+        let session = self.sessions.get_mut(&id)?;
+        session.last_used = now;
+        Some(session)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.sessions
+            .retain(|_, session| now.duration_since(session.last_used) < SESSION_TIMEOUT);
+    }
+}
+
+/// Response body for POST /session/create.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewSession {
+    pub token: String,
+}
+
+/// POST /session/create starts an operator session.
+#[post("/session/create")]
+pub fn create(_auth: Authenticated, state: &State<AppState>) -> Json<NewSession> {
+    let id = state.sessions.lock().unwrap().create(Instant::now());
+    Json(NewSession {
+        token: id.to_string(),
+    })
+}
+
+/// GET /session/<token>/log returns the commands sent in that session, oldest first.
+/// 404 UNKNOWN_SESSION if it does not exist or has expired.
+#[get("/session/<token>/log")]
+pub fn log(token: &str, state: &State<AppState>) -> Result<Json<Vec<FlightEvent>>, ApiError> {
+    token
+        .parse()
+        .ok()
+        .and_then(|id| state.sessions.lock().unwrap().events(id, Instant::now()))
+        .map(Json)
+        .ok_or_else(|| ApiError::UnknownSession(token.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_uuids() {
+        let id = SessionId::random();
+        let token = id.to_string();
+        assert_eq!(token.len(), 36);
+        assert_eq!(&token[14..15], "4");
+        assert!("89ab".contains(&token[19..20]));
+        assert_eq!(token.parse(), Ok(id));
+        assert_eq!(token.to_uppercase().parse(), Ok(id));
+        assert_ne!(SessionId::random(), id);
+        assert!("not-a-token".parse::<SessionId>().is_err());
+        assert!(token.replace('-', "").parse::<SessionId>().is_err());
+    }
+
+    #[test]
+    fn sessions_expire_when_idle() {
+        let start = Instant::now();
+        let mut store = SessionStore::default();
+        let id = store.create(start);
+        let other = store.create(start);
+
+        let later = start + SESSION_TIMEOUT - Duration::from_secs(1);
+        store.record_command(id, later, 4100, "a\ns31");
+        let events = store.events(id, later).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(store.events(other, later).unwrap().is_empty());
+
+        // `other` was read at `later` too, so only a full timeout after that do both go.
+        let expired = later + SESSION_TIMEOUT;
+        assert!(store.events(id, expired).is_none());
+        assert!(store.events(other, expired).is_none());
+        assert!(store.sessions.is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+// src/shutdown.rs
+
+//! Graceful shutdown: on Ctrl+C or SIGTERM, Rocket stops accepting requests and
+//! `ShutdownFairing` tells the serial loops to finish up. Each loop writes up to
+//! `[serial] shutdown_drain` commands still queued, then disarms ("d") and exits. The
+//! fairing waits up to `JOIN_TIMEOUT` for the serial threads, then saves the `--state-file`
+//! if there is one, before letting Rocket exit.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::task;
+use rocket::{Orbit, Rocket};
+use tracing::{error, info, warn};
+
+use crate::state_file::StateFile;
+
+/// How long shutdown waits for the serial threads.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default number of queued commands still written on shutdown.
+pub const DEFAULT_SHUTDOWN_DRAIN: usize = 10;
+
+/// The "shutting down" flag the serial loops check, and the threads they run on.
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    shutting_down: Arc<AtomicBool>,
+    threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl ShutdownSignal {
+    pub fn is_set(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Runs `serial_loop` on a thread that shutdown waits for.
+    pub fn spawn(&self, serial_loop: impl FnOnce() + Send + 'static) {
+        self.threads
+            .lock()
+            .unwrap()
+            .push(thread::spawn(serial_loop));
+    }
+
+    /// Sets the flag, then waits until every thread has exited or `timeout` has passed.
+    /// Returns how many threads were still running.
+    fn trigger_and_join(&self, timeout: Duration) -> usize {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        let mut threads = std::mem::take(&mut *self.threads.lock().unwrap());
+        while !threads.is_empty() && Instant::now() < deadline {
+            let (finished, running): (Vec<_>, _) =
+                threads.into_iter().partition(|t| t.is_finished());
+            for thread in finished {
+                let _ = thread.join();
+            }
+            threads = running;
+            thread::sleep(Duration::from_millis(10));
+        }
+        threads.len()
+    }
+}
+
+/// Stops the serial loops when Rocket shuts down, then saves the state file.
+pub struct ShutdownFairing {
+    signal: ShutdownSignal,
+    state_file: Option<StateFile>,
+}
+
+impl ShutdownFairing {
+    pub fn new(signal: ShutdownSignal, state_file: Option<StateFile>) -> Self {
+        ShutdownFairing { signal, state_file }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ShutdownFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Serial loop shutdown",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        info!("Shutting down: draining queued commands and disarming");
+        let signal = self.signal.clone();
+        let state_file = self.state_file.clone();
+        let running = task::spawn_blocking(move || {
+            let running = signal.trigger_and_join(JOIN_TIMEOUT);
+            if let Some(state_file) = state_file {
+                match state_file.save() {
+                    Ok(()) => info!(path = state_file.path(), "Saved the last known state"),
+                    Err(e) => error!(path = state_file.path(), error = %e, "Failed to save state"),
+                }
+            }
+            running
+        })
+        .await;
+        match running {
+            Ok(0) => info!("Serial loops stopped"),
+            Ok(running) => warn!(
+                running,
+                timeout_s = JOIN_TIMEOUT.as_secs(),
+                "Serial loops did not stop in time"
+            ),
+            Err(e) => warn!(error = %e, "Waiting for the serial loops failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_threads_until_the_timeout() {
+        let signal = ShutdownSignal::default();
+        let seen = signal.clone();
+        signal.spawn(move || {
+            while !seen.is_set() {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        signal.spawn(|| thread::sleep(Duration::from_secs(5)));
+        let started = Instant::now();
+        assert_eq!(signal.trigger_and_join(Duration::from_millis(300)), 1);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(signal.is_set());
+    }
+}
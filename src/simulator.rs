@@ -0,0 +1,228 @@
+// src/simulator.rs
+
+//! A simulated Arduino for `--simulate`, so the UI and API can be exercised without hardware.
+//!
+//! `spawn()` starts a thread that behaves like the firmware: it emits a telemetry line in the
+//! real wire format every 100 ms and applies (and ACKs) "a", "d" and "sXY" commands written to it.
+//! Anything else is NACKed. With the binary format it sends binary frames instead, and no ACKs.
+//! The returned reader/writer pair slots into the serial loop in place of a serial port,
+//! so everything downstream of the port runs unchanged.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::telemetry::TelemetryFormat;
+
+/// How often the simulated firmware sends a telemetry line.
+const TELEMETRY_PERIOD: Duration = Duration::from_millis(100);
+/// How long a read waits for data before timing out, like the real port.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// The armed flag flips on its own this often.
+const ARM_TOGGLE_PERIOD: Duration = Duration::from_secs(10);
+const START_BATTERY: f32 = 12.6;
+/// Battery drain in volts per second.
+const BATTERY_DRAIN: f32 = 0.01;
+
+/// Command bytes going to the simulated firmware.
+pub struct SimWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for SimWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "simulator stopped"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Telemetry bytes coming from the simulated firmware. Reads time out like a serial port
+/// when nothing arrives, and report end-of-file if the simulator thread has gone.
+pub struct SimReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for SimReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.pending.len() {
+            match self.rx.recv_timeout(READ_TIMEOUT) {
+                Ok(bytes) => {
+                    self.pending = bytes;
+                    self.pos = 0;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "no simulated data"));
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Starts the simulated firmware and returns the two ends of its "serial port".
+pub fn spawn(format: TelemetryFormat) -> (SimWriter, SimReader) {
+    let (cmd_tx, cmd_rx) = mpsc::channel();
+    let (line_tx, line_rx) = mpsc::channel();
+    thread::spawn(move || run_fake_arduino(format, cmd_rx, line_tx));
+    let reader = SimReader {
+        rx: line_rx,
+        pending: Vec::new(),
+        pos: 0,
+    };
+    (SimWriter { tx: cmd_tx }, reader)
+}
+
+/// The simulated firmware state (also used by `hil`).
+pub(crate) struct FakeArduino {
+    started: Instant,
+    armed: bool,
+    last_arm_change: Instant,
+    solenoids: [bool; 16],
+    /// Command bytes received but not yet terminated by a newline.
+    partial: String,
+}
+
+impl FakeArduino {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        FakeArduino {
+            started: now,
+            armed: false,
+            last_arm_change: now,
+            solenoids: [false; 16],
+            partial: String::new(),
+        }
+    }
+
+    /// Buffers command bytes written to the firmware.
+    pub(crate) fn receive(&mut self, bytes: &[u8]) {
+        self.partial.push_str(&String::from_utf8_lossy(bytes));
+    }
+
+    /// Splits off the next complete command line received so far, if any.
+    pub(crate) fn next_command(&mut self) -> Option<String> {
+        let end = self.partial.find('\n')?;
+        let cmd: String = self.partial.drain(..=end).collect();
+        Some(cmd.trim().to_string())
+    }
+
+    /// Applies one command line: "a" (arm), "d" (disarm) or "s<channel><state>" (e.g. "s51").
+    /// Returns whether the command was recognised (and so should be ACKed, not NACKed).
+    pub(crate) fn apply(&mut self, cmd: &str) -> bool {
+        match cmd {
+            "a" => self.set_armed(true),
+            "d" => self.set_armed(false),
+            _ => {
+                let Some(rest) = cmd.strip_prefix('s') else { return false };
+                if rest.len() < 2 {
+                    return false;
+                }
+                let (channel, state) = rest.split_at(rest.len() - 1);
+                match (channel.parse::<usize>(), state.parse::<u8>()) {
+                    (Ok(channel), Ok(state)) if (1..=16).contains(&channel) && state <= 1 => {
+                        self.solenoids[channel - 1] = state == 1;
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+
+    fn set_armed(&mut self, armed: bool) {
+        self.armed = armed;
+        self.last_arm_change = Instant::now();
+    }
+
+    fn voltages(&self) -> (f32, f32) {
+        let drained = BATTERY_DRAIN * self.started.elapsed().as_secs_f32();
+        let battery = (START_BATTERY - drained).max(0.0);
+        (battery, if self.armed { battery } else { 0.0 })
+    }
+
+    /// The firmware's `millis()`. Never 0, which `Telemetry::is_valid` rejects: the real
+    /// board has spent some time in `setup()` before its first sample.
+    fn millis(&self) -> u64 {
+        (self.started.elapsed().as_millis() as u64).max(1)
+    }
+
+    /// Formats the current state exactly as `parse_telemetry_line` expects it.
+    pub(crate) fn telemetry_line(&self) -> String {
+        let (battery, arming) = self.voltages();
+        let solenoids: Vec<String> = self
+            .solenoids
+            .iter()
+            .enumerate()
+            .map(|(i, &on)| format!("{}:{}", i + 1, if on { "ON" } else { "OFF" }))
+            .collect();
+        format!(
+            "TS:{} | ARM:{} | BATT:{:.2}V | ARM_SENSE:{:.2}V | SOL:{}\r\n",
+            self.millis(),
+            self.armed as u8,
+            battery,
+            arming,
+            solenoids.join(",")
+        )
+    }
+
+    /// Encodes the current state as `parse_telemetry_binary` expects it.
+    pub(crate) fn telemetry_frame(&self) -> Vec<u8> {
+        let (battery, arming) = self.voltages();
+        let mask = (0..16).filter(|&i| self.solenoids[i]).fold(0u16, |m, i| m | 1 << i);
+        let mut frame = (self.millis() as u32).to_le_bytes().to_vec();
+        frame.push(self.armed as u8);
+        frame.extend_from_slice(&((battery * 1000.0) as u16).to_le_bytes());
+        frame.extend_from_slice(&((arming * 1000.0) as u16).to_le_bytes());
+        frame.extend_from_slice(&mask.to_le_bytes());
+        frame.push(frame.iter().fold(0, |acc, b| acc ^ b));
+        frame
+    }
+}
+
+fn run_fake_arduino(
+    format: TelemetryFormat,
+    cmd_rx: mpsc::Receiver<Vec<u8>>,
+    line_tx: mpsc::Sender<Vec<u8>>,
+) {
+    let mut sim = FakeArduino::new();
+    loop {
+        // Apply every complete command line received since the last tick.
+        while let Ok(bytes) = cmd_rx.try_recv() {
+            sim.receive(&bytes);
+            while let Some(cmd) = sim.next_command() {
+                let reply = if sim.apply(&cmd) { "ACK" } else { "NACK" };
+                let reply = format!("{}:{}\r\n", reply, cmd);
+                let ascii = format == TelemetryFormat::Ascii;
+                if ascii && line_tx.send(reply.into_bytes()).is_err() {
+                    return;
+                }
+            }
+        }
+        if sim.last_arm_change.elapsed() >= ARM_TOGGLE_PERIOD {
+            let armed = !sim.armed;
+            sim.set_armed(armed);
+        }
+        let sample = match format {
+            TelemetryFormat::Ascii => sim.telemetry_line().into_bytes(),
+            TelemetryFormat::Binary => sim.telemetry_frame(),
+        };
+        if line_tx.send(sample).is_err() {
+            // The serial loop dropped its end; nothing left to simulate for.
+            return;
+        }
+        thread::sleep(TELEMETRY_PERIOD);
+    }
+}
@@ -0,0 +1,121 @@
+// src/snapshot.rs
+
+//! Point-in-time records for the countdown (POST /snapshot): the latest telemetry together
+//! with the state of the flight log, captured under both locks so they agree. The most
+//! recent `MAX_SNAPSHOTS` can be fetched again by ID.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+
+use crate::error::ApiError;
+use crate::flight_log::{serialize_epoch_ms, FlightEvent, FlightLog};
+use crate::{AppState, Telemetry};
+
+/// Snapshots kept for GET /snapshot/<id>; older ones are dropped.
+pub const MAX_SNAPSHOTS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Snapshot {
+    /// Counts up from 1, so a client can tell a repeated snapshot from a new one.
+    pub id: u64,
+    /// Serialized as milliseconds since the Unix epoch.
+    #[serde(serialize_with = "serialize_epoch_ms")]
+    pub captured_at: SystemTime,
+    pub telemetry: Telemetry,
+    /// How many events the flight log held.
+    pub event_count: usize,
+    /// The flight log's newest event.
+    pub last_command: Option<FlightEvent>,
+}
+
+/// The most recent snapshots, oldest first.
+#[derive(Debug, Default)]
+pub struct SnapshotStore {
+    snapshots: VecDeque<Snapshot>,
+    last_id: u64,
+}
+
+impl SnapshotStore {
+    /// Records a snapshot of `telemetry` and `flight_log`, dropping the oldest one if
+    /// `MAX_SNAPSHOTS` are stored.
+    pub fn capture(&mut self, telemetry: &Telemetry, flight_log: &FlightLog) -> Snapshot {
+        self.last_id += 1;
+        let snapshot = Snapshot {
+            id: self.last_id,
+            captured_at: SystemTime::now(),
+            telemetry: telemetry.clone(),
+            event_count: flight_log.events().len(),
+            last_command: flight_log.events().last().cloned(),
+        };
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot.clone());
+        snapshot
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|s| s.id == id)
+    }
+}
+
+/// The snapshots taken by POST /snapshot.
+pub type SharedSnapshots = Mutex<SnapshotStore>;
+
+/// POST /snapshot captures the current telemetry and flight log state, holding both locks
+/// so no sample or command lands in between.
+#[post("/snapshot")]
+pub fn capture(state: &State<AppState>) -> Json<Snapshot> {
+    let mut snapshots = state.snapshots.lock().unwrap();
+    let telemetry = state.telemetry.read().unwrap();
+    let flight_log = state.flight_log.lock().unwrap();
+    Json(snapshots.capture(&telemetry, &flight_log))
+}
+
+/// GET /snapshot/<id> returns a snapshot taken earlier, while it is among the last
+/// `MAX_SNAPSHOTS`.
+#[get("/snapshot/<id>")]
+pub fn get(id: u64, state: &State<AppState>) -> Result<Json<Snapshot>, ApiError> {
+    let snapshots = state.snapshots.lock().unwrap();
+    snapshots
+        .get(id)
+        .cloned()
+        .map(Json)
+        .ok_or(ApiError::UnknownSnapshot(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flight_log::EventType;
+
+    #[test]
+    fn keeps_the_last_hundred_snapshots() {
+        let mut store = SnapshotStore::default();
+        let mut log = FlightLog::default();
+        let telemetry = Telemetry::default();
+        let first = store.capture(&telemetry, &log);
+        assert_eq!((first.id, first.event_count), (1, 0));
+        assert!(first.last_command.is_none());
+
+        log.record(7, EventType::Arm);
+        log.record(9, EventType::Disarm);
+        let second = store.capture(&telemetry, &log);
+        assert_eq!((second.id, second.event_count), (2, 2));
+        let last = second.last_command.unwrap();
+        assert_eq!((last.telemetry_ts, last.event_type), (9, EventType::Disarm));
+
+        for _ in 0..MAX_SNAPSHOTS {
+            store.capture(&telemetry, &log);
+        }
+        assert!(store.get(2).is_none());
+        assert_eq!(store.get(3).unwrap().id, 3);
+        assert_eq!(store.get(102).unwrap().id, 102);
+        assert!(store.get(103).is_none());
+    }
+}
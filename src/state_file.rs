@@ -0,0 +1,80 @@
+// src/state_file.rs
+
+//! The last known telemetry, kept across restarts (`--state-file <path>`). It is written as
+//! JSON when the server shuts down cleanly, and loaded at startup as the telemetry shown
+//! until the first sample arrives, so the UI does not report every solenoid off meanwhile.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+use rocket::serde::json::serde_json;
+
+use crate::{SharedTelemetry, Telemetry};
+
+#[derive(Clone)]
+pub struct StateFile {
+    path: String,
+    telemetry: SharedTelemetry,
+}
+
+impl StateFile {
+    /// The state file at `path`, saving `telemetry`.
+    pub fn new(path: String, telemetry: SharedTelemetry) -> Self {
+        StateFile { path, telemetry }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The telemetry saved last time, or `None` if there is no state file yet.
+    pub fn load(&self) -> io::Result<Option<Telemetry>> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the current telemetry to `<path>.tmp` and renames it over the state file, so
+    /// a crash halfway through leaves the previous state intact.
+    pub fn save(&self) -> io::Result<()> {
+        let json = serde_json::to_string(&*self.telemetry.read().unwrap())?;
+        let tmp = format!("{}.tmp", self.path);
+        let mut file = File::create(&tmp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn saved_state_loads_back() {
+        let path = std::env::temp_dir().join(format!("gcs_state_{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let telemetry = SharedTelemetry::default();
+        let state_file = StateFile::new(path.clone(), telemetry.clone());
+        assert!(state_file.load().unwrap().is_none());
+
+        {
+            let mut tel = telemetry.write().unwrap();
+            tel.timestamp = 777;
+            tel.armed = true;
+            tel.solenoids[6] = true;
+        }
+        state_file.save().unwrap();
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        let loaded = state_file.load().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.timestamp, 777);
+        assert!(loaded.armed);
+        let on: Vec<_> = (0..16).filter(|&i| loaded.solenoids[i]).collect();
+        assert_eq!(on, [6]);
+    }
+}
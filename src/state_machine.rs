@@ -0,0 +1,173 @@
+// src/state_machine.rs
+
+//! The arm/disarm lifecycle of the primary board as ground control sees it:
+//! `Disarmed → ArmIntended → Armed → Disarming → Disarmed`. POST /arm, /arm/intent,
+//! /arm/confirm, /disarm and /emergency_stop drive it, and are refused with a 409 when the
+//! lifecycle does not allow them (arming while armed, say). Telemetry drives it too: a
+//! disarm is only done once the board reports disarmed, a board armed some other way is
+//! taken as armed, and a board reported disarmed after its arm showed (the watchdog's
+//! disarm, a board-side disarm) is taken as disarmed.
+//!
+//! An emergency stop is never refused, and neither is a disarm: while disarmed it is sent
+//! anyway in case the board is armed without telemetry showing it yet, and while disarming
+//! in case the first was lost.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Where the primary board is in its arm/disarm lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArmState {
+    #[default]
+    Disarmed,
+    /// POST /arm/intent issued a token that POST /arm/confirm has yet to present.
+    ArmIntended,
+    /// The arm command has been sent. Until telemetry has `confirmed` it, a frame reporting
+    /// the board disarmed is taken as the arm not having arrived yet.
+    Armed { confirmed: bool },
+    /// The disarm command has been sent; telemetry still shows the board armed.
+    Disarming,
+}
+
+impl fmt::Display for ArmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArmState::Disarmed => "disarmed",
+            ArmState::ArmIntended => "arm_intended",
+            ArmState::Armed { .. } => "armed",
+            ArmState::Disarming => "disarming",
+        })
+    }
+}
+
+/// What happened to the lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmEvent {
+    /// POST /arm/intent (and the first half of single-step POST /arm).
+    Intend,
+    /// POST /arm/confirm with the right token (and the second half of POST /arm).
+    Confirm,
+    /// POST /arm/confirm failed, which clears the intent.
+    IntentLapsed,
+    /// POST /disarm.
+    Disarm,
+    /// POST /emergency_stop.
+    EmergencyStop,
+    /// A telemetry frame reports the board armed.
+    BoardArmed,
+    /// A telemetry frame reports the board disarmed.
+    BoardDisarmed,
+}
+
+/// `event` is not allowed in `state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArmError {
+    pub state: ArmState,
+    pub event: ArmEvent,
+}
+
+/// The lifecycle, shared between the handlers and the serial loop.
+pub type SharedArmState = Arc<Mutex<ArmState>>;
+
+/// The state `event` leads to from `current`.
+pub fn try_transition(current: ArmState, event: ArmEvent) -> Result<ArmState, ArmError> {
+    use ArmEvent::*;
+    use ArmState::*;
+    match (current, event) {
+        (_, EmergencyStop) => Ok(Disarming),
+        (Disarmed | ArmIntended, Intend) => Ok(ArmIntended),
+        (ArmIntended, Confirm) => Ok(Armed { confirmed: false }),
+        (ArmIntended, IntentLapsed | Disarm) => Ok(Disarmed),
+        (Disarmed | Armed { .. } | Disarming, Disarm) => Ok(Disarming),
+        (Disarmed | ArmIntended | Armed { .. }, BoardArmed) => Ok(Armed { confirmed: true }),
+        (Disarming | Armed { confirmed: true }, BoardDisarmed) => Ok(Disarmed),
+        // Still waiting: for the disarm to show, or for the arm (or the intent's confirm).
+        (Disarming, BoardArmed)
+        | (Disarmed | ArmIntended | Armed { confirmed: false }, BoardDisarmed) => Ok(current),
+        (state, event) => Err(ArmError { state, event }),
+    }
+}
+
+/// Applies a telemetry frame reporting the board `armed` (or not).
+pub fn observe(arm_state: &SharedArmState, armed: bool) {
+    let event = if armed {
+        ArmEvent::BoardArmed
+    } else {
+        ArmEvent::BoardDisarmed
+    };
+    let mut state = arm_state.lock().unwrap();
+    if let Ok(next) = try_transition(*state, event) {
+        *state = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ArmEvent::*;
+    use ArmState::*;
+
+    #[test]
+    fn transition_table() {
+        let sent = Armed { confirmed: false };
+        let shown = Armed { confirmed: true };
+        let (dis, int, arm, ok, ing) = (
+            Some(Disarmed),
+            Some(ArmIntended),
+            Some(sent),
+            Some(shown),
+            Some(Disarming),
+        );
+        let no = None;
+        // What each event leads to in each state; `no` is refused.
+        #[rustfmt::skip]
+        let table = [
+            //             Intend Confirm Lapsed Disarm E-stop BoardArmed BoardDisarmed
+            (Disarmed,    [int,   no,     no,    ing,   ing,   ok,        dis]),
+            (ArmIntended, [int,   arm,    dis,   dis,   ing,   ok,        int]),
+            (sent,        [no,    no,     no,    ing,   ing,   ok,        arm]),
+            (shown,       [no,    no,     no,    ing,   ing,   ok,        dis]),
+            (Disarming,   [no,    no,     no,    ing,   ing,   ing,       dis]),
+        ];
+        let events = [
+            Intend,
+            Confirm,
+            IntentLapsed,
+            Disarm,
+            EmergencyStop,
+            BoardArmed,
+            BoardDisarmed,
+        ];
+        for (state, row) in table {
+            for (event, expected) in events.into_iter().zip(row) {
+                let expected = expected.ok_or(ArmError { state, event });
+                assert_eq!(
+                    try_transition(state, event),
+                    expected,
+                    "{:?} on {:?}",
+                    event,
+                    state
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rearming_after_the_watchdog_tripped() {
+        let arm_state = SharedArmState::default();
+        let drive = |events: &[ArmEvent]| {
+            let mut state = arm_state.lock().unwrap();
+            *state = events
+                .iter()
+                .try_fold(*state, |current, &event| try_transition(current, event))?;
+            Ok::<_, ArmError>(*state)
+        };
+        drive(&[Intend, Confirm]).unwrap();
+        observe(&arm_state, true);
+        // The watchdog disarms on the priority channel, without an event; telemetry then
+        // shows the board disarmed.
+        observe(&arm_state, false);
+        assert_eq!(*arm_state.lock().unwrap(), Disarmed);
+        assert_eq!(drive(&[Intend, Confirm]), Ok(Armed { confirmed: false }));
+    }
+}
@@ -0,0 +1,121 @@
+// src/stats.rs
+
+//! Summary statistics over recent telemetry, for GET /telemetry/stats.
+//!
+//! Computed on request from the history buffer, so the serial loop does no extra work.
+
+use rocket::serde::Serialize;
+
+use crate::Telemetry;
+
+/// Window used when GET /telemetry/stats has no `window_s`.
+pub const DEFAULT_STATS_WINDOW_S: u64 = 60;
+
+/// Min/max/mean of the voltages and how often each solenoid changed state, over a window of
+/// samples. The voltage fields are `null` when the window is empty.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TelemetryStats {
+    /// Number of samples in the window.
+    pub samples: usize,
+    pub battery_min: Option<f32>,
+    pub battery_max: Option<f32>,
+    pub battery_mean: Option<f32>,
+    pub arming_min: Option<f32>,
+    pub arming_max: Option<f32>,
+    pub arming_mean: Option<f32>,
+    /// State changes per solenoid (index = channel - 1) between consecutive samples.
+    pub solenoid_toggle_counts: [u32; 16],
+}
+
+/// Running min/max/sum of one field.
+#[derive(Default)]
+struct FieldStats {
+    min: Option<f32>,
+    max: Option<f32>,
+    sum: f64,
+}
+
+impl FieldStats {
+    fn push(&mut self, value: f32) {
+        self.min = Some(self.min.map_or(value, |m| m.min(value)));
+        self.max = Some(self.max.map_or(value, |m| m.max(value)));
+        self.sum += value as f64;
+    }
+
+    fn mean(&self, count: usize) -> Option<f32> {
+        (count > 0).then(|| (self.sum / count as f64) as f32)
+    }
+}
+
+impl TelemetryStats {
+    /// Computes the statistics over `samples`, oldest first.
+    pub fn compute<'a>(samples: impl IntoIterator<Item = &'a Telemetry>) -> TelemetryStats {
+        let mut count = 0;
+        let mut battery = FieldStats::default();
+        let mut arming = FieldStats::default();
+        let mut solenoid_toggle_counts = [0u32; 16];
+        let mut previous: Option<&Telemetry> = None;
+        for tel in samples {
+            count += 1;
+            battery.push(tel.battery);
+            arming.push(tel.arming);
+            if let Some(prev) = previous {
+                let changes = prev.solenoids.iter().zip(&tel.solenoids);
+                for (counter, (was, is)) in solenoid_toggle_counts.iter_mut().zip(changes) {
+                    *counter += (was != is) as u32;
+                }
+            }
+            previous = Some(tel);
+        }
+        TelemetryStats {
+            samples: count,
+            battery_min: battery.min,
+            battery_max: battery.max,
+            battery_mean: battery.mean(count),
+            arming_min: arming.min,
+            arming_max: arming.max,
+            arming_mean: arming.mean(count),
+            solenoid_toggle_counts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(battery: f32, sol1: bool) -> Telemetry {
+        let mut tel = Telemetry {
+            battery,
+            arming: battery / 2.0,
+            ..Telemetry::default()
+        };
+        tel.solenoids[0] = sol1;
+        tel
+    }
+
+    #[test]
+    fn min_max_mean_and_toggles() {
+        let samples = [sample(12.0, false), sample(11.8, true), sample(12.1, false)];
+        let stats = TelemetryStats::compute(&samples);
+        assert_eq!(stats.samples, 3);
+        assert_eq!(
+            (stats.battery_min, stats.battery_max),
+            (Some(11.8), Some(12.1))
+        );
+        assert!((stats.battery_mean.unwrap() - 11.966667).abs() < 1e-4);
+        assert_eq!(stats.arming_max, Some(6.05));
+        assert_eq!(stats.solenoid_toggle_counts[0], 2);
+        assert_eq!(stats.solenoid_toggle_counts[1], 0);
+    }
+
+    #[test]
+    fn empty_window_has_no_voltages() {
+        let stats = TelemetryStats::compute(&[]);
+        assert_eq!(
+            (stats.samples, stats.battery_min, stats.battery_mean),
+            (0, None, None)
+        );
+    }
+}
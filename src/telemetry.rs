@@ -0,0 +1,832 @@
+// src/telemetry.rs
+
+//! Parsing of the Arduino's telemetry (text lines or compact binary frames),
+//! and diffs between samples.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use rocket::serde::json::{self, Value};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::regex::Regex;
+use crate::Telemetry;
+
+/// The wire format the firmware sends telemetry in (`--format`, `[serial] format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TelemetryFormat {
+    /// One text line per sample, see `parse_telemetry_line`.
+    #[default]
+    Ascii,
+    /// Fixed-size frames, see `parse_telemetry_binary`.
+    Binary,
+}
+
+/// Size of a binary telemetry frame.
+pub const BINARY_FRAME_LEN: usize = 12;
+
+/// Response body for GET /telemetry/diff: the fields of the current sample that differ
+/// from an earlier one, keyed by their `Telemetry` JSON names.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TelemetryDiff {
+    /// Timestamp of the current sample (pass it as `since` next time).
+    pub timestamp: u64,
+    pub changed_fields: HashMap<String, Value>,
+}
+
+/// Compares `current` to `previous`. Without a previous sample, every field is "changed".
+/// The timestamp is reported separately and never listed as a changed field.
+pub fn diff(previous: Option<&Telemetry>, current: &Telemetry) -> TelemetryDiff {
+    // Going through the JSON text keeps the f32 voltages as short as GET /telemetry shows them
+    // (`to_value` would widen them to f64 first).
+    let fields = |tel: &Telemetry| match json::to_string(tel).map(|text| json::from_str(&text)) {
+        Ok(Ok(Value::Object(map))) => map,
+        _ => Default::default(),
+    };
+    let old = previous.map(fields).unwrap_or_default();
+    let changed_fields = fields(current)
+        .into_iter()
+        .filter(|(name, value)| name != "timestamp" && old.get(name) != Some(value))
+        .collect();
+    TelemetryDiff {
+        timestamp: current.timestamp,
+        changed_fields,
+    }
+}
+
+/// Why a telemetry line was rejected. The strings are the offending segment or entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Not 5 to 7 " | "-separated segments.
+    WrongSegmentCount(usize),
+    BadTimestamp(String),
+    BadArmFlag(String),
+    BadBattery(String),
+    BadArmingVoltage(String),
+    /// The SOL segment does not list 16 entries.
+    WrongSolenoidCount(usize),
+    BadSolenoidEntry { index: usize, raw: String },
+    /// The PYRO segment does not list 4 entries.
+    WrongPyroCount(usize),
+    BadPyroEntry { index: usize, raw: String },
+    BadExtraEntry(String),
+    /// A segment other than the one expected in its place: no "SOL:" in the fifth, an
+    /// optional one that is neither PYRO nor EXT, or one after EXT.
+    UnexpectedSegment(String),
+    /// The line does not match the `[telemetry.format]` regex, or a value in it is invalid.
+    FormatMismatch,
+}
+
+impl ParseError {
+    /// The name of each variant, in declaration order (see `kind`).
+    pub const KINDS: [&'static str; 12] = [
+        "wrong_segment_count",
+        "bad_timestamp",
+        "bad_arm_flag",
+        "bad_battery",
+        "bad_arming_voltage",
+        "wrong_solenoid_count",
+        "bad_solenoid_entry",
+        "wrong_pyro_count",
+        "bad_pyro_entry",
+        "bad_extra_entry",
+        "unexpected_segment",
+        "format_mismatch",
+    ];
+
+    /// The variant's index in `KINDS`.
+    pub fn kind_index(&self) -> usize {
+        match self {
+            ParseError::WrongSegmentCount(_) => 0,
+            ParseError::BadTimestamp(_) => 1,
+            ParseError::BadArmFlag(_) => 2,
+            ParseError::BadBattery(_) => 3,
+            ParseError::BadArmingVoltage(_) => 4,
+            ParseError::WrongSolenoidCount(_) => 5,
+            ParseError::BadSolenoidEntry { .. } => 6,
+            ParseError::WrongPyroCount(_) => 7,
+            ParseError::BadPyroEntry { .. } => 8,
+            ParseError::BadExtraEntry(_) => 9,
+            ParseError::UnexpectedSegment(_) => 10,
+            ParseError::FormatMismatch => 11,
+        }
+    }
+
+    /// The variant's name, e.g. "bad_battery".
+    pub fn kind(&self) -> &'static str {
+        Self::KINDS[self.kind_index()]
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::WrongSegmentCount(n) => write!(f, "{} segments (expected 5 to 7)", n),
+            ParseError::BadTimestamp(raw) => write!(f, "bad timestamp segment {:?}", raw),
+            ParseError::BadArmFlag(raw) => write!(f, "bad arm flag segment {:?}", raw),
+            ParseError::BadBattery(raw) => write!(f, "bad battery segment {:?}", raw),
+            ParseError::BadArmingVoltage(raw) => write!(f, "bad arming sense segment {:?}", raw),
+            ParseError::WrongSolenoidCount(n) => write!(f, "{} solenoid entries (expected 16)", n),
+            ParseError::BadSolenoidEntry { index, raw } => {
+                write!(f, "bad solenoid entry {} {:?}", index + 1, raw)
+            }
+            ParseError::WrongPyroCount(n) => write!(f, "{} pyro entries (expected 4)", n),
+            ParseError::BadPyroEntry { index, raw } => {
+                write!(f, "bad pyro entry {} {:?}", index + 1, raw)
+            }
+            ParseError::BadExtraEntry(raw) => write!(f, "bad extra reading {:?}", raw),
+            ParseError::UnexpectedSegment(raw) => write!(f, "unexpected segment {:?}", raw),
+            ParseError::FormatMismatch => f.write_str("does not match the configured format"),
+        }
+    }
+}
+
+/// Given a telemetry line string from the Arduino, parse and return a Telemetry instance.
+///
+/// Expected format (as sent from your Arduino):
+/// TS:<timestamp> | ARM:<0|1> | BATT:<voltage>V | ARM_SENSE:<voltage>V | SOL:1:ON,2:OFF,...,16:OFF
+///
+/// Newer firmware may append a segment with pyro continuity, and then one with extra
+/// sensor readings (thermocouples, pressure transducers), both optional:
+/// ... | PYRO:1:OK,2:OK,3:FAIL,4:OK | EXT:TC1:350.2,TC2:295.1
+pub(crate) fn parse_telemetry_line(line: &str) -> Result<Telemetry, ParseError> {
+    let parts: Vec<&str> = line.split(" | ").collect();
+    if !(5..=7).contains(&parts.len()) {
+        return Err(ParseError::WrongSegmentCount(parts.len()));
+    }
+    // Parse timestamp.
+    let bad_timestamp = || ParseError::BadTimestamp(parts[0].to_string());
+    let ts_part = parts[0].strip_prefix("TS:").ok_or_else(bad_timestamp)?;
+    let timestamp: u64 = ts_part.parse().map_err(|_| bad_timestamp())?;
+    // Parse armed flag.
+    let armed = match parts[1].strip_prefix("ARM:") {
+        Some("1") => true,
+        Some("0") => false,
+        _ => return Err(ParseError::BadArmFlag(parts[1].to_string())),
+    };
+    // Parse battery voltage (strip trailing "V").
+    let battery = parse_volts(parts[2], "BATT:")
+        .ok_or_else(|| ParseError::BadBattery(parts[2].to_string()))?;
+    // Parse arming sense voltage.
+    let arming = parse_volts(parts[3], "ARM_SENSE:")
+        .ok_or_else(|| ParseError::BadArmingVoltage(parts[3].to_string()))?;
+    // Parse solenoid states.
+    let sol_part = parts[4]
+        .strip_prefix("SOL:")
+        .ok_or_else(|| ParseError::UnexpectedSegment(parts[4].to_string()))?;
+    let sol_entries: Vec<&str> = sol_part.split(',').collect();
+    if sol_entries.len() != 16 {
+        return Err(ParseError::WrongSolenoidCount(sol_entries.len()));
+    }
+    let mut solenoids = Vec::with_capacity(16);
+    for (index, entry) in sol_entries.into_iter().enumerate() {
+        // Each entry should be in the format "channel:ON" or "channel:OFF"
+        let state = match entry.split_once(':').map(|(_, state)| state.trim()) {
+            Some("ON") => true,
+            Some("OFF") => false,
+            _ => {
+                let raw = entry.to_string();
+                return Err(ParseError::BadSolenoidEntry { index, raw });
+            }
+        };
+        solenoids.push(state);
+    }
+    // Parse the optional pyro continuity and extra readings segments, in that order.
+    let mut optional = parts[5..].iter().peekable();
+    let pyro_continuity = match optional.next_if(|segment| segment.starts_with("PYRO:")) {
+        Some(segment) => parse_pyro_segment(segment)?,
+        None => vec![false; 4],
+    };
+    let extra = match optional.next() {
+        Some(segment) => parse_extra_segment(segment)?,
+        None => HashMap::new(),
+    };
+    if let Some(segment) = optional.next() {
+        return Err(ParseError::UnexpectedSegment(segment.to_string()));
+    }
+    Ok(Telemetry {
+        timestamp,
+        armed,
+        battery,
+        battery_raw: battery,
+        arming,
+        solenoids,
+        pyro_continuity,
+        extra,
+    })
+}
+
+/// Parses "<prefix><volts>V".
+fn parse_volts(segment: &str, prefix: &str) -> Option<f32> {
+    segment.strip_prefix(prefix)?.strip_suffix("V")?.parse().ok()
+}
+
+/// Parses "EXT:TC1:350.2,TC2:295.1" into readings by name.
+fn parse_extra_segment(segment: &str) -> Result<HashMap<String, f64>, ParseError> {
+    let ext_part = segment
+        .strip_prefix("EXT:")
+        .ok_or_else(|| ParseError::UnexpectedSegment(segment.to_string()))?;
+    let mut extra = HashMap::new();
+    for entry in ext_part.split(',') {
+        let reading = entry.split_once(':').and_then(|(key, value)| {
+            let value: f64 = value.trim().parse().ok()?;
+            let key = key.trim();
+            (!key.is_empty() && value.is_finite()).then_some((key, value))
+        });
+        let (key, value) = reading.ok_or_else(|| ParseError::BadExtraEntry(entry.to_string()))?;
+        extra.insert(key.to_string(), value);
+    }
+    Ok(extra)
+}
+
+/// Parses "PYRO:1:OK,2:OK,3:FAIL,4:OK" into four continuity flags.
+fn parse_pyro_segment(segment: &str) -> Result<Vec<bool>, ParseError> {
+    let pyro_part = segment
+        .strip_prefix("PYRO:")
+        .ok_or_else(|| ParseError::UnexpectedSegment(segment.to_string()))?;
+    let entries: Vec<&str> = pyro_part.split(',').collect();
+    if entries.len() != 4 {
+        return Err(ParseError::WrongPyroCount(entries.len()));
+    }
+    let mut pyro = Vec::with_capacity(4);
+    for (index, entry) in entries.into_iter().enumerate() {
+        // Each entry should be in the format "channel:OK" or "channel:FAIL"
+        let ok = match entry.split_once(':').map(|(_, ok)| ok.trim()) {
+            Some("OK") => true,
+            Some("FAIL") => false,
+            _ => {
+                let raw = entry.to_string();
+                return Err(ParseError::BadPyroEntry { index, raw });
+            }
+        };
+        pyro.push(ok);
+    }
+    Ok(pyro)
+}
+
+/// The named groups of a `LineFormat` that fill `Telemetry` fields; any other group is an
+/// extra reading.
+const LINE_FORMAT_FIELDS: [&str; 6] =
+    ["timestamp", "armed", "battery", "arming", "solenoids", "pyro"];
+
+/// A custom telemetry line format (`[telemetry.format]`), for firmware that doesn't send the
+/// built-in one: a regex whose named groups pick the fields out of a line.
+///
+/// - `timestamp` (required): milliseconds, as an integer
+/// - `armed`: `1` or `0`
+/// - `battery`, `arming`: volts, as numbers
+/// - `solenoids`: 16 digits `1`/`0`, channel 1 first
+/// - `pyro`: 4 digits `1`/`0` for continuity, channel 1 first
+/// - any other name: an extra reading (a number), like those of the EXT segment
+///
+/// Fields without a group (or whose group did not take part in the match) keep their
+/// defaults. A line the regex doesn't match, or with a value that doesn't parse, is
+/// rejected like a malformed built-in line.
+#[derive(Debug, Clone)]
+pub struct LineFormat {
+    regex: Regex,
+}
+
+impl LineFormat {
+    /// Compiles `pattern`, which must have a `timestamp` group.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|e| format!("invalid regex: {}", e))?;
+        if !regex.capture_names().any(|name| name == "timestamp") {
+            return Err("the regex has no (?P<timestamp>...) group".to_string());
+        }
+        Ok(LineFormat { regex })
+    }
+
+    /// Parses `line` like `parse_telemetry_line` does the built-in format.
+    pub fn parse(&self, line: &str) -> Option<Telemetry> {
+        let captures = self.regex.captures(line)?;
+        let field = |name: &str| captures.name(name).map(str::trim);
+        let timestamp: u64 = field("timestamp")?.parse().ok()?;
+        let armed = match field("armed") {
+            Some("1") => true,
+            Some("0") | None => false,
+            Some(_) => return None,
+        };
+        let battery: f32 = match field("battery") {
+            Some(volts) => volts.parse().ok()?,
+            None => 0.0,
+        };
+        let arming: f32 = match field("arming") {
+            Some(volts) => volts.parse().ok()?,
+            None => 0.0,
+        };
+        let solenoids = match field("solenoids") {
+            Some(digits) => parse_flags(digits, 16)?,
+            None => vec![false; 16],
+        };
+        let pyro_continuity = match field("pyro") {
+            Some(digits) => parse_flags(digits, 4)?,
+            None => vec![false; 4],
+        };
+        let mut extra = HashMap::new();
+        for name in self.regex.capture_names() {
+            if let (false, Some(value)) = (LINE_FORMAT_FIELDS.contains(&name), field(name)) {
+                let value: f64 = value.parse().ok().filter(|v: &f64| v.is_finite())?;
+                extra.insert(name.to_string(), value);
+            }
+        }
+        Some(Telemetry {
+            timestamp,
+            armed,
+            battery,
+            battery_raw: battery,
+            arming,
+            solenoids,
+            pyro_continuity,
+            extra,
+        })
+    }
+}
+
+/// Parses exactly `count` digits `1`/`0` into flags.
+fn parse_flags(digits: &str, count: usize) -> Option<Vec<bool>> {
+    if digits.len() != count {
+        return None;
+    }
+    digits
+        .chars()
+        .map(|c| match c {
+            '1' => Some(true),
+            '0' => Some(false),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Packs solenoid states into a bitmask: bit N is solenoid N+1. Entries past 16 are ignored.
+pub(crate) fn solenoids_to_mask(solenoids: &[bool]) -> u16 {
+    solenoids
+        .iter()
+        .take(16)
+        .enumerate()
+        .filter(|(_, &on)| on)
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+/// Parses a 12-byte binary telemetry frame:
+///
+/// | bytes | field                                        |
+/// |-------|----------------------------------------------|
+/// | 0-3   | timestamp, little-endian u32                 |
+/// | 4     | flags, bit 0 = armed                         |
+/// | 5-6   | battery, little-endian u16 millivolts        |
+/// | 7-8   | arming sense, little-endian u16 millivolts   |
+/// | 9-10  | solenoids, little-endian u16, bit 0 = ch 1   |
+/// | 11    | checksum, XOR of bytes 0-10                  |
+///
+/// Binary frames carry no pyro continuity or extra readings, so pyro is reported as all
+/// `false`.
+pub(crate) fn parse_telemetry_binary(buf: &[u8]) -> Option<Telemetry> {
+    let frame: &[u8; BINARY_FRAME_LEN] = buf.try_into().ok()?;
+    let checksum = frame[..11].iter().fold(0, |acc, b| acc ^ b);
+    if checksum != frame[11] {
+        return None;
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([frame[i], frame[i + 1]]);
+    let battery = u16_at(5) as f32 / 1000.0;
+    let mask = u16_at(9);
+    Some(Telemetry {
+        timestamp: u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as u64,
+        armed: frame[4] & 1 != 0,
+        battery,
+        battery_raw: battery,
+        arming: u16_at(7) as f32 / 1000.0,
+        solenoids: (0..16).map(|i| mask & (1 << i) != 0).collect(),
+        pyro_continuity: vec![false; 4],
+        extra: HashMap::new(),
+    })
+}
+
+/// Splits a byte stream into binary telemetry frames. The frames have no start marker, so
+/// after a bad checksum the reader slides forward a byte at a time until frames line up again.
+#[derive(Default)]
+pub struct BinaryFrameReader {
+    buf: Vec<u8>,
+    /// Currently skipping bytes to find the next valid frame.
+    resyncing: bool,
+    /// Bad frames (runs of skipped bytes) since the last `take_bad_frames`.
+    bad_frames: u64,
+}
+
+impl BinaryFrameReader {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The next valid frame from the buffered bytes, if a complete one is available.
+    pub fn next_frame(&mut self) -> Option<Telemetry> {
+        while self.buf.len() >= BINARY_FRAME_LEN {
+            if let Some(tel) = parse_telemetry_binary(&self.buf[..BINARY_FRAME_LEN]) {
+                self.buf.drain(..BINARY_FRAME_LEN);
+                self.resyncing = false;
+                return Some(tel);
+            }
+            self.buf.remove(0);
+            if !self.resyncing {
+                self.resyncing = true;
+                self.bad_frames += 1;
+            }
+        }
+        None
+    }
+
+    /// Returns (and resets) the number of bad frames skipped so far.
+    pub fn take_bad_frames(&mut self) -> u64 {
+        std::mem::take(&mut self.bad_frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const GOLDEN: &str = "TS:123456 | ARM:1 | BATT:12.34V | ARM_SENSE:11.90V | \
+        SOL:1:ON,2:OFF,3:OFF,4:OFF,5:ON,6:OFF,7:OFF,8:OFF,\
+        9:OFF,10:OFF,11:OFF,12:OFF,13:OFF,14:OFF,15:OFF,16:ON";
+
+    /// The golden line with segment `index` replaced by `segment`.
+    fn with_segment(index: usize, segment: &str) -> String {
+        let mut parts: Vec<&str> = GOLDEN.split(" | ").collect();
+        parts[index] = segment;
+        parts.join(" | ")
+    }
+
+    #[test]
+    fn parses_golden_line() {
+        let t = parse_telemetry_line(GOLDEN).unwrap();
+        assert_eq!(t.timestamp, 123456);
+        assert!(t.armed);
+        assert_eq!(t.battery, 12.34);
+        assert_eq!(t.battery_raw, 12.34);
+        assert_eq!(t.arming, 11.90);
+        let on: Vec<usize> = (0..16).filter(|&i| t.solenoids[i]).map(|i| i + 1).collect();
+        assert_eq!(on, vec![1, 5, 16]);
+        #[rustfmt::skip]
+        let expected = [
+            true, false, false, false, true, false, false, false,
+            false, false, false, false, false, false, false, true,
+        ];
+        assert_eq!(t.solenoids, expected);
+        assert_eq!(t.pyro_continuity, vec![false; 4]);
+        assert!(t.extra.is_empty());
+    }
+
+    #[test]
+    fn rejects_each_missing_segment() {
+        let parts: Vec<&str> = GOLDEN.split(" | ").collect();
+        for missing in 0..parts.len() {
+            let mut rest = parts.clone();
+            rest.remove(missing);
+            let line = rest.join(" | ");
+            assert!(parse_telemetry_line(&line).is_err(), "{}", line);
+            // An optional segment doesn't stand in for the missing one.
+            let line = format!("{} | PYRO:1:OK,2:OK,3:OK,4:OK", line);
+            assert!(parse_telemetry_line(&line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn whitespace_around_values() {
+        // Around solenoid states (and pyro and extra entries) it is ignored...
+        let spaced = GOLDEN.replace(":ON", ": ON ").replace(":OFF", ":OFF ");
+        let t = parse_telemetry_line(&spaced).unwrap();
+        assert_eq!(t.solenoids, parse_telemetry_line(GOLDEN).unwrap().solenoids);
+        let tail = " | PYRO:1: OK,2:FAIL ,3:OK,4:OK | EXT:TC1: 20.5 , TC2 :1";
+        let t = parse_telemetry_line(&format!("{}{}", GOLDEN, tail)).unwrap();
+        assert_eq!(t.pyro_continuity, vec![true, false, true, true]);
+        assert_eq!((t.extra["TC1"], t.extra["TC2"]), (20.5, 1.0));
+        // ...but not in the numeric fields, or around the " | " separators.
+        assert!(parse_telemetry_line(&with_segment(0, "TS: 123456")).is_err());
+        assert!(parse_telemetry_line(&with_segment(1, "ARM: 1")).is_err());
+        assert!(parse_telemetry_line(&with_segment(2, "BATT:12.34 V")).is_err());
+        assert!(parse_telemetry_line(&with_segment(3, "ARM_SENSE: 11.90V")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replacen(" | ", "  |  ", 1)).is_err());
+    }
+
+    #[test]
+    fn parse_errors_say_what_failed() {
+        let error = |line: &str| parse_telemetry_line(line).unwrap_err();
+        assert_eq!(error("garbage"), ParseError::WrongSegmentCount(1));
+        assert_eq!(
+            error(&with_segment(0, "TS:12x")),
+            ParseError::BadTimestamp("TS:12x".to_string())
+        );
+        assert_eq!(error(&with_segment(1, "ARM:2")), ParseError::BadArmFlag("ARM:2".to_string()));
+        assert_eq!(
+            error(&with_segment(2, "BATT:12.34")),
+            ParseError::BadBattery("BATT:12.34".to_string())
+        );
+        assert_eq!(
+            error(&with_segment(3, "ARM_SENSE:xV")),
+            ParseError::BadArmingVoltage("ARM_SENSE:xV".to_string())
+        );
+        assert_eq!(error(&with_segment(4, "SOL:1:ON")), ParseError::WrongSolenoidCount(1));
+        let bad = error(&GOLDEN.replace("7:OFF", "7:MAYBE"));
+        let raw = "7:MAYBE".to_string();
+        assert_eq!(bad, ParseError::BadSolenoidEntry { index: 6, raw });
+        assert_eq!(bad.kind(), "bad_solenoid_entry");
+        let pyro = format!("{} | PYRO:1:OK,2:OK,3:OK", GOLDEN);
+        assert_eq!(error(&pyro), ParseError::WrongPyroCount(3));
+        let extra = format!("{} | EXT:TC1:hot", GOLDEN);
+        assert_eq!(error(&extra), ParseError::BadExtraEntry("TC1:hot".to_string()));
+        let late = format!("{} | EXT:TC1:1 | PYRO:1:OK,2:OK,3:OK,4:OK", GOLDEN);
+        assert_eq!(error(&late).kind(), "unexpected_segment");
+        // Every variant has its own name.
+        let mut kinds = ParseError::KINDS.to_vec();
+        kinds.dedup();
+        assert_eq!(kinds.len(), ParseError::KINDS.len());
+        assert_eq!(ParseError::FormatMismatch.kind(), "format_mismatch");
+    }
+
+    #[test]
+    fn parses_optional_pyro_segment() {
+        let line = format!("{} | PYRO:1:OK,2:OK,3:FAIL,4:OK", GOLDEN);
+        let t = parse_telemetry_line(&line).unwrap();
+        assert_eq!(t.pyro_continuity, vec![true, true, false, true]);
+    }
+
+    #[test]
+    fn diff_lists_only_changed_fields() {
+        let old = parse_telemetry_line(GOLDEN).unwrap();
+        let mut new = old.clone();
+        new.timestamp += 100;
+        new.solenoids[1] = true;
+        let d = diff(Some(&old), &new);
+        assert_eq!(d.timestamp, 123556);
+        assert_eq!(d.changed_fields.len(), 1);
+        assert_eq!(d.changed_fields["solenoids"][1], Value::Bool(true));
+    }
+
+    #[test]
+    fn diff_without_previous_lists_all_fields() {
+        let new = parse_telemetry_line(GOLDEN).unwrap();
+        let d = diff(None, &new);
+        assert!(d.changed_fields.contains_key("battery"));
+        assert!(!d.changed_fields.contains_key("timestamp"));
+    }
+
+    /// Builds a binary frame with a correct checksum.
+    fn binary_frame(ts: u32, flags: u8, batt_mv: u16, arm_mv: u16, mask: u16) -> Vec<u8> {
+        let mut frame = ts.to_le_bytes().to_vec();
+        frame.push(flags);
+        frame.extend_from_slice(&batt_mv.to_le_bytes());
+        frame.extend_from_slice(&arm_mv.to_le_bytes());
+        frame.extend_from_slice(&mask.to_le_bytes());
+        frame.push(frame.iter().fold(0, |acc, b| acc ^ b));
+        frame
+    }
+
+    #[test]
+    fn parses_binary_frame() {
+        let t = parse_telemetry_binary(&binary_frame(123456, 1, 12340, 11900, 0x8011)).unwrap();
+        assert_eq!(t.timestamp, 123456);
+        assert!(t.armed);
+        assert_eq!(t.battery, 12.34);
+        assert_eq!(t.arming, 11.9);
+        let on: Vec<usize> = (0..16).filter(|&i| t.solenoids[i]).map(|i| i + 1).collect();
+        assert_eq!(on, vec![1, 5, 16]);
+    }
+
+    #[test]
+    fn rejects_bad_binary_frames() {
+        let mut frame = binary_frame(1, 0, 12000, 0, 0);
+        assert!(parse_telemetry_binary(&frame[..11]).is_none());
+        frame[11] ^= 0xff;
+        assert!(parse_telemetry_binary(&frame).is_none());
+    }
+
+    #[test]
+    fn frame_reader_resyncs_after_garbage() {
+        let mut reader = BinaryFrameReader::default();
+        let frame = binary_frame(7, 1, 12000, 0, 2);
+        reader.push(&frame[..5]);
+        assert!(reader.next_frame().is_none());
+        reader.push(&frame[5..]);
+        reader.push(&[0xAA, 0x55, 0x01]);
+        reader.push(&binary_frame(8, 0, 12000, 0, 0));
+        assert_eq!(reader.next_frame().unwrap().timestamp, 7);
+        assert_eq!(reader.next_frame().unwrap().timestamp, 8);
+        assert!(reader.next_frame().is_none());
+        assert_eq!(reader.take_bad_frames(), 1);
+    }
+
+    #[test]
+    fn mask_all_off() {
+        assert_eq!(solenoids_to_mask(&[false; 16]), 0);
+    }
+
+    #[test]
+    fn mask_all_on() {
+        assert_eq!(solenoids_to_mask(&[true; 16]), 0xFFFF);
+    }
+
+    #[test]
+    fn mask_alternating() {
+        let odd_on: Vec<bool> = (0..16).map(|i| i % 2 == 0).collect();
+        assert_eq!(solenoids_to_mask(&odd_on), 0x5555);
+        let even_on: Vec<bool> = (0..16).map(|i| i % 2 == 1).collect();
+        assert_eq!(solenoids_to_mask(&even_on), 0xAAAA);
+    }
+
+    #[test]
+    fn mask_bit_n_is_solenoid_n_plus_one() {
+        let mut solenoids = [false; 16];
+        solenoids[0] = true;
+        solenoids[15] = true;
+        assert_eq!(solenoids_to_mask(&solenoids), 0x8001);
+    }
+
+    #[test]
+    fn rejects_empty_line() {
+        assert!(parse_telemetry_line("").is_err());
+    }
+
+    #[test]
+    fn rejects_single_segment() {
+        assert!(parse_telemetry_line("TS:123456").is_err());
+    }
+
+    #[test]
+    fn rejects_four_segments() {
+        let line = GOLDEN.rsplit_once(" | ").unwrap().0;
+        assert!(parse_telemetry_line(line).is_err());
+    }
+
+    #[test]
+    fn parses_optional_extra_segment() {
+        let line = format!("{} | PYRO:1:OK,2:OK,3:FAIL,4:OK | EXT:TC1:350.2,PT2:-1.5e1", GOLDEN);
+        let t = parse_telemetry_line(&line).unwrap();
+        assert_eq!(t.pyro_continuity, vec![true, true, false, true]);
+        assert_eq!(t.extra.len(), 2);
+        assert_eq!(t.extra["TC1"], 350.2);
+        assert_eq!(t.extra["PT2"], -15.0);
+        // Without the pyro segment.
+        let t = parse_telemetry_line(&format!("{} | EXT:TC1:20", GOLDEN)).unwrap();
+        assert_eq!(t.pyro_continuity, vec![false; 4]);
+        assert_eq!(t.extra["TC1"], 20.0);
+    }
+
+    #[test]
+    fn rejects_unknown_or_malformed_trailing_segments() {
+        let pyro = "PYRO:1:OK,2:OK,3:OK,4:OK";
+        for tail in [
+            format!("{} | EXTRA:1", pyro),
+            format!("{} | EXT:TC1", pyro),
+            format!("{} | EXT:TC1:hot", pyro),
+            format!("{} | EXT::1.0", pyro),
+            format!("{} | EXT:TC1:NaN", pyro),
+            format!("EXT:TC1:1 | {}", pyro),
+            format!("{} | EXT:TC1:1 | EXT:TC2:2", pyro),
+        ] {
+            let line = format!("{} | {}", GOLDEN, tail);
+            assert!(parse_telemetry_line(&line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn custom_line_format_fills_fields_from_named_groups() {
+        let format = LineFormat::new(concat!(
+            r"^(?P<timestamp>\d+);(?P<battery>[\d.]+)V;(?:A(?P<armed>\d))?;(?P<solenoids>[01]+)",
+            r"(?:;P(?P<pyro>[01]{4}))?(?:;T(?P<tc1>\S+))?$",
+        ))
+        .unwrap();
+        let t = format.parse("9001;12.5V;A1;1000000000000001;P1011;T-3.5").unwrap();
+        assert_eq!(t.timestamp, 9001);
+        assert!(t.armed);
+        assert_eq!((t.battery, t.battery_raw, t.arming), (12.5, 12.5, 0.0));
+        assert_eq!(solenoids_to_mask(&t.solenoids), 0b1000_0000_0000_0001);
+        assert_eq!(t.pyro_continuity, vec![true, false, true, true]);
+        assert_eq!(t.extra, HashMap::from([("tc1".to_string(), -3.5)]));
+
+        let t = format.parse("7;11.9V;;0000000000000000").unwrap();
+        assert!(!t.armed);
+        assert_eq!(t.pyro_continuity, vec![false; 4]);
+        assert!(t.extra.is_empty());
+
+        for line in [
+            "7;11.9V;;000000000000000",
+            "7;11.9V;A2;0000000000000000",
+            "7;1.2.3V;;0000000000000000",
+            "7;11.9V;;0000000000000000;Thot",
+            "TS:7",
+        ] {
+            assert!(format.parse(line).is_none(), "{}", line);
+        }
+        assert!(LineFormat::new(r"(?P<ts>\d+)").is_err(), "needs a timestamp group");
+        assert!(LineFormat::new(r"(?P<timestamp>\d+").is_err());
+    }
+
+    #[test]
+    fn rejects_other_separators() {
+        // Segments must be separated by " | " exactly.
+        assert!(parse_telemetry_line(&GOLDEN.replace(" | ", "|")).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_segment_prefixes() {
+        assert!(parse_telemetry_line(&with_segment(0, "T:123456")).is_err());
+        assert!(parse_telemetry_line(&with_segment(1, "ARMED:1")).is_err());
+        assert!(parse_telemetry_line(&with_segment(2, "BAT:12.34V")).is_err());
+        assert!(parse_telemetry_line(&with_segment(3, "ARMSENSE:11.90V")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replace("SOL:", "SOLENOIDS:")).is_err());
+    }
+
+    #[test]
+    fn rejects_non_numeric_timestamp() {
+        assert!(parse_telemetry_line(&with_segment(0, "TS:abc")).is_err());
+        assert!(parse_telemetry_line(&with_segment(0, "TS:-5")).is_err());
+        assert!(parse_telemetry_line(&with_segment(0, "TS:")).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_armed_flag() {
+        assert!(parse_telemetry_line(&with_segment(1, "ARM:2")).is_err());
+        assert!(parse_telemetry_line(&with_segment(1, "ARM:true")).is_err());
+    }
+
+    #[test]
+    fn rejects_non_numeric_voltages() {
+        assert!(parse_telemetry_line(&with_segment(2, "BATT:twelveV")).is_err());
+        assert!(parse_telemetry_line(&with_segment(2, "BATT:V")).is_err());
+        assert!(parse_telemetry_line(&with_segment(3, "ARM_SENSE:1.2.3V")).is_err());
+    }
+
+    #[test]
+    fn rejects_voltages_without_unit() {
+        assert!(parse_telemetry_line(&with_segment(2, "BATT:12.34")).is_err());
+        assert!(parse_telemetry_line(&with_segment(3, "ARM_SENSE:11.90")).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_solenoid_states() {
+        assert!(parse_telemetry_line(&GOLDEN.replace("2:OFF", "2:on")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replace("2:OFF", "2:OPEN")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replace("2:OFF", "2:")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replace("2:OFF", "2:OFF:1")).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_solenoid_count() {
+        // 15 and 17 entries.
+        assert!(parse_telemetry_line(&GOLDEN.replace(",16:ON", "")).is_err());
+        assert!(parse_telemetry_line(&GOLDEN.replace("16:ON", "16:ON,17:OFF")).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_pyro_segment() {
+        let three = format!("{} | PYRO:1:OK,2:OK,3:OK", GOLDEN);
+        assert!(parse_telemetry_line(&three).is_err());
+        let unknown = format!("{} | PYRO:1:OK,2:OK,3:MAYBE,4:OK", GOLDEN);
+        assert!(parse_telemetry_line(&unknown).is_err());
+        let prefix = format!("{} | PY:1:OK,2:OK,3:OK,4:OK", GOLDEN);
+        assert!(parse_telemetry_line(&prefix).is_err());
+    }
+
+    /// Characters that make up valid lines, so random strings get past the early checks.
+    const FUZZ_ALPHABET: &[char] = &[
+        'T', 'S', 'A', 'R', 'M', 'B', 'V', 'O', 'N', 'F', 'L', 'P', 'Y', 'K', 'I', '_', ':', '|',
+        ' ', ',', '.', '-', '+', '0', '1', '2', '9', 'e', '\r', '\n', '\0', 'é', '🚀',
+    ];
+
+    /// Random strings and random mutations of valid lines must never panic the parser.
+    #[test]
+    fn fuzz_never_panics() {
+        let mut rng = StdRng::seed_from_u64(0x0da7_0be5);
+        let pyro_line = format!("{} | PYRO:1:OK,2:OK,3:FAIL,4:OK", GOLDEN);
+        let seeds = [GOLDEN, pyro_line.as_str()];
+        for _ in 0..20_000 {
+            let line: String = if rng.gen_bool(0.5) {
+                let len = rng.gen_range(0..200);
+                (0..len)
+                    .map(|_| FUZZ_ALPHABET[rng.gen_range(0..FUZZ_ALPHABET.len())])
+                    .collect()
+            } else {
+                let mut chars: Vec<char> = seeds[rng.gen_range(0..seeds.len())].chars().collect();
+                for _ in 0..rng.gen_range(1..8) {
+                    let c = FUZZ_ALPHABET[rng.gen_range(0..FUZZ_ALPHABET.len())];
+                    let i = rng.gen_range(0..chars.len());
+                    match rng.gen_range(0..3) {
+                        0 => chars[i] = c,
+                        1 => chars.insert(i, c),
+                        _ if chars.len() > 1 => {
+                            chars.remove(i);
+                        }
+                        _ => {}
+                    }
+                }
+                chars.into_iter().collect()
+            };
+            let _ = parse_telemetry_line(&line);
+            let _ = parse_telemetry_binary(line.as_bytes());
+        }
+    }
+}
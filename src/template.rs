@@ -0,0 +1,156 @@
+// src/template.rs
+
+//! The dashboard from `templates/index.html.tera` (`templates` feature), so a deployment
+//! can ship its own page without rebuilding the server. GET / renders it with the current
+//! telemetry, so the page shows the right state before its first update arrives. Without a
+//! `templates` directory GET / serves the built-in page as usual.
+//!
+//! Only the part of Tera's syntax the dashboard needs is understood: `{{ path }}`, where
+//! the path walks the context with dots (`telemetry.armed`, `telemetry.solenoids.0`),
+//! optionally followed by the filters `json_encode` and `safe`. Output is HTML-escaped
+//! unless `safe` is given. Tags (`{% ... %}`) are refused, comments (`{# ... #}`) dropped.
+//! The file is read on every request, so edits show on the next reload.
+
+use std::fs;
+use std::path::Path;
+
+use rocket::serde::json::{self, Value};
+
+/// Where the templates are looked for, relative to the working directory.
+pub const TEMPLATE_DIR: &str = "templates";
+
+/// The dashboard template in `TEMPLATE_DIR`.
+pub const INDEX_TEMPLATE: &str = "index.html.tera";
+
+/// Renders `INDEX_TEMPLATE` with `context`. `None` if `TEMPLATE_DIR` does not exist.
+pub fn render_index(context: &Value) -> Option<Result<String, String>> {
+    let dir = Path::new(TEMPLATE_DIR);
+    if !dir.is_dir() {
+        return None;
+    }
+    let path = dir.join(INDEX_TEMPLATE);
+    Some(
+        fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|template| render(&template, context)),
+    )
+}
+
+/// Renders `template` with the values of `context` (see the module docs).
+pub fn render(template: &str, context: &Value) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let close = match after.get(..2) {
+            Some("{{") => "}}",
+            Some("{#") => "#}",
+            Some("{%") => return Err(format!("tags are not supported: {}", line_of(after))),
+            _ => {
+                out.push('{');
+                rest = &after[1..];
+                continue;
+            }
+        };
+        let end = after[2..]
+            .find(close)
+            .ok_or_else(|| format!("unclosed '{}': {}", &after[..2], line_of(after)))?;
+        if close == "}}" {
+            out.push_str(&expression(after[2..2 + end].trim(), context)?);
+        }
+        rest = &after[2 + end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The value of one `{{ ... }}`.
+fn expression(expr: &str, context: &Value) -> Result<String, String> {
+    let mut parts = expr.split('|').map(str::trim);
+    let path = parts.next().unwrap_or_default();
+    let mut value = context;
+    for key in path.split('.') {
+        value = match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        }
+        .ok_or_else(|| format!("'{}' is not in the context", path))?;
+    }
+    let (mut text, mut safe) = (plain(value), false);
+    for filter in parts {
+        match filter {
+            // `<` escaped so the JSON can sit in a <script> without closing it.
+            "json_encode" => {
+                text = json::to_string(value)
+                    .map_err(|e| e.to_string())?
+                    .replace('<', "\\u003c")
+            }
+            "safe" => safe = true,
+            _ => return Err(format!("unknown filter '{}' in '{}'", filter, expr)),
+        }
+    }
+    Ok(if safe { text } else { escape(&text) })
+}
+
+/// How a value prints without filters: strings without their quotes, the rest as JSON.
+fn plain(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Escapes like Tera's autoescape.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            '/' => escaped.push_str("&#x2F;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The line `text` starts, for error messages.
+fn line_of(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_values_from_the_context() {
+        let context = json::json!({
+            "telemetry": {"armed": true, "battery": 12.5, "solenoids": [false, true]},
+            "name": "<Stand 2>",
+        });
+        let template = "{# state #}<b class=\"{{ telemetry.armed }}\">{{telemetry.battery}} V\
+                        </b> {{ telemetry.solenoids.1 }} {{ name }} {{ name | safe }}\n\
+                        <script>const t = {{ telemetry | json_encode | safe }};</script> {x}";
+        assert_eq!(
+            render(template, &context).unwrap(),
+            "<b class=\"true\">12.5 V</b> true &lt;Stand 2&gt; <Stand 2>\n<script>const t = \
+             {\"armed\":true,\"battery\":12.5,\"solenoids\":[false,true]};</script> {x}"
+        );
+        assert_eq!(
+            render("{{ name | json_encode }}", &context).unwrap(),
+            "&quot;\\u003cStand 2&gt;&quot;"
+        );
+
+        assert!(render("{{ telemetry.missing }}", &context).is_err());
+        assert!(render("{{ telemetry.solenoids.5 }}", &context).is_err());
+        assert!(render("{{ name | upper }}", &context).is_err());
+        assert!(render("{% if telemetry.armed %}", &context).is_err());
+        assert!(render("{{ name", &context).is_err());
+    }
+}
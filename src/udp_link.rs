@@ -0,0 +1,207 @@
+// src/udp_link.rs
+
+//! Telemetry over UDP instead of a serial port (`--udp-in <host:port>`), for stands where
+//! a WiFi bridge (such as an ESP8266) relays the Arduino's serial output as datagrams.
+//!
+//! The socket stands in for the port: datagrams are read exactly like serial data (a
+//! datagram without a trailing newline still ends its line), so ACKs, binary frames, dry
+//! runs and reconciliation all work as over serial. Commands go back as datagrams to
+//! `--udp-command <host:port>` or, if that is not given, to wherever the last datagram came
+//! from.
+
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use tracing::warn;
+
+use crate::metrics::Metrics;
+use crate::{
+    spawn_serial_loop, SerialEndpoints, SerialLink, SerialSettings, SessionEnd,
+    SharedConnectionStatus, TelemetryFormat, TelemetrySinks,
+};
+
+/// Longest datagram read; the rest of a longer one is lost.
+const MAX_DATAGRAM: usize = 4096;
+
+/// The address the last datagram came from, shared between the reader and the writer.
+type LastPeer = Arc<Mutex<Option<SocketAddr>>>;
+
+/// Resolves a `host:port` given for `--udp-in` or `--udp-command`.
+pub fn resolve(addr: &str) -> Result<SocketAddr, String> {
+    addr.to_socket_addrs()
+        .map_err(|e| format!("'{}': {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("'{}' did not resolve to an address", addr))
+}
+
+/// Runs the serial loop (see `spawn_serial_loop`) on a UDP socket bound to `bind`, sending
+/// commands to `command_to`. A socket that cannot be bound is retried like a missing port.
+pub fn spawn_udp_loop(
+    sinks: TelemetrySinks,
+    endpoints: SerialEndpoints,
+    mut settings: SerialSettings,
+    bind: SocketAddr,
+    command_to: Option<SocketAddr>,
+    status: SharedConnectionStatus,
+    metrics: Arc<Metrics>,
+) {
+    settings.port_name = format!("udp://{}", bind);
+    let open = move |settings: &SerialSettings| {
+        open(bind, command_to, settings).map_err(|e| {
+            warn!(error = %e, addr = %bind, "Failed to bind UDP socket");
+            SessionEnd::Disconnected
+        })
+    };
+    spawn_serial_loop(sinks, endpoints, settings, status, metrics, open);
+}
+
+/// Binds the socket, with the serial read timeout.
+fn open(
+    bind: SocketAddr,
+    command_to: Option<SocketAddr>,
+    settings: &SerialSettings,
+) -> io::Result<SerialLink> {
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(settings.read_timeout))?;
+    link(
+        socket,
+        command_to,
+        settings.format == TelemetryFormat::Ascii,
+    )
+}
+
+/// The link over `socket`. With `terminate_lines`, every datagram ends a line.
+fn link(
+    socket: UdpSocket,
+    command_to: Option<SocketAddr>,
+    terminate_lines: bool,
+) -> io::Result<SerialLink> {
+    let last_peer = LastPeer::default();
+    let writer = DatagramWriter {
+        socket: socket.try_clone()?,
+        command_to,
+        last_peer: last_peer.clone(),
+    };
+    let reader = DatagramReader {
+        socket,
+        buf: Vec::new(),
+        pos: 0,
+        terminate_lines,
+        last_peer,
+    };
+    Ok(SerialLink {
+        writer: Box::new(writer),
+        reader: Box::new(reader),
+        control: None,
+    })
+}
+
+/// Reads the socket's datagrams as one stream of bytes.
+struct DatagramReader {
+    socket: UdpSocket,
+    /// The current datagram, and how much of it was consumed.
+    buf: Vec<u8>,
+    pos: usize,
+    terminate_lines: bool,
+    last_peer: LastPeer,
+}
+
+impl Read for DatagramReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for DatagramReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Waits for a non-empty datagram once the current one is used up.
+        while self.pos >= self.buf.len() {
+            let mut datagram = [0u8; MAX_DATAGRAM];
+            let (n, from) = self.socket.recv_from(&mut datagram)?;
+            *self.last_peer.lock().unwrap() = Some(from);
+            self.buf.clear();
+            self.buf.extend_from_slice(&datagram[..n]);
+            if self.terminate_lines && !self.buf.ends_with(b"\n") {
+                self.buf.push(b'\n');
+            }
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+/// Sends every write as one datagram.
+struct DatagramWriter {
+    socket: UdpSocket,
+    command_to: Option<SocketAddr>,
+    last_peer: LastPeer,
+}
+
+impl Write for DatagramWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let addr = self
+            .command_to
+            .or(*self.last_peer.lock().unwrap())
+            .ok_or_else(|| {
+                let message = "no datagram received yet to reply to; set --udp-command";
+                io::Error::new(io::ErrorKind::NotConnected, message)
+            })?;
+        self.socket.send_to(buf, addr)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn datagrams_are_lines_and_commands_go_back() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let server = socket.local_addr().unwrap();
+        let SerialLink {
+            mut writer,
+            mut reader,
+            ..
+        } = link(socket, None, true).unwrap();
+        let bridge = UdpSocket::bind("127.0.0.1:0").unwrap();
+        bridge
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        let err = writer.write_all(b"s31\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+
+        bridge.send_to(b"TS:1 | ARM:0", server).unwrap();
+        bridge.send_to(b"ACK:s31\nTS:2\n", server).unwrap();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            lines.push(line);
+        }
+        assert_eq!(lines, ["TS:1 | ARM:0\n", "ACK:s31\n", "TS:2\n"]);
+
+        writer.write_all(b"s31\ns50\n").unwrap();
+        let mut datagram = [0u8; 64];
+        let (n, _) = bridge.recv_from(&mut datagram).unwrap();
+        assert_eq!(&datagram[..n], b"s31\ns50\n");
+    }
+}
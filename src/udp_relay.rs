@@ -0,0 +1,107 @@
+// src/udp_relay.rs
+
+//! Forwards every parsed telemetry sample, as the JSON of GET /telemetry, in one UDP datagram
+//! to each destination of `--udp-out <host:port>[,<host:port>...]` (`[relay] udp_out`), for
+//! separate telemetry displays on the test stand network.
+//!
+//! The datagrams are sent from a thread of their own. The serial loop hands samples over on
+//! a bounded channel and drops them when it is full, so a slow network never holds it up.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+use rocket::serde::json;
+use tracing::{debug, error, warn};
+
+use crate::Telemetry;
+
+/// Samples waiting for the relay thread before new ones are dropped.
+const QUEUE_SIZE: usize = 64;
+
+/// Resolves `destinations` and starts the relay thread. Samples sent on the returned channel
+/// go to every destination; use `try_send`, so a full queue drops the sample.
+pub fn spawn(destinations: &[String]) -> Result<mpsc::SyncSender<Telemetry>, String> {
+    let mut targets = Vec::with_capacity(destinations.len());
+    for destination in destinations {
+        let addr = destination
+            .to_socket_addrs()
+            .map_err(|e| format!("'{}': {}", destination, e))?
+            .next()
+            .ok_or_else(|| format!("'{}' did not resolve to an address", destination))?;
+        let local = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(local).map_err(|e| format!("bind {}: {}", local, e))?;
+        targets.push((destination.clone(), addr, socket));
+    }
+    let (tx, rx) = mpsc::sync_channel::<Telemetry>(QUEUE_SIZE);
+    thread::spawn(move || {
+        // Whether the last datagram to each destination failed, to warn once per outage.
+        let mut failing = vec![false; targets.len()];
+        for tel in rx {
+            let datagram = match json::to_string(&tel) {
+                Ok(datagram) => datagram,
+                Err(e) => {
+                    error!(error = %e, "Could not serialize telemetry for the UDP relay");
+                    continue;
+                }
+            };
+            for ((destination, addr, socket), failing) in targets.iter().zip(&mut failing) {
+                match socket.send_to(datagram.as_bytes(), addr) {
+                    Ok(_) => *failing = false,
+                    Err(e) if !*failing => {
+                        warn!(destination = %destination, error = %e, "UDP relay send failed");
+                        *failing = true;
+                    }
+                    Err(e) => {
+                        debug!(destination = %destination, error = %e, "UDP relay send failed")
+                    }
+                }
+            }
+        }
+    });
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::Value;
+    use std::time::Duration;
+
+    #[test]
+    fn relays_telemetry_as_json_datagrams() {
+        let receivers: Vec<UdpSocket> = (0..2)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let destinations: Vec<String> = receivers
+            .iter()
+            .map(|r| r.local_addr().unwrap().to_string())
+            .collect();
+        let relay = spawn(&destinations).unwrap();
+        let tel = Telemetry {
+            timestamp: 42,
+            ..Telemetry::default()
+        };
+        relay.try_send(tel).unwrap();
+
+        for receiver in &receivers {
+            receiver
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0u8; 1024];
+            let n = receiver.recv(&mut buf).unwrap();
+            let datagram: Value = json::from_slice(&buf[..n]).unwrap();
+            assert_eq!(datagram["timestamp"], 42);
+            assert_eq!(datagram["solenoids"].as_array().unwrap().len(), 16);
+        }
+    }
+
+    #[test]
+    fn rejects_destinations_without_a_port() {
+        let err = spawn(&["localhost".to_string()]).unwrap_err();
+        assert!(err.starts_with("'localhost'"), "{}", err);
+    }
+}
@@ -0,0 +1,195 @@
+// src/verifier.rs
+
+//! Command echo verification: after "s31" is written, telemetry should soon show solenoid 3
+//! on. The serial loop records what each written solenoid command should change and checks
+//! every telemetry frame against the outstanding expectations. One not met within
+//! `verify_timeout_ms` (`[safety]`) points at a stuck-open or stuck-closed valve (or a
+//! broken driver); it is logged at ERROR and kept for GET /verifier/failures.
+//!
+//! A later command for the same channel replaces the expectation of an earlier one, which
+//! may never have shown if both came within one telemetry period. A disarmed board
+//! switches nothing, so expectations are dropped while telemetry shows it disarmed.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use rocket::serde::{json::Json, Serialize};
+use rocket::State;
+use tracing::error;
+
+use crate::flight_log::{serialize_epoch_ms, EventType};
+use crate::AppState;
+
+/// `verify_timeout_ms` unless configured: five frames of the firmware's 100 ms stream.
+pub const DEFAULT_VERIFY_TIMEOUT_MS: u64 = 500;
+
+/// Failures kept for GET /verifier/failures, oldest dropped first.
+pub const MAX_FAILURES: usize = 100;
+
+/// A solenoid state telemetry should show soon.
+#[derive(Debug)]
+struct Expectation {
+    channel: u8,
+    on: bool,
+    written_at: Instant,
+}
+
+/// A command whose effect telemetry never showed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct VerificationFailure {
+    pub channel: u8,
+    /// The energized state that was commanded.
+    pub expected: bool,
+    /// When the failure was noticed, in milliseconds since the Unix epoch.
+    #[serde(serialize_with = "serialize_epoch_ms")]
+    pub wall_clock: SystemTime,
+    /// From writing the command to giving up on it.
+    pub waited_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct CommandVerifier {
+    /// Zero disables verification.
+    timeout: Duration,
+    pending: Vec<Expectation>,
+    failures: VecDeque<VerificationFailure>,
+}
+
+/// The verifier, shared between the serial loop and GET /verifier/failures.
+pub type SharedVerifier = Arc<Mutex<CommandVerifier>>;
+
+impl Default for CommandVerifier {
+    fn default() -> Self {
+        CommandVerifier::new(Duration::from_millis(DEFAULT_VERIFY_TIMEOUT_MS))
+    }
+}
+
+impl CommandVerifier {
+    pub fn new(timeout: Duration) -> Self {
+        CommandVerifier {
+            timeout,
+            pending: Vec::new(),
+            failures: VecDeque::new(),
+        }
+    }
+
+    /// Expects the solenoid commands in `text` (one per line), written at `now`.
+    pub fn record(&mut self, text: &str, now: Instant) {
+        if self.timeout.is_zero() {
+            return;
+        }
+        for event in text.lines().filter_map(EventType::from_command) {
+            if let EventType::Solenoid {
+                channel: channel @ 1..=16,
+                state,
+            } = event
+            {
+                self.pending.retain(|expected| expected.channel != channel);
+                self.pending.push(Expectation {
+                    channel,
+                    on: state,
+                    written_at: now,
+                });
+            }
+        }
+    }
+
+    /// Checks a telemetry frame received at `now`: expectations it meets are done, those
+    /// older than the timeout have failed and are returned (and kept).
+    pub fn check(
+        &mut self,
+        armed: bool,
+        solenoids: &[bool],
+        now: Instant,
+    ) -> Vec<VerificationFailure> {
+        if !armed {
+            self.pending.clear();
+            return Vec::new();
+        }
+        let timeout = self.timeout;
+        let mut failed = Vec::new();
+        self.pending.retain(|expected| {
+            let shown = solenoids.get(usize::from(expected.channel) - 1) == Some(&expected.on);
+            let waited = now.saturating_duration_since(expected.written_at);
+            if !shown && waited > timeout {
+                failed.push(VerificationFailure {
+                    channel: expected.channel,
+                    expected: expected.on,
+                    wall_clock: SystemTime::now(),
+                    waited_ms: waited.as_millis() as u64,
+                });
+            }
+            !shown && waited <= timeout
+        });
+        for failure in &failed {
+            if self.failures.len() == MAX_FAILURES {
+                self.failures.pop_front();
+            }
+            self.failures.push_back(failure.clone());
+        }
+        failed
+    }
+
+    pub fn failures(&self) -> Vec<VerificationFailure> {
+        self.failures.iter().cloned().collect()
+    }
+}
+
+/// Checks a frame (see `CommandVerifier::check`) and logs each failure.
+pub fn check_frame(verifier: &SharedVerifier, armed: bool, solenoids: &[bool]) {
+    let failed = verifier
+        .lock()
+        .unwrap()
+        .check(armed, solenoids, Instant::now());
+    for failure in failed {
+        error!(
+            channel = failure.channel,
+            expected = failure.expected,
+            waited_ms = failure.waited_ms,
+            "Telemetry does not show the commanded solenoid state; valve may be stuck"
+        );
+    }
+}
+
+/// GET /verifier/failures returns the recent verification failures, oldest first.
+#[get("/verifier/failures")]
+pub fn failures(state: &State<AppState>) -> Json<Vec<VerificationFailure>> {
+    Json(state.verifier.lock().unwrap().failures())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmet_expectations_fail_after_the_timeout() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut verifier = CommandVerifier::new(Duration::from_millis(500));
+        verifier.record("s31\ns41", start);
+        // s50 replaces s51 before either could show.
+        verifier.record("s51", ms(10));
+        verifier.record("s50", ms(20));
+
+        let mut solenoids = vec![false; 16];
+        solenoids[2] = true;
+        assert!(verifier.check(true, &solenoids, ms(100)).is_empty());
+        let failed = verifier.check(true, &solenoids, ms(600));
+        assert_eq!(failed.len(), 1);
+        assert_eq!((failed[0].channel, failed[0].expected), (4, true));
+        assert_eq!(failed[0].waited_ms, 600);
+        assert!(verifier.check(true, &solenoids, ms(1000)).is_empty());
+        assert_eq!(verifier.failures().len(), 1);
+
+        // Nothing is expected of a disarmed board.
+        verifier.record("s71", ms(1000));
+        assert!(verifier.check(false, &solenoids, ms(2000)).is_empty());
+        assert!(verifier.check(true, &solenoids, ms(2000)).is_empty());
+
+        let mut disabled = CommandVerifier::new(Duration::ZERO);
+        disabled.record("s71", start);
+        assert!(disabled.check(true, &solenoids, ms(1000)).is_empty());
+    }
+}
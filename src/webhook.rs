@@ -0,0 +1,199 @@
+// src/webhook.rs
+
+//! Low-battery alerts posted to a webhook (`webhook` feature). The `[[alert]]` rules of
+//! `alerts` post through `WebhookUrl` too.
+//!
+//! A background thread follows the telemetry broadcast and, when the battery drops below
+//! `low_battery_threshold`, POSTs `{"event":"low_battery","voltage":..,"timestamp":..}` to
+//! the configured URL. It then stays quiet until the battery has recovered by the
+//! hysteresis band, so a voltage hovering at the threshold only alerts once.
+//!
+//! The POST is plain HTTP/1.1 over a `TcpStream`; only `http://` URLs are supported.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use rocket::serde::{json, Serialize};
+use rocket::tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::config::WebhookConfig;
+use crate::Telemetry;
+
+/// Connect/read/write timeout for a webhook request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The JSON body of a low-battery alert.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct LowBatteryEvent {
+    event: &'static str,
+    voltage: f32,
+    timestamp: u64,
+}
+
+/// Decides when a low-battery alert fires.
+struct LowBatteryAlert {
+    threshold: f32,
+    hysteresis: f32,
+    /// Set once the alert has fired, cleared when the battery recovers.
+    triggered: bool,
+}
+
+impl LowBatteryAlert {
+    /// Feeds a new reading; returns whether an alert should be sent for it.
+    fn update(&mut self, voltage: f32) -> bool {
+        if self.triggered {
+            if voltage >= self.threshold + self.hysteresis {
+                self.triggered = false;
+            }
+            false
+        } else if voltage < self.threshold {
+            self.triggered = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// An `http://host[:port]/path` URL.
+pub struct WebhookUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl WebhookUrl {
+    pub fn parse(url: &str) -> Result<WebhookUrl, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("'{}': only http:// webhook URLs are supported", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("'{}': invalid port", url))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("'{}': missing host", url));
+        }
+        Ok(WebhookUrl {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POSTs `body` as JSON and checks for a 2xx response.
+    pub fn post(&self, body: &str) -> io::Result<()> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, REQUEST_TIMEOUT)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            body.len(),
+            body
+        )?;
+        // Only the status line matters: "HTTP/1.1 200 OK".
+        let mut response = [0u8; 64];
+        let n = stream.read(&mut response)?;
+        let status_line = String::from_utf8_lossy(&response[..n]);
+        match status_line.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "unexpected response: {}",
+                status_line.lines().next().unwrap_or("")
+            ))),
+        }
+    }
+}
+
+/// Starts the low-battery watcher if a webhook URL is configured.
+/// Returns an error for an unusable URL so it can be reported at startup.
+pub fn spawn_low_battery_watcher(
+    config: &WebhookConfig,
+    mut telemetry: broadcast::Receiver<Telemetry>,
+) -> Result<(), String> {
+    let Some(url) = &config.url else {
+        return Ok(());
+    };
+    let url = WebhookUrl::parse(url)?;
+    let mut alert = LowBatteryAlert {
+        threshold: config.low_battery_threshold,
+        hysteresis: config.hysteresis,
+        triggered: false,
+    };
+    thread::spawn(move || loop {
+        let tel = match telemetry.blocking_recv() {
+            Ok(tel) => tel,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if alert.update(tel.battery) {
+            let event = LowBatteryEvent {
+                event: "low_battery",
+                voltage: tel.battery,
+                timestamp: tel.timestamp,
+            };
+            let body = json::to_string(&event).unwrap_or_default();
+            if let Err(e) = url.post(&body) {
+                warn!(error = %e, "Low-battery webhook failed");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_once_until_recovered_past_hysteresis() {
+        let mut alert = LowBatteryAlert {
+            threshold: 11.1,
+            hysteresis: 1.0,
+            triggered: false,
+        };
+        assert!(!alert.update(12.0));
+        assert!(alert.update(11.05));
+        assert!(!alert.update(11.0));
+        assert!(!alert.update(11.5));
+        assert!(!alert.update(11.05));
+        assert!(!alert.update(12.2));
+        assert!(alert.update(11.0));
+    }
+
+    #[test]
+    fn parses_http_urls() {
+        let url = WebhookUrl::parse("http://hooks.local:8080/gcs/alert").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("hooks.local", 8080, "/gcs/alert")
+        );
+        let url = WebhookUrl::parse("http://10.0.0.2").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("10.0.0.2", 80, "/")
+        );
+        assert!(WebhookUrl::parse("https://example.com/hook").is_err());
+    }
+}
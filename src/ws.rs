@@ -0,0 +1,303 @@
+// src/ws.rs
+
+//! A minimal RFC 6455 WebSocket server side, just enough to push telemetry to browsers.
+//!
+//! Rocket performs the HTTP upgrade for us (status 101, `Connection`/`Upgrade` headers);
+//! this module supplies the `Sec-WebSocket-Accept` handshake value and the framing.
+
+use std::io;
+use std::pin::Pin;
+
+use rocket::data::{IoHandler, IoStream};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use rocket::tokio::sync::{broadcast, mpsc};
+
+use crate::base64;
+use crate::Telemetry;
+
+/// The GUID every server appends to the client key (RFC 6455 section 1.3).
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Clients only ever send us control frames; anything larger than this is refused.
+const MAX_CLIENT_FRAME: u64 = 64 * 1024;
+
+/// Request guard for a WebSocket upgrade request. Holds the client's `Sec-WebSocket-Key`.
+/// Plain HTTP requests to a WebSocket route are rejected with `400 Bad Request`.
+pub struct WebSocketKey(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocketKey {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let wants_websocket = req
+            .headers()
+            .get("Upgrade")
+            .any(|v| v.eq_ignore_ascii_case("websocket"));
+        match req.headers().get_one("Sec-WebSocket-Key") {
+            Some(key) if wants_websocket => Outcome::Success(WebSocketKey(key.to_string())),
+            _ => Outcome::Error((Status::BadRequest, "expected a WebSocket upgrade request")),
+        }
+    }
+}
+
+/// Responder that upgrades the connection and then streams every telemetry update
+/// received on `rx` to the client as a JSON text frame.
+pub struct TelemetryStream {
+    key: WebSocketKey,
+    rx: broadcast::Receiver<Telemetry>,
+}
+
+impl TelemetryStream {
+    pub fn new(key: WebSocketKey, rx: broadcast::Receiver<Telemetry>) -> Self {
+        TelemetryStream { key, rx }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for TelemetryStream {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", accept_key(&self.key.0))
+            .upgrade("websocket", TelemetryFeed { rx: self.rx })
+            .ok()
+    }
+}
+
+/// The I/O handler that runs once the connection has been upgraded.
+struct TelemetryFeed {
+    rx: broadcast::Receiver<Telemetry>,
+}
+
+#[rocket::async_trait]
+impl IoHandler for TelemetryFeed {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let mut rx = Pin::into_inner(self).rx;
+        let (mut reader, mut writer) = rocket::tokio::io::split(io);
+        // Pongs are produced by the reader but have to go out through the writer.
+        let (pong_tx, mut pong_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+
+        let result = rocket::tokio::select! {
+            r = read_until_close(&mut reader, &pong_tx) => r,
+            r = write_updates(&mut writer, &mut rx, &mut pong_rx) => r,
+        };
+        // Whichever side finished, finish with a close frame so the client sees a clean shutdown.
+        let _ = writer.write_all(&encode_frame(OP_CLOSE, &[])).await;
+        result
+    }
+}
+
+/// Reads client frames until the client closes the connection, answering pings along the way.
+async fn read_until_close<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    pong_tx: &mpsc::UnboundedSender<Vec<u8>>,
+) -> io::Result<()> {
+    loop {
+        let (opcode, payload) = match read_frame(reader).await {
+            Ok(frame) => frame,
+            // The client went away without a close frame.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match opcode {
+            OP_CLOSE => return Ok(()),
+            OP_PING => {
+                let _ = pong_tx.send(encode_frame(OP_PONG, &payload));
+            }
+            // Anything else the client sends is ignored; this feed is read-only.
+            _ => {}
+        }
+    }
+}
+
+/// Forwards every broadcast telemetry update (and any pending pong) to the client.
+async fn write_updates<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    rx: &mut broadcast::Receiver<Telemetry>,
+    pong_rx: &mut mpsc::UnboundedReceiver<Vec<u8>>,
+) -> io::Result<()> {
+    loop {
+        rocket::tokio::select! {
+            update = rx.recv() => match update {
+                Ok(tel) => {
+                    let json = rocket::serde::json::to_string(&tel)
+                        .map_err(io::Error::other)?;
+                    writer.write_all(&encode_frame(OP_TEXT, json.as_bytes())).await?;
+                }
+                // A slow client simply skips the samples it missed.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            Some(pong) = pong_rx.recv() => writer.write_all(&pong).await?,
+        }
+    }
+}
+
+/// Reads one (possibly masked) frame, returning its opcode and unmasked payload.
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut ext = [0u8; 2];
+            reader.read_exact(&mut ext).await?;
+            u16::from_be_bytes(ext) as u64
+        }
+        127 => {
+            let mut ext = [0u8; 8];
+            reader.read_exact(&mut ext).await?;
+            u64::from_be_bytes(ext)
+        }
+        n => n as u64,
+    };
+    if len > MAX_CLIENT_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "WebSocket frame too large"));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+/// Builds a single unmasked, final frame (servers never mask).
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Computes `Sec-WebSocket-Accept` for a client key: base64(SHA-1(key + GUID)).
+fn accept_key(client_key: &str) -> String {
+    let mut input = client_key.trim().as_bytes().to_vec();
+    input.extend_from_slice(HANDSHAKE_GUID.as_bytes());
+    base64::encode(&sha1(&input))
+}
+
+/// SHA-1, used only for the handshake above (not for anything security-sensitive).
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut msg = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&bit_len.to_be_bytes());
+
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (hv, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hv = hv.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        // RFC 6455 section 1.3.
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[rocket::async_test]
+    async fn frames_round_trip_at_every_length_encoding() {
+        // 7-bit, 16-bit and 64-bit lengths, at the edges of each.
+        for (len, length_byte) in [(0, 0), (125, 125), (126, 126), (65535, 126), (65536, 127)] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let frame = encode_frame(OP_TEXT, &payload);
+            assert_eq!(frame[..2], [0x80 | OP_TEXT, length_byte]);
+            let mut reader = &frame[..];
+            assert_eq!(read_frame(&mut reader).await.unwrap(), (OP_TEXT, payload));
+            assert!(reader.is_empty());
+        }
+        // The unmasked "Hello" of RFC 6455 section 5.7.
+        assert_eq!(encode_frame(OP_TEXT, b"Hello"), [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']);
+    }
+
+    #[rocket::async_test]
+    async fn reads_masked_client_frames() {
+        // The masked "Hello" of RFC 6455 section 5.7.
+        let frame = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let (opcode, payload) = read_frame(&mut &frame[..]).await.unwrap();
+        assert_eq!((opcode, payload.as_slice()), (OP_TEXT, &b"Hello"[..]));
+    }
+
+    #[rocket::async_test]
+    async fn refuses_truncated_and_oversized_frames() {
+        let frame = encode_frame(OP_PING, b"hello");
+        for cut in [1, 4] {
+            let e = read_frame(&mut &frame[..cut]).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        let frame = encode_frame(OP_TEXT, &vec![0; MAX_CLIENT_FRAME as usize + 1]);
+        let e = read_frame(&mut &frame[..]).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // Refused from the header alone, before any payload is read.
+        let e = read_frame(&mut &[0x81, 0xFF, 0x80, 0, 0, 0, 0, 0, 0, 0][..]).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
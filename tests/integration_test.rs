@@ -0,0 +1,132 @@
+// tests/integration_test.rs
+
+//! End-to-end tests of the server and its serial loop. A socket pair stands in for the
+//! serial port: the tests play the Arduino on one end.
+
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+
+/// How long the tests wait for the serial loop before failing.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A client for a server linked to the returned socket, the Arduino's end of the port.
+fn client() -> (Client, UnixStream) {
+    let (port, arduino) = UnixStream::pair().expect("socket pair");
+    // Like the real port's read timeout, so the serial loop gets round to writing commands.
+    port.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    arduino.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let writer = port.try_clone().unwrap();
+    let rocket = telemetry_server::rocket_with_link(writer, port);
+    (Client::tracked(rocket).expect("valid rocket"), arduino)
+}
+
+/// Polls `uri` until its JSON body satisfies `done`, and returns that body.
+fn wait_for_json(client: &Client, uri: &str, done: impl Fn(&Value) -> bool) -> Value {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().expect("JSON body");
+        if done(&body) {
+            return body;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting on {}: {}", uri, body);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn telemetry_line_is_served_at_get_telemetry() {
+    let (client, mut arduino) = client();
+    arduino
+        .write_all(
+            b"TS:1234 | ARM:1 | BATT:12.60V | ARM_SENSE:11.90V | \
+              SOL:1:ON,2:OFF,3:OFF,4:OFF,5:OFF,6:OFF,7:OFF,8:OFF,\
+              9:OFF,10:OFF,11:OFF,12:OFF,13:OFF,14:OFF,15:OFF,16:ON\r\n",
+        )
+        .unwrap();
+
+    let telemetry = wait_for_json(&client, "/telemetry", |t| t["timestamp"] != 0);
+    let mut solenoids = vec![false; 16];
+    solenoids[0] = true;
+    solenoids[15] = true;
+    assert_eq!(
+        telemetry,
+        json!({
+            "timestamp": 1234,
+            "armed": true,
+            "battery": 12.6,
+            "battery_raw": 12.6,
+            "arming": 11.9,
+            "solenoids": solenoids,
+            "pyro_continuity": [false, false, false, false],
+        })
+    );
+    let status = wait_for_json(&client, "/status", |_| true);
+    assert_eq!(status["connection"]["state"], "Connected");
+}
+
+#[test]
+fn parse_errors_are_counted_by_kind() {
+    let (client, mut arduino) = client();
+    arduino
+        .write_all(b"garbage\r\nTS:x | ARM:1 | BATT:12.60V | ARM_SENSE:11.90V | SOL:1:ON\r\n")
+        .unwrap();
+
+    let counts = wait_for_json(&client, "/diagnostics/parse_errors", |c| c["bad_timestamp"] == 1);
+    assert_eq!(counts["wrong_segment_count"], 1);
+    assert_eq!(counts["bad_battery"], 0);
+}
+
+#[test]
+fn arm_is_written_to_the_port() {
+    let (client, arduino) = client();
+    assert_eq!(client.post("/arm").dispatch().status(), Status::Ok);
+
+    let mut line = String::new();
+    BufReader::new(arduino).read_line(&mut line).unwrap();
+    assert_eq!(line, "a\n");
+}
+
+#[test]
+fn reconcile_restores_commanded_solenoids() {
+    let (client, mut arduino) = client();
+    let mut commands = BufReader::new(arduino.try_clone().unwrap());
+    assert_eq!(client.post("/solenoid/3/1").dispatch().status(), Status::Ok);
+    let mut line = String::new();
+    commands.read_line(&mut line).unwrap();
+    assert_eq!(line, "s31\n");
+
+    // The board reports 3 off, as if the command had been lost.
+    assert_eq!(client.post("/reconcile").dispatch().status(), Status::Ok);
+    arduino
+        .write_all(
+            b"TS:1 | ARM:1 | BATT:12.60V | ARM_SENSE:11.90V | \
+              SOL:1:OFF,2:OFF,3:OFF,4:OFF,5:OFF,6:OFF,7:OFF,8:OFF,\
+              9:OFF,10:OFF,11:OFF,12:OFF,13:OFF,14:OFF,15:OFF,16:OFF\r\n",
+        )
+        .unwrap();
+    line.clear();
+    commands.read_line(&mut line).unwrap();
+    assert_eq!(line, "s31\n");
+}
+
+#[test]
+fn shutdown_sends_queued_commands_then_disarms() {
+    let (client, arduino) = client();
+    assert_eq!(client.post("/arm").dispatch().status(), Status::Ok);
+    assert_eq!(client.post("/solenoid/3/1").dispatch().status(), Status::Ok);
+    client.terminate();
+
+    // The serial loop has exited and closed the port once shutdown returns.
+    let lines: Vec<String> = BufReader::new(arduino).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines, ["a", "s31", "d"]);
+}
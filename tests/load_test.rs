@@ -0,0 +1,71 @@
+// tests/load_test.rs
+
+//! Throughput of GET /telemetry under concurrent load, as a regression gate for contention
+//! on the shared telemetry.
+
+#![cfg(unix)]
+
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::tokio;
+
+const TASKS: usize = 50;
+const REQUESTS_PER_TASK: usize = 100;
+/// Requests per second any modern laptop manages, even in a debug build.
+const MIN_THROUGHPUT: f64 = 1000.0;
+/// Every request waits behind the other tasks' on the shared runtime, so this is generous.
+const MAX_P99_LATENCY: Duration = Duration::from_secs(1);
+
+#[rocket::async_test]
+async fn telemetry_throughput_under_concurrent_load() {
+    // A socket pair stands in for the serial port, as in the integration tests; nothing is
+    // sent on it, so the telemetry stays at its defaults.
+    let (port, _arduino) = UnixStream::pair().expect("socket pair");
+    port.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let writer = port.try_clone().unwrap();
+    let rocket = telemetry_server::rocket_with_link(writer, port);
+    let client = Arc::new(Client::tracked(rocket).await.expect("valid rocket"));
+
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let mut latencies = Vec::with_capacity(REQUESTS_PER_TASK);
+                for _ in 0..REQUESTS_PER_TASK {
+                    let sent = Instant::now();
+                    let response = client.get("/telemetry").dispatch().await;
+                    assert_eq!(response.status(), Status::Ok);
+                    response.into_bytes().await.expect("body");
+                    latencies.push(sent.elapsed());
+                }
+                latencies
+            })
+        })
+        .collect();
+    let mut latencies = Vec::with_capacity(TASKS * REQUESTS_PER_TASK);
+    for task in tasks {
+        latencies.extend(task.await.expect("load task"));
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort();
+    let p99 = latencies[latencies.len() * 99 / 100];
+    let throughput = latencies.len() as f64 / elapsed.as_secs_f64();
+    assert!(
+        throughput > MIN_THROUGHPUT,
+        "{:.0} req/s is below {} req/s",
+        throughput,
+        MIN_THROUGHPUT
+    );
+    assert!(
+        p99 < MAX_P99_LATENCY,
+        "p99 latency {:?} is above {:?}",
+        p99,
+        MAX_P99_LATENCY
+    );
+}